@@ -2,5 +2,7 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     tonic_prost_build::compile_protos("src/backend/proto/project.proto")?;
     tonic_prost_build::compile_protos("src/backend/proto/session.proto")?;
     tonic_prost_build::compile_protos("src/backend/proto/message.proto")?;
+    tonic_prost_build::compile_protos("src/backend/proto/permission.proto")?;
+    tonic_prost_build::compile_protos("src/backend/proto/harness.proto")?;
     Ok(())
 }
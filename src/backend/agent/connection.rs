@@ -0,0 +1,354 @@
+use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc, sync::Arc};
+
+use agent_client_protocol::{self as acp, Agent as _};
+use tokio::sync::{broadcast, mpsc, oneshot};
+
+use crate::backend::{
+    agent::{Agent, AgentClient, AgentHub, AgentHubSessionError, AgentSession, fs::UnsavedBuffers},
+    harness::HarnessAssistantEvent,
+    permission::PermissionBroker,
+};
+
+type Reply<T> = oneshot::Sender<Result<T, AgentHubSessionError>>;
+type EventSender = broadcast::Sender<HarnessAssistantEvent>;
+
+enum ConnectionCommand {
+    NewSession {
+        cwd: PathBuf,
+        reply: Reply<(String, EventSender)>,
+    },
+    LoadSession {
+        session_id: String,
+        cwd: PathBuf,
+        reply: Reply<EventSender>,
+    },
+    Prompt {
+        session_id: String,
+        prompt: Vec<acp::ContentBlock>,
+    },
+    Cancel {
+        session_id: String,
+    },
+}
+
+/// Send-able handle to the actor owning one agent process and every session running on it.
+/// The ACP connection is `!Send`, so the actor lives on a `LocalSet` in its own thread and
+/// is reached through channels. It stops once every handle is dropped or the agent exits
+#[derive(Clone)]
+pub struct AgentConnection {
+    commands: mpsc::UnboundedSender<ConnectionCommand>,
+}
+
+impl AgentConnection {
+    /// Starts the agent process and waits until it finished the ACP handshake
+    pub async fn spawn(
+        agent: Agent,
+        permissions: Option<Arc<PermissionBroker>>,
+        unsaved: UnsavedBuffers,
+    ) -> Result<Self, AgentHubSessionError> {
+        let (commands, commands_rx) = mpsc::unbounded_channel();
+        let (ready_tx, ready_rx) = oneshot::channel();
+
+        std::thread::Builder::new()
+            .name(format!("acp-{}", agent.harness_type()))
+            .spawn(move || {
+                let runtime = match tokio::runtime::Builder::new_current_thread()
+                    .enable_all()
+                    .build()
+                {
+                    Ok(runtime) => runtime,
+                    Err(err) => {
+                        let _ = ready_tx.send(Err(AgentHubSessionError::Spawn(err.into())));
+                        return;
+                    }
+                };
+                let local_set = tokio::task::LocalSet::new();
+                local_set.block_on(
+                    &runtime,
+                    run_connection(agent, permissions, unsaved, commands_rx, ready_tx),
+                );
+            })
+            .map_err(|err| AgentHubSessionError::Spawn(err.into()))?;
+
+        ready_rx
+            .await
+            .map_err(|_| AgentHubSessionError::WorkerStopped)??;
+        Ok(Self { commands })
+    }
+
+    pub fn is_running(&self) -> bool {
+        !self.commands.is_closed()
+    }
+
+    pub async fn new_session(
+        &self,
+        cwd: PathBuf,
+    ) -> Result<AcpSessionHandle, AgentHubSessionError> {
+        let (session_id, events) = self
+            .request(|reply| ConnectionCommand::NewSession { cwd, reply })
+            .await?;
+        Ok(AcpSessionHandle {
+            session_id,
+            connection: self.clone(),
+            events,
+        })
+    }
+
+    /// Loads an existing agent session, replaying its history into the event mapper
+    pub async fn load_session(
+        &self,
+        session_id: String,
+        cwd: PathBuf,
+    ) -> Result<AcpSessionHandle, AgentHubSessionError> {
+        let events = self
+            .request(|reply| ConnectionCommand::LoadSession {
+                session_id: session_id.clone(),
+                cwd,
+                reply,
+            })
+            .await?;
+        Ok(AcpSessionHandle {
+            session_id,
+            connection: self.clone(),
+            events,
+        })
+    }
+
+    async fn request<T>(
+        &self,
+        command: impl FnOnce(Reply<T>) -> ConnectionCommand,
+    ) -> Result<T, AgentHubSessionError> {
+        let (reply, response) = oneshot::channel();
+        self.commands
+            .send(command(reply))
+            .map_err(|_| AgentHubSessionError::WorkerStopped)?;
+        response
+            .await
+            .map_err(|_| AgentHubSessionError::WorkerStopped)?
+    }
+}
+
+/// Send-able handle to one ACP session on a shared agent connection
+#[derive(Clone)]
+pub struct AcpSessionHandle {
+    session_id: String,
+    connection: AgentConnection,
+    events: EventSender,
+}
+
+impl AcpSessionHandle {
+    pub fn session_id(&self) -> &str {
+        &self.session_id
+    }
+
+    pub fn prompt(&self, prompt: Vec<acp::ContentBlock>) -> Result<(), AgentHubSessionError> {
+        self.connection
+            .commands
+            .send(ConnectionCommand::Prompt {
+                session_id: self.session_id.clone(),
+                prompt,
+            })
+            .map_err(|_| AgentHubSessionError::WorkerStopped)
+    }
+
+    /// Asks the agent to stop the running prompt, which then ends as cancelled
+    pub fn cancel(&self) -> Result<(), AgentHubSessionError> {
+        self.connection
+            .commands
+            .send(ConnectionCommand::Cancel {
+                session_id: self.session_id.clone(),
+            })
+            .map_err(|_| AgentHubSessionError::WorkerStopped)
+    }
+
+    pub fn subscribe(&self) -> broadcast::Receiver<HarnessAssistantEvent> {
+        self.events.subscribe()
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.connection.is_running()
+    }
+}
+
+async fn run_connection(
+    agent: Agent,
+    permissions: Option<Arc<PermissionBroker>>,
+    unsaved: UnsavedBuffers,
+    mut commands: mpsc::UnboundedReceiver<ConnectionCommand>,
+    ready: oneshot::Sender<Result<(), AgentHubSessionError>>,
+) {
+    let (command, args) = agent.command();
+    // Dropping the process at the end of this function kills the agent
+    let process = match AgentHub::spawn_agent_from_cmd(command, args).await {
+        Ok(process) => process,
+        Err(err) => {
+            let _ = ready.send(Err(AgentHubSessionError::Spawn(err)));
+            return;
+        }
+    };
+
+    let sessions = Rc::new(RefCell::new(HashMap::new()));
+    let (conn, handle_io) = acp::ClientSideConnection::new(
+        AgentClient::new(Rc::clone(&sessions), permissions),
+        process.outgoing,
+        process.incoming,
+        |fut| {
+            tokio::task::spawn_local(fut);
+        },
+    );
+    let mut io = tokio::task::spawn_local(handle_io);
+    let conn = Rc::new(conn);
+
+    let load_supported = match initialize(&conn).await {
+        Ok(initialized) => initialized.agent_capabilities.load_session,
+        Err(err) => {
+            let _ = ready.send(Err(err));
+            return;
+        }
+    };
+    if ready.send(Ok(())).is_err() {
+        return;
+    }
+    log::debug!("acp connection to {} ready", agent.harness_type());
+
+    // Agents handle one prompt per session at a time, later prompts wait their turn
+    let mut prompt_locks: HashMap<String, Rc<tokio::sync::Mutex<()>>> = HashMap::new();
+    loop {
+        let command = tokio::select! {
+            command = commands.recv() => match command {
+                Some(command) => command,
+                None => break,
+            },
+            _ = &mut io => {
+                log::warn!("acp connection to {} closed", agent.harness_type());
+                break;
+            }
+        };
+
+        match command {
+            ConnectionCommand::NewSession { cwd, reply } => {
+                let conn = Rc::clone(&conn);
+                let sessions = Rc::clone(&sessions);
+                let unsaved = unsaved.clone();
+                tokio::task::spawn_local(async move {
+                    let _ = reply.send(new_session(&conn, &sessions, cwd, unsaved).await);
+                });
+            }
+            ConnectionCommand::LoadSession {
+                session_id,
+                cwd,
+                reply,
+            } => {
+                if !load_supported {
+                    let _ = reply.send(Err(AgentHubSessionError::ResumeUnsupported(session_id)));
+                    continue;
+                }
+                let conn = Rc::clone(&conn);
+                let sessions = Rc::clone(&sessions);
+                let unsaved = unsaved.clone();
+                tokio::task::spawn_local(async move {
+                    let _ =
+                        reply.send(load_session(&conn, &sessions, session_id, cwd, unsaved).await);
+                });
+            }
+            ConnectionCommand::Prompt { session_id, prompt } => {
+                let session = sessions.borrow().get(&session_id).cloned();
+                let Some(session) = session else {
+                    log::warn!("prompt for unknown acp session {session_id}");
+                    continue;
+                };
+                let conn = Rc::clone(&conn);
+                let prompt_lock = Rc::clone(prompt_locks.entry(session_id.clone()).or_default());
+                tokio::task::spawn_local(async move {
+                    let _guard = prompt_lock.lock().await;
+                    let started = session.mapper.borrow_mut().begin_turn();
+                    session.publish(started);
+                    let result = conn
+                        .prompt(acp::PromptRequest::new(session_id.clone(), prompt))
+                        .await
+                        .map(|response| response.stop_reason)
+                        .map_err(|err| err.to_string());
+                    if let Err(err) = &result {
+                        log::warn!("acp prompt failed for session {session_id}: {err}");
+                    }
+                    let finished = session.mapper.borrow_mut().finish_turn(result);
+                    session.publish(finished);
+                });
+            }
+            ConnectionCommand::Cancel { session_id } => {
+                let conn = Rc::clone(&conn);
+                tokio::task::spawn_local(async move {
+                    // Not waiting for the prompt lock, the running prompt is what gets cancelled
+                    if let Err(err) = conn
+                        .cancel(acp::CancelNotification::new(session_id.clone()))
+                        .await
+                    {
+                        log::warn!("acp cancel failed for session {session_id}: {err}");
+                    }
+                });
+            }
+        }
+    }
+
+    log::debug!("acp connection to {} stopped", agent.harness_type());
+    drop(process.proc);
+}
+
+async fn initialize(
+    conn: &acp::ClientSideConnection,
+) -> Result<acp::InitializeResponse, AgentHubSessionError> {
+    let fs = acp::FileSystemCapabilities::new()
+        .read_text_file(true)
+        .write_text_file(true);
+    let init_request = acp::InitializeRequest::new(acp::ProtocolVersion::LATEST)
+        .client_capabilities(acp::ClientCapabilities::new().fs(fs).terminal(true));
+    Ok(conn.initialize(init_request).await?)
+}
+
+async fn new_session(
+    conn: &acp::ClientSideConnection,
+    sessions: &RefCell<HashMap<String, Rc<AgentSession>>>,
+    cwd: PathBuf,
+    unsaved: UnsavedBuffers,
+) -> Result<(String, EventSender), AgentHubSessionError> {
+    let created = conn
+        .new_session(acp::NewSessionRequest::new(cwd.clone()))
+        .await?;
+    let session_id = created.session_id.0.to_string();
+    let session = AgentSession::new(session_id.clone(), cwd, unsaved);
+    let events = session.events.clone();
+    sessions
+        .borrow_mut()
+        .insert(session_id.clone(), Rc::new(session));
+    Ok((session_id, events))
+}
+
+async fn load_session(
+    conn: &acp::ClientSideConnection,
+    sessions: &RefCell<HashMap<String, Rc<AgentSession>>>,
+    session_id: String,
+    cwd: PathBuf,
+    unsaved: UnsavedBuffers,
+) -> Result<EventSender, AgentHubSessionError> {
+    // Already loaded on this connection, e.g. by a caller that lost its handle
+    if let Some(session) = sessions.borrow().get(&session_id) {
+        return Ok(session.events.clone());
+    }
+
+    // Registered up front since the agent replays the history before answering
+    let session = Rc::new(AgentSession::new(session_id.clone(), cwd.clone(), unsaved));
+    session.mapper.borrow_mut().set_replaying(true);
+    sessions
+        .borrow_mut()
+        .insert(session_id.clone(), Rc::clone(&session));
+    let loaded = conn
+        .load_session(acp::LoadSessionRequest::new(session_id.clone(), cwd))
+        .await;
+    session.mapper.borrow_mut().set_replaying(false);
+
+    if let Err(err) = loaded {
+        sessions.borrow_mut().remove(&session_id);
+        return Err(err.into());
+    }
+    Ok(session.events.clone())
+}
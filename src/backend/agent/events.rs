@@ -0,0 +1,542 @@
+use agent_client_protocol as acp;
+use serde_json::{Map, Value, json};
+use uuid::Uuid;
+
+use crate::backend::{
+    agent::fs::{FILE_WRITE_PART_TYPE, FileWrite},
+    harness::{HarnessAssistantEvent, HarnessSessionStatus},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ChunkKind {
+    Text,
+    Reasoning,
+}
+
+impl ChunkKind {
+    fn part_type(self) -> &'static str {
+        match self {
+            ChunkKind::Text => "text",
+            ChunkKind::Reasoning => "reasoning",
+        }
+    }
+}
+
+struct Turn {
+    message_id: String,
+    open_chunk: Option<(ChunkKind, String)>,
+}
+
+/// Turns ACP session notifications into harness events.
+///
+/// ACP has no message or part ids, so every prompt turn becomes one assistant message and
+/// consecutive chunks of the same kind are streamed as deltas into a single part
+pub struct AcpEventMapper {
+    harness_session_id: String,
+    turn: Option<Turn>,
+    replaying: bool,
+}
+
+impl AcpEventMapper {
+    pub fn new(harness_session_id: impl Into<String>) -> Self {
+        Self {
+            harness_session_id: harness_session_id.into(),
+            turn: None,
+            replaying: false,
+        }
+    }
+
+    /// `session/load` replays the whole conversation, which is already persisted on our side
+    pub fn set_replaying(&mut self, replaying: bool) {
+        self.replaying = replaying;
+    }
+
+    pub fn begin_turn(&mut self) -> Vec<HarnessAssistantEvent> {
+        let message_id = self.start_turn();
+        vec![
+            self.status(HarnessSessionStatus::Busy),
+            self.message_updated(message_id, None, None),
+        ]
+    }
+
+    pub fn finish_turn(
+        &mut self,
+        result: Result<acp::StopReason, String>,
+    ) -> Vec<HarnessAssistantEvent> {
+        let mut events = Vec::new();
+        if let Some(turn) = self.turn.take() {
+            let error = match result {
+                Ok(stop_reason) => stop_reason_error(stop_reason),
+                Err(err) => Some(err),
+            };
+            let completed_at = chrono::Utc::now().timestamp_millis();
+            events.push(self.message_updated(turn.message_id, Some(completed_at), error));
+        }
+        events.push(self.status(HarnessSessionStatus::Idle));
+        events
+    }
+
+    pub fn map_notification(
+        &mut self,
+        notification: acp::SessionNotification,
+    ) -> Vec<HarnessAssistantEvent> {
+        if self.replaying || *notification.session_id.0 != *self.harness_session_id {
+            return Vec::new();
+        }
+
+        match notification.update {
+            acp::SessionUpdate::AgentMessageChunk(chunk) => {
+                self.map_chunk(ChunkKind::Text, chunk.content)
+            }
+            acp::SessionUpdate::AgentThoughtChunk(chunk) => {
+                self.map_chunk(ChunkKind::Reasoning, chunk.content)
+            }
+            acp::SessionUpdate::ToolCall(tool_call) => {
+                let mut state = Map::new();
+                state.insert("status".into(), tool_status(tool_call.status).into());
+                state.insert("title".into(), tool_call.title.into());
+                if let Some(input) = tool_call.raw_input {
+                    state.insert("input".into(), input);
+                }
+                if let Some(output) = tool_output(&tool_call.content, tool_call.raw_output) {
+                    state.insert("output".into(), output.into());
+                }
+                self.tool_part_updated(tool_call.tool_call_id, Some(tool_call.kind), state)
+            }
+            acp::SessionUpdate::ToolCallUpdate(update) => {
+                let fields = update.fields;
+                let mut state = Map::new();
+                if let Some(status) = fields.status {
+                    state.insert("status".into(), tool_status(status).into());
+                }
+                if let Some(title) = fields.title {
+                    state.insert("title".into(), title.into());
+                }
+                if let Some(input) = fields.raw_input {
+                    state.insert("input".into(), input);
+                }
+                if let Some(output) =
+                    tool_output(&fields.content.unwrap_or_default(), fields.raw_output)
+                {
+                    state.insert("output".into(), output.into());
+                }
+                self.tool_part_updated(update.tool_call_id, fields.kind, state)
+            }
+            acp::SessionUpdate::Plan(plan) => {
+                let text = plan
+                    .entries
+                    .iter()
+                    .map(|entry| {
+                        let mark = match entry.status {
+                            acp::PlanEntryStatus::Completed => "x",
+                            acp::PlanEntryStatus::InProgress => "~",
+                            _ => " ",
+                        };
+                        format!("- [{mark}] {}", entry.content)
+                    })
+                    .collect::<Vec<_>>()
+                    .join("\n");
+                let message_id = self.ensure_turn();
+                self.close_chunk();
+                // The agent resends the full plan on every change, so one part per turn
+                let part_id = format!("plan-{message_id}");
+                vec![self.part_updated(
+                    message_id,
+                    part_id,
+                    "plan",
+                    json!({"type": "plan", "text": text}),
+                )]
+            }
+            _ => Vec::new(),
+        }
+    }
+
+    /// Records a write the agent made through us as its own part, so it can be reviewed
+    /// and reverted from the transcript
+    pub fn file_written(&mut self, write: &FileWrite) -> Vec<HarnessAssistantEvent> {
+        let message_id = self.ensure_turn();
+        self.close_chunk();
+        vec![self.part_updated(
+            message_id,
+            Uuid::new_v4().to_string(),
+            FILE_WRITE_PART_TYPE,
+            json!({
+                "type": FILE_WRITE_PART_TYPE,
+                "path": write.path.to_string_lossy(),
+                "metadata": {"before": write.before, "after": write.after},
+            }),
+        )]
+    }
+
+    fn map_chunk(
+        &mut self,
+        kind: ChunkKind,
+        content: acp::ContentBlock,
+    ) -> Vec<HarnessAssistantEvent> {
+        let Some(text) = content_text(&content) else {
+            return Vec::new();
+        };
+        let message_id = self.ensure_turn();
+        let mut events = Vec::new();
+
+        let turn = self.turn.as_mut().expect("turn was just ensured");
+        let part_id = match &turn.open_chunk {
+            Some((open_kind, part_id)) if *open_kind == kind => part_id.clone(),
+            _ => {
+                let part_id = Uuid::new_v4().to_string();
+                turn.open_chunk = Some((kind, part_id.clone()));
+                events.push(self.part_updated(
+                    message_id.clone(),
+                    part_id.clone(),
+                    kind.part_type(),
+                    json!({"type": kind.part_type(), "text": ""}),
+                ));
+                part_id
+            }
+        };
+
+        events.push(HarnessAssistantEvent::MessagePartDelta {
+            harness_session_id: self.harness_session_id.clone(),
+            message_id,
+            part_id,
+            field: "text".to_string(),
+            delta: text,
+        });
+        events
+    }
+
+    fn tool_part_updated(
+        &mut self,
+        tool_call_id: acp::ToolCallId,
+        kind: Option<acp::ToolKind>,
+        state: Map<String, Value>,
+    ) -> Vec<HarnessAssistantEvent> {
+        let message_id = self.ensure_turn();
+        self.close_chunk();
+
+        let mut payload = Map::new();
+        payload.insert("type".into(), "tool".into());
+        payload.insert("callID".into(), tool_call_id.0.to_string().into());
+        if let Some(kind) = kind {
+            payload.insert("tool".into(), tool_kind(kind).into());
+        }
+        if !state.is_empty() {
+            payload.insert("state".into(), Value::Object(state));
+        }
+
+        vec![self.part_updated(
+            message_id,
+            tool_call_id.0.to_string(),
+            "tool",
+            Value::Object(payload),
+        )]
+    }
+
+    fn start_turn(&mut self) -> String {
+        let message_id = Uuid::new_v4().to_string();
+        self.turn = Some(Turn {
+            message_id: message_id.clone(),
+            open_chunk: None,
+        });
+        message_id
+    }
+
+    /// Agents may push updates outside of a prompt we sent, those still need a message to land in
+    fn ensure_turn(&mut self) -> String {
+        match &self.turn {
+            Some(turn) => turn.message_id.clone(),
+            None => self.start_turn(),
+        }
+    }
+
+    fn close_chunk(&mut self) {
+        if let Some(turn) = self.turn.as_mut() {
+            turn.open_chunk = None;
+        }
+    }
+
+    fn status(&self, status: HarnessSessionStatus) -> HarnessAssistantEvent {
+        HarnessAssistantEvent::SessionStatus {
+            harness_session_id: self.harness_session_id.clone(),
+            status,
+        }
+    }
+
+    fn message_updated(
+        &self,
+        message_id: String,
+        completed_at: Option<i64>,
+        error: Option<String>,
+    ) -> HarnessAssistantEvent {
+        HarnessAssistantEvent::MessageUpdated {
+            harness_session_id: self.harness_session_id.clone(),
+            message_id,
+            completed_at,
+            error,
+        }
+    }
+
+    fn part_updated(
+        &self,
+        message_id: String,
+        part_id: String,
+        part_type: &str,
+        payload: Value,
+    ) -> HarnessAssistantEvent {
+        HarnessAssistantEvent::MessagePartUpdated {
+            harness_session_id: self.harness_session_id.clone(),
+            message_id,
+            part_id,
+            part_type: part_type.to_string(),
+            payload,
+        }
+    }
+}
+
+fn content_text(content: &acp::ContentBlock) -> Option<String> {
+    match content {
+        acp::ContentBlock::Text(text) => Some(text.text.clone()),
+        acp::ContentBlock::ResourceLink(link) => Some(link.uri.clone()),
+        _ => None,
+    }
+}
+
+fn tool_output(content: &[acp::ToolCallContent], raw_output: Option<Value>) -> Option<String> {
+    let mut lines = Vec::new();
+    for item in content {
+        match item {
+            acp::ToolCallContent::Content(content) => {
+                if let Some(text) = content_text(&content.content) {
+                    lines.push(text);
+                }
+            }
+            acp::ToolCallContent::Diff(diff) => {
+                lines.push(format!("edited {}", diff.path.display()));
+            }
+            acp::ToolCallContent::Terminal(terminal) => {
+                lines.push(format!("terminal {}", terminal.terminal_id.0));
+            }
+            _ => {}
+        }
+    }
+
+    if lines.is_empty() {
+        return raw_output.map(|output| match output {
+            Value::String(text) => text,
+            other => other.to_string(),
+        });
+    }
+    Some(lines.join("\n"))
+}
+
+/// Uses opencode's status names so the transcript renders both harnesses the same way
+fn tool_status(status: acp::ToolCallStatus) -> &'static str {
+    match status {
+        acp::ToolCallStatus::Pending => "pending",
+        acp::ToolCallStatus::InProgress => "running",
+        acp::ToolCallStatus::Completed => "completed",
+        acp::ToolCallStatus::Failed => "error",
+        _ => "pending",
+    }
+}
+
+pub(super) fn tool_kind(kind: acp::ToolKind) -> &'static str {
+    match kind {
+        acp::ToolKind::Read => "read",
+        acp::ToolKind::Edit => "edit",
+        acp::ToolKind::Delete => "delete",
+        acp::ToolKind::Move => "move",
+        acp::ToolKind::Search => "search",
+        acp::ToolKind::Execute => "execute",
+        acp::ToolKind::Think => "think",
+        acp::ToolKind::Fetch => "fetch",
+        acp::ToolKind::SwitchMode => "switch_mode",
+        _ => "other",
+    }
+}
+
+fn stop_reason_error(stop_reason: acp::StopReason) -> Option<String> {
+    match stop_reason {
+        acp::StopReason::EndTurn => None,
+        acp::StopReason::MaxTokens => Some("max tokens reached".to_string()),
+        acp::StopReason::MaxTurnRequests => Some("max turn requests reached".to_string()),
+        acp::StopReason::Refusal => Some("agent refused to continue".to_string()),
+        acp::StopReason::Cancelled => Some("cancelled".to_string()),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn notification(update: acp::SessionUpdate) -> acp::SessionNotification {
+        acp::SessionNotification::new("acp-session", update)
+    }
+
+    fn text_chunk(text: &str) -> acp::ContentChunk {
+        acp::ContentChunk::new(text.into())
+    }
+
+    #[test]
+    fn consecutive_chunks_stream_into_one_part() {
+        let mut mapper = AcpEventMapper::new("acp-session");
+        mapper.begin_turn();
+
+        let first = mapper.map_notification(notification(acp::SessionUpdate::AgentMessageChunk(
+            text_chunk("hel"),
+        )));
+        let second = mapper.map_notification(notification(acp::SessionUpdate::AgentMessageChunk(
+            text_chunk("lo"),
+        )));
+
+        assert_eq!(first.len(), 2);
+        assert!(matches!(
+            &first[0],
+            HarnessAssistantEvent::MessagePartUpdated { part_type, .. } if part_type == "text"
+        ));
+        let (
+            HarnessAssistantEvent::MessagePartDelta {
+                part_id: first_part,
+                ..
+            },
+            [
+                HarnessAssistantEvent::MessagePartDelta {
+                    part_id: second_part,
+                    delta,
+                    ..
+                },
+            ],
+        ) = (&first[1], second.as_slice())
+        else {
+            panic!("expected deltas, got {first:?} / {second:?}");
+        };
+        assert_eq!(first_part, second_part);
+        assert_eq!(delta, "lo");
+    }
+
+    #[test]
+    fn switching_chunk_kind_starts_a_new_part() {
+        let mut mapper = AcpEventMapper::new("acp-session");
+        mapper.begin_turn();
+
+        mapper.map_notification(notification(acp::SessionUpdate::AgentThoughtChunk(
+            text_chunk("thinking"),
+        )));
+        let events = mapper.map_notification(notification(acp::SessionUpdate::AgentMessageChunk(
+            text_chunk("answer"),
+        )));
+
+        assert!(matches!(
+            &events[0],
+            HarnessAssistantEvent::MessagePartUpdated { part_type, .. } if part_type == "text"
+        ));
+    }
+
+    #[test]
+    fn tool_calls_map_to_tool_parts() {
+        let mut mapper = AcpEventMapper::new("acp-session");
+        mapper.begin_turn();
+
+        let events = mapper.map_notification(notification(acp::SessionUpdate::ToolCall(
+            acp::ToolCall::new("call-1", "Read file").kind(acp::ToolKind::Read),
+        )));
+        let [
+            HarnessAssistantEvent::MessagePartUpdated {
+                part_id, payload, ..
+            },
+        ] = events.as_slice()
+        else {
+            panic!("expected one part update, got {events:?}");
+        };
+        assert_eq!(part_id, "call-1");
+        assert_eq!(payload["tool"], "read");
+        assert_eq!(payload["state"]["status"], "pending");
+
+        let events = mapper.map_notification(notification(acp::SessionUpdate::ToolCallUpdate(
+            acp::ToolCallUpdate::new(
+                "call-1",
+                acp::ToolCallUpdateFields::new().status(acp::ToolCallStatus::Completed),
+            ),
+        )));
+        let [HarnessAssistantEvent::MessagePartUpdated { payload, .. }] = events.as_slice() else {
+            panic!("expected one part update, got {events:?}");
+        };
+        assert_eq!(payload["state"]["status"], "completed");
+        assert!(payload.get("tool").is_none());
+    }
+
+    #[test]
+    fn file_writes_are_recorded_in_the_current_turn() {
+        let mut mapper = AcpEventMapper::new("acp-session");
+        let begun = mapper.begin_turn();
+        let HarnessAssistantEvent::MessageUpdated {
+            message_id: turn_id,
+            ..
+        } = &begun[1]
+        else {
+            panic!("expected message update, got {begun:?}");
+        };
+
+        let events = mapper.file_written(&FileWrite {
+            path: "/project/a.txt".into(),
+            before: None,
+            after: "new".to_string(),
+        });
+        let [
+            HarnessAssistantEvent::MessagePartUpdated {
+                message_id,
+                part_type,
+                payload,
+                ..
+            },
+        ] = events.as_slice()
+        else {
+            panic!("expected one part update, got {events:?}");
+        };
+        assert_eq!(message_id, turn_id);
+        assert_eq!(part_type, FILE_WRITE_PART_TYPE);
+        assert_eq!(payload["path"], "/project/a.txt");
+        assert!(payload["metadata"]["before"].is_null());
+        assert_eq!(payload["metadata"]["after"], "new");
+    }
+
+    #[test]
+    fn finish_turn_completes_message_and_goes_idle() {
+        let mut mapper = AcpEventMapper::new("acp-session");
+        mapper.begin_turn();
+
+        let events = mapper.finish_turn(Ok(acp::StopReason::Cancelled));
+        assert!(matches!(
+            &events[0],
+            HarnessAssistantEvent::MessageUpdated { completed_at: Some(_), error: Some(error), .. }
+                if error == "cancelled"
+        ));
+        assert!(matches!(
+            &events[1],
+            HarnessAssistantEvent::SessionStatus {
+                status: HarnessSessionStatus::Idle,
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn replayed_and_foreign_notifications_are_ignored() {
+        let mut mapper = AcpEventMapper::new("acp-session");
+        let foreign = acp::SessionNotification::new(
+            "other-session",
+            acp::SessionUpdate::AgentMessageChunk(text_chunk("nope")),
+        );
+        assert!(mapper.map_notification(foreign).is_empty());
+
+        mapper.set_replaying(true);
+        assert!(
+            mapper
+                .map_notification(notification(acp::SessionUpdate::AgentMessageChunk(
+                    text_chunk("old")
+                )))
+                .is_empty()
+        );
+    }
+}
@@ -0,0 +1,327 @@
+use std::{
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex},
+};
+
+/// Assistant part type used to record agent writes in the transcript
+pub const FILE_WRITE_PART_TYPE: &str = "file-write";
+
+#[derive(thiserror::Error, Debug)]
+pub enum ProjectFsError {
+    #[error("{0} is outside the project directory")]
+    OutsideProject(PathBuf),
+    #[error("{0} changed on disk since the agent wrote it")]
+    Conflict(PathBuf),
+    #[error("{0} has unsaved changes in an editor")]
+    Unsaved(PathBuf),
+    #[error("failed to access {path}: {source}")]
+    Io {
+        path: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// A write an agent made, with what was on disk before so it can be reverted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileWrite {
+    pub path: PathBuf,
+    /// `None` when the agent created the file
+    pub before: Option<String>,
+    pub after: String,
+}
+
+/// What editors hold for files they changed but did not save, by resolved path.
+/// Clones share the buffers
+#[derive(Debug, Clone, Default)]
+pub struct UnsavedBuffers {
+    buffers: Arc<Mutex<HashMap<PathBuf, String>>>,
+}
+
+impl UnsavedBuffers {
+    /// Sets the unsaved content of `path`, `None` once the editor saved or dropped it
+    pub fn set(&self, path: PathBuf, content: Option<String>) {
+        let mut buffers = self.lock();
+        match content {
+            Some(content) => buffers.insert(path, content),
+            None => buffers.remove(&path),
+        };
+    }
+
+    pub fn get(&self, path: &Path) -> Option<String> {
+        self.lock().get(path).cloned()
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, HashMap<PathBuf, String>> {
+        self.buffers
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// File access for agents, confined to a project directory. Reads see unsaved editor
+/// content over what is on disk
+#[derive(Debug, Clone)]
+pub struct ProjectFs {
+    root: PathBuf,
+    unsaved: UnsavedBuffers,
+}
+
+impl ProjectFs {
+    pub fn new(root: impl Into<PathBuf>) -> Self {
+        Self {
+            root: root.into(),
+            unsaved: UnsavedBuffers::default(),
+        }
+    }
+
+    pub fn with_unsaved(mut self, unsaved: UnsavedBuffers) -> Self {
+        self.unsaved = unsaved;
+        self
+    }
+
+    /// Resolves `path` inside the project, following symlinks of the parts that already exist
+    pub async fn resolve(&self, path: &Path) -> Result<PathBuf, ProjectFsError> {
+        let root = canonicalize(&self.root).await?;
+        let normalized = normalize(&root.join(path));
+
+        // The file may not exist yet, so canonicalize its closest existing ancestor
+        let mut existing = normalized.as_path();
+        let mut missing = Vec::new();
+        let resolved = loop {
+            match tokio::fs::canonicalize(existing).await {
+                Ok(resolved) => break resolved,
+                Err(err) if err.kind() == std::io::ErrorKind::NotFound => {
+                    let (Some(parent), Some(name)) = (existing.parent(), existing.file_name())
+                    else {
+                        return Err(ProjectFsError::OutsideProject(path.to_path_buf()));
+                    };
+                    missing.push(name);
+                    existing = parent;
+                }
+                Err(source) => {
+                    return Err(ProjectFsError::Io {
+                        path: existing.to_path_buf(),
+                        source,
+                    });
+                }
+            }
+        };
+        let resolved = missing
+            .into_iter()
+            .rev()
+            .fold(resolved, |resolved, name| resolved.join(name));
+
+        if resolved.starts_with(&root) {
+            Ok(resolved)
+        } else {
+            Err(ProjectFsError::OutsideProject(path.to_path_buf()))
+        }
+    }
+
+    /// Reads a text file as an editor shows it, `line` is 1-based and `limit` caps the
+    /// number of lines returned
+    pub async fn read(
+        &self,
+        path: &Path,
+        line: Option<u32>,
+        limit: Option<u32>,
+    ) -> Result<String, ProjectFsError> {
+        let path = self.resolve(path).await?;
+        let content = match self.unsaved.get(&path) {
+            Some(content) => content,
+            None => tokio::fs::read_to_string(&path)
+                .await
+                .map_err(|source| ProjectFsError::Io { path, source })?,
+        };
+        if line.is_none() && limit.is_none() {
+            return Ok(content);
+        }
+
+        let skip = line.unwrap_or(1).saturating_sub(1) as usize;
+        let take = limit.map_or(usize::MAX, |limit| limit as usize);
+        Ok(content
+            .split_inclusive('\n')
+            .skip(skip)
+            .take(take)
+            .collect())
+    }
+
+    /// Writes a text file, creating missing directories, and returns what changed.
+    /// Refuses files with unsaved changes, which the write would silently drop
+    pub async fn write(&self, path: &Path, content: String) -> Result<FileWrite, ProjectFsError> {
+        let path = self.resolve(path).await?;
+        let before = read_existing(&path).await?;
+        if self
+            .unsaved
+            .get(&path)
+            .is_some_and(|unsaved| Some(&unsaved) != before.as_ref())
+        {
+            return Err(ProjectFsError::Unsaved(path));
+        }
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|source| ProjectFsError::Io {
+                    path: parent.to_path_buf(),
+                    source,
+                })?;
+        }
+        tokio::fs::write(&path, &content)
+            .await
+            .map_err(|source| ProjectFsError::Io {
+                path: path.clone(),
+                source,
+            })?;
+
+        Ok(FileWrite {
+            path,
+            before,
+            after: content,
+        })
+    }
+
+    /// Restores the content from before `write`. Refuses when the file was edited afterwards,
+    /// so reverting never throws away changes made outside of the agent
+    pub async fn revert(&self, write: &FileWrite) -> Result<(), ProjectFsError> {
+        let path = self.resolve(&write.path).await?;
+        if read_existing(&path).await?.as_deref() != Some(write.after.as_str()) {
+            return Err(ProjectFsError::Conflict(path));
+        }
+
+        let result = match &write.before {
+            Some(before) => tokio::fs::write(&path, before).await,
+            None => tokio::fs::remove_file(&path).await,
+        };
+        result.map_err(|source| ProjectFsError::Io { path, source })
+    }
+}
+
+async fn canonicalize(path: &Path) -> Result<PathBuf, ProjectFsError> {
+    tokio::fs::canonicalize(path)
+        .await
+        .map_err(|source| ProjectFsError::Io {
+            path: path.to_path_buf(),
+            source,
+        })
+}
+
+async fn read_existing(path: &Path) -> Result<Option<String>, ProjectFsError> {
+    match tokio::fs::read_to_string(path).await {
+        Ok(content) => Ok(Some(content)),
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(source) => Err(ProjectFsError::Io {
+            path: path.to_path_buf(),
+            source,
+        }),
+    }
+}
+
+/// Drops `.` and folds `..` without touching the filesystem
+fn normalize(path: &Path) -> PathBuf {
+    let mut normalized = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::CurDir => {}
+            Component::ParentDir => {
+                normalized.pop();
+            }
+            other => normalized.push(other),
+        }
+    }
+    normalized
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn project() -> (tempfile::TempDir, ProjectFs) {
+        let dir = tempfile::tempdir().unwrap();
+        let fs = ProjectFs::new(dir.path());
+        (dir, fs)
+    }
+
+    #[tokio::test]
+    async fn rejects_paths_outside_the_project() {
+        let (dir, fs) = project();
+
+        let escaped = dir.path().join("../outside.txt");
+        assert!(matches!(
+            fs.write(&escaped, "nope".to_string()).await,
+            Err(ProjectFsError::OutsideProject(_))
+        ));
+        assert!(matches!(
+            fs.read(Path::new("/etc/hostname"), None, None).await,
+            Err(ProjectFsError::OutsideProject(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn reads_line_ranges() {
+        let (dir, fs) = project();
+        std::fs::write(dir.path().join("a.txt"), "one\ntwo\nthree\n").unwrap();
+
+        let path = dir.path().join("a.txt");
+        assert_eq!(fs.read(&path, Some(2), None).await.unwrap(), "two\nthree\n");
+        assert_eq!(fs.read(&path, None, Some(1)).await.unwrap(), "one\n");
+    }
+
+    #[tokio::test]
+    async fn revert_restores_previous_content() {
+        let (dir, fs) = project();
+        let existing = dir.path().join("a.txt");
+        std::fs::write(&existing, "before").unwrap();
+
+        let edit = fs.write(&existing, "after".to_string()).await.unwrap();
+        assert_eq!(edit.before.as_deref(), Some("before"));
+        let created = fs
+            .write(&dir.path().join("new/b.txt"), "new".to_string())
+            .await
+            .unwrap();
+        assert_eq!(created.before, None);
+
+        fs.revert(&edit).await.unwrap();
+        fs.revert(&created).await.unwrap();
+        assert_eq!(std::fs::read_to_string(&existing).unwrap(), "before");
+        assert!(!dir.path().join("new/b.txt").exists());
+    }
+
+    #[tokio::test]
+    async fn revert_refuses_files_changed_since_the_write() {
+        let (dir, fs) = project();
+        let path = dir.path().join("a.txt");
+
+        let write = fs.write(&path, "agent".to_string()).await.unwrap();
+        std::fs::write(&path, "user").unwrap();
+
+        assert!(matches!(
+            fs.revert(&write).await,
+            Err(ProjectFsError::Conflict(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "user");
+    }
+
+    #[tokio::test]
+    async fn reads_show_unsaved_content_and_writes_keep_it() {
+        let (dir, fs) = project();
+        let unsaved = UnsavedBuffers::default();
+        let fs = fs.with_unsaved(unsaved.clone());
+        let path = dir.path().join("a.txt");
+        std::fs::write(&path, "saved").unwrap();
+        let resolved = fs.resolve(&path).await.unwrap();
+
+        unsaved.set(resolved.clone(), Some("edited".to_string()));
+        assert_eq!(fs.read(&path, None, None).await.unwrap(), "edited");
+        assert!(matches!(
+            fs.write(&path, "agent".to_string()).await,
+            Err(ProjectFsError::Unsaved(_))
+        ));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "saved");
+
+        unsaved.set(resolved, None);
+        assert_eq!(fs.read(&path, None, None).await.unwrap(), "saved");
+        fs.write(&path, "agent".to_string()).await.unwrap();
+    }
+}
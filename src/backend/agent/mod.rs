@@ -1,80 +1,282 @@
-use std::process::Stdio;
-
-use agent_client_protocol::{self as acp, ClientCapabilities, InitializeRequest, ProtocolVersion, Agent as _};
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::PathBuf,
+    process::Stdio,
+    rc::Rc,
+    sync::{Arc, Mutex},
+};
+
+use agent_client_protocol as acp;
+use tokio::sync::broadcast;
 use tokio_util::compat::{Compat, TokioAsyncReadCompatExt, TokioAsyncWriteCompatExt};
 
+use crate::backend::{
+    harness::HarnessAssistantEvent,
+    permission::{PermissionBroker, PermissionDecision, PermissionPrompt},
+};
+use connection::{AcpSessionHandle, AgentConnection};
+use events::AcpEventMapper;
+use fs::{ProjectFs, ProjectFsError, UnsavedBuffers};
+use terminal::{TerminalCommand, TerminalError, TerminalManager};
+
+mod connection;
+mod events;
+pub mod fs;
 mod opencode;
+pub mod stash;
+mod terminal;
+
+const EVENT_BUFFER: usize = 256;
+
+/// What the client keeps per ACP session to serve the agent's requests for it
+struct AgentSession {
+    mapper: RefCell<AcpEventMapper>,
+    events: broadcast::Sender<HarnessAssistantEvent>,
+    files: ProjectFs,
+    terminals: TerminalManager,
+}
+
+impl AgentSession {
+    fn new(session_id: String, cwd: PathBuf, unsaved: UnsavedBuffers) -> Self {
+        let (events, _) = broadcast::channel(EVENT_BUFFER);
+        let files = ProjectFs::new(cwd).with_unsaved(unsaved);
+        Self {
+            mapper: RefCell::new(AcpEventMapper::new(session_id)),
+            events,
+            terminals: TerminalManager::new(files.clone()),
+            files,
+        }
+    }
+
+    fn publish(&self, batch: Vec<HarnessAssistantEvent>) {
+        for event in batch {
+            // No receivers just means no tab is watching this session right now
+            let _ = self.events.send(event);
+        }
+    }
+}
+
+/// Handles requests the agent makes back to us for any of the sessions on its connection,
+/// session updates are forwarded as harness events
+pub struct AgentClient {
+    sessions: Rc<RefCell<HashMap<String, Rc<AgentSession>>>>,
+    permissions: Option<Arc<PermissionBroker>>,
+}
+
+impl AgentClient {
+    fn new(
+        sessions: Rc<RefCell<HashMap<String, Rc<AgentSession>>>>,
+        permissions: Option<Arc<PermissionBroker>>,
+    ) -> Self {
+        Self {
+            sessions,
+            permissions,
+        }
+    }
+
+    fn session(&self, session_id: &acp::SessionId) -> acp::Result<Rc<AgentSession>> {
+        self.sessions
+            .borrow()
+            .get(session_id.0.as_ref())
+            .cloned()
+            .ok_or_else(|| {
+                acp::Error::invalid_params().data(format!("unknown session {}", session_id.0))
+            })
+    }
+}
+
+fn fs_error(err: ProjectFsError) -> acp::Error {
+    let error = match &err {
+        ProjectFsError::OutsideProject(_)
+        | ProjectFsError::Conflict(_)
+        | ProjectFsError::Unsaved(_) => acp::Error::invalid_params(),
+        ProjectFsError::Io { source, .. } if source.kind() == std::io::ErrorKind::NotFound => {
+            acp::Error::resource_not_found(None)
+        }
+        ProjectFsError::Io { .. } => acp::Error::internal_error(),
+    };
+    error.data(err.to_string())
+}
+
+fn terminal_error(err: TerminalError) -> acp::Error {
+    match err {
+        TerminalError::Cwd(err) => fs_error(err),
+        TerminalError::UnknownTerminal(_) => acp::Error::invalid_params().data(err.to_string()),
+        TerminalError::Spawn { .. } => acp::Error::internal_error().data(err.to_string()),
+    }
+}
+
+/// Picks the option the agent offered that matches the decision, preferring the exact kind
+fn permission_option(
+    options: &[acp::PermissionOption],
+    decision: PermissionDecision,
+) -> Option<&acp::PermissionOption> {
+    let preferred: &[acp::PermissionOptionKind] = match decision {
+        PermissionDecision::Approve => &[
+            acp::PermissionOptionKind::AllowOnce,
+            acp::PermissionOptionKind::AllowAlways,
+        ],
+        PermissionDecision::AlwaysAllow => &[
+            acp::PermissionOptionKind::AllowAlways,
+            acp::PermissionOptionKind::AllowOnce,
+        ],
+        PermissionDecision::Deny => &[
+            acp::PermissionOptionKind::RejectOnce,
+            acp::PermissionOptionKind::RejectAlways,
+        ],
+    };
+    preferred
+        .iter()
+        .find_map(|kind| options.iter().find(|option| option.kind == *kind))
+}
 
-pub struct DummyAgentClient {}
 #[async_trait::async_trait(?Send)]
-impl acp::Client for DummyAgentClient {
+impl acp::Client for AgentClient {
     async fn request_permission(
         &self,
-        _args: acp::RequestPermissionRequest,
+        args: acp::RequestPermissionRequest,
     ) -> acp::Result<acp::RequestPermissionResponse> {
-        Err(acp::Error::method_not_found())
+        let Some(permissions) = &self.permissions else {
+            return Err(acp::Error::method_not_found());
+        };
+
+        let tool_call_id = args.tool_call.tool_call_id.0.to_string();
+        let fields = args.tool_call.fields;
+        let prompt = PermissionPrompt {
+            harness_request_id: None,
+            title: fields.title.unwrap_or_else(|| tool_call_id.clone()),
+            kind: fields.kind.map_or("other", events::tool_kind).to_string(),
+            tool_call_id: Some(tool_call_id),
+        };
+        let decision = permissions
+            .request(&args.session_id.0, prompt)
+            .await
+            .map_err(|err| acp::Error::internal_error().data(err.to_string()))?;
+
+        // An agent that offers no matching option gets treated as if the turn was cancelled
+        let outcome = permission_option(&args.options, decision).map_or(
+            acp::RequestPermissionOutcome::Cancelled,
+            |option| {
+                acp::RequestPermissionOutcome::Selected(acp::SelectedPermissionOutcome::new(
+                    option.option_id.clone(),
+                ))
+            },
+        );
+        Ok(acp::RequestPermissionResponse::new(outcome))
     }
 
     async fn write_text_file(
         &self,
-        _args: acp::WriteTextFileRequest,
+        args: acp::WriteTextFileRequest,
     ) -> acp::Result<acp::WriteTextFileResponse> {
-        Err(acp::Error::method_not_found())
+        let session = self.session(&args.session_id)?;
+        let write = session
+            .files
+            .write(&args.path, args.content)
+            .await
+            .map_err(fs_error)?;
+        let recorded = session.mapper.borrow_mut().file_written(&write);
+        session.publish(recorded);
+        Ok(acp::WriteTextFileResponse::new())
     }
 
     async fn read_text_file(
         &self,
-        _args: acp::ReadTextFileRequest,
+        args: acp::ReadTextFileRequest,
     ) -> acp::Result<acp::ReadTextFileResponse> {
-        Err(acp::Error::method_not_found())
+        let content = self
+            .session(&args.session_id)?
+            .files
+            .read(&args.path, args.line, args.limit)
+            .await
+            .map_err(fs_error)?;
+        Ok(acp::ReadTextFileResponse::new(content))
     }
 
     async fn create_terminal(
         &self,
-        _args: acp::CreateTerminalRequest,
+        args: acp::CreateTerminalRequest,
     ) -> Result<acp::CreateTerminalResponse, acp::Error> {
-        Err(acp::Error::method_not_found())
+        let terminal_id = self
+            .session(&args.session_id)?
+            .terminals
+            .create(TerminalCommand {
+                command: args.command,
+                args: args.args,
+                env: args
+                    .env
+                    .into_iter()
+                    .map(|variable| (variable.name, variable.value))
+                    .collect(),
+                cwd: args.cwd,
+                output_byte_limit: args.output_byte_limit,
+            })
+            .await
+            .map_err(terminal_error)?;
+        Ok(acp::CreateTerminalResponse::new(terminal_id))
     }
 
     async fn terminal_output(
         &self,
-        _args: acp::TerminalOutputRequest,
+        args: acp::TerminalOutputRequest,
     ) -> acp::Result<acp::TerminalOutputResponse> {
-        Err(acp::Error::method_not_found())
+        let (output, truncated, exit_status) = self
+            .session(&args.session_id)?
+            .terminals
+            .output(&args.terminal_id.0)
+            .map_err(terminal_error)?;
+        Ok(acp::TerminalOutputResponse::new(output, truncated).exit_status(exit_status))
     }
 
     async fn release_terminal(
         &self,
-        _args: acp::ReleaseTerminalRequest,
+        args: acp::ReleaseTerminalRequest,
     ) -> acp::Result<acp::ReleaseTerminalResponse> {
-        Err(acp::Error::method_not_found())
+        self.session(&args.session_id)?
+            .terminals
+            .release(&args.terminal_id.0)
+            .map_err(terminal_error)?;
+        Ok(acp::ReleaseTerminalResponse::new())
     }
 
     async fn wait_for_terminal_exit(
         &self,
-        _args: acp::WaitForTerminalExitRequest,
+        args: acp::WaitForTerminalExitRequest,
     ) -> acp::Result<acp::WaitForTerminalExitResponse> {
-        Err(acp::Error::method_not_found())
+        let exit_status = self
+            .session(&args.session_id)?
+            .terminals
+            .wait_for_exit(&args.terminal_id.0)
+            .await
+            .map_err(terminal_error)?;
+        Ok(acp::WaitForTerminalExitResponse::new(exit_status))
+    }
+
+    async fn kill_terminal(
+        &self,
+        args: acp::KillTerminalRequest,
+    ) -> acp::Result<acp::KillTerminalResponse> {
+        self.session(&args.session_id)?
+            .terminals
+            .kill(&args.terminal_id.0)
+            .map_err(terminal_error)?;
+        Ok(acp::KillTerminalResponse::new())
     }
 
     async fn session_notification(
         &self,
         args: acp::SessionNotification,
     ) -> acp::Result<(), acp::Error> {
-        match args.update {
-            acp::SessionUpdate::AgentMessageChunk(acp::ContentChunk { content, .. }) => {
-                let text = match content {
-                    acp::ContentBlock::Text(text_content) => text_content.text,
-                    acp::ContentBlock::Image(_) => "<image>".into(),
-                    acp::ContentBlock::Audio(_) => "<audio>".into(),
-                    acp::ContentBlock::ResourceLink(resource_link) => resource_link.uri,
-                    acp::ContentBlock::Resource(_) => "<resource>".into(),
-                    _ => "unknown".into(),
-                };
-                println!("| Agent: {text}");
-            }
-            _ => {} // Handle future variants gracefully
-        }
+        let Ok(session) = self.session(&args.session_id) else {
+            log::debug!(
+                "dropping update for unknown acp session {}",
+                args.session_id.0
+            );
+            return Ok(());
+        };
+        let mapped = session.mapper.borrow_mut().map_notification(args);
+        session.publish(mapped);
         Ok(())
     }
 
@@ -93,13 +295,36 @@ pub struct AgentProcess {
     incoming: Compat<tokio::process::ChildStdout>,
 }
 
-#[derive(Debug, PartialEq, Eq, Hash, Clone)]
+#[derive(Debug, PartialEq, Eq, Hash, Clone, Copy)]
 pub enum Agent {
     Opencode,
+    Gemini,
+}
+
+impl Agent {
+    /// Command line that starts the agent speaking ACP over stdio
+    fn command(self) -> (&'static str, &'static [&'static str]) {
+        match self {
+            Agent::Opencode => ("opencode", &["acp"]),
+            Agent::Gemini => ("gemini", &["--experimental-acp"]),
+        }
+    }
+
+    pub fn harness_type(self) -> &'static str {
+        match self {
+            Agent::Opencode => "opencode-acp",
+            Agent::Gemini => "gemini-acp",
+        }
+    }
 }
 
+/// Runs the ACP sessions of one agent on a single shared agent process
 pub struct AgentHub {
-    // agents: HashMap<Agent, AgentProcess>,
+    agent: Agent,
+    connection: tokio::sync::Mutex<Option<AgentConnection>>,
+    sessions: Mutex<HashMap<String, AcpSessionHandle>>,
+    permissions: Option<Arc<PermissionBroker>>,
+    unsaved: UnsavedBuffers,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -114,19 +339,41 @@ pub enum AgentHubError {
 
 #[derive(thiserror::Error, Debug)]
 pub enum AgentHubSessionError {
-    #[error("Failed to get stdin")]
-    AgentUnavailable,
+    #[error("failed to start agent: {0}")]
+    Spawn(#[from] AgentHubError),
+    #[error("agent protocol error: {0}")]
+    Protocol(#[from] acp::Error),
+    #[error("agent cannot resume session {0}")]
+    ResumeUnsupported(String),
+    #[error("agent session worker stopped")]
+    WorkerStopped,
 }
 
 impl AgentHub {
-    pub async fn new() -> Result<Self, AgentHubError> {
-        // let mut agents = HashMap::new();
-        // let opencode_process = AgentHub::spawn_agent_from_cmd("opencode", &["acp"]).await?;
-        // agents.insert(Agent::Opencode, opencode_process);
+    pub fn new(agent: Agent) -> Self {
+        Self {
+            agent,
+            connection: tokio::sync::Mutex::new(None),
+            sessions: Mutex::new(HashMap::new()),
+            permissions: None,
+            unsaved: UnsavedBuffers::default(),
+        }
+    }
 
-        Ok(Self { 
-            // agents
-        })
+    /// Lets the user answer the agent's permission requests, without it they are refused
+    pub fn with_permissions(mut self, permissions: Arc<PermissionBroker>) -> Self {
+        self.permissions = Some(permissions);
+        self
+    }
+
+    /// Shows the agent what editors hold for files they have not saved yet
+    pub fn with_unsaved(mut self, unsaved: UnsavedBuffers) -> Self {
+        self.unsaved = unsaved;
+        self
+    }
+
+    pub fn agent(&self) -> Agent {
+        self.agent
     }
 
     /// Spawns an agent CLI via a child process and tracks stdin and stdout
@@ -158,40 +405,65 @@ impl AgentHub {
         })
     }
 
-    // pub fn list_agents(&self) -> Vec<Agent> {
-    //     self.agents.keys().cloned().collect()
-    // }
-
-    pub async fn new_session(&mut self) -> Result<(), AgentHubSessionError> {
-        // let agent_process = match self.agents.get(&agent){
-        //     Some(proc) => proc,
-        //     None => {
-        //         return Err(AgentHubSessionError::AgentUnavailable)
-        //     }
-        // };
-        // For now, we'll spawn a process per session. in the future, we will optimize this
+    /// Returns the running agent connection, starting the agent if it is not running
+    async fn connection(&self) -> Result<AgentConnection, AgentHubSessionError> {
+        let mut connection = self.connection.lock().await;
+        if let Some(running) = connection.as_ref().filter(|running| running.is_running()) {
+            return Ok(running.clone());
+        }
 
-        let mut opencode_process = AgentHub::spawn_agent_from_cmd("opencode", &["acp"]).await.unwrap();
+        let spawned =
+            AgentConnection::spawn(self.agent, self.permissions.clone(), self.unsaved.clone())
+                .await?;
+        *connection = Some(spawned.clone());
+        Ok(spawned)
+    }
 
-        let local_set = tokio::task::LocalSet::new();
-        local_set.run_until(async move {
+    /// Starts a new ACP session rooted at `cwd` and returns the agent's session id
+    pub async fn new_session(&self, cwd: PathBuf) -> Result<String, AgentHubSessionError> {
+        let handle = self.connection().await?.new_session(cwd).await?;
+        let session_id = handle.session_id().to_string();
+        self.sessions_guard().insert(session_id.clone(), handle);
+        Ok(session_id)
+    }
 
-            let (conn, handle_io) = acp::ClientSideConnection::new(
-                DummyAgentClient {},
-                opencode_process.outgoing,
-                opencode_process.incoming,
-                |fut| {
-                    tokio::task::spawn_local(fut);
-                },
-            );
-            tokio::task::spawn_local(handle_io);
+    /// Returns the running session, loading it back into a fresh agent process after a restart
+    pub async fn session(
+        &self,
+        session_id: &str,
+        cwd: PathBuf,
+    ) -> Result<AcpSessionHandle, AgentHubSessionError> {
+        if let Some(handle) = self
+            .sessions_guard()
+            .get(session_id)
+            .filter(|handle| handle.is_running())
+        {
+            return Ok(handle.clone());
+        }
 
-            let init_request = InitializeRequest::new(ProtocolVersion::LATEST).client_capabilities(ClientCapabilities::default());
-            conn.initialize(init_request).await.unwrap();
+        let handle = self
+            .connection()
+            .await?
+            .load_session(session_id.to_string(), cwd)
+            .await?;
+        self.sessions_guard()
+            .insert(session_id.to_string(), handle.clone());
+        Ok(handle)
+    }
 
-        }).await;
+    /// Drops every handle, which stops the connection actor and kills the agent process
+    pub fn shutdown(&self) {
+        self.sessions_guard().clear();
+        match self.connection.try_lock() {
+            Ok(mut connection) => *connection = None,
+            Err(_) => log::warn!("acp connection is starting, cannot shut it down yet"),
+        }
+    }
 
-        Ok(())
+    fn sessions_guard(&self) -> std::sync::MutexGuard<'_, HashMap<String, AcpSessionHandle>> {
+        self.sessions
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
     }
 }
 
@@ -201,17 +473,13 @@ mod tests {
 
     #[tokio::test]
     async fn test_agent_client_spawn() {
-        let hub = AgentHub::new().await.unwrap();
-        let _ = hub;
-        // let agents = hub.list_agents();
-        // assert_eq!(agents.len(), 1);
-        // assert_eq!(agents.get(0), Some(&Agent::Opencode));
+        let hub = AgentHub::new(Agent::Opencode);
+        assert_eq!(hub.agent(), Agent::Opencode);
     }
 
     #[tokio::test]
     async fn test_session() {
-        let mut hub = AgentHub::new().await.unwrap();
-        hub.new_session().await.unwrap()
-        // hub.
+        let hub = AgentHub::new(Agent::Opencode);
+        hub.new_session(std::env::temp_dir()).await.unwrap();
     }
 }
@@ -0,0 +1,187 @@
+use std::path::{Path, PathBuf};
+
+use tokio::process::Command;
+
+#[derive(thiserror::Error, Debug)]
+pub enum StashError {
+    #[error("failed to run git in {dir}: {source}")]
+    Spawn {
+        dir: PathBuf,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("git {command} failed in {dir}: {stderr}")]
+    Git {
+        command: String,
+        dir: PathBuf,
+        stderr: String,
+    },
+    #[error("stash {0} is no longer in the stash list")]
+    Missing(String),
+}
+
+/// Shelves and restores uncommitted changes to a project's files with `git stash`,
+/// for reverting sessions whose harness keeps no snapshots of its own
+#[derive(Debug, Clone)]
+pub struct WorkspaceStash {
+    dir: PathBuf,
+}
+
+impl WorkspaceStash {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// Stashes the uncommitted changes to `paths`, untracked files included, and returns the
+    /// stash's commit. Other files stay as they are. `None` when there was nothing to stash
+    pub async fn push(
+        &self,
+        message: &str,
+        paths: &[impl AsRef<Path>],
+    ) -> Result<Option<String>, StashError> {
+        if paths.is_empty() {
+            return Ok(None);
+        }
+        let before = self.top().await?;
+        let mut args = vec![
+            "stash",
+            "push",
+            "--include-untracked",
+            "--message",
+            message,
+            "--",
+        ];
+        args.extend(paths.iter().filter_map(|path| path.as_ref().to_str()));
+        self.git(&args).await?;
+        let after = self.top().await?;
+        Ok(after.filter(|after| before.as_ref() != Some(after)))
+    }
+
+    /// Puts the changes of a stash `push` returned back and drops the stash
+    pub async fn pop(&self, stash: &str) -> Result<(), StashError> {
+        let stashes = self.git(&["stash", "list", "--format=%H"]).await?;
+        let index = stashes
+            .lines()
+            .position(|hash| hash == stash)
+            .ok_or_else(|| StashError::Missing(stash.to_string()))?;
+        self.git(&["stash", "pop", "--index", &format!("stash@{{{index}}}")])
+            .await?;
+        Ok(())
+    }
+
+    /// Commit of the latest stash, `None` without any
+    async fn top(&self) -> Result<Option<String>, StashError> {
+        let output = self
+            .command(&["rev-parse", "--verify", "--quiet", "refs/stash"])
+            .output()
+            .await
+            .map_err(|source| StashError::Spawn {
+                dir: self.dir.clone(),
+                source,
+            })?;
+        Ok(output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).trim().to_string()))
+    }
+
+    async fn git(&self, args: &[&str]) -> Result<String, StashError> {
+        let output = self
+            .command(args)
+            .output()
+            .await
+            .map_err(|source| StashError::Spawn {
+                dir: self.dir.clone(),
+                source,
+            })?;
+        if !output.status.success() {
+            return Err(StashError::Git {
+                command: args.join(" "),
+                dir: self.dir.clone(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        }
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    fn command(&self, args: &[&str]) -> Command {
+        let mut command = Command::new("git");
+        command.arg("-C").arg(&self.dir).args(args);
+        command
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn git(dir: &std::path::Path, args: &[&str]) {
+        let status = std::process::Command::new("git")
+            .arg("-C")
+            .arg(dir)
+            .args(args)
+            .output()
+            .unwrap()
+            .status;
+        assert!(status.success(), "git {args:?} failed");
+    }
+
+    fn repo() -> tempfile::TempDir {
+        let dir = tempfile::tempdir().unwrap();
+        git(dir.path(), &["init", "--quiet"]);
+        git(dir.path(), &["config", "user.name", "test"]);
+        git(dir.path(), &["config", "user.email", "test@example.com"]);
+        std::fs::write(dir.path().join("a.txt"), "committed\n").unwrap();
+        git(dir.path(), &["add", "a.txt"]);
+        git(dir.path(), &["commit", "--quiet", "-m", "initial"]);
+        dir
+    }
+
+    #[tokio::test]
+    async fn push_and_pop_round_trip_uncommitted_changes() {
+        let dir = repo();
+        let stash = WorkspaceStash::new(dir.path());
+        std::fs::write(dir.path().join("a.txt"), "edited\n").unwrap();
+        std::fs::write(dir.path().join("b.txt"), "new\n").unwrap();
+        std::fs::write(dir.path().join("c.txt"), "untouched\n").unwrap();
+
+        let pushed = stash
+            .push("revert", &["a.txt", "b.txt"])
+            .await
+            .unwrap()
+            .expect("changes should be stashed");
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+            "committed\n"
+        );
+        assert!(!dir.path().join("b.txt").exists());
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("c.txt")).unwrap(),
+            "untouched\n"
+        );
+
+        stash.pop(&pushed).await.unwrap();
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("a.txt")).unwrap(),
+            "edited\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.path().join("b.txt")).unwrap(),
+            "new\n"
+        );
+        assert!(matches!(
+            stash.pop(&pushed).await,
+            Err(StashError::Missing(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn push_without_changes_stashes_nothing() {
+        let dir = repo();
+        let stash = WorkspaceStash::new(dir.path());
+
+        assert_eq!(stash.push("revert", &["a.txt"]).await.unwrap(), None);
+        std::fs::write(dir.path().join("a.txt"), "edited\n").unwrap();
+        assert_eq!(stash.push("revert", &[] as &[&str]).await.unwrap(), None);
+    }
+}
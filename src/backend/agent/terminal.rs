@@ -0,0 +1,389 @@
+use std::{
+    collections::HashMap,
+    path::Path,
+    process::{ExitStatus, Stdio},
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use agent_client_protocol as acp;
+use tokio::{
+    io::{AsyncRead, AsyncReadExt},
+    sync::{oneshot, watch},
+};
+use uuid::Uuid;
+
+use crate::backend::agent::fs::{ProjectFs, ProjectFsError};
+
+#[derive(thiserror::Error, Debug)]
+pub enum TerminalError {
+    #[error("unknown terminal {0}")]
+    UnknownTerminal(String),
+    #[error("invalid terminal cwd: {0}")]
+    Cwd(#[from] ProjectFsError),
+    #[error("failed to spawn {command}: {source}")]
+    Spawn {
+        command: String,
+        #[source]
+        source: std::io::Error,
+    },
+}
+
+/// What an agent asked to run, see `terminal/create`
+pub struct TerminalCommand {
+    pub command: String,
+    pub args: Vec<String>,
+    pub env: Vec<(String, String)>,
+    pub cwd: Option<std::path::PathBuf>,
+    pub output_byte_limit: Option<u64>,
+}
+
+/// Combined stdout and stderr, keeping only the newest bytes once over the limit
+#[derive(Debug, Default)]
+struct OutputBuffer {
+    bytes: Vec<u8>,
+    limit: Option<usize>,
+    truncated: bool,
+}
+
+impl OutputBuffer {
+    fn new(limit: Option<usize>) -> Self {
+        Self {
+            limit,
+            ..Self::default()
+        }
+    }
+
+    fn push(&mut self, chunk: &[u8]) {
+        self.bytes.extend_from_slice(chunk);
+        let Some(limit) = self.limit else {
+            return;
+        };
+        if self.bytes.len() <= limit {
+            return;
+        }
+
+        // Truncate from the front without splitting a utf-8 character
+        let mut cut = self.bytes.len() - limit;
+        while cut < self.bytes.len() && (self.bytes[cut] & 0b1100_0000) == 0b1000_0000 {
+            cut += 1;
+        }
+        self.bytes.drain(..cut);
+        self.truncated = true;
+    }
+
+    fn text(&self) -> String {
+        String::from_utf8_lossy(&self.bytes).into_owned()
+    }
+}
+
+struct Terminal {
+    output: Arc<Mutex<OutputBuffer>>,
+    exit: watch::Receiver<Option<acp::TerminalExitStatus>>,
+    kill: Option<oneshot::Sender<()>>,
+}
+
+impl Terminal {
+    fn kill(&mut self) {
+        if let Some(kill) = self.kill.take() {
+            let _ = kill.send(());
+        }
+    }
+}
+
+/// Commands ACP agents run through us, confined to the project directory.
+/// Every command still running is killed when it is released or the manager is dropped
+pub struct TerminalManager {
+    files: ProjectFs,
+    terminals: Mutex<HashMap<String, Terminal>>,
+}
+
+impl TerminalManager {
+    pub fn new(files: ProjectFs) -> Self {
+        Self {
+            files,
+            terminals: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub async fn create(&self, request: TerminalCommand) -> Result<String, TerminalError> {
+        let cwd = self
+            .files
+            .resolve(request.cwd.as_deref().unwrap_or(Path::new(".")))
+            .await?;
+
+        // Agents often send a whole command line without args, that needs a shell to run
+        let mut command = if request.args.is_empty() {
+            let mut command = tokio::process::Command::new("sh");
+            command.arg("-c").arg(&request.command);
+            command
+        } else {
+            let mut command = tokio::process::Command::new(&request.command);
+            command.args(&request.args);
+            command
+        };
+        // A process group of its own lets kill reach whatever the command spawned
+        #[cfg(unix)]
+        command.process_group(0);
+        let mut child = command
+            .current_dir(cwd)
+            .envs(request.env)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::piped())
+            .kill_on_drop(true)
+            .spawn()
+            .map_err(|source| TerminalError::Spawn {
+                command: request.command.clone(),
+                source,
+            })?;
+
+        let limit = request
+            .output_byte_limit
+            .map(|limit| usize::try_from(limit).unwrap_or(usize::MAX));
+        let output = Arc::new(Mutex::new(OutputBuffer::new(limit)));
+        let stdout = child
+            .stdout
+            .take()
+            .map(|stdout| tokio::spawn(collect_output(stdout, Arc::clone(&output))));
+        let stderr = child
+            .stderr
+            .take()
+            .map(|stderr| tokio::spawn(collect_output(stderr, Arc::clone(&output))));
+
+        let (exit_tx, exit) = watch::channel(None);
+        let (kill, killed) = oneshot::channel();
+        let pid = child.id();
+        tokio::spawn(async move {
+            let status = tokio::select! {
+                status = child.wait() => status,
+                _ = killed => {
+                    kill_process_group(pid);
+                    let _ = child.start_kill();
+                    child.wait().await
+                }
+            };
+            // Drain what the command printed before reporting the exit
+            for reader in [stdout, stderr].into_iter().flatten() {
+                let _ = reader.await;
+            }
+            let status = match status {
+                Ok(status) => exit_status(status),
+                Err(err) => {
+                    log::warn!("failed to wait for terminal command: {err}");
+                    acp::TerminalExitStatus::new()
+                }
+            };
+            let _ = exit_tx.send(Some(status));
+        });
+
+        let terminal_id = Uuid::new_v4().to_string();
+        self.terminals_guard().insert(
+            terminal_id.clone(),
+            Terminal {
+                output,
+                exit,
+                kill: Some(kill),
+            },
+        );
+        Ok(terminal_id)
+    }
+
+    /// Output so far, whether it was truncated, and the exit status once the command finished
+    pub fn output(
+        &self,
+        terminal_id: &str,
+    ) -> Result<(String, bool, Option<acp::TerminalExitStatus>), TerminalError> {
+        let terminals = self.terminals_guard();
+        let terminal = terminals
+            .get(terminal_id)
+            .ok_or_else(|| TerminalError::UnknownTerminal(terminal_id.to_string()))?;
+        let output = terminal
+            .output
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner());
+        Ok((
+            output.text(),
+            output.truncated,
+            terminal.exit.borrow().clone(),
+        ))
+    }
+
+    pub async fn wait_for_exit(
+        &self,
+        terminal_id: &str,
+    ) -> Result<acp::TerminalExitStatus, TerminalError> {
+        let mut exit = self
+            .terminals_guard()
+            .get(terminal_id)
+            .ok_or_else(|| TerminalError::UnknownTerminal(terminal_id.to_string()))?
+            .exit
+            .clone();
+
+        let status = exit
+            .wait_for(Option::is_some)
+            .await
+            .map(|status| status.clone())
+            .ok()
+            .flatten();
+        Ok(status.unwrap_or_default())
+    }
+
+    /// Kills the command but keeps its output around until the terminal is released
+    pub fn kill(&self, terminal_id: &str) -> Result<(), TerminalError> {
+        self.terminals_guard()
+            .get_mut(terminal_id)
+            .ok_or_else(|| TerminalError::UnknownTerminal(terminal_id.to_string()))?
+            .kill();
+        Ok(())
+    }
+
+    pub fn release(&self, terminal_id: &str) -> Result<(), TerminalError> {
+        self.terminals_guard()
+            .remove(terminal_id)
+            .ok_or_else(|| TerminalError::UnknownTerminal(terminal_id.to_string()))?
+            .kill();
+        Ok(())
+    }
+
+    fn terminals_guard(&self) -> MutexGuard<'_, HashMap<String, Terminal>> {
+        self.terminals
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+impl Drop for TerminalManager {
+    fn drop(&mut self) {
+        for terminal in self.terminals_guard().values_mut() {
+            terminal.kill();
+        }
+    }
+}
+
+async fn collect_output(mut reader: impl AsyncRead + Unpin, output: Arc<Mutex<OutputBuffer>>) {
+    let mut chunk = [0u8; 8192];
+    loop {
+        match reader.read(&mut chunk).await {
+            Ok(0) | Err(_) => return,
+            Ok(read) => output
+                .lock()
+                .unwrap_or_else(|poisoned| poisoned.into_inner())
+                .push(&chunk[..read]),
+        }
+    }
+}
+
+#[cfg(unix)]
+fn kill_process_group(pid: Option<u32>) {
+    if let Some(pid) = pid.and_then(|pid| i32::try_from(pid).ok()) {
+        // SAFETY: kill only sends a signal, a stale group id at worst fails with ESRCH
+        unsafe {
+            libc::kill(-pid, libc::SIGKILL);
+        }
+    }
+}
+
+#[cfg(not(unix))]
+fn kill_process_group(_pid: Option<u32>) {}
+
+fn exit_status(status: ExitStatus) -> acp::TerminalExitStatus {
+    let exit_code = status.code().and_then(|code| u32::try_from(code).ok());
+    #[cfg(unix)]
+    let signal = std::os::unix::process::ExitStatusExt::signal(&status).map(signal_name);
+    #[cfg(not(unix))]
+    let signal: Option<String> = None;
+    acp::TerminalExitStatus::new()
+        .exit_code(exit_code)
+        .signal(signal)
+}
+
+#[cfg(unix)]
+fn signal_name(signal: i32) -> String {
+    match signal {
+        libc::SIGHUP => "SIGHUP".to_string(),
+        libc::SIGINT => "SIGINT".to_string(),
+        libc::SIGKILL => "SIGKILL".to_string(),
+        libc::SIGTERM => "SIGTERM".to_string(),
+        other => other.to_string(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn command(command: &str) -> TerminalCommand {
+        TerminalCommand {
+            command: command.to_string(),
+            args: Vec::new(),
+            env: Vec::new(),
+            cwd: None,
+            output_byte_limit: None,
+        }
+    }
+
+    #[test]
+    fn output_buffer_truncates_from_the_front_on_char_boundaries() {
+        let mut buffer = OutputBuffer::new(Some(3));
+        buffer.push("aé".as_bytes());
+        assert!(!buffer.truncated);
+
+        // Keeping the last 3 bytes would start inside `é`, so it is dropped whole
+        buffer.push("bc".as_bytes());
+        assert!(buffer.truncated);
+        assert_eq!(buffer.text(), "bc");
+    }
+
+    #[tokio::test]
+    async fn runs_commands_in_the_project_and_reports_exit() {
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("marker.txt"), "found").unwrap();
+        let terminals = TerminalManager::new(ProjectFs::new(dir.path()));
+
+        let id = terminals
+            .create(TerminalCommand {
+                env: vec![("SUFFIX".to_string(), "!".to_string())],
+                ..command("cat marker.txt; echo \"$SUFFIX\"; exit 3")
+            })
+            .await
+            .unwrap();
+        let status = terminals.wait_for_exit(&id).await.unwrap();
+        assert_eq!(status.exit_code, Some(3));
+
+        let (output, truncated, exit) = terminals.output(&id).unwrap();
+        assert_eq!(output, "found!\n");
+        assert!(!truncated);
+        assert_eq!(exit.and_then(|exit| exit.exit_code), Some(3));
+    }
+
+    #[tokio::test]
+    async fn kill_stops_the_command_and_release_forgets_it() {
+        let dir = tempfile::tempdir().unwrap();
+        let terminals = TerminalManager::new(ProjectFs::new(dir.path()));
+
+        let id = terminals.create(command("sleep 30")).await.unwrap();
+        terminals.kill(&id).unwrap();
+        let status = terminals.wait_for_exit(&id).await.unwrap();
+        assert_eq!(status.signal.as_deref(), Some("SIGKILL"));
+
+        terminals.release(&id).unwrap();
+        assert!(matches!(
+            terminals.output(&id),
+            Err(TerminalError::UnknownTerminal(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn rejects_cwd_outside_the_project() {
+        let dir = tempfile::tempdir().unwrap();
+        let terminals = TerminalManager::new(ProjectFs::new(dir.path()));
+
+        let result = terminals
+            .create(TerminalCommand {
+                cwd: Some("/".into()),
+                ..command("true")
+            })
+            .await;
+        assert!(matches!(result, Err(TerminalError::Cwd(_))));
+    }
+}
@@ -0,0 +1,202 @@
+use std::{fs, io::Read, path::Path};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use thiserror::Error;
+
+/// Largest file a message may carry, harnesses hand it to the model whole
+pub const MAX_ATTACHMENT_BYTES: u64 = 10 * 1024 * 1024;
+/// Largest file a message may carry inline as a data URL. Stored messages are replayed
+/// with every history, so bigger files have to go by `file://` URL
+pub const MAX_INLINE_BYTES: u64 = 1024 * 1024;
+
+const FALLBACK_MIME: &str = "application/octet-stream";
+/// Enough of a file to recognize its format
+const SNIFF_BYTES: usize = 512;
+
+#[derive(Debug, Error)]
+pub enum AttachmentError {
+    #[error("{name} is {size} bytes, attachments are limited to {MAX_ATTACHMENT_BYTES}")]
+    TooLarge { name: String, size: u64 },
+    #[error("{name} is {size} bytes, inline attachments are limited to {MAX_INLINE_BYTES}")]
+    TooLargeToInline { name: String, size: u64 },
+    #[error("could not read {name}: {source}")]
+    Io {
+        name: String,
+        #[source]
+        source: std::io::Error,
+    },
+    #[error("attachments need a file:// or data: url, got {0}")]
+    UnsupportedUrl(String),
+    #[error("attachment data url is not valid base64")]
+    InvalidDataUrl,
+}
+
+/// A file ready to be sent as a `file` message part
+#[derive(Debug, Clone, PartialEq)]
+pub struct Attachment {
+    pub file_name: String,
+    pub mime: String,
+    pub url: String,
+    pub size: u64,
+}
+
+impl Attachment {
+    pub fn is_image(&self) -> bool {
+        self.mime.starts_with("image/")
+    }
+}
+
+/// Checks the file at `path` and links it by `file://` URL, so stored messages stay small.
+/// Images are only inlined on their way to the harness, see `inline_file_url`
+pub fn load(path: &Path) -> Result<Attachment, AttachmentError> {
+    let file_name = path
+        .file_name()
+        .map(|name| name.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.display().to_string());
+    let io_error = |source| AttachmentError::Io {
+        name: file_name.clone(),
+        source,
+    };
+
+    let size = fs::metadata(path).map_err(io_error)?.len();
+    check_size(&file_name, size)?;
+
+    let mut head = Vec::with_capacity(SNIFF_BYTES);
+    fs::File::open(path)
+        .and_then(|file| file.take(SNIFF_BYTES as u64).read_to_end(&mut head))
+        .map_err(io_error)?;
+    let mime = sniff_mime(&head, &file_name);
+
+    Ok(Attachment {
+        file_name,
+        mime: mime.to_string(),
+        url: file_url(path),
+        size,
+    })
+}
+
+/// Recognizes common formats by their first bytes, then by extension
+pub fn sniff_mime(head: &[u8], file_name: &str) -> &'static str {
+    const SIGNATURES: &[(&[u8], &str)] = &[
+        (b"\x89PNG\r\n\x1a\n", "image/png"),
+        (b"\xff\xd8\xff", "image/jpeg"),
+        (b"GIF87a", "image/gif"),
+        (b"GIF89a", "image/gif"),
+        (b"%PDF-", "application/pdf"),
+    ];
+    if let Some((_, mime)) = SIGNATURES
+        .iter()
+        .find(|(signature, _)| head.starts_with(signature))
+    {
+        return mime;
+    }
+    if head.len() >= 12 && &head[..4] == b"RIFF" && &head[8..12] == b"WEBP" {
+        return "image/webp";
+    }
+
+    let extension = Path::new(file_name)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_lowercase());
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg" | "jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        Some("svg") => "image/svg+xml",
+        Some("pdf") => "application/pdf",
+        Some("json") => "application/json",
+        Some("md") => "text/markdown",
+        // Source files and other text are read as is
+        _ if !head.contains(&0) && std::str::from_utf8(head).is_ok() => "text/plain",
+        _ => FALLBACK_MIME,
+    }
+}
+
+pub fn encode_data_url(mime: &str, bytes: &[u8]) -> String {
+    format!("data:{mime};base64,{}", STANDARD.encode(bytes))
+}
+
+/// Splits a base64 data URL into its mime type and bytes
+pub fn decode_data_url(url: &str) -> Option<(&str, Vec<u8>)> {
+    let (mime, data) = url.strip_prefix("data:")?.split_once(";base64,")?;
+    Some((mime, STANDARD.decode(data).ok()?))
+}
+
+/// Reads a `file://` URL into a data URL of `mime`, for harnesses that want the bytes
+/// rather than a path. Other URLs are returned as they are
+pub fn inline_file_url(url: &str, mime: &str) -> Result<String, AttachmentError> {
+    let Some(path) = url.strip_prefix("file://") else {
+        return Ok(url.to_string());
+    };
+    let bytes = fs::read(decode_path(path)).map_err(|source| AttachmentError::Io {
+        name: url.to_string(),
+        source,
+    })?;
+    Ok(encode_data_url(mime, &bytes))
+}
+
+/// Bytes behind a data or `file://` URL, `None` when they cannot be had
+pub fn read_url(url: &str) -> Option<Vec<u8>> {
+    match url.strip_prefix("file://") {
+        Some(path) => fs::read(decode_path(path)).ok(),
+        None => decode_data_url(url).map(|(_, bytes)| bytes),
+    }
+}
+
+/// Checks a `file` part's URL before the message is stored and returns the file's size
+pub fn validate_url(name: &str, url: &str) -> Result<u64, AttachmentError> {
+    let size = if url.starts_with("data:") {
+        let (_, bytes) = decode_data_url(url).ok_or(AttachmentError::InvalidDataUrl)?;
+        let size = bytes.len() as u64;
+        if size > MAX_INLINE_BYTES {
+            return Err(AttachmentError::TooLargeToInline {
+                name: name.to_string(),
+                size,
+            });
+        }
+        size
+    } else if let Some(path) = url.strip_prefix("file://") {
+        fs::metadata(decode_path(path))
+            .map_err(|source| AttachmentError::Io {
+                name: name.to_string(),
+                source,
+            })?
+            .len()
+    } else {
+        return Err(AttachmentError::UnsupportedUrl(url.to_string()));
+    };
+    check_size(name, size)?;
+    Ok(size)
+}
+
+fn check_size(name: &str, size: u64) -> Result<(), AttachmentError> {
+    if size > MAX_ATTACHMENT_BYTES {
+        return Err(AttachmentError::TooLarge {
+            name: name.to_string(),
+            size,
+        });
+    }
+    Ok(())
+}
+
+/// Escapes the characters that would end or break the path part of a URL
+pub fn file_url(path: &Path) -> String {
+    let mut url = "file://".to_string();
+    for c in path.to_string_lossy().chars() {
+        match c {
+            '%' => url.push_str("%25"),
+            ' ' => url.push_str("%20"),
+            '#' => url.push_str("%23"),
+            '?' => url.push_str("%3F"),
+            c => url.push(c),
+        }
+    }
+    url
+}
+
+fn decode_path(path: &str) -> String {
+    path.replace("%20", " ")
+        .replace("%23", "#")
+        .replace("%3F", "?")
+        .replace("%25", "%")
+}
@@ -0,0 +1,77 @@
+use std::fs;
+
+use crate::backend::attachment::{
+    self, AttachmentError, MAX_ATTACHMENT_BYTES, MAX_INLINE_BYTES, decode_data_url,
+    encode_data_url, inline_file_url, sniff_mime, validate_url,
+};
+
+const PNG_HEAD: &[u8] = b"\x89PNG\r\n\x1a\n\0\0\0\rIHDR";
+
+#[test]
+fn formats_are_recognized_by_content_before_extension() {
+    assert_eq!(sniff_mime(PNG_HEAD, "screenshot.txt"), "image/png");
+    assert_eq!(sniff_mime(b"\xff\xd8\xff\xe0", "photo"), "image/jpeg");
+    assert_eq!(sniff_mime(b"RIFF\0\0\0\0WEBPVP8 ", "a"), "image/webp");
+    assert_eq!(sniff_mime(b"%PDF-1.7", "doc"), "application/pdf");
+    assert_eq!(sniff_mime(b"<svg", "icon.svg"), "image/svg+xml");
+    assert_eq!(sniff_mime(b"fn main() {}", "main.rs"), "text/plain");
+    assert_eq!(
+        sniff_mime(b"\0\x01\x02", "blob"),
+        "application/octet-stream"
+    );
+}
+
+#[test]
+fn files_are_linked_and_images_inlined_on_request() {
+    let dir = tempfile::tempdir().unwrap();
+    let image = dir.path().join("shot one.png");
+    fs::write(&image, PNG_HEAD).unwrap();
+    let notes = dir.path().join("notes #1.md");
+    fs::write(&notes, "# Notes").unwrap();
+
+    let image = attachment::load(&image).expect("image should load");
+    assert!(image.is_image());
+    assert_eq!(image.file_name, "shot one.png");
+    assert!(image.url.ends_with("shot%20one.png"));
+    let inlined = inline_file_url(&image.url, &image.mime).expect("image should inline");
+    let (mime, bytes) = decode_data_url(&inlined).expect("image should be a data url");
+    assert_eq!((mime, bytes.as_slice()), ("image/png", PNG_HEAD));
+
+    let notes = attachment::load(&notes).expect("notes should load");
+    assert_eq!(notes.mime, "text/markdown");
+    assert!(notes.url.starts_with("file://"));
+    assert!(notes.url.ends_with("notes%20%231.md"));
+    assert_eq!(validate_url(&notes.file_name, &notes.url).unwrap(), 7);
+}
+
+#[test]
+fn oversized_and_unknown_attachments_are_rejected() {
+    let dir = tempfile::tempdir().unwrap();
+    let big = dir.path().join("big.bin");
+    fs::File::create(&big)
+        .unwrap()
+        .set_len(MAX_ATTACHMENT_BYTES + 1)
+        .unwrap();
+
+    assert!(matches!(
+        attachment::load(&big),
+        Err(AttachmentError::TooLarge { .. })
+    ));
+    assert!(matches!(
+        validate_url("remote", "https://example.com/a.png"),
+        Err(AttachmentError::UnsupportedUrl(_))
+    ));
+    let inline = encode_data_url("image/png", &vec![0; MAX_INLINE_BYTES as usize + 1]);
+    assert!(matches!(
+        validate_url("pasted", &inline),
+        Err(AttachmentError::TooLargeToInline { .. })
+    ));
+    assert!(matches!(
+        validate_url("broken", "data:image/png;base64,%%%"),
+        Err(AttachmentError::InvalidDataUrl)
+    ));
+    assert!(matches!(
+        validate_url("gone", "file:///does/not/exist"),
+        Err(AttachmentError::Io { .. })
+    ));
+}
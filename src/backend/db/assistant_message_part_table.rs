@@ -50,6 +50,32 @@ pub fn get_by_harness_id(
     Ok(rows.next().transpose()?)
 }
 
+pub fn list_by_session(
+    conn: &Connection,
+    session_id: Uuid,
+) -> Result<Vec<AssistantMessagePart>, DatabaseError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {ASSISTANT_MESSAGE_PART_COLUMNS}
+         FROM assistant_message_part
+         WHERE session_id = :session_id
+         ORDER BY assistant_message_id, position"
+    ))?;
+    let rows = from_rows::<AssistantMessagePart>(stmt.query(named_params! {
+        ":session_id": session_id.to_string(),
+    })?);
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+pub fn next_position(conn: &Connection, assistant_message_id: Uuid) -> Result<i64, DatabaseError> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(MAX(position) + 1, 0)
+         FROM assistant_message_part
+         WHERE assistant_message_id = :assistant_message_id",
+        named_params! {":assistant_message_id": assistant_message_id.to_string()},
+        |row| row.get(0),
+    )?)
+}
+
 pub fn create(
     conn: &Connection,
     part: &AssistantMessagePart,
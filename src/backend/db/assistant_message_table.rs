@@ -9,7 +9,7 @@ pub const ASSISTANT_MESSAGE_COLUMNS: &str = "
 id, harness_message_id, session_id, user_message_id, agent, model_provider_id, model_id,
 cwd, root, cost,
 token_total, token_input, token_output, token_reasoning, token_cache_read, token_cache_write,
-error_message, created_at, updated_at, completed_at
+error_message, reverted, created_at, updated_at, completed_at
 ";
 
 pub fn get(
@@ -50,7 +50,7 @@ pub fn create(
             :id, :harness_message_id, :session_id, :user_message_id, :agent, :model_provider_id, :model_id,
             :cwd, :root, :cost,
             :token_total, :token_input, :token_output, :token_reasoning, :token_cache_read, :token_cache_write,
-            :error_message, :created_at, :updated_at, :completed_at
+            :error_message, :reverted, :created_at, :updated_at, :completed_at
         )
         RETURNING *
     "),
@@ -0,0 +1,158 @@
+use serde_rusqlite::{from_rows, to_params_named};
+use tokio_rusqlite::named_params;
+use tokio_rusqlite::rusqlite::Connection;
+use uuid::Uuid;
+
+use crate::backend::db::DatabaseError;
+use crate::backend::models::file_diff_model::{self, DiffSummary, FileDiffModel};
+
+/// Diffs of the session's turns that are not reverted, oldest first
+pub fn list_by_session(
+    conn: &Connection,
+    session_id: Uuid,
+) -> Result<Vec<FileDiffModel>, DatabaseError> {
+    let mut stmt = conn.prepare(
+        "
+        SELECT file_diffs.* FROM file_diffs
+        LEFT JOIN user_message ON user_message.id = file_diffs.user_message_id
+        WHERE file_diffs.session_id = :session_id AND COALESCE(user_message.reverted, 0) = 0
+        ORDER BY file_diffs.created_at ASC, file_diffs.rowid ASC
+    ",
+    )?;
+    let rows = from_rows::<FileDiffModel>(
+        stmt.query(named_params! {":session_id": session_id.to_string()})?,
+    );
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
+/// Replaces what a turn changed with `diffs` and updates the session's totals, in one
+/// transaction. Files seen before keep their place in the session's order, and the turn
+/// stays with the prompt it was first stored for
+pub fn replace_for_message(
+    conn: &mut Connection,
+    session_id: Uuid,
+    harness_message_id: &str,
+    diffs: &[FileDiffModel],
+) -> Result<DiffSummary, DatabaseError> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            "
+            SELECT file FROM file_diffs
+            WHERE session_id = :session_id AND harness_message_id = :harness_message_id
+        ",
+        )?;
+        let stored = stmt
+            .query_map(
+                named_params! {
+                    ":session_id": session_id.to_string(),
+                    ":harness_message_id": harness_message_id,
+                },
+                |row| row.get::<_, String>(0),
+            )?
+            .collect::<Result<Vec<_>, _>>()?;
+        for file in stored
+            .iter()
+            .filter(|file| !diffs.iter().any(|diff| &diff.file == *file))
+        {
+            tx.execute(
+                "
+                DELETE FROM file_diffs
+                WHERE session_id = :session_id
+                    AND harness_message_id = :harness_message_id
+                    AND file = :file
+            ",
+                named_params! {
+                    ":session_id": session_id.to_string(),
+                    ":harness_message_id": harness_message_id,
+                    ":file": file,
+                },
+            )?;
+        }
+
+        let mut stmt = tx.prepare(
+            "
+            INSERT INTO file_diffs (
+                id, session_id, user_message_id, harness_message_id, file, before, after,
+                additions, deletions, created_at, updated_at
+            )
+            VALUES (
+                :id, :session_id,
+                COALESCE(
+                    (
+                        SELECT user_message_id FROM file_diffs
+                        WHERE session_id = :session_id
+                            AND harness_message_id = :harness_message_id
+                            AND user_message_id IS NOT NULL
+                        LIMIT 1
+                    ),
+                    :user_message_id
+                ),
+                :harness_message_id, :file, :before, :after, :additions, :deletions,
+                :created_at, :updated_at
+            )
+            ON CONFLICT(session_id, harness_message_id, file) DO UPDATE
+            SET
+                before = excluded.before,
+                after = excluded.after,
+                additions = excluded.additions,
+                deletions = excluded.deletions,
+                updated_at = excluded.updated_at
+        ",
+        )?;
+        for diff in diffs {
+            let params = to_params_named(diff)?;
+            stmt.execute(params.to_slice().as_slice())?;
+        }
+    }
+    let summary = update_summary(&tx, session_id)?;
+    tx.commit()?;
+    Ok(summary)
+}
+
+/// Drops what a removed turn changed, `None` when it had no diffs
+pub fn remove_for_message(
+    conn: &mut Connection,
+    session_id: Uuid,
+    harness_message_id: &str,
+) -> Result<Option<DiffSummary>, DatabaseError> {
+    let tx = conn.transaction()?;
+    let removed = tx.execute(
+        "DELETE FROM file_diffs WHERE session_id = :session_id AND harness_message_id = :harness_message_id",
+        named_params! {
+            ":session_id": session_id.to_string(),
+            ":harness_message_id": harness_message_id,
+        },
+    )?;
+    if removed == 0 {
+        return Ok(None);
+    }
+    let summary = update_summary(&tx, session_id)?;
+    tx.commit()?;
+    Ok(Some(summary))
+}
+
+/// Writes the totals of the session's diffs onto the session row and returns them
+pub fn update_summary(conn: &Connection, session_id: Uuid) -> Result<DiffSummary, DatabaseError> {
+    let summary = DiffSummary::of(&file_diff_model::session_diff(list_by_session(
+        conn, session_id,
+    )?));
+    let rows = conn.execute(
+        "
+        UPDATE sessions
+        SET
+            summary_additions = :additions,
+            summary_deletions = :deletions,
+            summary_files = :files
+        WHERE id = :id
+    ",
+        named_params! {
+            ":additions": summary.additions,
+            ":deletions": summary.deletions,
+            ":files": summary.files,
+            ":id": session_id.to_string(),
+        },
+    )?;
+    super::assert_one_row_affected("update_session_diff_summary", rows)?;
+    Ok(summary)
+}
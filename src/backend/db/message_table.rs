@@ -1,9 +1,14 @@
 use serde_rusqlite::from_row;
+use tokio_rusqlite::named_params;
 use tokio_rusqlite::rusqlite::{self, Connection, Row, params};
 use uuid::Uuid;
 
 use crate::backend::{
-    db::DatabaseError,
+    db::{
+        DatabaseError, assistant_message_table,
+        revision_table::{self, RevisionedTable},
+        user_message_table,
+    },
     repo::{assistant_message::AssistantMessage, message::Message, user_message::UserMessage},
 };
 
@@ -75,6 +80,7 @@ pub fn list_messages_by_session(
             a.token_cache_read AS token_cache_read,
             a.token_cache_write AS token_cache_write,
             a.error_message AS error_message,
+            COALESCE(u.reverted, a.reverted) AS reverted,
             COALESCE(u.created_at, a.created_at) AS created_at,
             COALESCE(u.updated_at, a.updated_at) AS updated_at,
             a.completed_at AS completed_at
@@ -85,8 +91,70 @@ pub fn list_messages_by_session(
     )?;
 
     let mut rows = stmt
-        .query_and_then(params![session_id, i64::from(limit)], row_to_message)?
+        .query_and_then(
+            params![session_id.to_string(), i64::from(limit)],
+            row_to_message,
+        )?
         .collect::<Result<Vec<_>, DatabaseError>>()?;
     rows.reverse();
     Ok(rows)
 }
+
+fn message_row(message: &Message) -> (RevisionedTable, Uuid) {
+    match message {
+        Message::User(user) => (RevisionedTable::UserMessage, user.id),
+        Message::Assistant(assistant) => (RevisionedTable::AssistantMessage, assistant.id),
+    }
+}
+
+/// Flags the messages as reverted or not in one transaction, each stamped with the session's
+/// next revision so subscribers resuming from an earlier one see the change
+pub fn set_reverted(
+    conn: &mut Connection,
+    session_id: Uuid,
+    messages: &[Message],
+    reverted: bool,
+) -> Result<(), DatabaseError> {
+    let tx = conn.transaction()?;
+    for message in messages {
+        let (table, id) = message_row(message);
+        let rows_affected = tx.execute(
+            &format!(
+                "UPDATE {} SET reverted = :reverted WHERE id = :id",
+                table.name()
+            ),
+            named_params! {":reverted": reverted, ":id": id.to_string()},
+        )?;
+        super::assert_one_row_affected("set_message_reverted", rows_affected)?;
+        revision_table::stamp(&tx, table, id, session_id)?;
+    }
+    tx.commit()?;
+    Ok(())
+}
+
+/// Deletes the session's reverted messages, leaving tombstones like any removal,
+/// and returns their ids. Replies go before the prompts they would cascade with
+pub fn remove_reverted(
+    conn: &mut Connection,
+    session_id: Uuid,
+) -> Result<Vec<Uuid>, DatabaseError> {
+    let mut reverted = list_messages_by_session(conn, session_id, u32::MAX)?
+        .into_iter()
+        .filter(|message| match message {
+            Message::User(user) => user.reverted,
+            Message::Assistant(assistant) => assistant.reverted,
+        })
+        .collect::<Vec<_>>();
+    reverted.sort_by_key(|message| matches!(message, Message::User(_)));
+
+    let mut removed = Vec::with_capacity(reverted.len());
+    for message in &reverted {
+        let (table, id) = message_row(message);
+        revision_table::remove_stamped(conn, id, session_id, |conn| match table {
+            RevisionedTable::UserMessage => user_message_table::delete(conn, id),
+            _ => assistant_message_table::delete(conn, id),
+        })?;
+        removed.push(id);
+    }
+    Ok(removed)
+}
@@ -208,6 +208,126 @@ ALTER TABLE assistant_message_part ADD COLUMN harness_part_id TEXT;
 CREATE UNIQUE INDEX IF NOT EXISTS assistant_message_part_message_harness_part_id_uq
     ON assistant_message_part(assistant_message_id, harness_part_id)
     WHERE harness_part_id IS NOT NULL;
+",
+    ),
+    M::up(
+        "
+CREATE TABLE permission_rules (
+    id TEXT PRIMARY KEY NOT NULL CHECK(length(id) = 36),
+    project_id TEXT NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+
+    kind TEXT NOT NULL CHECK(length(trim(kind)) > 0),
+    action TEXT NOT NULL CHECK(action IN ('allow', 'deny')),
+
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+
+    UNIQUE(project_id, kind)
+);
+",
+    ),
+    M::up(
+        "
+ALTER TABLE sessions ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE user_message ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE user_message_part ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE assistant_message ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE assistant_message_part ADD COLUMN revision INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX user_message_session_revision_idx ON user_message(session_id, revision);
+CREATE INDEX user_message_part_session_revision_idx ON user_message_part(session_id, revision);
+CREATE INDEX assistant_message_session_revision_idx ON assistant_message(session_id, revision);
+CREATE INDEX assistant_message_part_session_revision_idx ON assistant_message_part(session_id, revision);
+",
+    ),
+    M::up(
+        "
+ALTER TABLE sessions ADD COLUMN status TEXT NOT NULL DEFAULT 'idle'
+    CHECK(status IN ('idle', 'busy', 'retry'));
+ALTER TABLE sessions ADD COLUMN status_attempt INTEGER;
+ALTER TABLE sessions ADD COLUMN status_message TEXT;
+ALTER TABLE sessions ADD COLUMN status_next INTEGER;
+ALTER TABLE sessions ADD COLUMN last_error TEXT;
+
+CREATE TABLE removed_message (
+    id TEXT PRIMARY KEY NOT NULL CHECK(length(id) = 36),
+    session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    revision INTEGER NOT NULL
+);
+CREATE INDEX removed_message_session_revision_idx ON removed_message(session_id, revision);
+",
+    ),
+    M::up(
+        "
+ALTER TABLE projects ADD COLUMN default_agent TEXT;
+ALTER TABLE projects ADD COLUMN default_model_provider_id TEXT;
+ALTER TABLE projects ADD COLUMN default_model_id TEXT;
+ALTER TABLE projects ADD COLUMN default_thinking_variant TEXT;
+
+ALTER TABLE sessions ADD COLUMN default_agent TEXT;
+ALTER TABLE sessions ADD COLUMN default_model_provider_id TEXT;
+ALTER TABLE sessions ADD COLUMN default_model_id TEXT;
+ALTER TABLE sessions ADD COLUMN default_thinking_variant TEXT;
+",
+    ),
+    M::up(
+        "
+ALTER TABLE user_message_part ADD COLUMN agent_source_value TEXT;
+ALTER TABLE user_message_part ADD COLUMN agent_source_start INTEGER;
+ALTER TABLE user_message_part ADD COLUMN agent_source_end INTEGER;
+",
+    ),
+    M::up(
+        "
+ALTER TABLE user_message_part ADD COLUMN file_mime TEXT;
+",
+    ),
+    M::up(
+        "
+ALTER TABLE user_message_part ADD COLUMN file_source_path TEXT;
+ALTER TABLE user_message_part ADD COLUMN file_source_text_value TEXT;
+ALTER TABLE user_message_part ADD COLUMN file_source_text_start INTEGER;
+ALTER TABLE user_message_part ADD COLUMN file_source_text_end INTEGER;
+",
+    ),
+    M::up(
+        "
+ALTER TABLE user_message ADD COLUMN reverted INTEGER NOT NULL DEFAULT 0 CHECK(reverted IN (0, 1));
+ALTER TABLE assistant_message ADD COLUMN reverted INTEGER NOT NULL DEFAULT 0 CHECK(reverted IN (0, 1));
+
+ALTER TABLE sessions ADD COLUMN revert_stash TEXT;
+",
+    ),
+    M::up(
+        "
+CREATE TABLE file_diffs (
+    id TEXT PRIMARY KEY NOT NULL CHECK(length(id) = 36),
+    session_id TEXT NOT NULL REFERENCES sessions(id) ON DELETE CASCADE,
+    harness_message_id TEXT NOT NULL,
+
+    file TEXT NOT NULL CHECK(length(trim(file)) > 0),
+    before TEXT NOT NULL,
+    after TEXT NOT NULL,
+    additions INTEGER NOT NULL,
+    deletions INTEGER NOT NULL,
+
+    created_at TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+
+    UNIQUE(session_id, harness_message_id, file)
+);
+CREATE INDEX file_diffs_session_created_idx ON file_diffs(session_id, created_at);
+",
+    ),
+    M::up(
+        "
+ALTER TABLE file_diffs ADD COLUMN user_message_id TEXT REFERENCES user_message(id) ON DELETE CASCADE;
+",
+    ),
+    M::up(
+        "
+ALTER TABLE user_message ADD COLUMN harness_message_id TEXT;
 ",
     ),
 ];
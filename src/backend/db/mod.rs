@@ -2,10 +2,16 @@ use thiserror::Error;
 use tokio_rusqlite::Connection;
 use uuid::Uuid;
 
+pub use revision_table::MessageChanges;
+
 use crate::backend::{
-    db::migrations::SQLITE_MIGRATIONS,
+    db::{migrations::SQLITE_MIGRATIONS, revision_table::RevisionedTable},
+    models::file_diff_model::{DiffSummary, FileDiffModel},
+    models::message_defaults_model::MessageDefaultsModel,
+    models::permission_rule_model::PermissionRuleModel,
     models::project_model::ProjectModel,
     models::session_model::SessionModel,
+    models::session_status_model::SessionStatusModel,
     repo::{
         assistant_message::{AssistantMessage, AssistantMessagePart},
         message::Message,
@@ -16,9 +22,12 @@ use crate::backend::{
 
 mod assistant_message_part_table;
 mod assistant_message_table;
+mod file_diff_table;
 mod message_table;
 mod migrations;
+mod permission_rule_table;
 mod project_table;
+mod revision_table;
 mod session_table;
 mod user_message_part_table;
 mod user_message_table;
@@ -192,6 +201,16 @@ impl Database {
             .await?)
     }
 
+    pub async fn get_session_by_harness_session_id(
+        &self,
+        harness_session_id: String,
+    ) -> Result<Option<SessionModel>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| session_table::get_by_harness_session_id(conn, &harness_session_id))
+            .await?)
+    }
+
     pub async fn create_session(
         &self,
         session: SessionModel,
@@ -219,6 +238,130 @@ impl Database {
             .await?)
     }
 
+    pub async fn fork_session(
+        &self,
+        child: SessionModel,
+        parent_session_id: Uuid,
+        messages: Vec<Message>,
+    ) -> Result<SessionModel, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| session_table::fork(conn, &child, parent_session_id, &messages))
+            .await?)
+    }
+
+    pub async fn get_session_revert_stash(
+        &self,
+        session_id: Uuid,
+    ) -> Result<Option<String>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| session_table::get_revert_stash(conn, session_id))
+            .await?)
+    }
+
+    pub async fn set_session_revert_stash(
+        &self,
+        session_id: Uuid,
+        stash: Option<String>,
+    ) -> Result<(), DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| session_table::set_revert_stash(conn, session_id, stash.as_deref()))
+            .await?)
+    }
+
+    pub async fn list_file_diffs_by_session(
+        &self,
+        session_id: Uuid,
+    ) -> Result<Vec<FileDiffModel>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| file_diff_table::list_by_session(conn, session_id))
+            .await?)
+    }
+
+    pub async fn replace_message_file_diffs(
+        &self,
+        session_id: Uuid,
+        harness_message_id: String,
+        diffs: Vec<FileDiffModel>,
+    ) -> Result<DiffSummary, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| {
+                file_diff_table::replace_for_message(conn, session_id, &harness_message_id, &diffs)
+            })
+            .await?)
+    }
+
+    pub async fn remove_message_file_diffs(
+        &self,
+        session_id: Uuid,
+        harness_message_id: String,
+    ) -> Result<Option<DiffSummary>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| {
+                file_diff_table::remove_for_message(conn, session_id, &harness_message_id)
+            })
+            .await?)
+    }
+
+    /// Counts the session's diffs again, after turns were reverted or restored
+    pub async fn update_session_diff_summary(
+        &self,
+        session_id: Uuid,
+    ) -> Result<DiffSummary, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| file_diff_table::update_summary(conn, session_id))
+            .await?)
+    }
+
+    pub async fn update_session_defaults(
+        &self,
+        session_id: Uuid,
+        defaults: MessageDefaultsModel,
+    ) -> Result<SessionModel, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| session_table::update_defaults(conn, session_id, &defaults))
+            .await?)
+    }
+
+    pub async fn get_session_status(
+        &self,
+        session_id: Uuid,
+    ) -> Result<Option<SessionStatusModel>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| session_table::get_status(conn, session_id))
+            .await?)
+    }
+
+    pub async fn update_session_status(
+        &self,
+        session_id: Uuid,
+        status: SessionStatusModel,
+    ) -> Result<SessionStatusModel, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| session_table::update_status(conn, session_id, &status))
+            .await?)
+    }
+
+    pub async fn update_session_last_error(
+        &self,
+        session_id: Uuid,
+        error: String,
+    ) -> Result<SessionStatusModel, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| session_table::update_last_error(conn, session_id, &error))
+            .await?)
+    }
+
     pub async fn list_messages_by_session(
         &self,
         session_id: Uuid,
@@ -230,6 +373,29 @@ impl Database {
             .await?)
     }
 
+    pub async fn set_messages_reverted(
+        &self,
+        session_id: Uuid,
+        messages: Vec<Message>,
+        reverted: bool,
+    ) -> Result<(), DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| message_table::set_reverted(conn, session_id, &messages, reverted))
+            .await?)
+    }
+
+    /// Deletes the reverted messages with their parts and returns their ids
+    pub async fn remove_reverted_messages(
+        &self,
+        session_id: Uuid,
+    ) -> Result<Vec<Uuid>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| message_table::remove_reverted(conn, session_id))
+            .await?)
+    }
+
     pub async fn list_user_messages_by_session(
         &self,
         session_id: Uuid,
@@ -241,6 +407,25 @@ impl Database {
             .await?)
     }
 
+    /// Counts every message and part write in the session, starting at 0
+    pub async fn session_revision(&self, session_id: Uuid) -> Result<i64, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| revision_table::current(conn, session_id))
+            .await?)
+    }
+
+    pub async fn list_message_changes_since(
+        &self,
+        session_id: Uuid,
+        since_revision: i64,
+    ) -> Result<MessageChanges, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| revision_table::changes_since(conn, session_id, since_revision))
+            .await?)
+    }
+
     pub async fn get_user_message(
         &self,
         user_message_id: Uuid,
@@ -251,13 +436,54 @@ impl Database {
             .await?)
     }
 
+    /// The prompt the harness knows as `harness_message_id`, claimed by the oldest prompt
+    /// still waiting for its id when the harness reports it for the first time
+    pub async fn claim_user_message_harness_id(
+        &self,
+        session_id: Uuid,
+        harness_message_id: String,
+    ) -> Result<Option<UserMessage>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| {
+                match user_message_table::get_by_harness_id(conn, session_id, &harness_message_id)?
+                {
+                    Some(message) => Ok(Some(message)),
+                    None => {
+                        user_message_table::claim_harness_id(conn, session_id, &harness_message_id)
+                    }
+                }
+            })
+            .await?)
+    }
+
+    pub async fn get_user_message_by_harness_id(
+        &self,
+        session_id: Uuid,
+        harness_message_id: String,
+    ) -> Result<Option<UserMessage>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| {
+                user_message_table::get_by_harness_id(conn, session_id, &harness_message_id)
+            })
+            .await?)
+    }
+
     pub async fn create_user_message(
         &self,
         user_message_item: UserMessage,
     ) -> Result<UserMessage, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| user_message_table::create(conn, &user_message_item))
+            .call(move |conn| {
+                revision_table::write_stamped(
+                    conn,
+                    RevisionedTable::UserMessage,
+                    |conn| user_message_table::create(conn, &user_message_item),
+                    |written| (written.id, written.session_id),
+                )
+            })
             .await?)
     }
 
@@ -267,14 +493,30 @@ impl Database {
     ) -> Result<UserMessage, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| user_message_table::update(conn, &user_message_item))
+            .call(move |conn| {
+                revision_table::write_stamped(
+                    conn,
+                    RevisionedTable::UserMessage,
+                    |conn| user_message_table::update(conn, &user_message_item),
+                    |written| (written.id, written.session_id),
+                )
+            })
             .await?)
     }
 
-    pub async fn delete_user_message(&self, user_message_id: Uuid) -> Result<(), DatabaseError> {
+    /// Deletes the message with its parts and returns the revision of the removal
+    pub async fn remove_user_message(
+        &self,
+        session_id: Uuid,
+        user_message_id: Uuid,
+    ) -> Result<i64, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| user_message_table::delete(conn, user_message_id))
+            .call(move |conn| {
+                revision_table::remove_stamped(conn, user_message_id, session_id, |conn| {
+                    user_message_table::delete(conn, user_message_id)
+                })
+            })
             .await?)
     }
 
@@ -288,13 +530,30 @@ impl Database {
             .await?)
     }
 
+    pub async fn list_user_message_parts_by_session(
+        &self,
+        session_id: Uuid,
+    ) -> Result<Vec<UserMessagePart>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| user_message_part_table::list_by_session(conn, session_id))
+            .await?)
+    }
+
     pub async fn create_user_message_part(
         &self,
         part: UserMessagePart,
     ) -> Result<UserMessagePart, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| user_message_part_table::create(conn, &part))
+            .call(move |conn| {
+                revision_table::write_stamped(
+                    conn,
+                    RevisionedTable::UserMessagePart,
+                    |conn| user_message_part_table::create(conn, &part),
+                    |written| (written.id, written.session_id),
+                )
+            })
             .await?)
     }
 
@@ -304,7 +563,14 @@ impl Database {
     ) -> Result<UserMessagePart, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| user_message_part_table::update(conn, &part))
+            .call(move |conn| {
+                revision_table::write_stamped(
+                    conn,
+                    RevisionedTable::UserMessagePart,
+                    |conn| user_message_part_table::update(conn, &part),
+                    |written| (written.id, written.session_id),
+                )
+            })
             .await?)
     }
 
@@ -344,7 +610,14 @@ impl Database {
     ) -> Result<AssistantMessage, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| assistant_message_table::create(conn, &assistant_message_item))
+            .call(move |conn| {
+                revision_table::write_stamped(
+                    conn,
+                    RevisionedTable::AssistantMessage,
+                    |conn| assistant_message_table::create(conn, &assistant_message_item),
+                    |written| (written.id, written.session_id),
+                )
+            })
             .await?)
     }
 
@@ -354,7 +627,14 @@ impl Database {
     ) -> Result<AssistantMessage, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| assistant_message_table::update(conn, &assistant_message_item))
+            .call(move |conn| {
+                revision_table::write_stamped(
+                    conn,
+                    RevisionedTable::AssistantMessage,
+                    |conn| assistant_message_table::update(conn, &assistant_message_item),
+                    |written| (written.id, written.session_id),
+                )
+            })
             .await?)
     }
 
@@ -368,6 +648,22 @@ impl Database {
             .await?)
     }
 
+    /// Deletes the message with its parts and returns the revision of the removal
+    pub async fn remove_assistant_message(
+        &self,
+        session_id: Uuid,
+        assistant_message_id: Uuid,
+    ) -> Result<i64, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| {
+                revision_table::remove_stamped(conn, assistant_message_id, session_id, |conn| {
+                    assistant_message_table::delete(conn, assistant_message_id)
+                })
+            })
+            .await?)
+    }
+
     pub async fn get_assistant_message_part(
         &self,
         part_id: Uuid,
@@ -395,13 +691,42 @@ impl Database {
             .await?)
     }
 
+    pub async fn list_assistant_message_parts_by_session(
+        &self,
+        session_id: Uuid,
+    ) -> Result<Vec<AssistantMessagePart>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| assistant_message_part_table::list_by_session(conn, session_id))
+            .await?)
+    }
+
+    pub async fn next_assistant_message_part_position(
+        &self,
+        assistant_message_id: Uuid,
+    ) -> Result<i64, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| {
+                assistant_message_part_table::next_position(conn, assistant_message_id)
+            })
+            .await?)
+    }
+
     pub async fn create_assistant_message_part(
         &self,
         part: AssistantMessagePart,
     ) -> Result<AssistantMessagePart, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| assistant_message_part_table::create(conn, &part))
+            .call(move |conn| {
+                revision_table::write_stamped(
+                    conn,
+                    RevisionedTable::AssistantMessagePart,
+                    |conn| assistant_message_part_table::create(conn, &part),
+                    |written| (written.id, written.session_id),
+                )
+            })
             .await?)
     }
 
@@ -411,7 +736,33 @@ impl Database {
     ) -> Result<AssistantMessagePart, DatabaseError> {
         Ok(self
             .conn
-            .call(move |conn| assistant_message_part_table::update(conn, &part))
+            .call(move |conn| {
+                revision_table::write_stamped(
+                    conn,
+                    RevisionedTable::AssistantMessagePart,
+                    |conn| assistant_message_part_table::update(conn, &part),
+                    |written| (written.id, written.session_id),
+                )
+            })
+            .await?)
+    }
+
+    /// Updates the parts in one transaction, a failed write leaves all of them untouched
+    pub async fn update_assistant_message_parts(
+        &self,
+        parts: Vec<AssistantMessagePart>,
+    ) -> Result<Vec<AssistantMessagePart>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| {
+                revision_table::write_all_stamped(
+                    conn,
+                    RevisionedTable::AssistantMessagePart,
+                    parts,
+                    |conn, part| assistant_message_part_table::update(conn, &part),
+                    |written| (written.id, written.session_id),
+                )
+            })
             .await?)
     }
 
@@ -421,4 +772,25 @@ impl Database {
             .call(move |conn| assistant_message_part_table::delete(conn, part_id))
             .await?)
     }
+
+    pub async fn get_permission_rule(
+        &self,
+        project_id: Uuid,
+        kind: String,
+    ) -> Result<Option<PermissionRuleModel>, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| permission_rule_table::get_by_kind(conn, project_id, &kind))
+            .await?)
+    }
+
+    pub async fn upsert_permission_rule(
+        &self,
+        rule: PermissionRuleModel,
+    ) -> Result<PermissionRuleModel, DatabaseError> {
+        Ok(self
+            .conn
+            .call(move |conn| permission_rule_table::upsert(conn, &rule))
+            .await?)
+    }
 }
@@ -0,0 +1,40 @@
+use serde_rusqlite::{from_rows, to_params_named};
+use tokio_rusqlite::named_params;
+use tokio_rusqlite::rusqlite::Connection;
+use uuid::Uuid;
+
+use crate::backend::db::DatabaseError;
+use crate::backend::models::permission_rule_model::PermissionRuleModel;
+
+pub fn get_by_kind(
+    conn: &Connection,
+    project_id: Uuid,
+    kind: &str,
+) -> Result<Option<PermissionRuleModel>, DatabaseError> {
+    let mut stmt = conn.prepare(
+        "SELECT * FROM permission_rules WHERE project_id = :project_id AND kind = :kind",
+    )?;
+    let mut rows = from_rows::<PermissionRuleModel>(
+        stmt.query(named_params! {":project_id": project_id.to_string(), ":kind": kind})?,
+    );
+    Ok(rows.next().transpose()?)
+}
+
+/// Inserts the rule, or replaces the action of the existing rule for the same kind
+pub fn upsert(
+    conn: &Connection,
+    rule: &PermissionRuleModel,
+) -> Result<PermissionRuleModel, DatabaseError> {
+    let params = to_params_named(rule)?;
+    let mut stmt = conn.prepare(
+        "
+        INSERT INTO permission_rules (id, project_id, kind, action, created_at, updated_at)
+        VALUES (:id, :project_id, :kind, :action, :created_at, :updated_at)
+        ON CONFLICT(project_id, kind) DO UPDATE
+        SET action = excluded.action, updated_at = excluded.updated_at
+        RETURNING *
+    ",
+    )?;
+    let rows = from_rows::<PermissionRuleModel>(stmt.query(params.to_slice().as_slice())?);
+    super::expect_one_returned_row("upsert_permission_rule", rows)
+}
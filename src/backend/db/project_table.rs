@@ -23,8 +23,14 @@ pub fn create(conn: &Connection, project: &ProjectModel) -> Result<ProjectModel,
     let params = to_params_named(project)?;
     let mut stmt = conn.prepare(
         "
-        INSERT INTO projects (id, name, dir, created_at, updated_at)
-        VALUES (:id, :name, :dir, :created_at, :updated_at)
+        INSERT INTO projects (
+            id, name, dir, default_agent, default_model_provider_id, default_model_id,
+            default_thinking_variant, created_at, updated_at
+        )
+        VALUES (
+            :id, :name, :dir, :default_agent, :default_model_provider_id, :default_model_id,
+            :default_thinking_variant, :created_at, :updated_at
+        )
         RETURNING *
     ",
     )?;
@@ -36,11 +42,30 @@ pub fn update(conn: &Connection, project: &ProjectModel) -> Result<ProjectModel,
     let mut updated = project.clone();
     updated.updated_at = chrono::Utc::now().naive_utc();
 
-    let params = to_params_named_with_fields(&updated, &["id", "name", "dir", "updated_at"])?;
+    let params = to_params_named_with_fields(
+        &updated,
+        &[
+            "id",
+            "name",
+            "dir",
+            "default_agent",
+            "default_model_provider_id",
+            "default_model_id",
+            "default_thinking_variant",
+            "updated_at",
+        ],
+    )?;
     let mut stmt = conn.prepare(
         "
         UPDATE projects
-        SET name = :name, dir = :dir, updated_at = :updated_at
+        SET
+            name = :name,
+            dir = :dir,
+            default_agent = :default_agent,
+            default_model_provider_id = :default_model_provider_id,
+            default_model_id = :default_model_id,
+            default_thinking_variant = :default_thinking_variant,
+            updated_at = :updated_at
         WHERE id = :id
         RETURNING *
     ",
@@ -0,0 +1,193 @@
+use std::collections::HashSet;
+
+use serde::Deserialize;
+use serde_rusqlite::from_rows;
+use tokio_rusqlite::named_params;
+use tokio_rusqlite::rusqlite::Connection;
+use uuid::Uuid;
+
+use crate::backend::db::DatabaseError;
+
+/// Tables whose rows carry the session revision of their last write
+#[derive(Debug, Clone, Copy)]
+pub enum RevisionedTable {
+    UserMessage,
+    UserMessagePart,
+    AssistantMessage,
+    AssistantMessagePart,
+}
+
+impl RevisionedTable {
+    pub(super) fn name(self) -> &'static str {
+        match self {
+            Self::UserMessage => "user_message",
+            Self::UserMessagePart => "user_message_part",
+            Self::AssistantMessage => "assistant_message",
+            Self::AssistantMessagePart => "assistant_message_part",
+        }
+    }
+}
+
+/// Ids of the rows written after a revision, with the session's revision at the time
+#[derive(Debug, Default)]
+pub struct MessageChanges {
+    pub revision: i64,
+    pub user_messages: HashSet<Uuid>,
+    pub user_message_parts: HashSet<Uuid>,
+    pub assistant_messages: HashSet<Uuid>,
+    pub assistant_message_parts: HashSet<Uuid>,
+    /// Messages deleted after the revision, their parts went with them
+    pub removed_messages: HashSet<Uuid>,
+}
+
+#[derive(Deserialize)]
+struct ChangedRow {
+    id: Uuid,
+}
+
+/// Runs a message write and stamps the written row with the session's next revision,
+/// both in one transaction so a revision never points at a half applied write
+pub fn write_stamped<T>(
+    conn: &mut Connection,
+    table: RevisionedTable,
+    write: impl FnOnce(&Connection) -> Result<T, DatabaseError>,
+    row: impl FnOnce(&T) -> (Uuid, Uuid),
+) -> Result<T, DatabaseError> {
+    let tx = conn.transaction()?;
+    let written = write(&tx)?;
+    let (id, session_id) = row(&written);
+    stamp(&tx, table, id, session_id)?;
+    tx.commit()?;
+    Ok(written)
+}
+
+/// `write_stamped` for many rows in one transaction, either every write lands or none does
+pub fn write_all_stamped<I, T>(
+    conn: &mut Connection,
+    table: RevisionedTable,
+    items: impl IntoIterator<Item = I>,
+    mut write: impl FnMut(&Connection, I) -> Result<T, DatabaseError>,
+    row: impl Fn(&T) -> (Uuid, Uuid),
+) -> Result<Vec<T>, DatabaseError> {
+    let tx = conn.transaction()?;
+    let mut written = Vec::new();
+    for item in items {
+        let item = write(&tx, item)?;
+        let (id, session_id) = row(&item);
+        stamp(&tx, table, id, session_id)?;
+        written.push(item);
+    }
+    tx.commit()?;
+    Ok(written)
+}
+
+/// Deletes a message and leaves a tombstone at the session's next revision,
+/// so subscribers resuming from an earlier revision still learn it is gone
+pub fn remove_stamped(
+    conn: &mut Connection,
+    id: Uuid,
+    session_id: Uuid,
+    delete: impl FnOnce(&Connection) -> Result<(), DatabaseError>,
+) -> Result<i64, DatabaseError> {
+    let tx = conn.transaction()?;
+    delete(&tx)?;
+    let revision = next_revision(&tx, session_id)?;
+    tx.execute(
+        "INSERT INTO removed_message (id, session_id, revision)
+         VALUES (:id, :session_id, :revision)
+         ON CONFLICT(id) DO UPDATE SET revision = excluded.revision",
+        named_params! {
+            ":id": id.to_string(),
+            ":session_id": session_id.to_string(),
+            ":revision": revision,
+        },
+    )?;
+    tx.commit()?;
+    Ok(revision)
+}
+
+fn next_revision(conn: &Connection, session_id: Uuid) -> Result<i64, DatabaseError> {
+    Ok(conn.query_row(
+        "UPDATE sessions SET revision = revision + 1 WHERE id = :session_id RETURNING revision",
+        named_params! {":session_id": session_id.to_string()},
+        |row| row.get(0),
+    )?)
+}
+
+pub(super) fn stamp(
+    conn: &Connection,
+    table: RevisionedTable,
+    id: Uuid,
+    session_id: Uuid,
+) -> Result<i64, DatabaseError> {
+    let revision = next_revision(conn, session_id)?;
+    let rows_affected = conn.execute(
+        &format!(
+            "UPDATE {} SET revision = :revision WHERE id = :id",
+            table.name()
+        ),
+        named_params! {":revision": revision, ":id": id.to_string()},
+    )?;
+    super::assert_one_row_affected("stamp_revision", rows_affected)?;
+    Ok(revision)
+}
+
+pub fn current(conn: &Connection, session_id: Uuid) -> Result<i64, DatabaseError> {
+    Ok(conn.query_row(
+        "SELECT COALESCE(MAX(revision), 0) FROM sessions WHERE id = :session_id",
+        named_params! {":session_id": session_id.to_string()},
+        |row| row.get(0),
+    )?)
+}
+
+pub fn changes_since(
+    conn: &Connection,
+    session_id: Uuid,
+    since_revision: i64,
+) -> Result<MessageChanges, DatabaseError> {
+    Ok(MessageChanges {
+        revision: current(conn, session_id)?,
+        user_messages: changed_ids(
+            conn,
+            RevisionedTable::UserMessage.name(),
+            session_id,
+            since_revision,
+        )?,
+        user_message_parts: changed_ids(
+            conn,
+            RevisionedTable::UserMessagePart.name(),
+            session_id,
+            since_revision,
+        )?,
+        assistant_messages: changed_ids(
+            conn,
+            RevisionedTable::AssistantMessage.name(),
+            session_id,
+            since_revision,
+        )?,
+        assistant_message_parts: changed_ids(
+            conn,
+            RevisionedTable::AssistantMessagePart.name(),
+            session_id,
+            since_revision,
+        )?,
+        removed_messages: changed_ids(conn, "removed_message", session_id, since_revision)?,
+    })
+}
+
+fn changed_ids(
+    conn: &Connection,
+    table: &str,
+    session_id: Uuid,
+    since_revision: i64,
+) -> Result<HashSet<Uuid>, DatabaseError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT id FROM {table} WHERE session_id = :session_id AND revision > :since_revision"
+    ))?;
+    let rows = from_rows::<ChangedRow>(stmt.query(named_params! {
+        ":session_id": session_id.to_string(),
+        ":since_revision": since_revision,
+    })?);
+    rows.map(|row| row.map(|row| row.id).map_err(Into::into))
+        .collect()
+}
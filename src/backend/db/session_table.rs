@@ -3,10 +3,21 @@ use tokio_rusqlite::named_params;
 use tokio_rusqlite::rusqlite::Connection;
 use uuid::Uuid;
 
-use crate::backend::db::DatabaseError;
+use std::collections::HashMap;
+
+use crate::backend::db::{
+    DatabaseError, assistant_message_part_table, assistant_message_table, user_message_part_table,
+    user_message_table,
+};
+use crate::backend::models::message_defaults_model::MessageDefaultsModel;
 use crate::backend::models::session_model::SessionModel;
+use crate::backend::models::session_status_model::SessionStatusModel;
+use crate::backend::repo::message::Message;
+
+const SESSION_STATUS_COLUMNS: &str =
+    "status, status_attempt, status_message, status_next, last_error";
 
-const SESSION_COLUMNS: &str = "\nid, project_id, parent_session_id, show_in_gui, name, harness_type, harness_session_id,\ndir, summary_additions, summary_deletions, summary_files, created_at, updated_at\n";
+const SESSION_COLUMNS: &str = "\nid, project_id, parent_session_id, show_in_gui, name, harness_type, harness_session_id,\ndir, summary_additions, summary_deletions, summary_files, default_agent, default_model_provider_id,\ndefault_model_id, default_thinking_variant, created_at, updated_at\n";
 
 pub fn list_by_project(
     conn: &Connection,
@@ -31,6 +42,18 @@ pub fn get(conn: &Connection, session_id: Uuid) -> Result<Option<SessionModel>,
     Ok(rows.next().transpose()?)
 }
 
+pub fn get_by_harness_session_id(
+    conn: &Connection,
+    harness_session_id: &str,
+) -> Result<Option<SessionModel>, DatabaseError> {
+    let mut stmt =
+        conn.prepare("SELECT * FROM sessions WHERE harness_session_id = :harness_session_id")?;
+    let mut rows = from_rows::<SessionModel>(
+        stmt.query(named_params! {":harness_session_id": harness_session_id})?,
+    );
+    Ok(rows.next().transpose()?)
+}
+
 pub fn create(conn: &Connection, session: &SessionModel) -> Result<SessionModel, DatabaseError> {
     let params = to_params_named(session)?;
     let mut stmt = conn.prepare(
@@ -38,7 +61,9 @@ pub fn create(conn: &Connection, session: &SessionModel) -> Result<SessionModel,
         INSERT INTO sessions ({SESSION_COLUMNS})
         VALUES (
             :id, :project_id, :parent_session_id, :show_in_gui, :name, :harness_type, :harness_session_id,
-            :dir, :summary_additions, :summary_deletions, :summary_files, :created_at, :updated_at
+            :dir, :summary_additions, :summary_deletions, :summary_files, :default_agent,
+            :default_model_provider_id, :default_model_id, :default_thinking_variant,
+            :created_at, :updated_at
         )
         RETURNING *
     "),
@@ -65,6 +90,10 @@ pub fn update(conn: &Connection, session: &SessionModel) -> Result<SessionModel,
             "summary_additions",
             "summary_deletions",
             "summary_files",
+            "default_agent",
+            "default_model_provider_id",
+            "default_model_id",
+            "default_thinking_variant",
             "updated_at",
         ],
     )?;
@@ -82,6 +111,10 @@ pub fn update(conn: &Connection, session: &SessionModel) -> Result<SessionModel,
             summary_additions = :summary_additions,
             summary_deletions = :summary_deletions,
             summary_files = :summary_files,
+            default_agent = :default_agent,
+            default_model_provider_id = :default_model_provider_id,
+            default_model_id = :default_model_id,
+            default_thinking_variant = :default_thinking_variant,
             updated_at = :updated_at
         WHERE id = :id
         RETURNING *
@@ -98,3 +131,197 @@ pub fn delete(conn: &Connection, session_id: Uuid) -> Result<(), DatabaseError>
     )?;
     super::assert_one_row_affected("delete_session", rows)
 }
+
+/// Stash holding the workspace as it was before a revert, for harnesses without snapshots
+pub fn get_revert_stash(
+    conn: &Connection,
+    session_id: Uuid,
+) -> Result<Option<String>, DatabaseError> {
+    Ok(conn.query_row(
+        "SELECT revert_stash FROM sessions WHERE id = :id",
+        named_params! {":id": session_id.to_string()},
+        |row| row.get(0),
+    )?)
+}
+
+pub fn set_revert_stash(
+    conn: &Connection,
+    session_id: Uuid,
+    stash: Option<&str>,
+) -> Result<(), DatabaseError> {
+    let rows = conn.execute(
+        "UPDATE sessions SET revert_stash = :revert_stash WHERE id = :id",
+        named_params! {":revert_stash": stash, ":id": session_id.to_string()},
+    )?;
+    super::assert_one_row_affected("set_session_revert_stash", rows)
+}
+
+/// Creates the forked session with copies of `messages` from its parent and their parts,
+/// in one transaction. Copies get new ids and no harness ids, the harness numbers its fork itself
+pub fn fork(
+    conn: &mut Connection,
+    child: &SessionModel,
+    parent_session_id: Uuid,
+    messages: &[Message],
+) -> Result<SessionModel, DatabaseError> {
+    let tx = conn.transaction()?;
+    let created = create(&tx, child)?;
+    let user_parts = user_message_part_table::list_by_session(&tx, parent_session_id)?;
+    let assistant_parts = assistant_message_part_table::list_by_session(&tx, parent_session_id)?;
+
+    let mut user_message_ids = HashMap::new();
+    for message in messages {
+        match message {
+            Message::User(user_message) => {
+                let mut copy = user_message.clone();
+                copy.id = Uuid::new_v4();
+                copy.session_id = created.id;
+                user_message_table::create(&tx, &copy)?;
+                user_message_ids.insert(user_message.id, copy.id);
+
+                for part in user_parts
+                    .iter()
+                    .filter(|part| part.user_message_id == user_message.id)
+                {
+                    let mut part = part.clone();
+                    part.id = Uuid::new_v4();
+                    part.user_message_id = copy.id;
+                    part.session_id = created.id;
+                    user_message_part_table::create(&tx, &part)?;
+                }
+            }
+            Message::Assistant(assistant_message) => {
+                let mut copy = assistant_message.clone();
+                copy.id = Uuid::new_v4();
+                copy.session_id = created.id;
+                copy.harness_message_id = None;
+                if let Some(user_message_id) = user_message_ids.get(&copy.user_message_id) {
+                    copy.user_message_id = *user_message_id;
+                }
+                assistant_message_table::create(&tx, &copy)?;
+
+                for part in assistant_parts
+                    .iter()
+                    .filter(|part| part.assistant_message_id == assistant_message.id)
+                {
+                    let mut part = part.clone();
+                    part.id = Uuid::new_v4();
+                    part.harness_part_id = None;
+                    part.assistant_message_id = copy.id;
+                    part.session_id = created.id;
+                    assistant_message_part_table::create(&tx, &part)?;
+                }
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(created)
+}
+
+/// Stores the defaults on the session and on its project, so the next session starts from them.
+/// Missing agent and model keep the stored ones, the thinking variant is taken as given
+pub fn update_defaults(
+    conn: &mut Connection,
+    session_id: Uuid,
+    defaults: &MessageDefaultsModel,
+) -> Result<SessionModel, DatabaseError> {
+    let tx = conn.transaction()?;
+    let session = {
+        let mut stmt = tx.prepare(
+            "
+            UPDATE sessions
+            SET
+                default_agent = COALESCE(:default_agent, default_agent),
+                default_model_provider_id = COALESCE(:default_model_provider_id, default_model_provider_id),
+                default_model_id = COALESCE(:default_model_id, default_model_id),
+                default_thinking_variant = :default_thinking_variant
+            WHERE id = :id
+            RETURNING *
+        ",
+        )?;
+        let rows = from_rows::<SessionModel>(stmt.query(named_params! {
+            ":id": session_id.to_string(),
+            ":default_agent": defaults.default_agent,
+            ":default_model_provider_id": defaults.default_model_provider_id,
+            ":default_model_id": defaults.default_model_id,
+            ":default_thinking_variant": defaults.default_thinking_variant,
+        })?);
+        super::expect_one_returned_row("update_session_defaults", rows)?
+    };
+    tx.execute(
+        "
+        UPDATE projects
+        SET
+            default_agent = COALESCE(:default_agent, default_agent),
+            default_model_provider_id = COALESCE(:default_model_provider_id, default_model_provider_id),
+            default_model_id = COALESCE(:default_model_id, default_model_id),
+            default_thinking_variant = :default_thinking_variant
+        WHERE id = :project_id
+    ",
+        named_params! {
+            ":project_id": session.project_id.to_string(),
+            ":default_agent": defaults.default_agent,
+            ":default_model_provider_id": defaults.default_model_provider_id,
+            ":default_model_id": defaults.default_model_id,
+            ":default_thinking_variant": defaults.default_thinking_variant,
+        },
+    )?;
+    tx.commit()?;
+    Ok(session)
+}
+
+pub fn get_status(
+    conn: &Connection,
+    session_id: Uuid,
+) -> Result<Option<SessionStatusModel>, DatabaseError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {SESSION_STATUS_COLUMNS} FROM sessions WHERE id = :id"
+    ))?;
+    let mut rows =
+        from_rows::<SessionStatusModel>(stmt.query(named_params! {":id": session_id.to_string()})?);
+    Ok(rows.next().transpose()?)
+}
+
+/// Stores the reported status, a busy session starts over without the last error
+pub fn update_status(
+    conn: &Connection,
+    session_id: Uuid,
+    status: &SessionStatusModel,
+) -> Result<SessionStatusModel, DatabaseError> {
+    let mut stmt = conn.prepare(&format!(
+        "
+        UPDATE sessions
+        SET
+            status = :status,
+            status_attempt = :status_attempt,
+            status_message = :status_message,
+            status_next = :status_next,
+            last_error = CASE WHEN :status = 'busy' THEN NULL ELSE last_error END
+        WHERE id = :id
+        RETURNING {SESSION_STATUS_COLUMNS}
+    "
+    ))?;
+    let rows = from_rows::<SessionStatusModel>(stmt.query(named_params! {
+        ":id": session_id.to_string(),
+        ":status": status.status,
+        ":status_attempt": status.status_attempt,
+        ":status_message": status.status_message,
+        ":status_next": status.status_next,
+    })?);
+    super::expect_one_returned_row("update_session_status", rows)
+}
+
+pub fn update_last_error(
+    conn: &Connection,
+    session_id: Uuid,
+    error: &str,
+) -> Result<SessionStatusModel, DatabaseError> {
+    let mut stmt = conn.prepare(&format!(
+        "UPDATE sessions SET last_error = :error WHERE id = :id RETURNING {SESSION_STATUS_COLUMNS}"
+    ))?;
+    let rows = from_rows::<SessionStatusModel>(stmt.query(named_params! {
+        ":id": session_id.to_string(),
+        ":error": error,
+    })?);
+    super::expect_one_returned_row("update_session_last_error", rows)
+}
@@ -7,8 +7,9 @@ use crate::backend::{db::DatabaseError, repo::user_message_part::UserMessagePart
 
 const USER_MESSAGE_PART_COLUMNS: &str = "
 id, user_message_id, session_id, position, part_type,
-text, file_name, file_url, agent_name, subtask_prompt, subtask_description,
-created_at, updated_at
+text, file_name, file_url, file_mime, agent_name, agent_source_value, agent_source_start,
+agent_source_end, file_source_path, file_source_text_value, file_source_text_start,
+file_source_text_end, subtask_prompt, subtask_description, created_at, updated_at
 ";
 
 pub fn get(conn: &Connection, part_id: Uuid) -> Result<Option<UserMessagePart>, DatabaseError> {
@@ -23,14 +24,32 @@ pub fn get(conn: &Connection, part_id: Uuid) -> Result<Option<UserMessagePart>,
     Ok(rows.next().transpose()?)
 }
 
+pub fn list_by_session(
+    conn: &Connection,
+    session_id: Uuid,
+) -> Result<Vec<UserMessagePart>, DatabaseError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {USER_MESSAGE_PART_COLUMNS}
+         FROM user_message_part
+         WHERE session_id = :session_id
+         ORDER BY user_message_id, position"
+    ))?;
+    let rows = from_rows::<UserMessagePart>(stmt.query(named_params! {
+        ":session_id": session_id.to_string(),
+    })?);
+    Ok(rows.collect::<Result<Vec<_>, _>>()?)
+}
+
 pub fn create(conn: &Connection, part: &UserMessagePart) -> Result<UserMessagePart, DatabaseError> {
     let params = to_params_named(part)?;
     let mut stmt = conn.prepare(&format!(
         "INSERT INTO user_message_part ({USER_MESSAGE_PART_COLUMNS})
          VALUES (
              :id, :user_message_id, :session_id, :position, :part_type,
-             :text, :file_name, :file_url, :agent_name, :subtask_prompt, :subtask_description,
-             :created_at, :updated_at
+             :text, :file_name, :file_url, :file_mime, :agent_name, :agent_source_value,
+             :agent_source_start, :agent_source_end, :file_source_path, :file_source_text_value,
+             :file_source_text_start, :file_source_text_end, :subtask_prompt,
+             :subtask_description, :created_at, :updated_at
          )
          RETURNING *"
     ))?;
@@ -53,7 +72,15 @@ pub fn update(conn: &Connection, part: &UserMessagePart) -> Result<UserMessagePa
             "text",
             "file_name",
             "file_url",
+            "file_mime",
             "agent_name",
+            "agent_source_value",
+            "agent_source_start",
+            "agent_source_end",
+            "file_source_path",
+            "file_source_text_value",
+            "file_source_text_start",
+            "file_source_text_end",
             "subtask_prompt",
             "subtask_description",
             "updated_at",
@@ -69,7 +96,15 @@ pub fn update(conn: &Connection, part: &UserMessagePart) -> Result<UserMessagePa
             text = :text,
             file_name = :file_name,
             file_url = :file_url,
+            file_mime = :file_mime,
             agent_name = :agent_name,
+            agent_source_value = :agent_source_value,
+            agent_source_start = :agent_source_start,
+            agent_source_end = :agent_source_end,
+            file_source_path = :file_source_path,
+            file_source_text_value = :file_source_text_value,
+            file_source_text_start = :file_source_text_start,
+            file_source_text_end = :file_source_text_end,
             subtask_prompt = :subtask_prompt,
             subtask_description = :subtask_description,
             updated_at = :updated_at
@@ -7,7 +7,8 @@ use crate::backend::{db::DatabaseError, repo::user_message::UserMessage};
 
 pub const USER_MESSAGE_COLUMNS: &str = "
 id, session_id, agent, model_provider_id, model_id, system_prompt,
-structured_output_type, tools_list, thinking_variant, created_at, updated_at
+structured_output_type, tools_list, thinking_variant, harness_message_id, reverted, created_at,
+updated_at
 ";
 
 pub fn get(conn: &Connection, user_message_id: Uuid) -> Result<Option<UserMessage>, DatabaseError> {
@@ -48,13 +49,64 @@ pub fn list_by_session(
     Ok(rows)
 }
 
+pub fn get_by_harness_id(
+    conn: &Connection,
+    session_id: Uuid,
+    harness_message_id: &str,
+) -> Result<Option<UserMessage>, DatabaseError> {
+    let mut stmt = conn.prepare(&format!(
+        "SELECT {USER_MESSAGE_COLUMNS}
+         FROM user_message
+         WHERE session_id = :session_id AND harness_message_id = :harness_message_id"
+    ))?;
+    let mut rows = from_rows::<UserMessage>(stmt.query(named_params! {
+        ":session_id": session_id.to_string(),
+        ":harness_message_id": harness_message_id,
+    })?);
+    Ok(rows.next().transpose()?)
+}
+
+/// Gives `harness_message_id` to the oldest prompt still waiting for one, harnesses store
+/// prompts in the order they were sent. Prompts that got a reply were seen before ids were
+/// recorded and wait for none
+pub fn claim_harness_id(
+    conn: &Connection,
+    session_id: Uuid,
+    harness_message_id: &str,
+) -> Result<Option<UserMessage>, DatabaseError> {
+    let mut stmt = conn.prepare(&format!(
+        "UPDATE user_message
+         SET harness_message_id = :harness_message_id
+         WHERE id = (
+             SELECT id
+             FROM user_message
+             WHERE session_id = :session_id
+               AND harness_message_id IS NULL
+               AND reverted = 0
+               AND NOT EXISTS (
+                   SELECT 1 FROM assistant_message
+                   WHERE assistant_message.user_message_id = user_message.id
+               )
+             ORDER BY created_at ASC
+             LIMIT 1
+         )
+         RETURNING {USER_MESSAGE_COLUMNS}"
+    ))?;
+    let mut rows = from_rows::<UserMessage>(stmt.query(named_params! {
+        ":session_id": session_id.to_string(),
+        ":harness_message_id": harness_message_id,
+    })?);
+    Ok(rows.next().transpose()?)
+}
+
 pub fn create(conn: &Connection, user_message: &UserMessage) -> Result<UserMessage, DatabaseError> {
     let params = to_params_named(user_message)?;
     let mut stmt = conn.prepare(&format!(
         "INSERT INTO user_message ({USER_MESSAGE_COLUMNS})
          VALUES (
              :id, :session_id, :agent, :model_provider_id, :model_id, :system_prompt,
-             :structured_output_type, :tools_list, :thinking_variant, :created_at, :updated_at
+             :structured_output_type, :tools_list, :thinking_variant, :harness_message_id,
+             :reverted, :created_at, :updated_at
          )
          RETURNING *"
     ))?;
@@ -0,0 +1,96 @@
+/// Most cells the line table of one diff may have, bigger changes show as replaced whole
+const MAX_TABLE_CELLS: usize = 4_000_000;
+
+/// One line of a diff between two versions of a file
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DiffLine<'a> {
+    Same(&'a str),
+    Removed(&'a str),
+    Added(&'a str),
+}
+
+/// Lines of `after` against `before`, with the fewest lines removed and added
+pub fn diff_lines<'a>(before: &'a str, after: &'a str) -> Vec<DiffLine<'a>> {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+    let prefix = before
+        .iter()
+        .zip(&after)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+
+    let mut lines: Vec<DiffLine> = before[..prefix]
+        .iter()
+        .map(|line| DiffLine::Same(line))
+        .collect();
+    lines.extend(changed_middle(
+        &before[prefix..before.len() - suffix],
+        &after[prefix..after.len() - suffix],
+    ));
+    lines.extend(
+        before[before.len() - suffix..]
+            .iter()
+            .map(|line| DiffLine::Same(line)),
+    );
+    lines
+}
+
+/// Lines added and removed between the two versions
+pub fn count_changes(before: &str, after: &str) -> (i64, i64) {
+    diff_lines(before, after)
+        .iter()
+        .fold((0, 0), |(additions, deletions), line| match line {
+            DiffLine::Same(_) => (additions, deletions),
+            DiffLine::Removed(_) => (additions, deletions + 1),
+            DiffLine::Added(_) => (additions + 1, deletions),
+        })
+}
+
+/// Longest common subsequence of the lines between the common prefix and suffix
+fn changed_middle<'a>(before: &[&'a str], after: &[&'a str]) -> Vec<DiffLine<'a>> {
+    if before.len().saturating_mul(after.len()) > MAX_TABLE_CELLS {
+        return before
+            .iter()
+            .map(|line| DiffLine::Removed(line))
+            .chain(after.iter().map(|line| DiffLine::Added(line)))
+            .collect();
+    }
+
+    // common[i][j] is the longest common subsequence of before[i..] and after[j..]
+    let width = after.len() + 1;
+    let mut common = vec![0_u32; (before.len() + 1) * width];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i * width + j] = if before[i] == after[j] {
+                common[(i + 1) * width + j + 1] + 1
+            } else {
+                common[(i + 1) * width + j].max(common[i * width + j + 1])
+            };
+        }
+    }
+
+    let (mut i, mut j) = (0, 0);
+    let mut lines = Vec::with_capacity(before.len() + after.len());
+    while i < before.len() && j < after.len() {
+        if before[i] == after[j] {
+            lines.push(DiffLine::Same(before[i]));
+            i += 1;
+            j += 1;
+        } else if common[(i + 1) * width + j] >= common[i * width + j + 1] {
+            lines.push(DiffLine::Removed(before[i]));
+            i += 1;
+        } else {
+            lines.push(DiffLine::Added(after[j]));
+            j += 1;
+        }
+    }
+    lines.extend(before[i..].iter().map(|line| DiffLine::Removed(line)));
+    lines.extend(after[j..].iter().map(|line| DiffLine::Added(line)));
+    lines
+}
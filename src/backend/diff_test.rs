@@ -0,0 +1,41 @@
+use crate::backend::diff::{DiffLine, count_changes, diff_lines};
+
+#[test]
+fn unchanged_lines_around_an_edit_stay() {
+    let lines = diff_lines("a\nb\nc\nd\n", "a\nx\nc\nd\ne\n");
+
+    assert_eq!(
+        lines,
+        [
+            DiffLine::Same("a"),
+            DiffLine::Removed("b"),
+            DiffLine::Added("x"),
+            DiffLine::Same("c"),
+            DiffLine::Same("d"),
+            DiffLine::Added("e"),
+        ]
+    );
+    assert_eq!(count_changes("a\nb\nc\nd\n", "a\nx\nc\nd\ne\n"), (2, 1));
+}
+
+#[test]
+fn moved_lines_keep_the_longest_common_run() {
+    let lines = diff_lines("a\nb\nc\n", "b\nc\na\n");
+
+    assert_eq!(
+        lines,
+        [
+            DiffLine::Removed("a"),
+            DiffLine::Same("b"),
+            DiffLine::Same("c"),
+            DiffLine::Added("a"),
+        ]
+    );
+}
+
+#[test]
+fn created_and_deleted_files_are_all_added_or_removed() {
+    assert_eq!(count_changes("", "a\nb\n"), (2, 0));
+    assert_eq!(count_changes("a\nb\n", ""), (0, 2));
+    assert_eq!(count_changes("a\n", "a\n"), (0, 0));
+}
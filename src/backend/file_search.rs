@@ -0,0 +1,100 @@
+use std::path::{Path, PathBuf};
+
+use thiserror::Error;
+
+/// Caps how many files a search looks at so huge trees stay responsive
+const MAX_SEARCHED_FILES: usize = 50_000;
+
+#[derive(Debug, Error)]
+pub enum FileSearchError {
+    #[error("search of {path} stopped: {source}")]
+    Task {
+        path: PathBuf,
+        #[source]
+        source: tokio::task::JoinError,
+    },
+}
+
+/// A project file matching a search, `path` is relative to the project root
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FileMatch {
+    pub path: String,
+    pub absolute_path: PathBuf,
+    pub score: i64,
+}
+
+/// Fuzzy matches `query` against the file paths under `root`, skipping ignored files.
+/// Best matches come first, an empty query lists files by path length
+pub async fn search(
+    root: impl Into<PathBuf>,
+    query: &str,
+    limit: usize,
+) -> Result<Vec<FileMatch>, FileSearchError> {
+    let root = root.into();
+    let query = query.to_lowercase();
+    let task_root = root.clone();
+    tokio::task::spawn_blocking(move || search_files(&task_root, &query, limit))
+        .await
+        .map_err(|source| FileSearchError::Task { path: root, source })
+}
+
+fn search_files(root: &Path, query: &str, limit: usize) -> Vec<FileMatch> {
+    let walker = ignore::WalkBuilder::new(root)
+        .hidden(true)
+        .git_ignore(true)
+        .require_git(false)
+        .build();
+    let mut matches = walker
+        .filter_map(Result::ok)
+        .filter(|entry| entry.file_type().is_some_and(|kind| kind.is_file()))
+        .take(MAX_SEARCHED_FILES)
+        .filter_map(|entry| {
+            let path = entry.path().strip_prefix(root).ok()?;
+            let path = path.to_string_lossy().replace('\\', "/");
+            let score = fuzzy_score(query, &path.to_lowercase())?;
+            Some(FileMatch {
+                path,
+                absolute_path: entry.path().to_path_buf(),
+                score,
+            })
+        })
+        .collect::<Vec<_>>();
+    matches.sort_by(|a, b| {
+        b.score
+            .cmp(&a.score)
+            .then(a.path.len().cmp(&b.path.len()))
+            .then_with(|| a.path.cmp(&b.path))
+    });
+    matches.truncate(limit);
+    matches
+}
+
+/// Scores `candidate` when it contains every char of `query` in order. Consecutive chars
+/// and chars starting a path segment or word score higher, so `fsrs` prefers `fs.rs`
+fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate = candidate.chars().collect::<Vec<_>>();
+    let file_name_start = candidate
+        .iter()
+        .rposition(|c| *c == '/')
+        .map_or(0, |index| index + 1);
+    let mut score = 0;
+    let mut next = 0;
+    let mut previous: Option<usize> = None;
+    for wanted in query.chars().filter(|c| !c.is_whitespace()) {
+        let offset = candidate[next..].iter().position(|c| *c == wanted)?;
+        let index = next + offset;
+        score += 1;
+        if previous.is_some_and(|previous| previous + 1 == index) {
+            score += 5;
+        }
+        if index == 0 || matches!(candidate[index - 1], '/' | '_' | '-' | '.' | ' ') {
+            score += 3;
+        }
+        if index >= file_name_start {
+            score += 2;
+        }
+        previous = Some(index);
+        next = index + 1;
+    }
+    Some(score)
+}
@@ -0,0 +1,19 @@
+use crate::backend::file_search::search;
+
+#[tokio::test]
+async fn search_ranks_fuzzy_matches_and_skips_ignored_files() {
+    let dir = tempfile::tempdir().unwrap();
+    std::fs::create_dir_all(dir.path().join("src/backend")).unwrap();
+    std::fs::create_dir_all(dir.path().join("target")).unwrap();
+    std::fs::write(dir.path().join(".gitignore"), "target/\n").unwrap();
+    std::fs::write(dir.path().join("src/backend/fs.rs"), "").unwrap();
+    std::fs::write(dir.path().join("src/backend/files_test.rs"), "").unwrap();
+    std::fs::write(dir.path().join("target/fs.rs"), "").unwrap();
+
+    let matches = search(dir.path(), "fsrs", 10).await.unwrap();
+    let paths = matches.iter().map(|m| m.path.as_str()).collect::<Vec<_>>();
+    assert_eq!(paths, ["src/backend/fs.rs", "src/backend/files_test.rs"]);
+
+    assert!(search(dir.path(), "nothing", 10).await.unwrap().is_empty());
+    assert_eq!(search(dir.path(), "", 1).await.unwrap().len(), 1);
+}
@@ -0,0 +1,152 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use agent_client_protocol as acp;
+use futures::stream;
+use tokio::sync::broadcast;
+
+use crate::backend::{
+    agent::{Agent, AgentHub, fs::UnsavedBuffers},
+    harness::{Harness, HarnessAssistantEventStream, HarnessError, HarnessMessage},
+    models::session_model::SessionModel,
+    permission::PermissionBroker,
+    repo::{user_message::UserMessage, user_message_part::UserMessagePart},
+};
+
+/// Runs sessions against any agent that speaks the Agent Client Protocol over stdio
+#[derive(Clone)]
+pub struct AcpHarness {
+    hub: Arc<AgentHub>,
+}
+
+impl AcpHarness {
+    pub fn new(agent: Agent, permissions: Arc<PermissionBroker>, unsaved: UnsavedBuffers) -> Self {
+        Self {
+            hub: Arc::new(
+                AgentHub::new(agent)
+                    .with_permissions(permissions)
+                    .with_unsaved(unsaved),
+            ),
+        }
+    }
+}
+
+fn session_cwd(directory: Option<&str>) -> Result<PathBuf, HarnessError> {
+    directory
+        .map(PathBuf::from)
+        .ok_or_else(|| HarnessError::InvalidRequest("acp sessions need a directory".to_string()))
+}
+
+fn prompt_block(part: UserMessagePart) -> Result<acp::ContentBlock, HarnessError> {
+    match part.part_type.as_str() {
+        "text" => part
+            .text
+            .map(acp::ContentBlock::from)
+            .ok_or_else(|| HarnessError::InvalidRequest("text part missing text".to_string())),
+        "file" => {
+            let url = part.file_url.ok_or_else(|| {
+                HarnessError::InvalidRequest("file part missing file_url".to_string())
+            })?;
+            let name = part.file_name.unwrap_or_else(|| url.clone());
+            Ok(acp::ContentBlock::ResourceLink(acp::ResourceLink::new(
+                name, url,
+            )))
+        }
+        "subtask" => part
+            .subtask_prompt
+            .map(acp::ContentBlock::from)
+            .ok_or_else(|| {
+                HarnessError::InvalidRequest("subtask part missing subtask_prompt".to_string())
+            }),
+        other => Err(HarnessError::InvalidRequest(format!(
+            "unsupported user message part_type for acp: {other}"
+        ))),
+    }
+}
+
+#[async_trait::async_trait]
+impl Harness for AcpHarness {
+    fn harness_type(&self) -> &'static str {
+        self.hub.agent().harness_type()
+    }
+
+    fn cleanup(&self) {
+        self.hub.shutdown();
+    }
+
+    async fn create_session(
+        &self,
+        _session: SessionModel,
+        directory: Option<&str>,
+    ) -> anyhow::Result<String> {
+        let cwd = session_cwd(directory)?;
+        Ok(self.hub.new_session(cwd).await?)
+    }
+
+    async fn send_message_async(
+        &self,
+        harness_session_id: String,
+        _message: UserMessage,
+        message_parts: Vec<UserMessagePart>,
+        directory: Option<String>,
+    ) -> Result<(), HarnessError> {
+        let mut message_parts = message_parts;
+        message_parts.sort_by_key(|part| part.position);
+        let prompt = message_parts
+            .into_iter()
+            .map(prompt_block)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let cwd = session_cwd(directory.as_deref())?;
+        let session = self.hub.session(&harness_session_id, cwd).await?;
+        session.prompt(prompt)?;
+        Ok(())
+    }
+
+    async fn get_session_messages(
+        &self,
+        _session_id: &str,
+        _limit: Option<i32>,
+        _directory: Option<&str>,
+    ) -> Result<Vec<HarnessMessage>, HarnessError> {
+        // ACP only streams updates, the conversation itself is persisted on our side
+        Ok(Vec::new())
+    }
+
+    async fn abort_session(
+        &self,
+        harness_session_id: String,
+        directory: Option<String>,
+    ) -> Result<(), HarnessError> {
+        let cwd = session_cwd(directory.as_deref())?;
+        self.hub.session(&harness_session_id, cwd).await?.cancel()?;
+        Ok(())
+    }
+
+    async fn listen_assistant_events(
+        &self,
+        harness_session_id: String,
+        directory: Option<String>,
+    ) -> Result<HarnessAssistantEventStream, HarnessError> {
+        let cwd = session_cwd(directory.as_deref())?;
+        let receiver = self
+            .hub
+            .session(&harness_session_id, cwd)
+            .await?
+            .subscribe();
+
+        let events = stream::unfold(receiver, |mut receiver| async move {
+            loop {
+                match receiver.recv().await {
+                    Ok(event) => return Some((Ok(event), receiver)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!("acp event subscriber lagged, skipped {skipped} events");
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        Ok(Box::pin(events))
+    }
+}
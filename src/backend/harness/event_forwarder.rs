@@ -1 +1,791 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
 
+use futures::{StreamExt, stream};
+use tokio::{sync::broadcast, task::AbortHandle};
+
+use crate::backend::{
+    harness::{
+        HarnessAssistantEvent, HarnessAssistantEventStream, HarnessError, HarnessFileDiff,
+        HarnessSessionStatus,
+        opencode_client::{
+            FileDiff, OpencodeApiClient, OpencodeEventPayload, OpencodeEventStream,
+            OpencodeMessage, OpencodePart, OpencodePermissionAskedProps, OpencodePermissionReply,
+            OpencodeSessionStatus,
+        },
+    },
+    permission::{PermissionBroker, PermissionDecision, PermissionError, PermissionPrompt},
+};
+
+/// Events a session keeps for a slow subscriber before it starts skipping
+const SESSION_EVENT_BUFFER: usize = 256;
+/// Pause before reconnecting a dropped stream, e.g. while the supervisor restarts opencode
+const RECONNECT_DELAY: Duration = Duration::from_secs(1);
+
+/// One `/event` pump for a directory and the sessions listening on it
+struct Pump {
+    sessions: HashMap<String, broadcast::Sender<HarnessAssistantEvent>>,
+    task: AbortHandle,
+}
+
+type Pumps = Arc<Mutex<HashMap<Option<String>, Pump>>>;
+
+/// One session's view of its pump, with what a refetch after lagging still has to hand out
+struct Subscription {
+    receiver: broadcast::Receiver<HarnessAssistantEvent>,
+    refetched: VecDeque<HarnessAssistantEvent>,
+    client: OpencodeApiClient,
+    harness_session_id: String,
+    directory: Option<String>,
+}
+
+/// Shares one opencode event stream per directory between every session subscribed to it.
+/// Events are decoded once and fanned out by session, the stream closes with its last subscriber
+pub struct EventForwarder {
+    client: OpencodeApiClient,
+    permissions: Option<Arc<PermissionBroker>>,
+    pumps: Pumps,
+}
+
+impl EventForwarder {
+    pub fn new(client: OpencodeApiClient, permissions: Option<Arc<PermissionBroker>>) -> Self {
+        Self {
+            client,
+            permissions,
+            pumps: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    pub async fn subscribe(
+        &self,
+        harness_session_id: String,
+        directory: Option<String>,
+    ) -> Result<HarnessAssistantEventStream, HarnessError> {
+        let receiver = match self.join(&harness_session_id, &directory) {
+            Some(receiver) => receiver,
+            None => self.start(&harness_session_id, directory.clone()).await?,
+        };
+
+        let subscription = Subscription {
+            receiver,
+            refetched: VecDeque::new(),
+            client: self.client.clone(),
+            harness_session_id,
+            directory,
+        };
+        let events = stream::unfold(subscription, |mut subscription| async move {
+            loop {
+                if let Some(event) = subscription.refetched.pop_front() {
+                    return Some((Ok(event), subscription));
+                }
+                match subscription.receiver.recv().await {
+                    Ok(event) => return Some((Ok(event), subscription)),
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!(
+                            "{} skipped {skipped} opencode events, refetching its messages",
+                            subscription.harness_session_id
+                        );
+                        subscription.refetched = refetch_messages(
+                            &subscription.client,
+                            &subscription.harness_session_id,
+                            subscription.directory.as_deref(),
+                        )
+                        .await
+                        .into();
+                    }
+                    Err(broadcast::error::RecvError::Closed) => return None,
+                }
+            }
+        });
+
+        Ok(Box::pin(events))
+    }
+
+    /// Subscribes to a pump that is already running for the directory
+    fn join(
+        &self,
+        harness_session_id: &str,
+        directory: &Option<String>,
+    ) -> Option<broadcast::Receiver<HarnessAssistantEvent>> {
+        lock(&self.pumps)
+            .get_mut(directory)
+            .map(|pump| session_receiver(pump, harness_session_id))
+    }
+
+    async fn start(
+        &self,
+        harness_session_id: &str,
+        directory: Option<String>,
+    ) -> Result<broadcast::Receiver<HarnessAssistantEvent>, HarnessError> {
+        let events = self
+            .client
+            .get_event_stream(directory.as_deref())
+            .await
+            .map_err(HarnessError::ApiTransport)?;
+
+        let mut pumps = lock(&self.pumps);
+        // Another subscriber may have started the pump while we connected, ours is dropped then
+        if let Some(pump) = pumps.get_mut(&directory) {
+            return Ok(session_receiver(pump, harness_session_id));
+        }
+
+        let task = tokio::spawn(pump(
+            Arc::clone(&self.pumps),
+            self.client.clone(),
+            self.permissions.clone(),
+            directory.clone(),
+            events,
+        ))
+        .abort_handle();
+        let pump = pumps.entry(directory).or_insert(Pump {
+            sessions: HashMap::new(),
+            task,
+        });
+        Ok(session_receiver(pump, harness_session_id))
+    }
+
+    #[cfg(test)]
+    fn pump_count(&self) -> usize {
+        lock(&self.pumps).len()
+    }
+}
+
+impl Drop for EventForwarder {
+    fn drop(&mut self) {
+        for pump in lock(&self.pumps).values() {
+            pump.task.abort();
+        }
+    }
+}
+
+fn session_receiver(
+    pump: &mut Pump,
+    harness_session_id: &str,
+) -> broadcast::Receiver<HarnessAssistantEvent> {
+    pump.sessions
+        .entry(harness_session_id.to_string())
+        .or_insert_with(|| broadcast::channel(SESSION_EVENT_BUFFER).0)
+        .subscribe()
+}
+
+fn lock(pumps: &Pumps) -> MutexGuard<'_, HashMap<Option<String>, Pump>> {
+    pumps
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+/// Reads the directory's event stream until no session listens anymore, reconnecting when it drops
+async fn pump(
+    pumps: Pumps,
+    client: OpencodeApiClient,
+    permissions: Option<Arc<PermissionBroker>>,
+    directory: Option<String>,
+    mut events: OpencodeEventStream,
+) {
+    loop {
+        while let Some(item) = events.next().await {
+            let event = match item {
+                Ok(event) => event,
+                Err(err) => {
+                    log::warn!("opencode event stream failed: {err}");
+                    break;
+                }
+            };
+
+            let payload = match parse_event_payload(&event.data) {
+                Ok(payload) => payload,
+                Err(err) => {
+                    log::warn!("skipping opencode event: {err}");
+                    None
+                }
+            };
+            let event = match payload {
+                Some(OpencodeEventPayload::PermissionAsked { props }) => {
+                    if let Some(permissions) = &permissions {
+                        spawn_permission_request(
+                            Arc::clone(permissions),
+                            client.clone(),
+                            props,
+                            directory.clone(),
+                        );
+                    }
+                    None
+                }
+                Some(payload) => map_payload_to_harness_event(payload),
+                None => None,
+            };
+
+            if !deliver(&pumps, &directory, event) {
+                return;
+            }
+        }
+
+        loop {
+            if !deliver(&pumps, &directory, None) {
+                return;
+            }
+            tokio::time::sleep(RECONNECT_DELAY).await;
+            match client.get_event_stream(directory.as_deref()).await {
+                Ok(reconnected) => {
+                    events = reconnected;
+                    break;
+                }
+                Err(err) => log::warn!("failed to reconnect opencode event stream: {err}"),
+            }
+        }
+    }
+}
+
+/// The session's messages as events, for a subscriber that skipped some. Parts come whole,
+/// so they cover the deltas it missed
+async fn refetch_messages(
+    client: &OpencodeApiClient,
+    harness_session_id: &str,
+    directory: Option<&str>,
+) -> Vec<HarnessAssistantEvent> {
+    let messages = match client
+        .get_session_messages(harness_session_id, None, directory)
+        .await
+    {
+        Ok(messages) => messages,
+        Err(err) => {
+            log::warn!("failed to refetch the messages of {harness_session_id}: {err}");
+            return Vec::new();
+        }
+    };
+    messages
+        .into_iter()
+        .flat_map(|message| {
+            map_message_updated(message.info)
+                .into_iter()
+                .chain(message.parts.into_iter().map(map_message_part_updated))
+        })
+        .collect()
+}
+
+/// Hands the event to its session and forgets sessions nobody listens to anymore.
+/// Returns false once the pump has no sessions left and was removed
+fn deliver(
+    pumps: &Pumps,
+    directory: &Option<String>,
+    event: Option<HarnessAssistantEvent>,
+) -> bool {
+    let mut pumps = lock(pumps);
+    let Some(pump) = pumps.get_mut(directory) else {
+        return false;
+    };
+
+    if let Some(event) = event {
+        match event.harness_session_id() {
+            Some(harness_session_id) => {
+                if let Some(sender) = pump.sessions.get(harness_session_id) {
+                    let _ = sender.send(event);
+                }
+            }
+            // Errors without a session could belong to any of them
+            None => {
+                for sender in pump.sessions.values() {
+                    let _ = sender.send(event.clone());
+                }
+            }
+        }
+    }
+
+    pump.sessions
+        .retain(|_, sender| sender.receiver_count() > 0);
+    if pump.sessions.is_empty() {
+        pumps.remove(directory);
+        return false;
+    }
+    true
+}
+
+fn is_supported_event_type(event_type: &str) -> bool {
+    matches!(
+        event_type,
+        "session.status"
+            | "message.updated"
+            | "message.part.updated"
+            | "message.part.delta"
+            | "message.removed"
+            | "session.error"
+            | "permission.asked"
+    )
+}
+
+/// Names opencode permissions after ACP tool kinds, so project rules cover both harnesses
+fn permission_kind(permission: &str) -> &str {
+    match permission {
+        "bash" => "execute",
+        "webfetch" => "fetch",
+        "glob" | "grep" | "list" => "search",
+        other => other,
+    }
+}
+
+fn spawn_permission_request(
+    permissions: Arc<PermissionBroker>,
+    opencode_client: OpencodeApiClient,
+    props: OpencodePermissionAskedProps,
+    directory: Option<String>,
+) {
+    tokio::spawn(async move {
+        let title = if props.patterns.is_empty() {
+            props.permission.clone()
+        } else {
+            format!("{}: {}", props.permission, props.patterns.join(", "))
+        };
+        let prompt = PermissionPrompt {
+            harness_request_id: Some(props.id.clone()),
+            tool_call_id: props.tool.map(|tool| tool.call_id),
+            title,
+            kind: permission_kind(&props.permission).to_string(),
+        };
+
+        let reply = match permissions.request(&props.session_id, prompt).await {
+            Ok(PermissionDecision::Approve) => OpencodePermissionReply::Once,
+            Ok(PermissionDecision::AlwaysAllow) => OpencodePermissionReply::Always,
+            Ok(PermissionDecision::Deny) => OpencodePermissionReply::Reject,
+            // opencode repeats the request after a reconnect while it is still waiting
+            Err(PermissionError::AlreadyPending(_)) => return,
+            Err(err) => {
+                log::warn!("failed to ask for opencode permission {}: {err}", props.id);
+                OpencodePermissionReply::Reject
+            }
+        };
+
+        if let Err(err) = opencode_client
+            .reply_permission(&props.id, reply, directory.as_deref())
+            .await
+        {
+            log::warn!("failed to answer opencode permission {}: {err}", props.id);
+        }
+    });
+}
+
+fn parse_event_payload(data: &str) -> Result<Option<OpencodeEventPayload>, HarnessError> {
+    let payload: serde_json::Value = serde_json::from_str(data).map_err(|err| {
+        HarnessError::ApiRequest(anyhow::anyhow!(
+            "failed to parse opencode event JSON: {err}; data={data}"
+        ))
+    })?;
+
+    let event_type = payload
+        .get("type")
+        .and_then(serde_json::Value::as_str)
+        .unwrap_or_default();
+    if !is_supported_event_type(event_type) {
+        return Ok(None);
+    }
+
+    serde_json::from_value(payload).map(Some).map_err(|err| {
+        HarnessError::ApiRequest(anyhow::anyhow!("failed to decode opencode event: {err}"))
+    })
+}
+
+fn map_payload_to_harness_event(payload: OpencodeEventPayload) -> Option<HarnessAssistantEvent> {
+    match payload {
+        OpencodeEventPayload::SessionStatus { props } => {
+            Some(HarnessAssistantEvent::SessionStatus {
+                harness_session_id: props.session_id,
+                status: map_session_status(props.status),
+            })
+        }
+        OpencodeEventPayload::MessageUpdated { props } => map_message_updated(props.info),
+        OpencodeEventPayload::MessagePartUpdated { props } => {
+            Some(map_message_part_updated(props.part))
+        }
+        OpencodeEventPayload::MessagePartDelta { props } => {
+            Some(HarnessAssistantEvent::MessagePartDelta {
+                harness_session_id: props.session_id,
+                message_id: props.message_id,
+                part_id: props.part_id,
+                field: props.field,
+                delta: props.delta,
+            })
+        }
+        OpencodeEventPayload::MessageRemoved { props } => {
+            Some(HarnessAssistantEvent::MessageRemoved {
+                harness_session_id: props.session_id,
+                message_id: props.message_id,
+            })
+        }
+        OpencodeEventPayload::SessionError { props } => Some(HarnessAssistantEvent::SessionError {
+            harness_session_id: props.session_id,
+            error: props.error.to_string(),
+        }),
+        _ => None,
+    }
+}
+
+fn map_session_status(status: OpencodeSessionStatus) -> HarnessSessionStatus {
+    match status {
+        OpencodeSessionStatus::Idle => HarnessSessionStatus::Idle,
+        OpencodeSessionStatus::Busy => HarnessSessionStatus::Busy,
+        OpencodeSessionStatus::Retry {
+            attempt,
+            message,
+            next,
+        } => HarnessSessionStatus::Retry {
+            attempt,
+            message,
+            next,
+        },
+    }
+}
+
+fn map_message_updated(message: OpencodeMessage) -> Option<HarnessAssistantEvent> {
+    match message {
+        OpencodeMessage::Assistant(assistant) => Some(HarnessAssistantEvent::MessageUpdated {
+            harness_session_id: assistant.session_id,
+            message_id: assistant.id,
+            completed_at: assistant.time.completed,
+            error: assistant
+                .error
+                .and_then(|err| serde_json::to_string(&err).ok()),
+        }),
+        // The diffs of a turn land on its prompt once opencode summarized the turn
+        OpencodeMessage::User(user) => match user.summary.and_then(|summary| summary.diffs) {
+            Some(diffs) => Some(HarnessAssistantEvent::MessageDiffs {
+                harness_session_id: user.session_id,
+                message_id: user.id,
+                diffs: diffs.into_iter().map(map_file_diff).collect(),
+            }),
+            None => Some(HarnessAssistantEvent::PromptRecorded {
+                harness_session_id: user.session_id,
+                message_id: user.id,
+            }),
+        },
+    }
+}
+
+fn map_file_diff(diff: FileDiff) -> HarnessFileDiff {
+    HarnessFileDiff {
+        file: diff.file,
+        before: diff.before,
+        after: diff.after,
+        additions: diff.additions.into(),
+        deletions: diff.deletions.into(),
+    }
+}
+
+fn map_message_part_updated(part: OpencodePart) -> HarnessAssistantEvent {
+    HarnessAssistantEvent::MessagePartUpdated {
+        harness_session_id: part.session_id().to_string(),
+        message_id: part.message_id().to_string(),
+        part_id: part.id().to_string(),
+        part_type: part.part_type().to_string(),
+        payload: serde_json::to_value(part).unwrap_or(serde_json::Value::Null),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::backend::harness::opencode_supervisor::free_port;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+        sync::watch,
+    };
+
+    fn delta(harness_session_id: &str, delta: &str) -> String {
+        serde_json::json!({
+            "type": "message.part.delta",
+            "properties": {
+                "sessionID": harness_session_id,
+                "messageID": "msg-1",
+                "partID": "part-1",
+                "field": "text",
+                "delta": delta,
+            },
+        })
+        .to_string()
+    }
+
+    /// Serves `/event` on every connection, sending the events once `send` flips to true.
+    /// Message listings get `messages`
+    async fn serve_events(
+        port: u32,
+        events: Vec<String>,
+        messages: serde_json::Value,
+        send: watch::Receiver<bool>,
+    ) -> Arc<AtomicUsize> {
+        let listener = TcpListener::bind(("127.0.0.1", port as u16)).await.unwrap();
+        let connections = Arc::new(AtomicUsize::new(0));
+        let accepted = Arc::clone(&connections);
+        tokio::spawn(async move {
+            while let Ok((mut socket, _)) = listener.accept().await {
+                let events = events.clone();
+                let messages = messages.to_string();
+                let mut send = send.clone();
+                let accepted = Arc::clone(&accepted);
+                tokio::spawn(async move {
+                    let mut buf = [0_u8; 1024];
+                    let read = socket.read(&mut buf).await.unwrap_or_default();
+                    if String::from_utf8_lossy(&buf[..read]).starts_with("GET /session/") {
+                        let _ = socket
+                            .write_all(
+                                format!(
+                                    "HTTP/1.1 200 OK\r\ncontent-length: {}\r\n\r\n{messages}",
+                                    messages.len()
+                                )
+                                .as_bytes(),
+                            )
+                            .await;
+                        return;
+                    }
+                    accepted.fetch_add(1, Ordering::SeqCst);
+                    let _ = socket
+                        .write_all(b"HTTP/1.1 200 OK\r\ncontent-type: text/event-stream\r\n\r\n")
+                        .await;
+                    let _ = send.wait_for(|send| *send).await;
+                    for event in events {
+                        let _ = socket
+                            .write_all(format!("data: {event}\n\n").as_bytes())
+                            .await;
+                    }
+                    // Keep the stream open like opencode does
+                    std::future::pending::<()>().await;
+                });
+            }
+        });
+        connections
+    }
+
+    fn delta_text(event: HarnessAssistantEvent) -> String {
+        match event {
+            HarnessAssistantEvent::MessagePartDelta { delta, .. } => delta,
+            other => panic!("expected a delta, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decodes_deltas_and_skips_unknown_events() {
+        let payload = parse_event_payload(&delta("ses-1", "hi"))
+            .unwrap()
+            .expect("delta should be supported");
+        let event = map_payload_to_harness_event(payload).expect("delta should map");
+        assert_eq!(event.harness_session_id(), Some("ses-1"));
+        assert_eq!(delta_text(event), "hi");
+
+        let heartbeat = r#"{"type":"server.heartbeat","properties":{}}"#;
+        assert!(parse_event_payload(heartbeat).unwrap().is_none());
+    }
+
+    #[test]
+    fn decodes_message_removals() {
+        let removed =
+            r#"{"type":"message.removed","properties":{"sessionID":"ses-1","messageID":"msg-1"}}"#;
+        let payload = parse_event_payload(removed)
+            .unwrap()
+            .expect("removals should be supported");
+        match map_payload_to_harness_event(payload) {
+            Some(HarnessAssistantEvent::MessageRemoved {
+                harness_session_id,
+                message_id,
+            }) => {
+                assert_eq!(harness_session_id, "ses-1");
+                assert_eq!(message_id, "msg-1");
+            }
+            other => panic!("expected a removal, got {other:?}"),
+        }
+    }
+
+    #[test]
+    fn decodes_the_diffs_of_a_summarized_prompt() {
+        let prompt = |summary: serde_json::Value| {
+            serde_json::json!({
+                "type": "message.updated",
+                "properties": {"info": {
+                    "role": "user",
+                    "id": "msg-1",
+                    "sessionID": "ses-1",
+                    "time": {"created": 1},
+                    "summary": summary,
+                    "agent": "build",
+                    "model": {"providerID": "openai", "modelID": "gpt-5"},
+                }},
+            })
+            .to_string()
+        };
+
+        let summarized = prompt(serde_json::json!({"diffs": [{
+            "file": "src/main.rs",
+            "before": "a\n",
+            "after": "b\n",
+            "additions": 1,
+            "deletions": 1,
+        }]}));
+        let payload = parse_event_payload(&summarized).unwrap().unwrap();
+        match map_payload_to_harness_event(payload) {
+            Some(HarnessAssistantEvent::MessageDiffs {
+                harness_session_id,
+                message_id,
+                diffs,
+            }) => {
+                assert_eq!(harness_session_id, "ses-1");
+                assert_eq!(message_id, "msg-1");
+                assert_eq!(
+                    diffs,
+                    [HarnessFileDiff {
+                        file: "src/main.rs".to_string(),
+                        before: "a\n".to_string(),
+                        after: "b\n".to_string(),
+                        additions: 1,
+                        deletions: 1,
+                    }]
+                );
+            }
+            other => panic!("expected diffs, got {other:?}"),
+        }
+
+        // A prompt that was not summarized yet is only recorded
+        let payload = parse_event_payload(&prompt(serde_json::json!({"title": "t"})))
+            .unwrap()
+            .unwrap();
+        assert!(matches!(
+            map_payload_to_harness_event(payload),
+            Some(HarnessAssistantEvent::PromptRecorded { message_id, .. }) if message_id == "msg-1"
+        ));
+    }
+
+    #[tokio::test]
+    async fn sessions_share_one_stream_and_only_see_their_events() {
+        let port = free_port().unwrap();
+        let (send, send_rx) = watch::channel(false);
+        let connections = serve_events(
+            port,
+            vec![
+                delta("ses-a", "a1"),
+                delta("ses-b", "b1"),
+                delta("ses-a", "a2"),
+            ],
+            serde_json::json!([]),
+            send_rx,
+        )
+        .await;
+        let forwarder = EventForwarder::new(OpencodeApiClient::new(port), None);
+
+        let mut a = forwarder
+            .subscribe("ses-a".to_string(), Some("/proj".to_string()))
+            .await
+            .unwrap();
+        let mut b = forwarder
+            .subscribe("ses-b".to_string(), Some("/proj".to_string()))
+            .await
+            .unwrap();
+        send.send_replace(true);
+
+        assert_eq!(delta_text(a.next().await.unwrap().unwrap()), "a1");
+        assert_eq!(delta_text(a.next().await.unwrap().unwrap()), "a2");
+        assert_eq!(delta_text(b.next().await.unwrap().unwrap()), "b1");
+        assert_eq!(connections.load(Ordering::SeqCst), 1);
+        assert_eq!(forwarder.pump_count(), 1);
+    }
+
+    #[tokio::test]
+    async fn the_pump_stops_once_every_subscriber_is_gone() {
+        let port = free_port().unwrap();
+        let (send, send_rx) = watch::channel(false);
+        serve_events(
+            port,
+            vec![delta("ses-a", "a1")],
+            serde_json::json!([]),
+            send_rx,
+        )
+        .await;
+        let forwarder = EventForwarder::new(OpencodeApiClient::new(port), None);
+
+        let events = forwarder
+            .subscribe("ses-a".to_string(), None)
+            .await
+            .unwrap();
+        drop(events);
+        send.send_replace(true);
+
+        // The next event finds nobody listening and retires the pump
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while forwarder.pump_count() > 0 {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("pump should stop");
+    }
+
+    #[tokio::test]
+    async fn a_lagging_subscriber_refetches_the_session_messages() {
+        let port = free_port().unwrap();
+        let (send, send_rx) = watch::channel(false);
+        let deltas = (0..SESSION_EVENT_BUFFER + 10)
+            .map(|index| delta("ses-a", &index.to_string()))
+            .collect();
+        let messages = serde_json::json!([{
+            "info": {
+                "role": "assistant",
+                "id": "msg-1",
+                "sessionID": "ses-a",
+                "time": {"created": 1, "completed": 2},
+                "error": null,
+                "parentID": "msg-0",
+                "modelID": "gpt-5",
+                "providerID": "openai",
+                "mode": "build",
+                "path": {"cwd": "/proj", "root": "/proj"},
+                "cost": 0.0,
+                "tokens": {
+                    "input": 1,
+                    "output": 2,
+                    "reasoning": 0,
+                    "cache": {"read": 0, "write": 0},
+                },
+                "finish": "stop",
+            },
+            "parts": [{
+                "id": "part-1",
+                "sessionID": "ses-a",
+                "messageID": "msg-1",
+                "type": "text",
+                "text": "the whole text",
+            }],
+        }]);
+        serve_events(port, deltas, messages, send_rx).await;
+        let forwarder = EventForwarder::new(OpencodeApiClient::new(port), None);
+
+        let mut events = forwarder
+            .subscribe("ses-a".to_string(), None)
+            .await
+            .unwrap();
+        send.send_replace(true);
+        // Let the pump overrun the subscriber's buffer before it reads anything
+        tokio::time::sleep(Duration::from_millis(500)).await;
+
+        match events.next().await.unwrap().unwrap() {
+            HarnessAssistantEvent::MessageUpdated {
+                message_id,
+                completed_at,
+                ..
+            } => {
+                assert_eq!(message_id, "msg-1");
+                assert_eq!(completed_at, Some(2));
+            }
+            other => panic!("expected the refetched message, got {other:?}"),
+        }
+        match events.next().await.unwrap().unwrap() {
+            HarnessAssistantEvent::MessagePartUpdated {
+                part_id, payload, ..
+            } => {
+                assert_eq!(part_id, "part-1");
+                assert_eq!(payload["text"], "the whole text");
+            }
+            other => panic!("expected the refetched part, got {other:?}"),
+        }
+        // The buffered events follow, newer than the refetch's
+        delta_text(events.next().await.unwrap().unwrap());
+    }
+}
@@ -1,22 +1,55 @@
 use crate::backend::{
     models::session_model::SessionModel,
-    repo::{session::SessionRepo, user_message::UserMessage, user_message_part::UserMessagePart},
+    repo::{user_message::UserMessage, user_message_part::UserMessagePart},
 };
 use futures::Stream;
 use serde::{Deserialize, Serialize};
 use std::pin::Pin;
+use tokio::sync::watch;
 use uuid::Uuid;
 
+pub mod acp;
 pub mod event_forwarder;
 pub mod opencode;
 mod opencode_client;
+mod opencode_supervisor;
+mod registry;
 pub(crate) use opencode_client::{OpencodePartInput, OpencodeSendMessageRequest};
+pub use registry::HarnessRegistry;
 
+#[derive(Debug, Clone, PartialEq, Eq)]
 pub struct Model {
     pub provider_id: String,
     pub model_id: String,
 }
 
+/// A model a harness can run prompts with
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HarnessModel {
+    pub provider_id: String,
+    pub provider_name: String,
+    pub model_id: String,
+    pub model_name: String,
+}
+
+/// Models a harness offers, with the one it would pick for a prompt that names none
+#[derive(Debug, Clone, Default)]
+pub struct HarnessModelCatalog {
+    pub models: Vec<HarnessModel>,
+    pub default_model: Option<Model>,
+}
+
+/// An agent a harness can hand a prompt to
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HarnessAgent {
+    pub name: String,
+    pub description: Option<String>,
+    /// Can answer the user's messages directly
+    pub primary: bool,
+    /// Can be mentioned or handed a subtask
+    pub subagent: bool,
+}
+
 pub struct UserMessageRequest {
     pub id: Uuid,
     pub session_id: Uuid,
@@ -35,6 +68,12 @@ pub enum HarnessError {
 
     #[error("API transport failed: {0}")]
     ApiTransport(#[from] reqwest::Error),
+
+    #[error("no harness registered for type {0}")]
+    UnknownHarness(String),
+
+    #[error("agent error: {0}")]
+    Agent(#[from] crate::backend::agent::AgentHubSessionError),
 }
 
 #[derive(Debug, Clone)]
@@ -57,6 +96,16 @@ pub enum HarnessSessionStatus {
     },
 }
 
+/// A file before and after a turn, with the lines the turn added and removed
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HarnessFileDiff {
+    pub file: String,
+    pub before: String,
+    pub after: String,
+    pub additions: i64,
+    pub deletions: i64,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum HarnessAssistantEvent {
     SessionStatus {
@@ -83,12 +132,82 @@ pub enum HarnessAssistantEvent {
         field: String,
         delta: String,
     },
+    MessageRemoved {
+        harness_session_id: String,
+        message_id: String,
+    },
+    /// The harness stored a prompt, it reports prompts in the order they were sent
+    PromptRecorded {
+        harness_session_id: String,
+        message_id: String,
+    },
+    /// Every file the turn of a user message changed so far, replacing what was reported before
+    MessageDiffs {
+        harness_session_id: String,
+        message_id: String,
+        diffs: Vec<HarnessFileDiff>,
+    },
     SessionError {
         harness_session_id: Option<String>,
         error: String,
     },
 }
 
+/// Where a revert starts, it undoes that and everything after it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RevertPoint {
+    /// The prompt the assistant message `reply_id` answers
+    PromptOf { reply_id: String },
+    /// An assistant message
+    Message { message_id: String },
+    /// A part of an assistant message, the parts before it stay
+    Part { message_id: String, part_id: String },
+}
+
+/// Whether a harness can serve requests right now
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum HarnessHealth {
+    Starting,
+    Healthy,
+    /// The harness went down and is being restarted, `attempt` counts up until it is healthy
+    Restarting {
+        attempt: u32,
+        error: String,
+    },
+}
+
+impl HarnessAssistantEvent {
+    /// Session the event belongs to, `None` for errors opencode reports without one
+    pub fn harness_session_id(&self) -> Option<&str> {
+        match self {
+            Self::SessionStatus {
+                harness_session_id, ..
+            }
+            | Self::MessageUpdated {
+                harness_session_id, ..
+            }
+            | Self::MessagePartUpdated {
+                harness_session_id, ..
+            }
+            | Self::MessagePartDelta {
+                harness_session_id, ..
+            }
+            | Self::MessageRemoved {
+                harness_session_id, ..
+            }
+            | Self::PromptRecorded {
+                harness_session_id, ..
+            }
+            | Self::MessageDiffs {
+                harness_session_id, ..
+            } => Some(harness_session_id),
+            Self::SessionError {
+                harness_session_id, ..
+            } => harness_session_id.as_deref(),
+        }
+    }
+}
+
 impl HarnessMessage {
     pub fn id(&self) -> &str {
         &self.id
@@ -99,8 +218,13 @@ impl HarnessMessage {
     }
 }
 
-pub trait Harness: Sized {
-    fn new() -> anyhow::Result<Self>;
+/// Harness type used when a session does not name one
+pub const DEFAULT_HARNESS_TYPE: &str = opencode::OPENCODE_HARNESS_TYPE;
+
+#[async_trait::async_trait]
+pub trait Harness: Send + Sync {
+    /// Key this harness is registered under, matches `sessions.harness_type`
+    fn harness_type(&self) -> &'static str;
     fn cleanup(&self);
 
     async fn create_session(
@@ -124,24 +248,71 @@ pub trait Harness: Sized {
         directory: Option<&str>,
     ) -> Result<Vec<HarnessMessage>, HarnessError>;
 
+    /// Stops the session's running turn, a session that is not running stays as it is
+    async fn abort_session(
+        &self,
+        harness_session_id: String,
+        directory: Option<String>,
+    ) -> Result<(), HarnessError>;
+
     async fn listen_assistant_events(
         &self,
         harness_session_id: String,
         directory: Option<String>,
     ) -> Result<HarnessAssistantEventStream, HarnessError>;
 
-    async fn get_event_stream(
+    /// Copies the session into a new one that holds its messages up to and including
+    /// `through_message_id`, `None` copies none. Returns the new harness session id
+    async fn fork_session(
+        &self,
+        _harness_session_id: String,
+        _through_message_id: Option<String>,
+        _directory: Option<String>,
+    ) -> Result<String, HarnessError> {
+        Err(HarnessError::InvalidRequest(format!(
+            "{} sessions cannot be forked",
+            self.harness_type()
+        )))
+    }
+
+    /// Undoes the session from `point` on and restores the workspace from the harness's
+    /// snapshots. `false` when the harness keeps none and the caller has to restore it
+    async fn revert_session(
         &self,
-    ) -> anyhow::Result<
-        Pin<
-            Box<
-                dyn Stream<
-                        Item = Result<
-                            eventsource_stream::Event,
-                            eventsource_stream::EventStreamError<reqwest::Error>,
-                        >,
-                    > + Send,
-            >,
-        >,
-    >;
+        _harness_session_id: String,
+        _point: RevertPoint,
+        _directory: Option<String>,
+    ) -> Result<bool, HarnessError> {
+        Ok(false)
+    }
+
+    /// Takes back the session's last revert, `false` like for `revert_session`
+    async fn unrevert_session(
+        &self,
+        _harness_session_id: String,
+        _directory: Option<String>,
+    ) -> Result<bool, HarnessError> {
+        Ok(false)
+    }
+
+    /// Harnesses that pick the model themselves offer none to choose from
+    async fn list_models(
+        &self,
+        _directory: Option<String>,
+    ) -> Result<HarnessModelCatalog, HarnessError> {
+        Ok(HarnessModelCatalog::default())
+    }
+
+    /// Harnesses without agents to choose from run every prompt the same way
+    async fn list_agents(
+        &self,
+        _directory: Option<String>,
+    ) -> Result<Vec<HarnessAgent>, HarnessError> {
+        Ok(Vec::new())
+    }
+
+    /// Harnesses without a long-running server to watch are always healthy
+    fn subscribe_health(&self) -> watch::Receiver<HarnessHealth> {
+        watch::channel(HarnessHealth::Healthy).1
+    }
 }
@@ -1,21 +1,23 @@
-use futures::StreamExt;
-use std::os::unix::process::CommandExt;
-use std::pin::Pin;
-use std::process::{Child, Command};
-use std::sync::{Arc, Mutex};
+use std::sync::Arc;
+use std::time::Duration;
 use thiserror::Error;
+use tokio::sync::watch;
 
 use crate::backend::harness::{
-    Harness, HarnessAssistantEvent, HarnessAssistantEventStream, HarnessError, HarnessMessage,
-    HarnessSessionStatus, OpencodePartInput, OpencodeSendMessageRequest,
+    Harness, HarnessAgent, HarnessAssistantEventStream, HarnessError, HarnessHealth,
+    HarnessMessage, HarnessModel, HarnessModelCatalog, Model, OpencodePartInput,
+    OpencodeSendMessageRequest, RevertPoint,
+    event_forwarder::EventForwarder,
+    opencode_supervisor::{OpencodeSupervisor, free_port},
 };
 use crate::backend::repo::{user_message::UserMessage, user_message_part::UserMessagePart};
 use crate::backend::{
     harness::opencode_client::{
-        OpencodeApiClient, OpencodeCreateSessionRequest, OpencodeEventPayload, OpencodeMessage,
-        OpencodeSessionStatus,
+        OpencodeAgentInfo, OpencodeApiClient, OpencodeCreateSessionRequest, OpencodeMessage,
+        OpencodeMessageWithParts, OpencodeProviderListResponse,
     },
     models::session_model::SessionModel,
+    permission::PermissionBroker,
 };
 
 #[derive(Error, Debug)]
@@ -32,116 +34,37 @@ pub enum OpencodeHarnessError {
 
     #[error("API transport failed: {0}")]
     ApiTransport(#[from] reqwest::Error),
+
+    #[error("opencode exited before it was ready: {0}")]
+    ExitedBeforeReady(String),
+
+    #[error("opencode not ready after {timeout:?}: {last_error}")]
+    NotReady {
+        timeout: Duration,
+        last_error: String,
+    },
 }
 
+pub const OPENCODE_HARNESS_TYPE: &str = "opencode";
+
 // Needs to be clonable since we pass this around in the repos
 #[derive(Clone)]
 pub struct OpencodeHarness {
-    proc: Arc<Mutex<Option<Child>>>,
+    supervisor: Arc<OpencodeSupervisor>,
     opencode_client: OpencodeApiClient,
+    /// One event stream per directory, shared by every session listening on it
+    events: Arc<EventForwarder>,
+    permissions: Option<Arc<PermissionBroker>>,
 }
 
+#[async_trait::async_trait]
 impl Harness for OpencodeHarness {
-    fn new() -> anyhow::Result<Self> {
-        Self::new_with_port(6767)
+    fn harness_type(&self) -> &'static str {
+        OPENCODE_HARNESS_TYPE
     }
 
     fn cleanup(&self) {
-        let Ok(mut proc) = self.proc.lock() else {
-            log::error!("Failed to lock process mutex during cleanup");
-            return;
-        };
-
-        let Some(mut proc) = proc.take() else {
-            log::debug!("No opencode process to cleanup");
-            return;
-        };
-
-        let pid = proc.id() as i32;
-        log::debug!("Cleaning up opencode process (PID: {})", pid);
-
-        // Send SIGTERM to the process group (negative PID kills the entire group)
-        let term_result = unsafe { libc::kill(-pid, libc::SIGTERM) };
-        if term_result != 0 {
-            log::debug!(
-                "Failed to send SIGTERM to opencode process group (PID: {}): {}",
-                pid,
-                std::io::Error::last_os_error()
-            );
-        }
-
-        // Wait for graceful shutdown (up to 2 seconds)
-        let mut exited = false;
-        for _ in 0..20 {
-            match proc.try_wait() {
-                Ok(Some(_)) => {
-                    log::debug!("Opencode process terminated gracefully");
-                    exited = true;
-                    break;
-                }
-                Ok(None) => {
-                    std::thread::sleep(std::time::Duration::from_millis(100));
-                }
-                Err(e) => {
-                    log::warn!("Error waiting for opencode process: {}", e);
-                    break;
-                }
-            }
-        }
-
-        // If still running, force kill with SIGKILL
-        if !exited {
-            log::warn!("Opencode process did not terminate gracefully, sending SIGKILL");
-            let kill_result = unsafe { libc::kill(-pid, libc::SIGKILL) };
-            if kill_result != 0 {
-                log::warn!(
-                    "Failed to send SIGKILL to opencode process group (PID: {}): {}",
-                    pid,
-                    std::io::Error::last_os_error()
-                );
-            }
-
-            // Wait for process to exit with timeout
-            for _ in 0..50 {
-                match proc.try_wait() {
-                    Ok(Some(_)) => {
-                        log::debug!("Opencode process killed successfully");
-                        exited = true;
-                        break;
-                    }
-                    Ok(None) => {
-                        std::thread::sleep(std::time::Duration::from_millis(100));
-                    }
-                    Err(e) => {
-                        log::warn!("Error waiting for opencode process after SIGKILL: {}", e);
-                        break;
-                    }
-                }
-            }
-        }
-
-        // Final wait to reap the process
-        if !exited {
-            match proc.wait() {
-                Ok(status) => {
-                    log::warn!(
-                        "Opencode process exited after timeout wait (PID: {}, status: {})",
-                        pid,
-                        status
-                    );
-                    exited = true;
-                }
-                Err(e) => {
-                    log::error!("Failed to wait for opencode process (PID: {}): {}", pid, e);
-                }
-            }
-        }
-
-        if !exited {
-            log::error!("Failed to terminate opencode process (PID: {})", pid);
-        }
-
-        log::debug!("Opencode process cleanup completed");
+        self.supervisor.shutdown();
     }
 
     async fn create_session(
@@ -152,7 +75,8 @@ impl Harness for OpencodeHarness {
         let request = OpencodeCreateSessionRequest {
             parent_id: None,
             title: Some(session.name),
-            permission: None,
+            // Without a broker nobody could answer, so keep opencode's own defaults then
+            permission: self.permissions.as_ref().map(|_| ask_permission_ruleset()),
         };
 
         let created = self
@@ -209,223 +133,250 @@ impl Harness for OpencodeHarness {
             .collect())
     }
 
-    async fn listen_assistant_events(
+    async fn abort_session(
         &self,
         harness_session_id: String,
         directory: Option<String>,
-    ) -> Result<HarnessAssistantEventStream, HarnessError> {
-        let stream = self
+    ) -> Result<(), HarnessError> {
+        self.opencode_client
+            .abort_session(&harness_session_id, directory.as_deref())
+            .await
+            .map_err(HarnessError::ApiRequest)
+    }
+
+    async fn list_models(
+        &self,
+        directory: Option<String>,
+    ) -> Result<HarnessModelCatalog, HarnessError> {
+        let providers = self
             .opencode_client
-            .get_event_stream(directory.as_deref())
+            .get_providers(directory.as_deref())
             .await
-            .map_err(HarnessError::ApiTransport)?;
-
-        let mapped = stream.filter_map(move |item| {
-            let harness_session_id = harness_session_id.clone();
-            async move {
-                let event = match item {
-                    Ok(event) => event,
-                    Err(err) => return Some(Err(stream_error("event stream error", err))),
-                };
-
-                let payload = match parse_event_payload(&event.data) {
-                    Ok(Some(payload)) => payload,
-                    Ok(None) => return None,
-                    Err(err) => return Some(Err(err)),
-                };
-
-                map_payload_to_harness_event(payload, &harness_session_id)
-            }
-        });
+            .map_err(HarnessError::ApiRequest)?;
+        Ok(model_catalog(providers))
+    }
+
+    async fn list_agents(
+        &self,
+        directory: Option<String>,
+    ) -> Result<Vec<HarnessAgent>, HarnessError> {
+        let agents = self
+            .opencode_client
+            .get_agents(directory.as_deref())
+            .await
+            .map_err(HarnessError::ApiRequest)?;
+        let mut agents = agents
+            .into_iter()
+            .map(HarnessAgent::from)
+            .collect::<Vec<_>>();
+        agents.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(agents)
+    }
 
-        Ok(Box::pin(mapped))
+    fn subscribe_health(&self) -> watch::Receiver<HarnessHealth> {
+        self.supervisor.subscribe_health()
     }
 
-    async fn get_event_stream(
+    async fn fork_session(
         &self,
-    ) -> anyhow::Result<
-        Pin<
-            Box<
-                dyn futures::Stream<
-                        Item = Result<
-                            eventsource_stream::Event,
-                            eventsource_stream::EventStreamError<reqwest::Error>,
-                        >,
-                    > + Send,
-            >,
-        >,
-    > {
-        let stream = self
+        harness_session_id: String,
+        through_message_id: Option<String>,
+        directory: Option<String>,
+    ) -> Result<String, HarnessError> {
+        let messages = self
             .opencode_client
-            .get_event_stream(None)
+            .get_session_messages(&harness_session_id, None, directory.as_deref())
             .await
-            .map_err(OpencodeHarnessError::ApiTransport)?;
-        Ok(Box::pin(stream))
+            .map_err(HarnessError::ApiRequest)?;
+        let message_ids = messages
+            .iter()
+            .map(|message| message.id().to_string())
+            .collect::<Vec<_>>();
+        let before = fork_boundary(&message_ids, through_message_id.as_deref())?;
+
+        let forked = self
+            .opencode_client
+            .fork_session(&harness_session_id, before, directory.as_deref())
+            .await
+            .map_err(HarnessError::ApiRequest)?;
+        Ok(forked.id)
     }
-}
 
-fn stream_error(context: &str, err: impl std::fmt::Display) -> HarnessError {
-    HarnessError::ApiRequest(anyhow::anyhow!("{context}: {err}"))
-}
+    async fn revert_session(
+        &self,
+        harness_session_id: String,
+        point: RevertPoint,
+        directory: Option<String>,
+    ) -> Result<bool, HarnessError> {
+        let (message_id, part_id) = match point {
+            RevertPoint::PromptOf { reply_id } => {
+                let messages = self
+                    .opencode_client
+                    .get_session_messages(&harness_session_id, None, directory.as_deref())
+                    .await
+                    .map_err(HarnessError::ApiRequest)?;
+                (prompt_of(&messages, &reply_id)?.to_string(), None)
+            }
+            RevertPoint::Message { message_id } => (message_id, None),
+            RevertPoint::Part {
+                message_id,
+                part_id,
+            } => (message_id, Some(part_id)),
+        };
 
-fn is_supported_event_type(event_type: &str) -> bool {
-    matches!(
-        event_type,
-        "session.status"
-            | "message.updated"
-            | "message.part.updated"
-            | "message.part.delta"
-            | "session.error"
-    )
-}
+        self.opencode_client
+            .revert_session(
+                &harness_session_id,
+                &message_id,
+                part_id.as_deref(),
+                directory.as_deref(),
+            )
+            .await
+            .map_err(HarnessError::ApiRequest)?;
+        Ok(true)
+    }
 
-fn parse_event_payload(data: &str) -> Result<Option<OpencodeEventPayload>, HarnessError> {
-    let payload: serde_json::Value = serde_json::from_str(data).map_err(|err| {
-        HarnessError::ApiRequest(anyhow::anyhow!(
-            "failed to parse opencode event JSON: {err}; data={data}"
-        ))
-    })?;
-
-    let event_type = payload
-        .get("type")
-        .and_then(serde_json::Value::as_str)
-        .unwrap_or_default();
-    if !is_supported_event_type(event_type) {
-        return Ok(None);
+    async fn unrevert_session(
+        &self,
+        harness_session_id: String,
+        directory: Option<String>,
+    ) -> Result<bool, HarnessError> {
+        self.opencode_client
+            .unrevert_session(&harness_session_id, directory.as_deref())
+            .await
+            .map_err(HarnessError::ApiRequest)?;
+        Ok(true)
     }
 
-    serde_json::from_value(payload).map(Some).map_err(|err| {
-        HarnessError::ApiRequest(anyhow::anyhow!("failed to decode opencode event: {err}"))
-    })
+    async fn listen_assistant_events(
+        &self,
+        harness_session_id: String,
+        directory: Option<String>,
+    ) -> Result<HarnessAssistantEventStream, HarnessError> {
+        self.events.subscribe(harness_session_id, directory).await
+    }
 }
 
-fn map_payload_to_harness_event(
-    payload: OpencodeEventPayload,
-    harness_session_id: &str,
-) -> Option<Result<HarnessAssistantEvent, HarnessError>> {
-    match payload {
-        OpencodeEventPayload::SessionStatus { props } => {
-            if props.session_id != harness_session_id {
-                return None;
-            }
-            Some(Ok(HarnessAssistantEvent::SessionStatus {
-                harness_session_id: props.session_id,
-                status: map_session_status(props.status),
-            }))
-        }
-        OpencodeEventPayload::MessageUpdated { props } => {
-            map_message_updated(props.info, harness_session_id).map(Ok)
-        }
-        OpencodeEventPayload::MessagePartUpdated { props } => {
-            map_message_part_updated(props.part, harness_session_id).map(Ok)
-        }
-        OpencodeEventPayload::MessagePartDelta { props } => {
-            if props.session_id != harness_session_id {
-                return None;
-            }
-            Some(Ok(HarnessAssistantEvent::MessagePartDelta {
-                harness_session_id: props.session_id,
-                message_id: props.message_id,
-                part_id: props.part_id,
-                field: props.field,
-                delta: props.delta,
-            }))
-        }
-        OpencodeEventPayload::SessionError { props } => {
-            if let Some(session_id) = &props.session_id
-                && session_id != harness_session_id
-            {
-                return None;
-            }
-            Some(Ok(HarnessAssistantEvent::SessionError {
-                harness_session_id: props.session_id,
-                error: props.error.to_string(),
-            }))
+impl From<OpencodeAgentInfo> for HarnessAgent {
+    fn from(agent: OpencodeAgentInfo) -> Self {
+        Self {
+            primary: matches!(agent.mode.as_str(), "primary" | "all"),
+            subagent: matches!(agent.mode.as_str(), "subagent" | "all"),
+            name: agent.name,
+            description: agent.description,
         }
-        _ => None,
     }
 }
 
-fn map_session_status(status: OpencodeSessionStatus) -> HarnessSessionStatus {
-    match status {
-        OpencodeSessionStatus::Idle => HarnessSessionStatus::Idle,
-        OpencodeSessionStatus::Busy => HarnessSessionStatus::Busy,
-        OpencodeSessionStatus::Retry {
-            attempt,
-            message,
-            next,
-        } => HarnessSessionStatus::Retry {
-            attempt,
-            message,
-            next,
-        },
-    }
+/// Opencode forks the messages before the one it is given, so this is the message after
+/// `through`. `None` when the fork takes every message
+fn fork_boundary<'a>(
+    message_ids: &'a [String],
+    through: Option<&str>,
+) -> Result<Option<&'a str>, HarnessError> {
+    let Some(through) = through else {
+        return Ok(message_ids.first().map(String::as_str));
+    };
+    let index = message_ids
+        .iter()
+        .position(|id| id == through)
+        .ok_or_else(|| HarnessError::InvalidRequest(format!("unknown message {through}")))?;
+    Ok(message_ids.get(index + 1).map(String::as_str))
 }
 
-fn map_message_updated(
-    message: OpencodeMessage,
-    harness_session_id: &str,
-) -> Option<HarnessAssistantEvent> {
-    match message {
-        OpencodeMessage::Assistant(assistant) if assistant.session_id == harness_session_id => {
-            Some(HarnessAssistantEvent::MessageUpdated {
-                harness_session_id: assistant.session_id,
-                message_id: assistant.id,
-                completed_at: assistant.time.completed,
-                error: assistant
-                    .error
-                    .and_then(|err| serde_json::to_string(&err).ok()),
-            })
-        }
-        _ => None,
-    }
+/// Id of the user message the assistant message `reply_id` answers
+fn prompt_of<'a>(
+    messages: &'a [OpencodeMessageWithParts],
+    reply_id: &str,
+) -> Result<&'a str, HarnessError> {
+    messages
+        .iter()
+        .find_map(|message| match &message.info {
+            OpencodeMessage::Assistant(assistant) if assistant.id == reply_id => {
+                Some(assistant.parent_id.as_str())
+            }
+            _ => None,
+        })
+        .ok_or_else(|| HarnessError::InvalidRequest(format!("unknown message {reply_id}")))
 }
 
-fn map_message_part_updated(
-    part: crate::backend::harness::opencode_client::OpencodePart,
-    harness_session_id: &str,
-) -> Option<HarnessAssistantEvent> {
-    if part.session_id() != harness_session_id {
-        return None;
+/// Lists the models of connected providers, the others cannot run prompts.
+/// The default is the first connected provider's default model, like opencode picks it
+fn model_catalog(providers: OpencodeProviderListResponse) -> HarnessModelCatalog {
+    let mut models = providers
+        .all
+        .iter()
+        .filter(|provider| providers.connected.contains(&provider.id))
+        .flat_map(|provider| {
+            provider.models.values().map(|model| HarnessModel {
+                provider_id: provider.id.clone(),
+                provider_name: provider.name.clone(),
+                model_id: model.id.clone(),
+                model_name: model.name.clone(),
+            })
+        })
+        .collect::<Vec<_>>();
+    models
+        .sort_by(|a, b| (&a.provider_name, &a.model_name).cmp(&(&b.provider_name, &b.model_name)));
+
+    let default_model = providers.connected.iter().find_map(|provider_id| {
+        let model_id = providers.default.get(provider_id)?;
+        models
+            .iter()
+            .any(|model| &model.provider_id == provider_id && &model.model_id == model_id)
+            .then(|| Model {
+                provider_id: provider_id.clone(),
+                model_id: model_id.clone(),
+            })
+    });
+
+    HarnessModelCatalog {
+        models,
+        default_model,
     }
+}
 
-    Some(HarnessAssistantEvent::MessagePartUpdated {
-        harness_session_id: part.session_id().to_string(),
-        message_id: part.message_id().to_string(),
-        part_id: part.id().to_string(),
-        part_type: part.part_type().to_string(),
-        payload: serde_json::to_value(part).unwrap_or(serde_json::Value::Null),
-    })
+/// Makes opencode ask before editing files, running commands or fetching urls
+fn ask_permission_ruleset() -> serde_json::Value {
+    serde_json::json!([
+        { "permission": "edit", "pattern": "*", "action": "ask" },
+        { "permission": "bash", "pattern": "*", "action": "ask" },
+        { "permission": "webfetch", "pattern": "*", "action": "ask" },
+    ])
 }
 
 impl OpencodeHarness {
-    fn new_with_port(port: u32) -> anyhow::Result<Self> {
-        log::debug!("Starting opencode on port {port}");
-        let proc = unsafe {
-            Command::new("opencode")
-                .arg("serve")
-                .arg("--port")
-                .arg(port.to_string())
-                .arg("--print-logs")
-                .arg("--log-level")
-                .arg("DEBUG")
-                .pre_exec(|| {
-                    libc::setpgid(0, 0);
-                    Ok(())
-                })
-                .spawn()
-                .map_err(OpencodeHarnessError::Spawn)?
-        };
-
-        log::debug!("Opencode running on port {port}");
+    pub fn new() -> Result<Self, OpencodeHarnessError> {
+        Self::new_with_port(free_port()?)
+    }
 
+    /// Starts `opencode serve` under a supervisor, which moves it to another port on restarts
+    fn new_with_port(port: u32) -> Result<Self, OpencodeHarnessError> {
         let opencode_client = OpencodeApiClient::new(port);
+        let supervisor = OpencodeSupervisor::spawn(port, opencode_client.clone())?;
 
         Ok(Self {
-            proc: Arc::new(Mutex::new(Some(proc))),
+            supervisor,
+            events: Arc::new(EventForwarder::new(opencode_client.clone(), None)),
             opencode_client,
+            permissions: None,
         })
     }
+
+    /// Waits until the server answers requests, so the first session does not race its startup
+    pub async fn wait_until_ready(&self, timeout: Duration) -> Result<(), OpencodeHarnessError> {
+        self.supervisor.wait_ready(timeout).await
+    }
+
+    /// Routes opencode's permission prompts through the broker instead of allowing everything
+    pub fn with_permissions(mut self, permissions: Arc<PermissionBroker>) -> Self {
+        self.events = Arc::new(EventForwarder::new(
+            self.opencode_client.clone(),
+            Some(Arc::clone(&permissions)),
+        ));
+        self.permissions = Some(permissions);
+        self
+    }
 }
 
 #[cfg(test)]
@@ -439,20 +390,23 @@ impl OpencodeHarness {
     }
 
     pub(crate) fn new_for_test(port: u32) -> Self {
+        let opencode_client = OpencodeApiClient::new(port);
         Self {
-            proc: Arc::new(Mutex::new(None)),
-            opencode_client: OpencodeApiClient::new(port),
+            supervisor: OpencodeSupervisor::unsupervised(opencode_client.clone()),
+            events: Arc::new(EventForwarder::new(opencode_client.clone(), None)),
+            opencode_client,
+            permissions: None,
         }
     }
 
-    pub(crate) fn new_with_process_for_test(port: u32) -> anyhow::Result<Self> {
+    pub(crate) fn new_with_process_for_test(port: u32) -> Result<Self, OpencodeHarnessError> {
         Self::new_with_port(port)
     }
 }
 
 impl Drop for OpencodeHarness {
     fn drop(&mut self) {
-        if Arc::strong_count(&self.proc) != 1 {
+        if Arc::strong_count(&self.supervisor) != 1 {
             log::debug!("OpencodeHarness dropped but shared owners remain, skipping cleanup");
             return;
         }
@@ -461,3 +415,192 @@ impl Drop for OpencodeHarness {
         self.cleanup();
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn model_catalog_lists_connected_providers_with_their_default() {
+        let providers: OpencodeProviderListResponse = serde_json::from_value(serde_json::json!({
+            "all": [
+                {
+                    "id": "openai",
+                    "name": "OpenAI",
+                    "models": {
+                        "gpt-5": { "id": "gpt-5", "name": "GPT-5" },
+                        "gpt-5-mini": { "id": "gpt-5-mini", "name": "GPT-5 mini" }
+                    }
+                },
+                {
+                    "id": "anthropic",
+                    "name": "Anthropic",
+                    "models": { "sonnet": { "id": "sonnet", "name": "Sonnet" } }
+                }
+            ],
+            "default": { "openai": "gpt-5-mini", "anthropic": "sonnet" },
+            "connected": ["openai"]
+        }))
+        .unwrap();
+
+        let catalog = model_catalog(providers);
+
+        let ids = catalog
+            .models
+            .iter()
+            .map(|model| model.model_id.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(ids, ["gpt-5", "gpt-5-mini"]);
+        assert_eq!(
+            catalog.default_model,
+            Some(Model {
+                provider_id: "openai".to_string(),
+                model_id: "gpt-5-mini".to_string(),
+            })
+        );
+    }
+
+    #[test]
+    fn agent_modes_decide_where_agents_can_be_used() {
+        let agents: Vec<OpencodeAgentInfo> = serde_json::from_value(serde_json::json!([
+            { "name": "build", "mode": "primary" },
+            { "name": "general", "description": "Researches", "mode": "subagent" },
+            { "name": "docs", "mode": "all" }
+        ]))
+        .unwrap();
+
+        let agents = agents
+            .into_iter()
+            .map(HarnessAgent::from)
+            .map(|agent| (agent.name, agent.primary, agent.subagent))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            agents,
+            [
+                ("build".to_string(), true, false),
+                ("general".to_string(), false, true),
+                ("docs".to_string(), true, true),
+            ]
+        );
+    }
+
+    #[test]
+    fn agent_parts_keep_where_they_were_mentioned() {
+        let now = chrono::Utc::now().naive_utc();
+        let part = UserMessagePart {
+            id: uuid::Uuid::new_v4(),
+            user_message_id: uuid::Uuid::new_v4(),
+            session_id: uuid::Uuid::new_v4(),
+            position: 1,
+            part_type: "agent".to_string(),
+            text: None,
+            file_name: None,
+            file_url: None,
+            file_mime: None,
+            agent_name: Some("general".to_string()),
+            agent_source_value: Some("@general".to_string()),
+            agent_source_start: Some(4),
+            agent_source_end: Some(12),
+            file_source_path: None,
+            file_source_text_value: None,
+            file_source_text_start: None,
+            file_source_text_end: None,
+            subtask_prompt: None,
+            subtask_description: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let input = OpencodePartInput::try_from(part).unwrap();
+
+        assert_eq!(
+            serde_json::to_value(input).unwrap()["source"],
+            serde_json::json!({ "value": "@general", "start": 4, "end": 12 })
+        );
+    }
+
+    #[test]
+    fn file_parts_keep_the_mentioned_project_path() {
+        let now = chrono::Utc::now().naive_utc();
+        let part = UserMessagePart {
+            id: uuid::Uuid::new_v4(),
+            user_message_id: uuid::Uuid::new_v4(),
+            session_id: uuid::Uuid::new_v4(),
+            position: 1,
+            part_type: "file".to_string(),
+            text: None,
+            file_name: Some("src/main.rs".to_string()),
+            file_url: Some("file:///work/src/main.rs".to_string()),
+            file_mime: Some("text/plain".to_string()),
+            agent_name: None,
+            agent_source_value: None,
+            agent_source_start: None,
+            agent_source_end: None,
+            file_source_path: Some("/work/src/main.rs".to_string()),
+            file_source_text_value: Some("@src/main.rs".to_string()),
+            file_source_text_start: Some(5),
+            file_source_text_end: Some(17),
+            subtask_prompt: None,
+            subtask_description: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let input = serde_json::to_value(OpencodePartInput::try_from(part).unwrap()).unwrap();
+
+        assert_eq!(input["type"], "file");
+        assert_eq!(
+            input["source"],
+            serde_json::json!({
+                "type": "file",
+                "path": "/work/src/main.rs",
+                "text": { "value": "@src/main.rs", "start": 5, "end": 17 },
+            })
+        );
+    }
+
+    #[test]
+    fn image_parts_are_inlined_for_the_model() {
+        let dir = tempfile::tempdir().unwrap();
+        let image = dir.path().join("shot.png");
+        std::fs::write(&image, b"\x89PNG").unwrap();
+        let now = chrono::Utc::now().naive_utc();
+        let part = UserMessagePart {
+            id: uuid::Uuid::new_v4(),
+            user_message_id: uuid::Uuid::new_v4(),
+            session_id: uuid::Uuid::new_v4(),
+            position: 0,
+            part_type: "file".to_string(),
+            text: None,
+            file_name: Some("shot.png".to_string()),
+            file_url: Some(crate::backend::attachment::file_url(&image)),
+            file_mime: Some("image/png".to_string()),
+            agent_name: None,
+            agent_source_value: None,
+            agent_source_start: None,
+            agent_source_end: None,
+            file_source_path: None,
+            file_source_text_value: None,
+            file_source_text_start: None,
+            file_source_text_end: None,
+            subtask_prompt: None,
+            subtask_description: None,
+            created_at: now,
+            updated_at: now,
+        };
+
+        let input = serde_json::to_value(OpencodePartInput::try_from(part).unwrap()).unwrap();
+
+        assert_eq!(input["url"], "data:image/png;base64,iVBORw==");
+    }
+
+    #[test]
+    fn forks_end_before_the_message_after_the_last_one_kept() {
+        let ids = ["msg_1", "msg_2", "msg_3"].map(str::to_string);
+
+        assert_eq!(fork_boundary(&ids, Some("msg_2")).unwrap(), Some("msg_3"));
+        assert_eq!(fork_boundary(&ids, Some("msg_3")).unwrap(), None);
+        assert_eq!(fork_boundary(&ids, None).unwrap(), Some("msg_1"));
+        assert!(fork_boundary(&ids, Some("msg_9")).is_err());
+    }
+}
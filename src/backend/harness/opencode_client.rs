@@ -4,14 +4,18 @@ use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::pin::Pin;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
+use crate::backend::attachment;
 use crate::backend::harness::Model;
 use crate::backend::repo::{user_message::UserMessage, user_message_part::UserMessagePart};
 
+/// Clones share the server url, so a restart on another port reaches every clone
 #[derive(Clone)]
 pub struct OpencodeApiClient {
     http_client: Client,
-    server_url: String,
+    server_url: Arc<RwLock<String>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -60,6 +64,16 @@ pub struct OpencodeProviderListResponse {
     pub connected: Vec<String>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpencodeAgentInfo {
+    pub name: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    /// `primary`, `subagent` or `all`
+    pub mode: String,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "type")]
 pub enum OpencodePartInput {
@@ -72,6 +86,7 @@ pub enum OpencodePartInput {
         #[serde(skip_serializing_if = "Option::is_none")]
         ignored: Option<bool>,
     },
+    #[serde(rename = "file")]
     OpencodeFile {
         #[serde(skip_serializing_if = "Option::is_none")]
         id: Option<String>,
@@ -79,6 +94,8 @@ pub enum OpencodePartInput {
         #[serde(skip_serializing_if = "Option::is_none")]
         filename: Option<String>,
         url: String,
+        #[serde(skip_serializing_if = "Option::is_none")]
+        source: Option<OpencodeFileSource>,
     },
     Agent {
         #[serde(skip_serializing_if = "Option::is_none")]
@@ -103,6 +120,31 @@ pub struct OpencodeSourceRange {
     pub end: i32,
 }
 
+impl OpencodeSourceRange {
+    fn from_parts(
+        value: Option<String>,
+        start: Option<i64>,
+        end: Option<i64>,
+    ) -> anyhow::Result<Option<Self>> {
+        match (value, start, end) {
+            (Some(value), Some(start), Some(end)) => Ok(Some(Self {
+                value,
+                start: i32::try_from(start)?,
+                end: i32::try_from(end)?,
+            })),
+            _ => Ok(None),
+        }
+    }
+}
+
+/// Project file a file part was mentioned as in the prompt
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename = "file")]
+pub struct OpencodeFileSource {
+    pub path: String,
+    pub text: OpencodeSourceRange,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
 pub struct OpencodeSendMessageRequest {
@@ -134,12 +176,19 @@ impl From<&UserMessage> for OpencodeSendMessageRequest {
                 }
             });
 
-        Self {
-            message_id: None,
-            model: Some(ModelSelection {
+        // An empty model lets opencode fall back to its configured default
+        let model = if value.model_provider_id.is_empty() || value.model_id.is_empty() {
+            None
+        } else {
+            Some(ModelSelection {
                 provider_id: value.model_provider_id.clone(),
                 model_id: value.model_id.clone(),
-            }),
+            })
+        };
+
+        Self {
+            message_id: None,
+            model,
             agent: Some(value.agent.clone()),
             no_reply: None,
             system: value.system_prompt.clone(),
@@ -162,20 +211,47 @@ impl TryFrom<UserMessagePart> for OpencodePartInput {
                 synthetic: None,
                 ignored: None,
             }),
-            "file" | "opencode_file" => Ok(Self::OpencodeFile {
-                id: Some(value.id.to_string()),
-                mime: "application/octet-stream".to_string(),
-                filename: value.file_name,
-                url: value
+            "file" | "opencode_file" => {
+                let mime = value
+                    .file_mime
+                    .unwrap_or_else(|| "application/octet-stream".to_string());
+                let url = value
                     .file_url
-                    .ok_or_else(|| anyhow::anyhow!("file part missing file_url"))?,
-            }),
+                    .ok_or_else(|| anyhow::anyhow!("file part missing file_url"))?;
+                // Images are stored by path, the model only sees them inlined
+                let url = if mime.starts_with("image/") {
+                    attachment::inline_file_url(&url, &mime)?
+                } else {
+                    url
+                };
+                Ok(Self::OpencodeFile {
+                    id: Some(value.id.to_string()),
+                    mime,
+                    filename: value.file_name,
+                    url,
+                    source: match (
+                        value.file_source_path,
+                        OpencodeSourceRange::from_parts(
+                            value.file_source_text_value,
+                            value.file_source_text_start,
+                            value.file_source_text_end,
+                        )?,
+                    ) {
+                        (Some(path), Some(text)) => Some(OpencodeFileSource { path, text }),
+                        _ => None,
+                    },
+                })
+            }
             "agent" => Ok(Self::Agent {
                 id: Some(value.id.to_string()),
                 name: value
                     .agent_name
                     .ok_or_else(|| anyhow::anyhow!("agent part missing agent_name"))?,
-                source: None,
+                source: OpencodeSourceRange::from_parts(
+                    value.agent_source_value,
+                    value.agent_source_start,
+                    value.agent_source_end,
+                )?,
             }),
             "subtask" => Ok(Self::Subtask {
                 id: Some(value.id.to_string()),
@@ -204,6 +280,22 @@ pub struct OpencodeCreateSessionRequest {
     pub permission: Option<serde_json::Value>,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpencodeForkSessionRequest {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "messageID")]
+    pub message_id: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OpencodeRevertSessionRequest {
+    #[serde(rename = "messageID")]
+    pub message_id: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    #[serde(rename = "partID")]
+    pub part_id: Option<String>,
+}
+
 // OpencodeMessage types from types.ts
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase")]
@@ -233,7 +325,8 @@ pub struct FileDiff {
 pub struct MessageSummary {
     pub title: Option<String>,
     pub body: Option<String>,
-    pub diffs: Vec<FileDiff>,
+    /// Left out until the turn is summarized
+    pub diffs: Option<Vec<FileDiff>>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -560,6 +653,18 @@ pub struct OpencodeGlobalEvent {
     pub payload: OpencodeEventPayload,
 }
 
+/// Raw server-sent events from `/event`
+pub type OpencodeEventStream = Pin<
+    Box<
+        dyn futures::Stream<
+                Item = Result<
+                    eventsource_stream::Event,
+                    eventsource_stream::EventStreamError<reqwest::Error>,
+                >,
+            > + Send,
+    >,
+>;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 #[serde(rename_all = "camelCase", tag = "type")]
 pub enum OpencodeEventPayload {
@@ -593,6 +698,11 @@ pub enum OpencodeEventPayload {
         #[serde(rename = "properties")]
         props: OpencodeSessionErrorProps,
     },
+    #[serde(rename = "permission.asked")]
+    PermissionAsked {
+        #[serde(rename = "properties")]
+        props: OpencodePermissionAskedProps,
+    },
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -667,18 +777,74 @@ pub struct OpencodeSessionErrorProps {
     pub error: serde_json::Value,
 }
 
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpencodePermissionAskedProps {
+    pub id: String,
+    #[serde(rename = "sessionID", alias = "sessionId")]
+    pub session_id: String,
+    pub permission: String,
+    #[serde(default)]
+    pub patterns: Vec<String>,
+    #[serde(default)]
+    pub tool: Option<OpencodePermissionTool>,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OpencodePermissionTool {
+    #[serde(rename = "messageID", alias = "messageId")]
+    pub message_id: String,
+    #[serde(rename = "callID", alias = "callId")]
+    pub call_id: String,
+}
+
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub enum OpencodePermissionReply {
+    Once,
+    Always,
+    Reject,
+}
+
 impl OpencodeApiClient {
     pub fn new(port: u32) -> Self {
         Self {
             http_client: Client::new(),
-            server_url: format!("http://127.0.0.1:{port}"),
+            server_url: Arc::new(RwLock::new(server_url(port))),
         }
     }
 
+    /// Points this client and every clone at a server restarted on `port`
+    pub fn set_port(&self, port: u32) {
+        *self
+            .server_url
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = server_url(port);
+    }
+
+    fn server_url(&self) -> String {
+        self.server_url
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Succeeds when the server answers API requests
+    pub async fn health_check(&self, timeout: Duration) -> anyhow::Result<()> {
+        self.http_client
+            .get(format!("{}/config", self.server_url()))
+            .timeout(timeout)
+            .send()
+            .await?
+            .error_for_status()?;
+        Ok(())
+    }
+
     pub async fn get_sessions(&self) -> anyhow::Result<Vec<OpencodeSession>> {
         let sessions: Vec<OpencodeSession> = self
             .http_client
-            .get(format!("{}/session", self.server_url))
+            .get(format!("{}/session", self.server_url()))
             .send()
             .await?
             .json()
@@ -693,7 +859,7 @@ impl OpencodeApiClient {
     ) -> anyhow::Result<OpencodeSession> {
         let mut req = self
             .http_client
-            .post(format!("{}/session", self.server_url));
+            .post(format!("{}/session", self.server_url()));
 
         if let Some(dir) = directory {
             req = req.query(&[("directory", dir)]);
@@ -709,22 +875,10 @@ impl OpencodeApiClient {
     pub async fn get_event_stream(
         &self,
         directory: Option<&str>,
-    ) -> Result<
-        Pin<
-            Box<
-                dyn futures::Stream<
-                        Item = Result<
-                            eventsource_stream::Event,
-                            eventsource_stream::EventStreamError<reqwest::Error>,
-                        >,
-                    > + Send,
-            >,
-        >,
-        reqwest::Error,
-    > {
+    ) -> Result<OpencodeEventStream, reqwest::Error> {
         use eventsource_stream::Eventsource;
 
-        let mut request = self.http_client.get(format!("{}/event", self.server_url));
+        let mut request = self.http_client.get(format!("{}/event", self.server_url()));
         if let Some(dir) = directory {
             request = request.query(&[("directory", dir)]);
         }
@@ -743,7 +897,8 @@ impl OpencodeApiClient {
             .http_client
             .post(format!(
                 "{}/session/{}/prompt_async",
-                self.server_url, session_id
+                self.server_url(),
+                session_id
             ))
             .json(request);
         if let Some(dir) = directory {
@@ -762,6 +917,125 @@ impl OpencodeApiClient {
         Ok(())
     }
 
+    /// Stops the session's running turn, opencode answers whether one was running
+    pub async fn abort_session(
+        &self,
+        session_id: &str,
+        directory: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut req = self.http_client.post(format!(
+            "{}/session/{}/abort",
+            self.server_url(),
+            session_id
+        ));
+        if let Some(dir) = directory {
+            req = req.query(&[("directory", dir)]);
+        }
+        let response = req.send().await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(anyhow::anyhow!(
+                "opencode abort_session failed with status {status}: {body}"
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Copies the session's messages that come before `message_id` into a new session,
+    /// all of them without one
+    pub async fn fork_session(
+        &self,
+        session_id: &str,
+        message_id: Option<&str>,
+        directory: Option<&str>,
+    ) -> anyhow::Result<OpencodeSession> {
+        let mut req = self
+            .http_client
+            .post(format!("{}/session/{}/fork", self.server_url(), session_id))
+            .json(&OpencodeForkSessionRequest {
+                message_id: message_id.map(str::to_string),
+            });
+        if let Some(dir) = directory {
+            req = req.query(&[("directory", dir)]);
+        }
+        let response = req.send().await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(anyhow::anyhow!(
+                "opencode fork_session failed with status {status}: {body}"
+            ));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    /// Undoes the session from `message_id`, or from `part_id` within it, and restores
+    /// the files opencode snapshotted before it
+    pub async fn revert_session(
+        &self,
+        session_id: &str,
+        message_id: &str,
+        part_id: Option<&str>,
+        directory: Option<&str>,
+    ) -> anyhow::Result<OpencodeSession> {
+        let mut req = self
+            .http_client
+            .post(format!(
+                "{}/session/{}/revert",
+                self.server_url(),
+                session_id
+            ))
+            .json(&OpencodeRevertSessionRequest {
+                message_id: message_id.to_string(),
+                part_id: part_id.map(str::to_string),
+            });
+        if let Some(dir) = directory {
+            req = req.query(&[("directory", dir)]);
+        }
+        let response = req.send().await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(anyhow::anyhow!(
+                "opencode revert_session failed with status {status}: {body}"
+            ));
+        }
+
+        Ok(response.json().await?)
+    }
+
+    pub async fn unrevert_session(
+        &self,
+        session_id: &str,
+        directory: Option<&str>,
+    ) -> anyhow::Result<OpencodeSession> {
+        let mut req = self.http_client.post(format!(
+            "{}/session/{}/unrevert",
+            self.server_url(),
+            session_id
+        ));
+        if let Some(dir) = directory {
+            req = req.query(&[("directory", dir)]);
+        }
+        let response = req.send().await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(anyhow::anyhow!(
+                "opencode unrevert_session failed with status {status}: {body}"
+            ));
+        }
+
+        Ok(response.json().await?)
+    }
+
     pub async fn get_session_messages(
         &self,
         session_id: &str,
@@ -770,7 +1044,8 @@ impl OpencodeApiClient {
     ) -> anyhow::Result<Vec<OpencodeMessageWithParts>> {
         let mut request = self.http_client.get(format!(
             "{}/session/{}/message",
-            self.server_url, session_id
+            self.server_url(),
+            session_id
         ));
         if let Some(l) = limit {
             request = request.query(&[("limit", l.to_string())]);
@@ -788,11 +1063,57 @@ impl OpencodeApiClient {
     ) -> anyhow::Result<OpencodeProviderListResponse> {
         let mut request = self
             .http_client
-            .get(format!("{}/provider", self.server_url));
+            .get(format!("{}/provider", self.server_url()));
         if let Some(dir) = directory {
             request = request.query(&[("directory", dir)]);
         }
         let response: OpencodeProviderListResponse = request.send().await?.json().await?;
         Ok(response)
     }
+
+    pub async fn get_agents(
+        &self,
+        directory: Option<&str>,
+    ) -> anyhow::Result<Vec<OpencodeAgentInfo>> {
+        let mut request = self.http_client.get(format!("{}/agent", self.server_url()));
+        if let Some(dir) = directory {
+            request = request.query(&[("directory", dir)]);
+        }
+        let agents: Vec<OpencodeAgentInfo> = request.send().await?.json().await?;
+        Ok(agents)
+    }
+
+    pub async fn reply_permission(
+        &self,
+        request_id: &str,
+        reply: OpencodePermissionReply,
+        directory: Option<&str>,
+    ) -> anyhow::Result<()> {
+        let mut req = self
+            .http_client
+            .post(format!(
+                "{}/permission/{}/reply",
+                self.server_url(),
+                request_id
+            ))
+            .json(&serde_json::json!({ "reply": reply }));
+        if let Some(dir) = directory {
+            req = req.query(&[("directory", dir)]);
+        }
+        let response = req.send().await?;
+        let status = response.status();
+
+        if !status.is_success() {
+            let body = response.text().await?;
+            return Err(anyhow::anyhow!(
+                "opencode reply_permission failed with status {status}: {body}"
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+fn server_url(port: u32) -> String {
+    format!("http://127.0.0.1:{port}")
 }
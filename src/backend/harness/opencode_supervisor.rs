@@ -0,0 +1,401 @@
+use std::os::unix::process::CommandExt;
+use std::process::{Child, Command, ExitStatus};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard, Weak};
+use std::time::Duration;
+
+use tokio::sync::watch;
+use tokio::task::AbortHandle;
+
+use crate::backend::harness::{
+    HarnessHealth, opencode::OpencodeHarnessError, opencode_client::OpencodeApiClient,
+};
+
+const HEALTH_INTERVAL: Duration = Duration::from_secs(2);
+const HEALTH_TIMEOUT: Duration = Duration::from_secs(2);
+const READY_POLL_INTERVAL: Duration = Duration::from_millis(100);
+/// Failed checks in a row before a running server is restarted
+const MAX_FAILED_CHECKS: u32 = 3;
+/// opencode needs a while to boot, so a starting server gets more slack
+const MAX_FAILED_STARTUP_CHECKS: u32 = 15;
+const INITIAL_BACKOFF: Duration = Duration::from_millis(500);
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+/// Keeps `opencode serve` running. Health checks the server, restarts it on a free port with
+/// exponential backoff when it exits or stops answering, and publishes its health
+pub struct OpencodeSupervisor {
+    proc: Mutex<Option<Child>>,
+    client: OpencodeApiClient,
+    health: watch::Sender<HarnessHealth>,
+    stopped: AtomicBool,
+    task: Mutex<Option<AbortHandle>>,
+}
+
+impl OpencodeSupervisor {
+    /// Starts the server on `port` and watches it from a background task
+    pub fn spawn(port: u32, client: OpencodeApiClient) -> std::io::Result<Arc<Self>> {
+        let proc = spawn_server(port)?;
+        let (health, _) = watch::channel(HarnessHealth::Starting);
+        let supervisor = Arc::new(Self {
+            proc: Mutex::new(Some(proc)),
+            client,
+            health,
+            stopped: AtomicBool::new(false),
+            task: Mutex::new(None),
+        });
+
+        // The task only holds a weak reference, so dropping the harness still shuts down
+        let task = tokio::spawn(supervise(Arc::downgrade(&supervisor)));
+        *lock(&supervisor.task) = Some(task.abort_handle());
+        Ok(supervisor)
+    }
+
+    /// A supervisor for a server someone else runs, it never checks or restarts anything
+    #[cfg(test)]
+    pub fn unsupervised(client: OpencodeApiClient) -> Arc<Self> {
+        let (health, _) = watch::channel(HarnessHealth::Healthy);
+        Arc::new(Self {
+            proc: Mutex::new(None),
+            client,
+            health,
+            stopped: AtomicBool::new(true),
+            task: Mutex::new(None),
+        })
+    }
+
+    pub fn subscribe_health(&self) -> watch::Receiver<HarnessHealth> {
+        self.health.subscribe()
+    }
+
+    /// Stops supervising and terminates the server
+    pub fn shutdown(&self) {
+        self.stopped.store(true, Ordering::SeqCst);
+        if let Some(task) = lock(&self.task).take() {
+            task.abort();
+        }
+
+        match lock(&self.proc).take() {
+            Some(proc) => terminate(proc),
+            None => log::debug!("No opencode process to cleanup"),
+        }
+    }
+
+    fn is_stopped(&self) -> bool {
+        self.stopped.load(Ordering::SeqCst)
+    }
+
+    /// Polls the server until it answers, giving up early when the process exits
+    pub async fn wait_ready(&self, timeout: Duration) -> Result<(), OpencodeHarnessError> {
+        let deadline = tokio::time::Instant::now() + timeout;
+        loop {
+            if let Some(status) = self.exit_status() {
+                return Err(OpencodeHarnessError::ExitedBeforeReady(status.to_string()));
+            }
+
+            let last_error = match self.client.health_check(HEALTH_TIMEOUT).await {
+                Ok(()) => {
+                    log::debug!("Opencode ready");
+                    self.set_health(HarnessHealth::Healthy);
+                    return Ok(());
+                }
+                Err(err) => err.to_string(),
+            };
+            if tokio::time::Instant::now() >= deadline {
+                return Err(OpencodeHarnessError::NotReady {
+                    timeout,
+                    last_error,
+                });
+            }
+            tokio::time::sleep(READY_POLL_INTERVAL).await;
+        }
+    }
+
+    fn exit_status(&self) -> Option<ExitStatus> {
+        lock(&self.proc)
+            .as_mut()
+            .and_then(|proc| proc.try_wait().ok().flatten())
+    }
+
+    async fn check(&self) -> Result<(), String> {
+        if let Some(status) = self.exit_status() {
+            return Err(format!("opencode exited with {status}"));
+        }
+
+        self.client
+            .health_check(HEALTH_TIMEOUT)
+            .await
+            .map_err(|err| format!("opencode health check failed: {err}"))
+    }
+
+    /// Replaces the server with a fresh one on a free port
+    async fn restart(self: Arc<Self>) -> std::io::Result<()> {
+        let previous = lock(&self.proc).take();
+        if let Some(previous) = previous {
+            // Terminating waits for the process, keep that off the runtime threads
+            let _ = tokio::task::spawn_blocking(move || terminate(previous)).await;
+        }
+        if self.is_stopped() {
+            return Ok(());
+        }
+
+        let port = free_port()?;
+        let proc = spawn_server(port)?;
+        *lock(&self.proc) = Some(proc);
+        self.client.set_port(port);
+        log::info!("Restarted opencode on port {port}");
+        Ok(())
+    }
+
+    fn set_health(&self, health: HarnessHealth) {
+        self.health.send_if_modified(|current| {
+            if *current == health {
+                return false;
+            }
+            *current = health;
+            true
+        });
+    }
+}
+
+impl Drop for OpencodeSupervisor {
+    fn drop(&mut self) {
+        log::debug!("OpencodeSupervisor dropped, initiating cleanup");
+        self.shutdown();
+    }
+}
+
+async fn supervise(supervisor: Weak<OpencodeSupervisor>) {
+    let mut failed_checks = 0;
+    let mut attempt = 0;
+    loop {
+        tokio::time::sleep(HEALTH_INTERVAL).await;
+        let Some(current) = supervisor.upgrade().filter(|current| !current.is_stopped()) else {
+            return;
+        };
+
+        let error = match current.check().await {
+            Ok(()) => {
+                failed_checks = 0;
+                attempt = 0;
+                current.set_health(HarnessHealth::Healthy);
+                continue;
+            }
+            Err(error) => error,
+        };
+
+        let exited = lock(&current.proc)
+            .as_mut()
+            .is_none_or(|proc| matches!(proc.try_wait(), Ok(Some(_))));
+        failed_checks += 1;
+        let max_failed_checks = if *current.health.borrow() == HarnessHealth::Healthy {
+            MAX_FAILED_CHECKS
+        } else {
+            MAX_FAILED_STARTUP_CHECKS
+        };
+        if !exited && failed_checks < max_failed_checks {
+            log::debug!("{error} ({failed_checks}/{max_failed_checks})");
+            continue;
+        }
+
+        attempt += 1;
+        failed_checks = 0;
+        log::warn!("{error}, restarting opencode (attempt {attempt})");
+        current.set_health(HarnessHealth::Restarting {
+            attempt,
+            error: error.clone(),
+        });
+        drop(current);
+
+        tokio::time::sleep(backoff(attempt)).await;
+        let Some(current) = supervisor.upgrade().filter(|current| !current.is_stopped()) else {
+            return;
+        };
+        if let Err(err) = current.restart().await {
+            log::error!("Failed to restart opencode: {err}");
+        }
+    }
+}
+
+fn backoff(attempt: u32) -> Duration {
+    INITIAL_BACKOFF
+        .saturating_mul(2u32.saturating_pow(attempt.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// Asks the OS for a port nobody listens on. Another process could still take it before
+/// opencode binds it, the health checks then restart on yet another port
+pub fn free_port() -> std::io::Result<u32> {
+    let listener = std::net::TcpListener::bind("127.0.0.1:0")?;
+    Ok(u32::from(listener.local_addr()?.port()))
+}
+
+fn spawn_server(port: u32) -> std::io::Result<Child> {
+    log::debug!("Starting opencode on port {port}");
+    let proc = unsafe {
+        Command::new("opencode")
+            .arg("serve")
+            .arg("--port")
+            .arg(port.to_string())
+            .arg("--print-logs")
+            .arg("--log-level")
+            .arg("DEBUG")
+            .pre_exec(|| {
+                libc::setpgid(0, 0);
+                Ok(())
+            })
+            .spawn()?
+    };
+    log::debug!("Spawned opencode on port {port}, waiting for it to answer");
+    Ok(proc)
+}
+
+/// Stops the server's whole process group, gracefully first and with SIGKILL if it lingers
+fn terminate(mut proc: Child) {
+    let pid = proc.id() as i32;
+    log::debug!("Cleaning up opencode process (PID: {})", pid);
+
+    // Send SIGTERM to the process group (negative PID kills the entire group)
+    let term_result = unsafe { libc::kill(-pid, libc::SIGTERM) };
+    if term_result != 0 {
+        log::debug!(
+            "Failed to send SIGTERM to opencode process group (PID: {}): {}",
+            pid,
+            std::io::Error::last_os_error()
+        );
+    }
+
+    // Wait for graceful shutdown (up to 2 seconds)
+    let mut exited = false;
+    for _ in 0..20 {
+        match proc.try_wait() {
+            Ok(Some(_)) => {
+                log::debug!("Opencode process terminated gracefully");
+                exited = true;
+                break;
+            }
+            Ok(None) => {
+                std::thread::sleep(std::time::Duration::from_millis(100));
+            }
+            Err(e) => {
+                log::warn!("Error waiting for opencode process: {}", e);
+                break;
+            }
+        }
+    }
+
+    // If still running, force kill with SIGKILL
+    if !exited {
+        log::warn!("Opencode process did not terminate gracefully, sending SIGKILL");
+        let kill_result = unsafe { libc::kill(-pid, libc::SIGKILL) };
+        if kill_result != 0 {
+            log::warn!(
+                "Failed to send SIGKILL to opencode process group (PID: {}): {}",
+                pid,
+                std::io::Error::last_os_error()
+            );
+        }
+
+        // Wait for process to exit with timeout
+        for _ in 0..50 {
+            match proc.try_wait() {
+                Ok(Some(_)) => {
+                    log::debug!("Opencode process killed successfully");
+                    exited = true;
+                    break;
+                }
+                Ok(None) => {
+                    std::thread::sleep(std::time::Duration::from_millis(100));
+                }
+                Err(e) => {
+                    log::warn!("Error waiting for opencode process after SIGKILL: {}", e);
+                    break;
+                }
+            }
+        }
+    }
+
+    // Final wait to reap the process
+    if !exited {
+        match proc.wait() {
+            Ok(status) => {
+                log::warn!(
+                    "Opencode process exited after timeout wait (PID: {}, status: {})",
+                    pid,
+                    status
+                );
+                exited = true;
+            }
+            Err(e) => {
+                log::error!("Failed to wait for opencode process (PID: {}): {}", pid, e);
+            }
+        }
+    }
+
+    if !exited {
+        log::error!("Failed to terminate opencode process (PID: {})", pid);
+    }
+
+    log::debug!("Opencode process cleanup completed");
+}
+
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn backoff_doubles_up_to_the_limit() {
+        assert_eq!(backoff(1), Duration::from_millis(500));
+        assert_eq!(backoff(2), Duration::from_secs(1));
+        assert_eq!(backoff(4), Duration::from_secs(4));
+        assert_eq!(backoff(20), MAX_BACKOFF);
+    }
+
+    #[test]
+    fn free_port_can_be_bound() {
+        let port = free_port().unwrap();
+        std::net::TcpListener::bind(("127.0.0.1", port as u16)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn wait_ready_times_out_while_nothing_listens() {
+        let supervisor =
+            OpencodeSupervisor::unsupervised(OpencodeApiClient::new(free_port().unwrap()));
+
+        let result = supervisor.wait_ready(Duration::from_millis(300)).await;
+        assert!(matches!(result, Err(OpencodeHarnessError::NotReady { .. })));
+    }
+
+    #[tokio::test]
+    async fn wait_ready_returns_once_the_server_answers() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+        let port = free_port().unwrap();
+        let supervisor = OpencodeSupervisor::unsupervised(OpencodeApiClient::new(port));
+
+        // Start listening only after the first checks already failed
+        let server = tokio::spawn(async move {
+            tokio::time::sleep(Duration::from_millis(250)).await;
+            let listener = tokio::net::TcpListener::bind(("127.0.0.1", port as u16))
+                .await
+                .unwrap();
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = [0_u8; 1024];
+            let _ = socket.read(&mut buf).await;
+            let _ = socket
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 2\r\nconnection: close\r\n\r\n{}")
+                .await;
+        });
+
+        supervisor
+            .wait_ready(Duration::from_secs(5))
+            .await
+            .expect("server should become ready");
+        server.await.unwrap();
+    }
+}
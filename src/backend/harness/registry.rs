@@ -0,0 +1,54 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::backend::harness::{DEFAULT_HARNESS_TYPE, Harness, HarnessError};
+
+/// Harnesses available to the backend, keyed by `harness_type`.
+/// Sessions store the type they were created with so every call routes back to the same harness
+#[derive(Clone, Default)]
+pub struct HarnessRegistry {
+    harnesses: HashMap<String, Arc<dyn Harness>>,
+}
+
+impl HarnessRegistry {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn with(mut self, harness: impl Harness + 'static) -> Self {
+        self.register(Arc::new(harness));
+        self
+    }
+
+    pub fn register(&mut self, harness: Arc<dyn Harness>) {
+        let harness_type = harness.harness_type().to_string();
+        if self
+            .harnesses
+            .insert(harness_type.clone(), harness)
+            .is_some()
+        {
+            log::warn!("replaced previously registered {harness_type} harness");
+        }
+    }
+
+    /// Looks up the harness for a session, an empty type falls back to the default harness
+    pub fn get(&self, harness_type: &str) -> Result<Arc<dyn Harness>, HarnessError> {
+        let harness_type = if harness_type.is_empty() {
+            DEFAULT_HARNESS_TYPE
+        } else {
+            harness_type
+        };
+
+        self.harnesses
+            .get(harness_type)
+            .cloned()
+            .ok_or_else(|| HarnessError::UnknownHarness(harness_type.to_string()))
+    }
+
+    /// Every registered harness, ordered by type
+    pub fn all(&self) -> Vec<Arc<dyn Harness>> {
+        let mut harnesses = self.harnesses.values().cloned().collect::<Vec<_>>();
+        harnesses.sort_by_key(|harness| harness.harness_type());
+        harnesses
+    }
+}
@@ -0,0 +1,564 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+    time::Duration,
+};
+
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use tokio::{
+    sync::broadcast,
+    task::AbortHandle,
+    time::{Instant, MissedTickBehavior},
+};
+use uuid::Uuid;
+
+use crate::backend::{
+    db::{Database, DatabaseError},
+    harness::{
+        HarnessAssistantEvent, HarnessAssistantEventStream, HarnessError, HarnessRegistry,
+        HarnessSessionStatus,
+    },
+    models::{file_diff_model::FileDiffModel, session_model::SessionModel},
+    proto_message::{self, MessageHistory, message_event::Event},
+    repo::assistant_message::{AssistantMessage, AssistantMessagePart},
+};
+
+mod part_buffer;
+
+use part_buffer::PartBuffer;
+
+#[cfg(test)]
+mod mod_test;
+#[cfg(test)]
+mod part_buffer_test;
+
+/// Changes a session keeps for a slow viewer before it starts skipping
+const CHANGE_BUFFER: usize = 256;
+/// How long an idle session with nobody watching waits for its last turn's summary
+const SUMMARY_WAIT: Duration = Duration::from_secs(60);
+
+#[derive(thiserror::Error, Debug)]
+pub enum IngestError {
+    #[error("database error: {0}")]
+    Database(#[from] DatabaseError),
+    #[error("no user message to reply to in session {0}")]
+    NoUserMessage(Uuid),
+}
+
+/// What one harness event changed, current up to the session `revision`
+#[derive(Debug, Clone)]
+pub struct IngestedChange {
+    pub revision: i64,
+    pub events: Vec<proto_message::MessageEvent>,
+}
+
+struct Ingestion {
+    changes: broadcast::Sender<IngestedChange>,
+    parts: Arc<tokio::sync::Mutex<PartBuffer>>,
+    task: AbortHandle,
+}
+
+type Ingestions = Arc<Mutex<HashMap<Uuid, Ingestion>>>;
+
+/// Persists what harnesses stream back for a session whether or not a GUI is watching.
+/// Ingestion starts with the session's first prompt or viewer and stops once the session
+/// is idle with nobody watching, viewers only get the messages that changed
+pub struct EventIngestor {
+    db: Arc<Database>,
+    harnesses: HarnessRegistry,
+    sessions: Ingestions,
+}
+
+impl EventIngestor {
+    pub fn new(db: Arc<Database>, harnesses: HarnessRegistry) -> Self {
+        Self {
+            db,
+            harnesses,
+            sessions: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    /// Starts ingesting the session's events unless that already happens.
+    /// Returns the messages the ingestion changes from now on
+    pub async fn ingest(
+        &self,
+        session: &SessionModel,
+    ) -> Result<broadcast::Receiver<IngestedChange>, HarnessError> {
+        if let Some(ingestion) = lock(&self.sessions).get(&session.id) {
+            return Ok(ingestion.changes.subscribe());
+        }
+
+        let events = self
+            .harnesses
+            .get(&session.harness_type)?
+            .listen_assistant_events(session.harness_session_id.clone(), session.dir.clone())
+            .await?;
+
+        let mut sessions = lock(&self.sessions);
+        // Another caller may have started it while we connected, our stream is dropped then
+        if let Some(ingestion) = sessions.get(&session.id) {
+            return Ok(ingestion.changes.subscribe());
+        }
+
+        let (changes, receiver) = broadcast::channel(CHANGE_BUFFER);
+        let parts = Arc::new(tokio::sync::Mutex::new(PartBuffer::default()));
+        let task = tokio::spawn(run(
+            Arc::clone(&self.db),
+            Arc::clone(&self.sessions),
+            Arc::clone(&parts),
+            session.id,
+            events,
+        ))
+        .abort_handle();
+        sessions.insert(
+            session.id,
+            Ingestion {
+                changes,
+                parts,
+                task,
+            },
+        );
+        Ok(receiver)
+    }
+
+    /// Writes the session's buffered deltas now, so reads see everything viewers were sent
+    pub async fn flush(&self, session_id: Uuid) -> Result<(), IngestError> {
+        let Some(parts) = lock(&self.sessions)
+            .get(&session_id)
+            .map(|ingestion| Arc::clone(&ingestion.parts))
+        else {
+            return Ok(());
+        };
+        part_buffer::flush(&self.db, &mut *parts.lock().await).await?;
+        Ok(())
+    }
+
+    /// Sends a change made outside the harness's events, e.g. a revert, to the session's viewers
+    pub fn publish(&self, session_id: Uuid, change: IngestedChange) {
+        send_change(&self.sessions, session_id, change);
+    }
+
+    #[cfg(test)]
+    fn is_ingesting(&self, session_id: Uuid) -> bool {
+        lock(&self.sessions).contains_key(&session_id)
+    }
+}
+
+impl Drop for EventIngestor {
+    fn drop(&mut self) {
+        for ingestion in lock(&self.sessions).values() {
+            ingestion.task.abort();
+        }
+    }
+}
+
+fn lock(sessions: &Ingestions) -> MutexGuard<'_, HashMap<Uuid, Ingestion>> {
+    sessions
+        .lock()
+        .unwrap_or_else(|poisoned| poisoned.into_inner())
+}
+
+fn send_change(sessions: &Ingestions, session_id: Uuid, change: IngestedChange) {
+    if let Some(ingestion) = lock(sessions).get(&session_id) {
+        // Nobody watching is fine, the changes are already stored
+        let _ = ingestion.changes.send(change);
+    }
+}
+
+async fn run(
+    db: Arc<Database>,
+    sessions: Ingestions,
+    parts: Arc<tokio::sync::Mutex<PartBuffer>>,
+    session_id: Uuid,
+    mut events: HarnessAssistantEventStream,
+) {
+    let mut flush_timer = tokio::time::interval(part_buffer::FLUSH_INTERVAL);
+    flush_timer.set_missed_tick_behavior(MissedTickBehavior::Delay);
+    // The summary with a turn's diffs can come after the session went idle
+    let mut unsummarized: Option<String> = None;
+    let mut summary_deadline: Option<Instant> = None;
+
+    loop {
+        let item = tokio::select! {
+            item = events.next() => item,
+            _ = flush_timer.tick() => {
+                flush_parts(&db, &mut *parts.lock().await, session_id).await;
+                continue;
+            }
+            _ = sleep_until(summary_deadline) => {
+                log::debug!("session {session_id} stopped waiting for its turn's summary");
+                unsummarized = None;
+                summary_deadline = None;
+                if stop_unwatched(&sessions, session_id) {
+                    return;
+                }
+                continue;
+            }
+        };
+        let Some(item) = item else {
+            break;
+        };
+        let event = match item {
+            Ok(event) => event,
+            Err(err) => {
+                log::warn!("event stream for session {session_id} failed: {err}");
+                break;
+            }
+        };
+
+        let idle = matches!(
+            event,
+            HarnessAssistantEvent::SessionStatus {
+                status: HarnessSessionStatus::Idle,
+                ..
+            }
+        );
+        let mut turn_over = idle;
+        match &event {
+            HarnessAssistantEvent::SessionStatus { .. } if !idle => summary_deadline = None,
+            HarnessAssistantEvent::PromptRecorded { message_id, .. } => {
+                unsummarized = Some(message_id.clone());
+            }
+            HarnessAssistantEvent::MessageDiffs { message_id, .. }
+                if unsummarized.as_ref() == Some(message_id) =>
+            {
+                unsummarized = None;
+                turn_over = summary_deadline.take().is_some();
+            }
+            _ => {}
+        }
+        let mut parts = parts.lock().await;
+        match store(&db, &mut parts, session_id, event).await {
+            Ok(Some(change)) => send_change(&sessions, session_id, change),
+            Ok(None) => {}
+            Err(err) => log::warn!("failed to store event for session {session_id}: {err}"),
+        }
+
+        if idle {
+            flush_parts(&db, &mut parts, session_id).await;
+            parts.forget_written();
+        }
+        if turn_over {
+            if unsummarized.is_some() {
+                summary_deadline = Some(Instant::now() + SUMMARY_WAIT);
+            } else if stop_unwatched(&sessions, session_id) {
+                return;
+            }
+        }
+    }
+
+    flush_parts(&db, &mut *parts.lock().await, session_id).await;
+    lock(&sessions).remove(&session_id);
+}
+
+/// Stops the session's ingestion when nobody watches it, returns whether it stopped
+fn stop_unwatched(sessions: &Ingestions, session_id: Uuid) -> bool {
+    let mut sessions = lock(sessions);
+    if sessions
+        .get(&session_id)
+        .is_some_and(|ingestion| ingestion.changes.receiver_count() == 0)
+    {
+        sessions.remove(&session_id);
+        return true;
+    }
+    false
+}
+
+/// Waits until `deadline`, forever without one
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline).await,
+        None => std::future::pending().await,
+    }
+}
+
+async fn flush_parts(db: &Database, parts: &mut PartBuffer, session_id: Uuid) {
+    if let Err(err) = part_buffer::flush(db, parts).await {
+        log::warn!("failed to write streamed parts for session {session_id}: {err}");
+    }
+}
+
+async fn store(
+    db: &Database,
+    parts: &mut PartBuffer,
+    session_id: Uuid,
+    event: HarnessAssistantEvent,
+) -> Result<Option<IngestedChange>, IngestError> {
+    let events = apply_harness_event(db, parts, session_id, event).await?;
+    if events.is_empty() {
+        return Ok(None);
+    }
+    Ok(Some(IngestedChange {
+        revision: db.session_revision(session_id).await?,
+        events,
+    }))
+}
+
+/// Stores one harness event and returns what changed, as small as the change allows
+async fn apply_harness_event(
+    db: &Database,
+    parts: &mut PartBuffer,
+    session_id: Uuid,
+    event: HarnessAssistantEvent,
+) -> Result<Vec<proto_message::MessageEvent>, IngestError> {
+    match event {
+        HarnessAssistantEvent::SessionStatus { status, .. } => {
+            let status = db.update_session_status(session_id, status.into()).await?;
+            Ok(vec![message_event(Event::SessionStatus(status.into()))])
+        }
+        HarnessAssistantEvent::MessageUpdated {
+            message_id,
+            completed_at,
+            error,
+            ..
+        } => {
+            let (message, _) =
+                upsert_assistant_message(db, session_id, &message_id, completed_at, error).await?;
+            Ok(vec![message_upserted(message)])
+        }
+        HarnessAssistantEvent::MessagePartUpdated {
+            message_id: harness_message_id,
+            part_id: harness_part_id,
+            part_type,
+            payload,
+            ..
+        } => {
+            let (message, message_created) =
+                upsert_assistant_message(db, session_id, &harness_message_id, None, None).await?;
+            let message_id = message.id;
+            let mut events = Vec::new();
+            if message_created {
+                events.push(message_upserted(message));
+            }
+            // The harness sends the whole part once it is done, it supersedes buffered deltas
+            parts.forget_part(&harness_message_id, &harness_part_id);
+            let (part, _) = upsert_assistant_part(
+                db,
+                session_id,
+                message_id,
+                &harness_part_id,
+                &part_type,
+                Some(payload),
+                None,
+            )
+            .await?;
+            events.push(message_event(Event::PartUpserted(part.into())));
+            Ok(events)
+        }
+        HarnessAssistantEvent::MessagePartDelta {
+            message_id: harness_message_id,
+            part_id: harness_part_id,
+            field,
+            delta,
+            ..
+        } => {
+            if let Some(part) = parts.append(
+                &harness_message_id,
+                &harness_part_id,
+                field.clone(),
+                delta.clone(),
+            ) {
+                return Ok(vec![message_event(Event::PartDelta(
+                    proto_message::PartDelta {
+                        message_id: part.assistant_message_id.to_string(),
+                        part_id: part.id.to_string(),
+                        field,
+                        delta,
+                    },
+                ))]);
+            }
+
+            let (message, message_created) =
+                upsert_assistant_message(db, session_id, &harness_message_id, None, None).await?;
+            let message_id = message.id;
+            let mut events = Vec::new();
+            if message_created {
+                events.push(message_upserted(message));
+            }
+            let (part, part_created) = upsert_assistant_part(
+                db,
+                session_id,
+                message_id,
+                &harness_part_id,
+                "text",
+                None,
+                Some((field.clone(), delta.clone())),
+            )
+            .await?;
+            parts.track(&harness_message_id, &harness_part_id, part.clone());
+            // Clients can only append to parts they already have
+            events.push(message_event(if part_created {
+                Event::PartUpserted(part.into())
+            } else {
+                Event::PartDelta(proto_message::PartDelta {
+                    message_id: message_id.to_string(),
+                    part_id: part.id.to_string(),
+                    field,
+                    delta,
+                })
+            }));
+            Ok(events)
+        }
+        HarnessAssistantEvent::PromptRecorded { message_id, .. } => {
+            if db
+                .claim_user_message_harness_id(session_id, message_id.clone())
+                .await?
+                .is_none()
+            {
+                log::debug!("no prompt of session {session_id} waits for harness id {message_id}");
+            }
+            Ok(Vec::new())
+        }
+        HarnessAssistantEvent::MessageDiffs {
+            message_id, diffs, ..
+        } => {
+            // Prompts from before their harness ids were recorded keep their diffs unattributed
+            let user_message_id = db
+                .get_user_message_by_harness_id(session_id, message_id.clone())
+                .await?
+                .map(|message| message.id);
+            let diffs = diffs
+                .into_iter()
+                .map(|diff| {
+                    FileDiffModel::new_from_harness(session_id, user_message_id, &message_id, diff)
+                })
+                .collect();
+            let summary = db
+                .replace_message_file_diffs(session_id, message_id, diffs)
+                .await?;
+            Ok(vec![message_event(Event::SessionDiff(summary.into()))])
+        }
+        HarnessAssistantEvent::MessageRemoved { message_id, .. } => {
+            parts.discard_message(&message_id);
+            // The files a removed prompt's turn changed are no longer the session's doing
+            if let Some(summary) = db
+                .remove_message_file_diffs(session_id, message_id.clone())
+                .await?
+            {
+                return Ok(vec![message_event(Event::SessionDiff(summary.into()))]);
+            }
+            let Some(message) = db
+                .get_assistant_message_by_harness_id(session_id, message_id)
+                .await?
+            else {
+                return Ok(Vec::new());
+            };
+            db.remove_assistant_message(session_id, message.id).await?;
+            Ok(vec![message_event(Event::MessageRemoved(
+                proto_message::MessageRemoved {
+                    message_id: message.id.to_string(),
+                },
+            ))])
+        }
+        HarnessAssistantEvent::SessionError { error, .. } => {
+            let status = db.update_session_last_error(session_id, error).await?;
+            Ok(vec![message_event(Event::SessionStatus(status.into()))])
+        }
+    }
+}
+
+fn message_event(event: Event) -> proto_message::MessageEvent {
+    proto_message::MessageEvent { event: Some(event) }
+}
+
+/// The message without its parts, subscribers keep the parts they have
+fn message_upserted(message: AssistantMessage) -> proto_message::MessageEvent {
+    message_event(Event::MessageUpserted(MessageHistory {
+        message: Some(proto_message::message_history::Message::AssistantMessage(
+            message.into(),
+        )),
+    }))
+}
+
+async fn upsert_assistant_message(
+    db: &Database,
+    session_id: Uuid,
+    harness_message_id: &str,
+    completed_at: Option<i64>,
+    error: Option<String>,
+) -> Result<(AssistantMessage, bool), IngestError> {
+    let existing = db
+        .get_assistant_message_by_harness_id(session_id, harness_message_id.to_string())
+        .await?;
+
+    let mut message = if let Some(message) = existing {
+        message
+    } else {
+        AssistantMessage::new_from_harness(
+            session_id,
+            latest_user_message_id(db, session_id).await?,
+            harness_message_id,
+        )
+    };
+
+    message.ensure_harness_message_id(harness_message_id);
+
+    message.apply_harness_update(
+        completed_at
+            .and_then(|ms| DateTime::<Utc>::from_timestamp_millis(ms).map(|dt| dt.naive_utc())),
+        error,
+    );
+
+    if db.get_assistant_message(message.id).await?.is_some() {
+        Ok((db.update_assistant_message(message).await?, false))
+    } else {
+        Ok((db.create_assistant_message(message).await?, true))
+    }
+}
+
+async fn upsert_assistant_part(
+    db: &Database,
+    session_id: Uuid,
+    assistant_message_id: Uuid,
+    harness_part_id: &str,
+    default_part_type: &str,
+    payload: Option<serde_json::Value>,
+    delta: Option<(String, String)>,
+) -> Result<(AssistantMessagePart, bool), IngestError> {
+    let existing = db
+        .get_assistant_message_part_by_harness_id(assistant_message_id, harness_part_id.to_string())
+        .await?;
+    let created = existing.is_none();
+
+    let mut part = match existing {
+        Some(part) => part,
+        None => {
+            let mut part = AssistantMessagePart::new_from_harness(
+                session_id,
+                assistant_message_id,
+                harness_part_id,
+                default_part_type,
+            );
+            part.position = db
+                .next_assistant_message_part_position(assistant_message_id)
+                .await?;
+            part
+        }
+    };
+
+    if let Some(payload) = payload {
+        part.apply_payload_json(payload, default_part_type);
+    }
+
+    if let Some((field, value)) = delta {
+        part.apply_delta(field, value);
+    }
+
+    part.ensure_harness_part_id(harness_part_id);
+
+    if created {
+        Ok((db.create_assistant_message_part(part).await?, true))
+    } else {
+        Ok((db.update_assistant_message_part(part).await?, false))
+    }
+}
+
+async fn latest_user_message_id(db: &Database, session_id: Uuid) -> Result<Uuid, IngestError> {
+    db.list_user_messages_by_session(session_id, 1)
+        .await?
+        .into_iter()
+        .next()
+        .map(|message| message.id)
+        .ok_or(IngestError::NoUserMessage(session_id))
+}
@@ -0,0 +1,538 @@
+use std::{sync::Arc, time::Duration};
+
+use chrono::Utc;
+use futures::stream;
+use tokio::sync::{Mutex, mpsc};
+use uuid::Uuid;
+
+use crate::backend::{
+    db::Database,
+    harness::{
+        DEFAULT_HARNESS_TYPE, Harness, HarnessAssistantEvent, HarnessAssistantEventStream,
+        HarnessError, HarnessFileDiff, HarnessMessage, HarnessRegistry, HarnessSessionStatus,
+    },
+    ingest::EventIngestor,
+    models::{project_model::ProjectModel, session_model::SessionModel},
+    proto_message::{SessionStatusKind, message_event::Event},
+    repo::{user_message::UserMessage, user_message_part::UserMessagePart},
+};
+
+const HARNESS_SESSION_ID: &str = "harness-session";
+
+/// Streams whatever the test pushes into the channel
+struct ChannelHarness {
+    events: Mutex<Option<mpsc::UnboundedReceiver<HarnessAssistantEvent>>>,
+}
+
+#[async_trait::async_trait]
+impl Harness for ChannelHarness {
+    fn harness_type(&self) -> &'static str {
+        DEFAULT_HARNESS_TYPE
+    }
+
+    fn cleanup(&self) {}
+
+    async fn create_session(
+        &self,
+        _session: SessionModel,
+        _directory: Option<&str>,
+    ) -> anyhow::Result<String> {
+        Ok(HARNESS_SESSION_ID.to_string())
+    }
+
+    async fn send_message_async(
+        &self,
+        _harness_session_id: String,
+        _message: UserMessage,
+        _message_parts: Vec<UserMessagePart>,
+        _directory: Option<String>,
+    ) -> Result<(), HarnessError> {
+        Ok(())
+    }
+
+    async fn get_session_messages(
+        &self,
+        _session_id: &str,
+        _limit: Option<i32>,
+        _directory: Option<&str>,
+    ) -> Result<Vec<HarnessMessage>, HarnessError> {
+        Ok(Vec::new())
+    }
+
+    async fn abort_session(
+        &self,
+        _harness_session_id: String,
+        _directory: Option<String>,
+    ) -> Result<(), HarnessError> {
+        Ok(())
+    }
+
+    async fn listen_assistant_events(
+        &self,
+        _harness_session_id: String,
+        _directory: Option<String>,
+    ) -> Result<HarnessAssistantEventStream, HarnessError> {
+        let receiver = self
+            .events
+            .lock()
+            .await
+            .take()
+            .ok_or_else(|| HarnessError::InvalidRequest("already listening".to_string()))?;
+        let events = stream::unfold(receiver, |mut receiver| async move {
+            receiver.recv().await.map(|event| (Ok(event), receiver))
+        });
+        Ok(Box::pin(events))
+    }
+}
+
+struct Fixture {
+    db: Arc<Database>,
+    ingestor: EventIngestor,
+    session: SessionModel,
+    events: mpsc::UnboundedSender<HarnessAssistantEvent>,
+}
+
+async fn fixture() -> Fixture {
+    let db = Arc::new(
+        Database::new_in_memory()
+            .await
+            .expect("in-memory db should initialize"),
+    );
+    let now = Utc::now().naive_utc();
+    let project_id = Uuid::new_v4();
+    let session = SessionModel {
+        id: Uuid::new_v4(),
+        project_id,
+        parent_session_id: None,
+        show_in_gui: true,
+        name: "session".to_string(),
+        harness_type: DEFAULT_HARNESS_TYPE.to_string(),
+        harness_session_id: HARNESS_SESSION_ID.to_string(),
+        dir: None,
+        summary_additions: None,
+        summary_deletions: None,
+        summary_files: None,
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        created_at: now,
+        updated_at: now,
+    };
+
+    db.create_project(ProjectModel {
+        id: project_id,
+        name: "proj".to_string(),
+        dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+    .expect("create project should succeed");
+    db.create_session(session.clone())
+        .await
+        .expect("create session should succeed");
+    db.create_user_message(UserMessage {
+        id: Uuid::new_v4(),
+        session_id: session.id,
+        agent: "build".to_string(),
+        model_provider_id: "openai".to_string(),
+        model_id: "gpt-5".to_string(),
+        system_prompt: None,
+        structured_output_type: "text".to_string(),
+        tools_list: "{}".to_string(),
+        thinking_variant: None,
+        harness_message_id: None,
+        reverted: false,
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+    .expect("create user message should succeed");
+
+    let (events, receiver) = mpsc::unbounded_channel();
+    let harness = ChannelHarness {
+        events: Mutex::new(Some(receiver)),
+    };
+    Fixture {
+        ingestor: EventIngestor::new(Arc::clone(&db), HarnessRegistry::new().with(harness)),
+        db,
+        session,
+        events,
+    }
+}
+
+fn delta(text: &str) -> HarnessAssistantEvent {
+    HarnessAssistantEvent::MessagePartDelta {
+        harness_session_id: HARNESS_SESSION_ID.to_string(),
+        message_id: "msg-1".to_string(),
+        part_id: "part-1".to_string(),
+        field: "text".to_string(),
+        delta: text.to_string(),
+    }
+}
+
+fn idle() -> HarnessAssistantEvent {
+    HarnessAssistantEvent::SessionStatus {
+        harness_session_id: HARNESS_SESSION_ID.to_string(),
+        status: HarnessSessionStatus::Idle,
+    }
+}
+
+#[tokio::test]
+async fn stores_events_without_viewers_and_stops_once_idle() {
+    let fixture = fixture().await;
+
+    let changes = fixture
+        .ingestor
+        .ingest(&fixture.session)
+        .await
+        .expect("ingest should start");
+    drop(changes);
+
+    fixture.events.send(delta("hel")).unwrap();
+    fixture.events.send(delta("lo")).unwrap();
+    fixture.events.send(idle()).unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while fixture.ingestor.is_ingesting(fixture.session.id) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("ingestion should stop once idle");
+
+    let parts = fixture
+        .db
+        .list_assistant_message_parts_by_session(fixture.session.id)
+        .await
+        .expect("parts should load");
+    assert_eq!(parts.len(), 1);
+    assert_eq!(parts[0].text.as_deref(), Some("hello"));
+}
+
+#[tokio::test]
+async fn viewers_share_the_ingestion_and_get_changed_messages() {
+    let fixture = fixture().await;
+
+    let mut first = fixture
+        .ingestor
+        .ingest(&fixture.session)
+        .await
+        .expect("ingest should start");
+    // The harness only hands out one stream, a second listen would fail
+    let mut second = fixture
+        .ingestor
+        .ingest(&fixture.session)
+        .await
+        .expect("second viewer should join");
+
+    fixture.events.send(delta("hi")).unwrap();
+    fixture.events.send(idle()).unwrap();
+
+    for changes in [&mut first, &mut second] {
+        let change = changes.recv().await.expect("viewer should get the change");
+        // A new message and part come whole, later deltas only carry the appended text
+        assert_eq!(change.events.len(), 2);
+        // The user message, then the assistant message and its part
+        assert_eq!(change.revision, 3);
+    }
+
+    // Idle with viewers attached keeps ingesting for their next prompt
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(fixture.ingestor.is_ingesting(fixture.session.id));
+}
+
+#[tokio::test]
+async fn deltas_to_known_parts_only_carry_the_appended_text() {
+    let fixture = fixture().await;
+
+    let mut changes = fixture
+        .ingestor
+        .ingest(&fixture.session)
+        .await
+        .expect("ingest should start");
+
+    fixture.events.send(delta("hel")).unwrap();
+    fixture.events.send(delta("lo")).unwrap();
+
+    changes.recv().await.expect("first change should arrive");
+    let change = changes.recv().await.expect("second change should arrive");
+    assert_eq!(change.events.len(), 1);
+    match change.events[0].event.as_ref() {
+        Some(Event::PartDelta(delta)) => {
+            assert_eq!(delta.field, "text");
+            assert_eq!(delta.delta, "lo");
+        }
+        other => panic!("expected a part delta, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn removals_delete_the_message_and_reach_viewers() {
+    let fixture = fixture().await;
+
+    let mut changes = fixture
+        .ingestor
+        .ingest(&fixture.session)
+        .await
+        .expect("ingest should start");
+
+    fixture.events.send(delta("hi")).unwrap();
+    changes.recv().await.expect("the message should arrive");
+    let before_removal = fixture
+        .db
+        .session_revision(fixture.session.id)
+        .await
+        .expect("revision should load");
+
+    fixture
+        .events
+        .send(HarnessAssistantEvent::MessageRemoved {
+            harness_session_id: HARNESS_SESSION_ID.to_string(),
+            message_id: "msg-1".to_string(),
+        })
+        .unwrap();
+    let change = changes.recv().await.expect("the removal should arrive");
+    let removed_id = match change.events[0].event.as_ref() {
+        Some(Event::MessageRemoved(removed)) => removed.message_id.clone(),
+        other => panic!("expected a removal, got {other:?}"),
+    };
+
+    let stored = fixture
+        .db
+        .get_assistant_message_by_harness_id(fixture.session.id, "msg-1".to_string())
+        .await
+        .expect("message lookup should succeed");
+    assert!(stored.is_none());
+    let parts = fixture
+        .db
+        .list_assistant_message_parts_by_session(fixture.session.id)
+        .await
+        .expect("parts should load");
+    assert!(parts.is_empty());
+
+    // A viewer resuming from before the removal still learns about it
+    let replayed = fixture
+        .db
+        .list_message_changes_since(fixture.session.id, before_removal)
+        .await
+        .expect("changes should load");
+    assert_eq!(replayed.revision, change.revision);
+    assert!(
+        replayed
+            .removed_messages
+            .iter()
+            .any(|id| id.to_string() == removed_id)
+    );
+}
+
+#[tokio::test]
+async fn statuses_and_errors_are_stored_on_the_session() {
+    let fixture = fixture().await;
+
+    let mut changes = fixture
+        .ingestor
+        .ingest(&fixture.session)
+        .await
+        .expect("ingest should start");
+
+    let status_event = |status| HarnessAssistantEvent::SessionStatus {
+        harness_session_id: HARNESS_SESSION_ID.to_string(),
+        status,
+    };
+    fixture
+        .events
+        .send(status_event(HarnessSessionStatus::Retry {
+            attempt: 2,
+            message: "rate limited".to_string(),
+            next: 5_000,
+        }))
+        .unwrap();
+    fixture
+        .events
+        .send(HarnessAssistantEvent::SessionError {
+            harness_session_id: Some(HARNESS_SESSION_ID.to_string()),
+            error: "overloaded".to_string(),
+        })
+        .unwrap();
+
+    changes.recv().await.expect("the retry should arrive");
+    let change = changes.recv().await.expect("the error should arrive");
+    match change.events[0].event.as_ref() {
+        Some(Event::SessionStatus(status)) => {
+            assert_eq!(status.kind, i32::from(SessionStatusKind::Retry));
+            assert_eq!(status.attempt, 2);
+            assert_eq!(status.error.as_deref(), Some("overloaded"));
+        }
+        other => panic!("expected a status, got {other:?}"),
+    }
+
+    let stored = fixture
+        .db
+        .get_session_status(fixture.session.id)
+        .await
+        .expect("status should load")
+        .expect("session should exist");
+    assert_eq!(stored.status, "retry");
+    assert_eq!(stored.status_next, Some(5_000));
+    assert_eq!(stored.last_error.as_deref(), Some("overloaded"));
+
+    // The next prompt starts without the old error
+    fixture
+        .events
+        .send(status_event(HarnessSessionStatus::Busy))
+        .unwrap();
+    changes.recv().await.expect("busy should arrive");
+    let stored = fixture
+        .db
+        .get_session_status(fixture.session.id)
+        .await
+        .expect("status should load")
+        .expect("session should exist");
+    assert_eq!(stored.status, "busy");
+    assert_eq!(stored.last_error, None);
+}
+
+#[tokio::test]
+async fn diffs_update_the_session_totals_until_their_prompt_is_removed() {
+    let fixture = fixture().await;
+
+    let mut changes = fixture
+        .ingestor
+        .ingest(&fixture.session)
+        .await
+        .expect("ingest should start");
+
+    fixture
+        .events
+        .send(HarnessAssistantEvent::MessageDiffs {
+            harness_session_id: HARNESS_SESSION_ID.to_string(),
+            message_id: "msg-user".to_string(),
+            diffs: vec![HarnessFileDiff {
+                file: "src/main.rs".to_string(),
+                before: "fn main() {}\n".to_string(),
+                after: "fn main() {\n    run();\n}\n".to_string(),
+                additions: 3,
+                deletions: 1,
+            }],
+        })
+        .unwrap();
+    let change = changes.recv().await.expect("the diff should arrive");
+    match change.events[0].event.as_ref() {
+        Some(Event::SessionDiff(summary)) => {
+            assert_eq!(summary.files, 1);
+            assert_eq!(summary.additions, 3);
+            assert_eq!(summary.deletions, 1);
+        }
+        other => panic!("expected a session diff, got {other:?}"),
+    }
+
+    let stored = fixture
+        .db
+        .get_session(fixture.session.id)
+        .await
+        .expect("session should load")
+        .expect("session should exist");
+    assert_eq!(stored.summary_files, Some(1));
+    assert_eq!(stored.summary_additions, Some(3));
+    assert_eq!(stored.summary_deletions, Some(1));
+
+    fixture
+        .events
+        .send(HarnessAssistantEvent::MessageRemoved {
+            harness_session_id: HARNESS_SESSION_ID.to_string(),
+            message_id: "msg-user".to_string(),
+        })
+        .unwrap();
+    let change = changes.recv().await.expect("the removal should arrive");
+    match change.events[0].event.as_ref() {
+        Some(Event::SessionDiff(summary)) => assert_eq!(summary.files, 0),
+        other => panic!("expected a session diff, got {other:?}"),
+    }
+    let diffs = fixture
+        .db
+        .list_file_diffs_by_session(fixture.session.id)
+        .await
+        .expect("diffs should load");
+    assert!(diffs.is_empty());
+}
+
+#[tokio::test]
+async fn idle_sessions_wait_for_the_summary_and_file_it_under_the_named_prompt() {
+    let fixture = fixture().await;
+
+    let changes = fixture
+        .ingestor
+        .ingest(&fixture.session)
+        .await
+        .expect("ingest should start");
+    drop(changes);
+
+    fixture
+        .events
+        .send(HarnessAssistantEvent::PromptRecorded {
+            harness_session_id: HARNESS_SESSION_ID.to_string(),
+            message_id: "msg-user".to_string(),
+        })
+        .unwrap();
+    fixture.events.send(idle()).unwrap();
+
+    // Nobody watches, but the turn's summary is still due
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    assert!(fixture.ingestor.is_ingesting(fixture.session.id));
+    let prompt = fixture
+        .db
+        .get_user_message_by_harness_id(fixture.session.id, "msg-user".to_string())
+        .await
+        .expect("user message should load")
+        .expect("the prompt should hold the harness id");
+
+    // A later prompt must not take the earlier turn's diffs
+    let now = Utc::now().naive_utc();
+    fixture
+        .db
+        .create_user_message(UserMessage {
+            id: Uuid::new_v4(),
+            created_at: now,
+            updated_at: now,
+            ..prompt.clone()
+        })
+        .await
+        .expect("create user message should succeed");
+
+    fixture
+        .events
+        .send(HarnessAssistantEvent::MessageDiffs {
+            harness_session_id: HARNESS_SESSION_ID.to_string(),
+            message_id: "msg-user".to_string(),
+            diffs: vec![HarnessFileDiff {
+                file: "src/main.rs".to_string(),
+                before: String::new(),
+                after: "fn main() {}\n".to_string(),
+                additions: 1,
+                deletions: 0,
+            }],
+        })
+        .unwrap();
+
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while fixture.ingestor.is_ingesting(fixture.session.id) {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("ingestion should stop once the summary is stored");
+
+    let diffs = fixture
+        .db
+        .list_file_diffs_by_session(fixture.session.id)
+        .await
+        .expect("diffs should load");
+    assert_eq!(diffs.len(), 1);
+    assert_eq!(diffs[0].user_message_id, Some(prompt.id));
+}
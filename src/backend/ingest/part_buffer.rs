@@ -0,0 +1,102 @@
+use std::{collections::HashMap, time::Duration};
+
+use crate::backend::{
+    db::{Database, DatabaseError},
+    repo::assistant_message::AssistantMessagePart,
+};
+
+/// How long streamed text may stay in memory before it is written
+pub const FLUSH_INTERVAL: Duration = Duration::from_millis(100);
+
+struct BufferedPart {
+    part: AssistantMessagePart,
+    /// Holds deltas the database does not have yet
+    dirty: bool,
+}
+
+/// Parts that are streaming, keyed by their harness message and part ids.
+/// Deltas to a known part are appended in memory and written together by `flush`,
+/// so a fast token stream costs one transaction per interval instead of queries per token
+#[derive(Default)]
+pub struct PartBuffer {
+    parts: HashMap<(String, String), BufferedPart>,
+}
+
+impl PartBuffer {
+    /// Appends the delta to a known part and returns it, `None` leaves the write to the caller
+    pub fn append(
+        &mut self,
+        harness_message_id: &str,
+        harness_part_id: &str,
+        field: String,
+        delta: String,
+    ) -> Option<&AssistantMessagePart> {
+        let buffered = self
+            .parts
+            .get_mut(&key(harness_message_id, harness_part_id))?;
+        buffered.part.apply_delta(field, delta);
+        buffered.dirty = true;
+        Some(&buffered.part)
+    }
+
+    /// Starts buffering deltas for a part that was just written
+    pub fn track(
+        &mut self,
+        harness_message_id: &str,
+        harness_part_id: &str,
+        part: AssistantMessagePart,
+    ) {
+        self.parts.insert(
+            key(harness_message_id, harness_part_id),
+            BufferedPart { part, dirty: false },
+        );
+    }
+
+    /// Stops buffering the part, unwritten deltas included
+    pub fn forget_part(&mut self, harness_message_id: &str, harness_part_id: &str) {
+        self.parts.remove(&key(harness_message_id, harness_part_id));
+    }
+
+    /// Drops the message's parts, unwritten deltas included
+    pub fn discard_message(&mut self, harness_message_id: &str) {
+        self.parts
+            .retain(|(message_id, _), _| message_id != harness_message_id);
+    }
+
+    /// Stops buffering parts that are fully written, e.g. once the session went idle
+    pub fn forget_written(&mut self) {
+        self.parts.retain(|_, buffered| buffered.dirty);
+    }
+
+    #[cfg(test)]
+    pub fn is_dirty(&self) -> bool {
+        self.parts.values().any(|buffered| buffered.dirty)
+    }
+}
+
+fn key(harness_message_id: &str, harness_part_id: &str) -> (String, String) {
+    (harness_message_id.to_string(), harness_part_id.to_string())
+}
+
+/// Writes every part with unwritten deltas in one transaction and returns how many.
+/// A failed flush writes nothing and keeps the deltas for the next one
+pub async fn flush(db: &Database, buffer: &mut PartBuffer) -> Result<usize, DatabaseError> {
+    let dirty = buffer
+        .parts
+        .values()
+        .filter(|buffered| buffered.dirty)
+        .map(|buffered| buffered.part.clone())
+        .collect::<Vec<_>>();
+    if dirty.is_empty() {
+        return Ok(0);
+    }
+
+    let written = db.update_assistant_message_parts(dirty).await?;
+    for buffered in buffer.parts.values_mut() {
+        if let Some(part) = written.iter().find(|part| part.id == buffered.part.id) {
+            buffered.part = part.clone();
+            buffered.dirty = false;
+        }
+    }
+    Ok(written.len())
+}
@@ -0,0 +1,251 @@
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::backend::{
+    db::Database,
+    harness::DEFAULT_HARNESS_TYPE,
+    ingest::part_buffer::{self, PartBuffer},
+    models::{project_model::ProjectModel, session_model::SessionModel},
+    repo::{
+        assistant_message::{AssistantMessage, AssistantMessagePart},
+        user_message::UserMessage,
+    },
+};
+
+const HARNESS_MESSAGE_ID: &str = "msg-1";
+
+struct Fixture {
+    db: Database,
+    session_id: Uuid,
+    assistant_message_id: Uuid,
+}
+
+async fn fixture() -> Fixture {
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let now = Utc::now().naive_utc();
+    let project_id = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+    let user_message_id = Uuid::new_v4();
+
+    db.create_project(ProjectModel {
+        id: project_id,
+        name: "proj".to_string(),
+        dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+    .expect("create project should succeed");
+    db.create_session(SessionModel {
+        id: session_id,
+        project_id,
+        parent_session_id: None,
+        show_in_gui: true,
+        name: "session".to_string(),
+        harness_type: DEFAULT_HARNESS_TYPE.to_string(),
+        harness_session_id: "harness-session".to_string(),
+        dir: None,
+        summary_additions: None,
+        summary_deletions: None,
+        summary_files: None,
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+    .expect("create session should succeed");
+    db.create_user_message(UserMessage {
+        id: user_message_id,
+        session_id,
+        agent: "build".to_string(),
+        model_provider_id: "openai".to_string(),
+        model_id: "gpt-5".to_string(),
+        system_prompt: None,
+        structured_output_type: "text".to_string(),
+        tools_list: "{}".to_string(),
+        thinking_variant: None,
+        harness_message_id: None,
+        reverted: false,
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+    .expect("create user message should succeed");
+    let assistant = db
+        .create_assistant_message(AssistantMessage::new_from_harness(
+            session_id,
+            user_message_id,
+            HARNESS_MESSAGE_ID,
+        ))
+        .await
+        .expect("create assistant message should succeed");
+
+    Fixture {
+        db,
+        session_id,
+        assistant_message_id: assistant.id,
+    }
+}
+
+impl Fixture {
+    /// Writes a part the way the first delta does and starts buffering it
+    async fn track_part(
+        &self,
+        buffer: &mut PartBuffer,
+        harness_part_id: &str,
+        position: i64,
+    ) -> AssistantMessagePart {
+        let mut part = AssistantMessagePart::new_from_harness(
+            self.session_id,
+            self.assistant_message_id,
+            harness_part_id,
+            "text",
+        );
+        part.position = position;
+        part.apply_delta("text".to_string(), "a".to_string());
+        let part = self
+            .db
+            .create_assistant_message_part(part)
+            .await
+            .expect("create part should succeed");
+        buffer.track(HARNESS_MESSAGE_ID, harness_part_id, part.clone());
+        part
+    }
+
+    async fn stored_text(&self, part_id: Uuid) -> Option<String> {
+        self.db
+            .get_assistant_message_part(part_id)
+            .await
+            .expect("part lookup should succeed")
+            .and_then(|part| part.text)
+    }
+
+    async fn revision(&self) -> i64 {
+        self.db
+            .session_revision(self.session_id)
+            .await
+            .expect("revision should load")
+    }
+}
+
+fn append(buffer: &mut PartBuffer, harness_part_id: &str, delta: &str) {
+    buffer
+        .append(
+            HARNESS_MESSAGE_ID,
+            harness_part_id,
+            "text".to_string(),
+            delta.to_string(),
+        )
+        .expect("the part should be buffered");
+}
+
+#[tokio::test]
+async fn deltas_are_coalesced_into_one_write_per_flush() {
+    let fixture = fixture().await;
+    let mut buffer = PartBuffer::default();
+    let part = fixture.track_part(&mut buffer, "part-1", 0).await;
+    let revision = fixture.revision().await;
+
+    for delta in ["b", "c", "d"] {
+        append(&mut buffer, "part-1", delta);
+    }
+    assert!(buffer.is_dirty());
+    assert_eq!(fixture.stored_text(part.id).await.as_deref(), Some("a"));
+    assert_eq!(fixture.revision().await, revision);
+
+    let written = part_buffer::flush(&fixture.db, &mut buffer)
+        .await
+        .expect("flush should succeed");
+    assert_eq!(written, 1);
+    assert!(!buffer.is_dirty());
+    assert_eq!(fixture.stored_text(part.id).await.as_deref(), Some("abcd"));
+    assert_eq!(fixture.revision().await, revision + 1);
+
+    // Nothing new, nothing written
+    let written = part_buffer::flush(&fixture.db, &mut buffer)
+        .await
+        .expect("flush should succeed");
+    assert_eq!(written, 0);
+    assert_eq!(fixture.revision().await, revision + 1);
+}
+
+#[tokio::test]
+async fn unknown_parts_are_left_to_the_caller() {
+    let mut buffer = PartBuffer::default();
+    assert!(
+        buffer
+            .append(
+                HARNESS_MESSAGE_ID,
+                "part-1",
+                "text".to_string(),
+                "a".to_string()
+            )
+            .is_none()
+    );
+    assert!(!buffer.is_dirty());
+}
+
+#[tokio::test]
+async fn a_failed_flush_writes_nothing_and_keeps_the_deltas() {
+    let fixture = fixture().await;
+    let mut buffer = PartBuffer::default();
+    let kept = fixture.track_part(&mut buffer, "part-1", 0).await;
+    let gone = fixture.track_part(&mut buffer, "part-2", 1).await;
+    append(&mut buffer, "part-1", "b");
+    append(&mut buffer, "part-2", "b");
+
+    // The row vanishing mid-stream makes its update fail inside the flush transaction
+    fixture
+        .db
+        .delete_assistant_message_part(gone.id)
+        .await
+        .expect("delete part should succeed");
+    let revision = fixture.revision().await;
+
+    part_buffer::flush(&fixture.db, &mut buffer)
+        .await
+        .expect_err("flush should fail");
+    assert_eq!(fixture.stored_text(kept.id).await.as_deref(), Some("a"));
+    assert_eq!(fixture.revision().await, revision);
+    assert!(buffer.is_dirty());
+
+    buffer.forget_part(HARNESS_MESSAGE_ID, "part-2");
+    let written = part_buffer::flush(&fixture.db, &mut buffer)
+        .await
+        .expect("flush should succeed");
+    assert_eq!(written, 1);
+    assert_eq!(fixture.stored_text(kept.id).await.as_deref(), Some("ab"));
+}
+
+#[tokio::test]
+async fn idle_sessions_only_keep_unwritten_parts() {
+    let fixture = fixture().await;
+    let mut buffer = PartBuffer::default();
+    fixture.track_part(&mut buffer, "part-1", 0).await;
+    fixture.track_part(&mut buffer, "part-2", 1).await;
+    append(&mut buffer, "part-2", "b");
+
+    buffer.forget_written();
+
+    assert!(buffer.is_dirty());
+    assert!(
+        buffer
+            .append(
+                HARNESS_MESSAGE_ID,
+                "part-1",
+                "text".to_string(),
+                "c".to_string()
+            )
+            .is_none()
+    );
+    append(&mut buffer, "part-2", "c");
+}
@@ -1,34 +1,61 @@
 use crate::backend::{
+    agent::{Agent, fs::UnsavedBuffers},
     db::{Database, DatabaseStartupError},
-    harness::{Harness, opencode::OpencodeHarness},
+    harness::{
+        HarnessRegistry,
+        acp::AcpHarness,
+        opencode::{OpencodeHarness, OpencodeHarnessError},
+    },
+    ingest::EventIngestor,
+    permission::PermissionBroker,
     repo::{message::MessageRepo, project::ProjectRepo, session::SessionRepo},
 };
 use std::{
     collections::HashMap,
     net::SocketAddr,
     sync::{Arc, Mutex},
+    time::Duration,
 };
 use tokio::{sync::watch, task::JoinHandle};
 use tonic::transport::Server;
 use uuid::Uuid;
 
+/// How long startup waits for the opencode server before giving up
+const HARNESS_READY_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Message size both ends of the messages service accept. Histories replay every stored
+/// attachment, and images used to be stored inline at up to 10 MiB each
+pub const MAX_MESSAGES_BYTES: usize = 64 * 1024 * 1024;
+
 pub use models::project_model::ProjectModel;
 pub use models::session_model::SessionModel;
 pub mod agent;
+pub mod attachment;
 mod db;
+pub mod diff;
+mod file_search;
 mod harness;
+mod ingest;
 mod models;
+mod permission;
 pub mod proto_utils;
 mod repo;
 mod service;
 
+#[cfg(test)]
+mod attachment_test;
+#[cfg(test)]
+mod diff_test;
+#[cfg(test)]
+mod file_search_test;
+
 pub(crate) mod proto_project {
     tonic::include_proto!("project");
 }
 use proto_project::project_server::ProjectServer;
 pub use proto_project::{
-    SubscribeProjectReply, SubscribeProjectRequest, SubscribeProjectsReply,
-    SubscribeProjectsRequest, project_client::ProjectClient,
+    FileMatch, SearchFilesRequest, SubscribeProjectReply, SubscribeProjectRequest,
+    SubscribeProjectsReply, SubscribeProjectsRequest, project_client::ProjectClient,
 };
 
 pub(crate) mod proto_session {
@@ -39,37 +66,84 @@ pub use proto_session::{
     ListSessionsByProjectReply, ListSessionsByProjectRequest, session_client::SessionClient,
 };
 
+// Generated oneofs carry whole messages next to small deltas
+#[allow(clippy::large_enum_variant)]
 pub(crate) mod proto_message {
     tonic::include_proto!("messages");
 }
 use proto_message::messages_server::MessagesServer;
 pub use proto_message::{
     CreateUserMessageReply, CreateUserMessageRequest, ListMessagesBySessionReply,
-    ListMessagesBySessionRequest, SubscribeMessagesBySessionReply,
+    ListMessagesBySessionRequest, RevertFileWriteRequest, SubscribeMessagesBySessionReply,
     SubscribeMessagesBySessionRequest, messages_client::MessagesClient,
 };
 
+pub(crate) mod proto_permission {
+    tonic::include_proto!("permission");
+}
+use proto_permission::permissions_server::PermissionsServer;
+pub use proto_permission::{
+    PermissionDecision, RespondPermissionRequest, SubscribePermissionRequestsRequest,
+    permissions_client::PermissionsClient,
+};
+
+pub(crate) mod proto_harness {
+    tonic::include_proto!("harness");
+}
+use proto_harness::harnesses_server::HarnessesServer;
+pub use proto_harness::{
+    HarnessHealthStatus, ListAgentsRequest, ListModelsRequest, SubscribeHarnessHealthRequest,
+    harnesses_client::HarnessesClient,
+};
+
 pub struct BackendContext {
     db: Arc<Database>,
-    harness: OpencodeHarness,
+    harnesses: HarnessRegistry,
+    permissions: Arc<PermissionBroker>,
+    ingestor: Arc<EventIngestor>,
+    unsaved: UnsavedBuffers,
 }
 
 impl Clone for BackendContext {
     fn clone(&self) -> Self {
         Self {
             db: Arc::clone(&self.db),
-            harness: self.harness.clone(),
+            harnesses: self.harnesses.clone(),
+            permissions: Arc::clone(&self.permissions),
+            ingestor: Arc::clone(&self.ingestor),
+            unsaved: self.unsaved.clone(),
         }
     }
 }
 
 impl BackendContext {
-    fn new(db: Database, harness: OpencodeHarness) -> Self {
+    #[cfg(test)]
+    fn new(db: Database, harnesses: HarnessRegistry) -> Self {
+        let db = Arc::new(db);
+        let permissions = Arc::new(PermissionBroker::new(Arc::clone(&db)));
+        Self::with_permissions(db, harnesses, permissions)
+    }
+
+    fn with_permissions(
+        db: Arc<Database>,
+        harnesses: HarnessRegistry,
+        permissions: Arc<PermissionBroker>,
+    ) -> Self {
+        let ingestor = Arc::new(EventIngestor::new(Arc::clone(&db), harnesses.clone()));
         Self {
-            db: Arc::new(db),
-            harness,
+            db,
+            harnesses,
+            permissions,
+            ingestor,
+            unsaved: UnsavedBuffers::default(),
         }
     }
+
+    /// Shares the editors' unsaved files with the agents reading them
+    fn with_unsaved(mut self, unsaved: UnsavedBuffers) -> Self {
+        self.unsaved = unsaved;
+        self
+    }
 }
 
 pub struct BackendService {
@@ -86,15 +160,31 @@ pub enum BackendServiceError {
     #[error("Database initialization failed: {0}")]
     Database(#[from] DatabaseStartupError),
     #[error("Harness initialization failed: {0}")]
-    Harness(String),
+    Harness(#[from] OpencodeHarnessError),
 }
 
 impl BackendService {
     pub async fn new() -> Result<Self, BackendServiceError> {
-        let db = Database::new().await?;
-        let harness =
-            OpencodeHarness::new().map_err(|e| BackendServiceError::Harness(e.to_string()))?;
-        let ctx = BackendContext::new(db, harness);
+        let db = Arc::new(Database::new().await?);
+        let permissions = Arc::new(PermissionBroker::new(Arc::clone(&db)));
+        let unsaved = UnsavedBuffers::default();
+        let harness = OpencodeHarness::new()?.with_permissions(Arc::clone(&permissions));
+        harness.wait_until_ready(HARNESS_READY_TIMEOUT).await?;
+        // ACP agents only start once a session asks for them, so registering them is free
+        let harnesses = HarnessRegistry::new()
+            .with(harness)
+            .with(AcpHarness::new(
+                Agent::Opencode,
+                Arc::clone(&permissions),
+                unsaved.clone(),
+            ))
+            .with(AcpHarness::new(
+                Agent::Gemini,
+                Arc::clone(&permissions),
+                unsaved.clone(),
+            ));
+        let ctx =
+            BackendContext::with_permissions(db, harnesses, permissions).with_unsaved(unsaved);
 
         let project_repo = ProjectRepo::new(ctx.clone());
         let (projects_sender, _) = watch::channel(Vec::new());
@@ -120,7 +210,11 @@ pub async fn spawn_backend(
 
     let project_service = ProjectServer::new(backend.clone());
     let session_service = SessionServer::new(backend.clone());
-    let message_service = MessagesServer::new(backend.clone());
+    let message_service = MessagesServer::new(backend.clone())
+        .max_decoding_message_size(MAX_MESSAGES_BYTES)
+        .max_encoding_message_size(MAX_MESSAGES_BYTES);
+    let permission_service = PermissionsServer::new(backend.clone());
+    let harness_service = HarnessesServer::new(backend.clone());
 
     Ok(tokio::spawn(async move {
         log::info!("gRPC backend listening on {addr}");
@@ -128,6 +222,8 @@ pub async fn spawn_backend(
             .add_service(project_service)
             .add_service(session_service)
             .add_service(message_service)
+            .add_service(permission_service)
+            .add_service(harness_service)
             .serve(addr)
             .await
     }))
@@ -0,0 +1,134 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+use crate::backend::{diff, harness::HarnessFileDiff, proto_message, proto_session};
+
+/// What the turn of one message changed in a file, as the harness reported it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FileDiffModel {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    /// The prompt starting the turn, `None` for diffs stored before prompts were linked
+    pub user_message_id: Option<Uuid>,
+    /// The harness's id of the prompt starting the turn
+    pub harness_message_id: String,
+    /// Path relative to the session's dir
+    pub file: String,
+    pub before: String,
+    pub after: String,
+    pub additions: i64,
+    pub deletions: i64,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl FileDiffModel {
+    pub fn new_from_harness(
+        session_id: Uuid,
+        user_message_id: Option<Uuid>,
+        harness_message_id: &str,
+        diff: HarnessFileDiff,
+    ) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: Uuid::new_v4(),
+            session_id,
+            user_message_id,
+            harness_message_id: harness_message_id.to_string(),
+            file: diff.file,
+            before: diff.before,
+            after: diff.after,
+            additions: diff.additions,
+            deletions: diff.deletions,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
+
+/// A file over the whole session, from before its first change to after its last
+#[derive(Debug, Clone, PartialEq)]
+pub struct SessionFileDiff {
+    pub file: String,
+    pub before: String,
+    pub after: String,
+    pub additions: i64,
+    pub deletions: i64,
+}
+
+/// Totals kept on the session row
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DiffSummary {
+    pub additions: i64,
+    pub deletions: i64,
+    pub files: i64,
+}
+
+/// Folds the per message diffs, oldest first, into one diff per file. Files that ended up
+/// as they started are left out
+pub fn session_diff(diffs: Vec<FileDiffModel>) -> Vec<SessionFileDiff> {
+    let mut files: Vec<(SessionFileDiff, usize)> = Vec::new();
+    for diff in diffs {
+        match files.iter_mut().find(|(file, _)| file.file == diff.file) {
+            Some((file, turns)) => {
+                file.after = diff.after;
+                *turns += 1;
+            }
+            None => files.push((
+                SessionFileDiff {
+                    file: diff.file,
+                    before: diff.before,
+                    after: diff.after,
+                    additions: diff.additions,
+                    deletions: diff.deletions,
+                },
+                1,
+            )),
+        }
+    }
+
+    files
+        .into_iter()
+        .filter(|(file, _)| file.before != file.after)
+        .map(|(mut file, turns)| {
+            // The harness counted single turns, several are counted again as a whole
+            if turns > 1 {
+                (file.additions, file.deletions) = diff::count_changes(&file.before, &file.after);
+            }
+            file
+        })
+        .collect()
+}
+
+impl DiffSummary {
+    pub fn of(files: &[SessionFileDiff]) -> Self {
+        Self {
+            additions: files.iter().map(|file| file.additions).sum(),
+            deletions: files.iter().map(|file| file.deletions).sum(),
+            files: files.len() as i64,
+        }
+    }
+}
+
+impl From<SessionFileDiff> for proto_session::FileDiffModel {
+    fn from(diff: SessionFileDiff) -> Self {
+        Self {
+            file: diff.file,
+            before: diff.before,
+            after: diff.after,
+            additions: diff.additions,
+            deletions: diff.deletions,
+        }
+    }
+}
+
+impl From<DiffSummary> for proto_message::SessionDiffSummary {
+    fn from(summary: DiffSummary) -> Self {
+        Self {
+            additions: summary.additions,
+            deletions: summary.deletions,
+            files: summary.files,
+        }
+    }
+}
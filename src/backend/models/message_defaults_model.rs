@@ -0,0 +1,25 @@
+use serde::{Deserialize, Serialize};
+
+use crate::backend::repo::user_message::UserMessage;
+
+/// The agent, model and thinking variant the composer starts from, kept on projects and sessions
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct MessageDefaultsModel {
+    pub default_agent: Option<String>,
+    pub default_model_provider_id: Option<String>,
+    pub default_model_id: Option<String>,
+    pub default_thinking_variant: Option<String>,
+}
+
+impl From<&UserMessage> for MessageDefaultsModel {
+    /// What the message was sent with, an empty model means the harness picked it
+    fn from(message: &UserMessage) -> Self {
+        let has_model = !message.model_provider_id.is_empty() && !message.model_id.is_empty();
+        Self {
+            default_agent: Some(message.agent.clone()).filter(|agent| !agent.is_empty()),
+            default_model_provider_id: has_model.then(|| message.model_provider_id.clone()),
+            default_model_id: has_model.then(|| message.model_id.clone()),
+            default_thinking_variant: message.thinking_variant.clone(),
+        }
+    }
+}
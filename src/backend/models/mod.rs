@@ -1,4 +1,8 @@
 pub mod assistant_message_part_model;
+pub mod file_diff_model;
+pub mod message_defaults_model;
+pub mod permission_rule_model;
 pub mod project_model;
 pub mod session_model;
+pub mod session_status_model;
 pub mod user_message_model;
@@ -0,0 +1,32 @@
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+pub const PERMISSION_ACTION_ALLOW: &str = "allow";
+
+/// Standing answer to permission requests of one tool kind within a project
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PermissionRuleModel {
+    pub id: Uuid,
+    pub project_id: Uuid,
+    /// Tool kind the rule applies to, e.g. `edit` or `execute`
+    pub kind: String,
+    /// `allow` or `deny`
+    pub action: String,
+    pub created_at: NaiveDateTime,
+    pub updated_at: NaiveDateTime,
+}
+
+impl PermissionRuleModel {
+    pub fn new(project_id: Uuid, kind: impl Into<String>, action: impl Into<String>) -> Self {
+        let now = chrono::Utc::now().naive_utc();
+        Self {
+            id: Uuid::new_v4(),
+            project_id,
+            kind: kind.into(),
+            action: action.into(),
+            created_at: now,
+            updated_at: now,
+        }
+    }
+}
@@ -12,6 +12,10 @@ pub struct ProjectModel {
     pub id: Uuid,
     pub name: String,
     pub dir: String,
+    pub default_agent: Option<String>,
+    pub default_model_provider_id: Option<String>,
+    pub default_model_id: Option<String>,
+    pub default_thinking_variant: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -22,6 +26,10 @@ impl From<ProjectModel> for proto_project::ProjectModel {
             id: project.id.to_string(),
             name: project.name,
             dir: project.dir,
+            default_agent: project.default_agent,
+            default_model_provider_id: project.default_model_provider_id,
+            default_model_id: project.default_model_id,
+            default_thinking_variant: project.default_thinking_variant,
             created_at: Some(naive_datetime_to_timestamp(project.created_at)),
             updated_at: Some(naive_datetime_to_timestamp(project.updated_at)),
         }
@@ -35,6 +43,10 @@ impl TryFrom<proto_project::ProjectModel> for ProjectModel {
             id: parse_uuid("project.id", &model.id)?,
             name: model.name,
             dir: model.dir,
+            default_agent: model.default_agent,
+            default_model_provider_id: model.default_model_provider_id,
+            default_model_id: model.default_model_id,
+            default_thinking_variant: model.default_thinking_variant,
             created_at: timestamp_to_naive_datetime("project.created_at", model.created_at)?,
             updated_at: timestamp_to_naive_datetime("project.updated_at", model.updated_at)?,
         })
@@ -3,6 +3,8 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::backend::{
+    harness::DEFAULT_HARNESS_TYPE,
+    models::project_model::ProjectModel,
     proto_session,
     proto_utils::{naive_datetime_to_timestamp, parse_uuid, timestamp_to_naive_datetime},
 };
@@ -20,10 +22,31 @@ pub struct SessionModel {
     pub summary_additions: Option<i64>,
     pub summary_deletions: Option<i64>,
     pub summary_files: Option<i64>,
+    pub default_agent: Option<String>,
+    pub default_model_provider_id: Option<String>,
+    pub default_model_id: Option<String>,
+    pub default_thinking_variant: Option<String>,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
 
+impl SessionModel {
+    /// Takes the project's defaults for whatever the session does not choose itself
+    pub fn inherit_defaults(&mut self, project: &ProjectModel) {
+        if self.default_agent.is_none() {
+            self.default_agent = project.default_agent.clone();
+        }
+        // Provider and model only make sense together
+        if self.default_model_id.is_none() {
+            self.default_model_provider_id = project.default_model_provider_id.clone();
+            self.default_model_id = project.default_model_id.clone();
+        }
+        if self.default_thinking_variant.is_none() {
+            self.default_thinking_variant = project.default_thinking_variant.clone();
+        }
+    }
+}
+
 impl From<SessionModel> for proto_session::SessionModel {
     fn from(session: SessionModel) -> Self {
         Self {
@@ -31,6 +54,12 @@ impl From<SessionModel> for proto_session::SessionModel {
             project_id: session.project_id.to_string(),
             show_in_gui: session.show_in_gui,
             name: session.name,
+            harness_type: session.harness_type,
+            default_agent: session.default_agent,
+            default_model_provider_id: session.default_model_provider_id,
+            default_model_id: session.default_model_id,
+            default_thinking_variant: session.default_thinking_variant,
+            parent_session_id: session.parent_session_id.map(|id| id.to_string()),
             created_at: Some(naive_datetime_to_timestamp(session.created_at)),
             updated_at: Some(naive_datetime_to_timestamp(session.updated_at)),
         }
@@ -43,15 +72,27 @@ impl TryFrom<proto_session::SessionModel> for SessionModel {
         Ok(Self {
             id: parse_uuid("session.id", &model.id)?,
             project_id: parse_uuid("session.project_id", &model.project_id)?,
-            parent_session_id: None,
+            parent_session_id: model
+                .parent_session_id
+                .as_deref()
+                .map(|id| parse_uuid("session.parent_session_id", id))
+                .transpose()?,
             show_in_gui: model.show_in_gui,
             name: model.name,
-            harness_type: "opencode".to_string(),
+            harness_type: if model.harness_type.is_empty() {
+                DEFAULT_HARNESS_TYPE.to_string()
+            } else {
+                model.harness_type
+            },
             harness_session_id: String::new(),
             dir: None,
             summary_additions: None,
             summary_deletions: None,
             summary_files: None,
+            default_agent: model.default_agent,
+            default_model_provider_id: model.default_model_provider_id,
+            default_model_id: model.default_model_id,
+            default_thinking_variant: model.default_thinking_variant,
             created_at: timestamp_to_naive_datetime("session.created_at", model.created_at)?,
             updated_at: timestamp_to_naive_datetime("session.updated_at", model.updated_at)?,
         })
@@ -0,0 +1,67 @@
+use serde::{Deserialize, Serialize};
+
+use crate::backend::{
+    harness::HarnessSessionStatus,
+    proto_message::{self, SessionStatusKind},
+};
+
+pub const SESSION_STATUS_IDLE: &str = "idle";
+pub const SESSION_STATUS_BUSY: &str = "busy";
+pub const SESSION_STATUS_RETRY: &str = "retry";
+
+/// What the harness last reported for a session, stored on the session row
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct SessionStatusModel {
+    /// `idle`, `busy` or `retry`
+    pub status: String,
+    /// Only set while retrying
+    pub status_attempt: Option<i64>,
+    pub status_message: Option<String>,
+    /// Unix millis of the next attempt
+    pub status_next: Option<i64>,
+    pub last_error: Option<String>,
+}
+
+impl From<HarnessSessionStatus> for SessionStatusModel {
+    fn from(status: HarnessSessionStatus) -> Self {
+        let (status, status_attempt, status_message, status_next) = match status {
+            HarnessSessionStatus::Idle => (SESSION_STATUS_IDLE, None, None, None),
+            HarnessSessionStatus::Busy => (SESSION_STATUS_BUSY, None, None, None),
+            HarnessSessionStatus::Retry {
+                attempt,
+                message,
+                next,
+            } => (
+                SESSION_STATUS_RETRY,
+                Some(attempt),
+                Some(message),
+                Some(next),
+            ),
+        };
+        Self {
+            status: status.to_string(),
+            status_attempt,
+            status_message,
+            status_next,
+            last_error: None,
+        }
+    }
+}
+
+impl From<SessionStatusModel> for proto_message::SessionStatusModel {
+    fn from(status: SessionStatusModel) -> Self {
+        let kind = match status.status.as_str() {
+            SESSION_STATUS_IDLE => SessionStatusKind::Idle,
+            SESSION_STATUS_BUSY => SessionStatusKind::Busy,
+            SESSION_STATUS_RETRY => SessionStatusKind::Retry,
+            _ => SessionStatusKind::Unspecified,
+        };
+        Self {
+            kind: kind.into(),
+            attempt: status.status_attempt.unwrap_or_default(),
+            message: status.status_message.unwrap_or_default(),
+            next: status.status_next.unwrap_or_default(),
+            error: status.last_error,
+        }
+    }
+}
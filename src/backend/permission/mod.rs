@@ -0,0 +1,231 @@
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex, MutexGuard},
+};
+
+use chrono::{NaiveDateTime, Utc};
+use tokio::sync::{oneshot, watch};
+use uuid::Uuid;
+
+use crate::backend::{
+    db::{Database, DatabaseError},
+    models::permission_rule_model::{PERMISSION_ACTION_ALLOW, PermissionRuleModel},
+    proto_permission,
+    proto_utils::naive_datetime_to_timestamp,
+};
+
+#[cfg(test)]
+mod mod_test;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PermissionDecision {
+    Approve,
+    Deny,
+    /// Approves this request and every later one of the same kind in the project
+    AlwaysAllow,
+}
+
+/// What a harness wants to do, as reported by the agent
+#[derive(Debug, Clone)]
+pub struct PermissionPrompt {
+    /// The harness' own id for the request, used to ignore the same request seen twice
+    pub harness_request_id: Option<String>,
+    pub tool_call_id: Option<String>,
+    pub title: String,
+    /// Tool kind in ACP terms, e.g. `edit` or `execute`
+    pub kind: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct PendingPermission {
+    pub id: Uuid,
+    pub session_id: Uuid,
+    pub project_id: Uuid,
+    pub prompt: PermissionPrompt,
+    pub created_at: NaiveDateTime,
+}
+
+impl From<PendingPermission> for proto_permission::PermissionRequestModel {
+    fn from(value: PendingPermission) -> Self {
+        Self {
+            id: value.id.to_string(),
+            session_id: value.session_id.to_string(),
+            tool_call_id: value.prompt.tool_call_id,
+            title: value.prompt.title,
+            kind: value.prompt.kind,
+            created_at: Some(naive_datetime_to_timestamp(value.created_at)),
+        }
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum PermissionError {
+    #[error("database error: {0}")]
+    Database(#[from] DatabaseError),
+    #[error("no session for harness session {0}")]
+    SessionNotFound(String),
+    #[error("permission request {0} is already pending")]
+    AlreadyPending(String),
+    #[error("permission request {0} not found")]
+    RequestNotFound(Uuid),
+}
+
+impl From<PermissionError> for tonic::Status {
+    fn from(err: PermissionError) -> Self {
+        match err {
+            PermissionError::Database(e) => tonic::Status::internal(e.to_string()),
+            PermissionError::SessionNotFound(_) | PermissionError::RequestNotFound(_) => {
+                tonic::Status::not_found(err.to_string())
+            }
+            PermissionError::AlreadyPending(_) => tonic::Status::already_exists(err.to_string()),
+        }
+    }
+}
+
+struct Waiting {
+    pending: PendingPermission,
+    reply: oneshot::Sender<PermissionDecision>,
+}
+
+/// Hands permission requests from harnesses to the GUI and waits for the answer.
+/// Project rules answer requests up front, everything else stays pending until the user decides
+pub struct PermissionBroker {
+    db: Arc<Database>,
+    waiting: Mutex<HashMap<Uuid, Waiting>>,
+    pending_sender: watch::Sender<Vec<PendingPermission>>,
+}
+
+impl PermissionBroker {
+    pub fn new(db: Arc<Database>) -> Self {
+        let (pending_sender, _) = watch::channel(Vec::new());
+        Self {
+            db,
+            waiting: Mutex::new(HashMap::new()),
+            pending_sender,
+        }
+    }
+
+    /// Asks for permission on behalf of a harness session. Waits until the user answers
+    /// unless a project rule already covers the kind
+    pub async fn request(
+        &self,
+        harness_session_id: &str,
+        prompt: PermissionPrompt,
+    ) -> Result<PermissionDecision, PermissionError> {
+        let session = self
+            .db
+            .get_session_by_harness_session_id(harness_session_id.to_string())
+            .await?
+            .ok_or_else(|| PermissionError::SessionNotFound(harness_session_id.to_string()))?;
+
+        if let Some(rule) = self
+            .db
+            .get_permission_rule(session.project_id, prompt.kind.clone())
+            .await?
+        {
+            return Ok(if rule.action == PERMISSION_ACTION_ALLOW {
+                PermissionDecision::Approve
+            } else {
+                PermissionDecision::Deny
+            });
+        }
+
+        let (reply, decision) = oneshot::channel();
+        let id = Uuid::new_v4();
+        {
+            let mut waiting = self.waiting_guard();
+            if let Some(harness_request_id) = &prompt.harness_request_id
+                && waiting.values().any(|waiting| {
+                    waiting.pending.prompt.harness_request_id.as_ref() == Some(harness_request_id)
+                })
+            {
+                return Err(PermissionError::AlreadyPending(harness_request_id.clone()));
+            }
+            waiting.insert(
+                id,
+                Waiting {
+                    pending: PendingPermission {
+                        id,
+                        session_id: session.id,
+                        project_id: session.project_id,
+                        prompt,
+                        created_at: Utc::now().naive_utc(),
+                    },
+                    reply,
+                },
+            );
+            self.publish(&waiting);
+        }
+
+        // Forget the request if the harness stops waiting, e.g. because the turn was cancelled
+        let _cleanup = ForgetOnDrop { broker: self, id };
+        Ok(decision.await.unwrap_or(PermissionDecision::Deny))
+    }
+
+    pub async fn respond(
+        &self,
+        request_id: Uuid,
+        decision: PermissionDecision,
+    ) -> Result<(), PermissionError> {
+        let waiting = {
+            let mut waiting = self.waiting_guard();
+            let removed = waiting
+                .remove(&request_id)
+                .ok_or(PermissionError::RequestNotFound(request_id))?;
+            self.publish(&waiting);
+            removed
+        };
+
+        if decision == PermissionDecision::AlwaysAllow {
+            self.db
+                .upsert_permission_rule(PermissionRuleModel::new(
+                    waiting.pending.project_id,
+                    waiting.pending.prompt.kind.clone(),
+                    PERMISSION_ACTION_ALLOW,
+                ))
+                .await?;
+        }
+
+        // The harness may have given up in the meantime, nothing is waiting then
+        let _ = waiting.reply.send(decision);
+        Ok(())
+    }
+
+    /// Every pending request, oldest first. Callers filter for the sessions they show
+    pub fn subscribe(&self) -> watch::Receiver<Vec<PendingPermission>> {
+        self.pending_sender.subscribe()
+    }
+
+    fn forget(&self, id: Uuid) {
+        let mut waiting = self.waiting_guard();
+        if waiting.remove(&id).is_some() {
+            self.publish(&waiting);
+        }
+    }
+
+    fn publish(&self, waiting: &HashMap<Uuid, Waiting>) {
+        let mut pending = waiting
+            .values()
+            .map(|waiting| waiting.pending.clone())
+            .collect::<Vec<_>>();
+        pending.sort_by_key(|pending| pending.created_at);
+        self.pending_sender.send_replace(pending);
+    }
+
+    fn waiting_guard(&self) -> MutexGuard<'_, HashMap<Uuid, Waiting>> {
+        self.waiting
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+struct ForgetOnDrop<'a> {
+    broker: &'a PermissionBroker,
+    id: Uuid,
+}
+
+impl Drop for ForgetOnDrop<'_> {
+    fn drop(&mut self) {
+        self.broker.forget(self.id);
+    }
+}
@@ -0,0 +1,223 @@
+use std::sync::Arc;
+
+use chrono::{NaiveDateTime, Utc};
+use uuid::Uuid;
+
+use crate::backend::{
+    db::Database,
+    harness::DEFAULT_HARNESS_TYPE,
+    models::{
+        permission_rule_model::PermissionRuleModel, project_model::ProjectModel,
+        session_model::SessionModel,
+    },
+    permission::{PermissionBroker, PermissionDecision, PermissionError, PermissionPrompt},
+};
+
+const HARNESS_SESSION_ID: &str = "harness-session";
+
+struct Fixture {
+    db: Arc<Database>,
+    broker: Arc<PermissionBroker>,
+    project_id: Uuid,
+    session_id: Uuid,
+}
+
+async fn fixture() -> Fixture {
+    let db = Arc::new(
+        Database::new_in_memory()
+            .await
+            .expect("in-memory db should initialize"),
+    );
+    let now = now();
+    let project_id = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+
+    db.create_project(ProjectModel {
+        id: project_id,
+        name: "proj".to_string(),
+        dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+    .expect("create project should succeed");
+    db.create_session(SessionModel {
+        id: session_id,
+        project_id,
+        parent_session_id: None,
+        show_in_gui: true,
+        name: "session".to_string(),
+        harness_type: DEFAULT_HARNESS_TYPE.to_string(),
+        harness_session_id: HARNESS_SESSION_ID.to_string(),
+        dir: None,
+        summary_additions: None,
+        summary_deletions: None,
+        summary_files: None,
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        created_at: now,
+        updated_at: now,
+    })
+    .await
+    .expect("create session should succeed");
+
+    Fixture {
+        broker: Arc::new(PermissionBroker::new(Arc::clone(&db))),
+        db,
+        project_id,
+        session_id,
+    }
+}
+
+fn now() -> NaiveDateTime {
+    Utc::now().naive_utc()
+}
+
+fn prompt(harness_request_id: Option<&str>, kind: &str) -> PermissionPrompt {
+    PermissionPrompt {
+        harness_request_id: harness_request_id.map(str::to_string),
+        tool_call_id: Some("call-1".to_string()),
+        title: "Run ls".to_string(),
+        kind: kind.to_string(),
+    }
+}
+
+/// Starts a request in the background and waits until the broker publishes it
+async fn pending_request(
+    fixture: &Fixture,
+    prompt: PermissionPrompt,
+) -> (
+    Uuid,
+    tokio::task::JoinHandle<Result<PermissionDecision, PermissionError>>,
+) {
+    let mut receiver = fixture.broker.subscribe();
+    let broker = Arc::clone(&fixture.broker);
+    let task = tokio::spawn(async move { broker.request(HARNESS_SESSION_ID, prompt).await });
+
+    let pending = receiver
+        .wait_for(|pending| !pending.is_empty())
+        .await
+        .expect("broker should publish the request");
+    (pending[0].id, task)
+}
+
+#[tokio::test]
+async fn request_waits_for_the_user_and_clears_once_answered() {
+    let fixture = fixture().await;
+
+    let (request_id, task) = pending_request(&fixture, prompt(None, "execute")).await;
+    let pending = fixture.broker.subscribe().borrow().clone();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].session_id, fixture.session_id);
+    assert_eq!(pending[0].project_id, fixture.project_id);
+    assert_eq!(pending[0].prompt.title, "Run ls");
+
+    fixture
+        .broker
+        .respond(request_id, PermissionDecision::Deny)
+        .await
+        .expect("respond should succeed");
+
+    assert_eq!(task.await.unwrap().unwrap(), PermissionDecision::Deny);
+    assert!(fixture.broker.subscribe().borrow().is_empty());
+    assert!(matches!(
+        fixture
+            .broker
+            .respond(request_id, PermissionDecision::Approve)
+            .await,
+        Err(PermissionError::RequestNotFound(_))
+    ));
+}
+
+#[tokio::test]
+async fn always_allow_stores_a_rule_that_answers_later_requests() {
+    let fixture = fixture().await;
+
+    let (request_id, task) = pending_request(&fixture, prompt(None, "edit")).await;
+    fixture
+        .broker
+        .respond(request_id, PermissionDecision::AlwaysAllow)
+        .await
+        .expect("respond should succeed");
+    assert_eq!(
+        task.await.unwrap().unwrap(),
+        PermissionDecision::AlwaysAllow
+    );
+
+    let rule = fixture
+        .db
+        .get_permission_rule(fixture.project_id, "edit".to_string())
+        .await
+        .expect("rule lookup should succeed")
+        .expect("rule should be stored");
+    assert_eq!(rule.action, "allow");
+
+    let decision = fixture
+        .broker
+        .request(HARNESS_SESSION_ID, prompt(None, "edit"))
+        .await
+        .expect("request should succeed");
+    assert_eq!(decision, PermissionDecision::Approve);
+    assert!(fixture.broker.subscribe().borrow().is_empty());
+}
+
+#[tokio::test]
+async fn deny_rules_refuse_without_asking() {
+    let fixture = fixture().await;
+    fixture
+        .db
+        .upsert_permission_rule(PermissionRuleModel::new(
+            fixture.project_id,
+            "execute".to_string(),
+            "deny",
+        ))
+        .await
+        .expect("rule upsert should succeed");
+
+    let decision = fixture
+        .broker
+        .request(HARNESS_SESSION_ID, prompt(None, "execute"))
+        .await
+        .expect("request should succeed");
+    assert_eq!(decision, PermissionDecision::Deny);
+}
+
+#[tokio::test]
+async fn the_same_harness_request_is_only_asked_once() {
+    let fixture = fixture().await;
+
+    let (request_id, task) = pending_request(&fixture, prompt(Some("perm-1"), "execute")).await;
+    assert!(matches!(
+        fixture
+            .broker
+            .request(HARNESS_SESSION_ID, prompt(Some("perm-1"), "execute"))
+            .await,
+        Err(PermissionError::AlreadyPending(id)) if id == "perm-1"
+    ));
+
+    fixture
+        .broker
+        .respond(request_id, PermissionDecision::Approve)
+        .await
+        .expect("respond should succeed");
+    assert_eq!(task.await.unwrap().unwrap(), PermissionDecision::Approve);
+}
+
+#[tokio::test]
+async fn unknown_sessions_are_rejected() {
+    let fixture = fixture().await;
+
+    assert!(matches!(
+        fixture
+            .broker
+            .request("missing", prompt(None, "edit"))
+            .await,
+        Err(PermissionError::SessionNotFound(_))
+    ));
+}
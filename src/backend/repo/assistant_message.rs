@@ -3,6 +3,7 @@ use serde::{Deserialize, Serialize};
 use uuid::Uuid;
 
 use crate::backend::{
+    agent::fs::{FILE_WRITE_PART_TYPE, FileWrite},
     proto_message,
     proto_utils::{naive_datetime_to_timestamp, optional_naive_datetime_to_timestamp},
 };
@@ -119,6 +120,8 @@ pub struct AssistantMessage {
     pub token_cache_read: i64,
     pub token_cache_write: i64,
     pub error_message: Option<String>,
+    /// Undone by a revert, gone once the next message is sent
+    pub reverted: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
     pub completed_at: Option<NaiveDateTime>,
@@ -149,6 +152,7 @@ impl AssistantMessage {
             token_cache_read: 0,
             token_cache_write: 0,
             error_message: None,
+            reverted: false,
             created_at: now,
             updated_at: now,
             completed_at: None,
@@ -261,6 +265,10 @@ fn json_serialized(value: &serde_json::Value, key: &str) -> Option<String> {
     value.get(key).and_then(|v| serde_json::to_string(v).ok())
 }
 
+fn json_i64(value: &serde_json::Value, key: &str) -> Option<i64> {
+    value.get(key).and_then(serde_json::Value::as_i64)
+}
+
 impl AssistantMessagePart {
     pub fn new_from_harness(
         session_id: Uuid,
@@ -370,6 +378,12 @@ impl AssistantMessagePart {
             merge_option(&mut self.tool_state_raw, json_string(state, "raw"));
         }
 
+        merge_option(&mut self.file_source_path, json_string(&payload, "path"));
+        merge_option(
+            &mut self.part_metadata_json,
+            json_serialized(&payload, "metadata"),
+        );
+
         merge_option(&mut self.tool_call_id, json_string(&payload, "callID"));
         merge_option(&mut self.tool_name, json_string(&payload, "tool"));
         merge_option(&mut self.finish_reason, json_string(&payload, "reason"));
@@ -377,6 +391,20 @@ impl AssistantMessagePart {
             &mut self.step_snapshot_hash,
             json_string(&payload, "snapshot"),
         );
+        merge_option(
+            &mut self.cost,
+            payload.get("cost").and_then(serde_json::Value::as_f64),
+        );
+        if let Some(tokens) = payload.get("tokens") {
+            merge_option(&mut self.token_total, json_i64(tokens, "total"));
+            merge_option(&mut self.token_input, json_i64(tokens, "input"));
+            merge_option(&mut self.token_output, json_i64(tokens, "output"));
+            merge_option(&mut self.token_reasoning, json_i64(tokens, "reasoning"));
+            if let Some(cache) = tokens.get("cache") {
+                merge_option(&mut self.token_cache_read, json_i64(cache, "read"));
+                merge_option(&mut self.token_cache_write, json_i64(cache, "write"));
+            }
+        }
         self.updated_at = Utc::now().naive_utc();
     }
 
@@ -407,6 +435,39 @@ impl AssistantMessagePart {
         }
     }
 
+    /// The agent write recorded by a `file-write` part, `None` for every other part
+    pub fn file_write(&self) -> Option<FileWrite> {
+        if self.part_type != FILE_WRITE_PART_TYPE {
+            return None;
+        }
+        let metadata: serde_json::Value =
+            serde_json::from_str(self.part_metadata_json.as_deref()?).ok()?;
+        Some(FileWrite {
+            path: self.file_source_path.clone()?.into(),
+            before: json_string(&metadata, "before"),
+            after: json_string(&metadata, "after")?,
+        })
+    }
+
+    pub fn is_reverted(&self) -> bool {
+        self.part_metadata_json
+            .as_deref()
+            .and_then(|metadata| serde_json::from_str::<serde_json::Value>(metadata).ok())
+            .and_then(|metadata| metadata.get("reverted")?.as_bool())
+            .unwrap_or(false)
+    }
+
+    pub fn mark_reverted(&mut self) {
+        let mut metadata = self
+            .part_metadata_json
+            .as_deref()
+            .and_then(|metadata| serde_json::from_str::<serde_json::Value>(metadata).ok())
+            .unwrap_or_else(|| serde_json::json!({}));
+        metadata["reverted"] = true.into();
+        self.part_metadata_json = Some(metadata.to_string());
+        self.updated_at = Utc::now().naive_utc();
+    }
+
     pub fn tool_status_value(&self) -> Option<ToolStatus> {
         self.tool_status.as_deref().and_then(ToolStatus::from_str)
     }
@@ -640,6 +701,7 @@ impl From<AssistantMessage> for proto_message::AssistantMessageModel {
             token_cache_read: value.token_cache_read,
             token_cache_write: value.token_cache_write,
             error_message: value.error_message,
+            reverted: value.reverted,
             created_at: Some(naive_datetime_to_timestamp(value.created_at)),
             updated_at: Some(naive_datetime_to_timestamp(value.updated_at)),
             completed_at: optional_naive_datetime_to_timestamp(value.completed_at),
@@ -1,10 +1,14 @@
+use std::collections::HashMap;
 use uuid::Uuid;
 
 use crate::backend::{
     BackendContext,
+    agent::fs::{ProjectFs, ProjectFsError},
+    attachment::{self, AttachmentError},
     db::DatabaseError,
-    harness::Harness,
-    proto_message,
+    ingest::IngestedChange,
+    models::message_defaults_model::MessageDefaultsModel,
+    proto_message::{self, message_event::Event},
     repo::{
         assistant_message::{AssistantMessage, AssistantMessagePart},
         user_message::UserMessage,
@@ -12,6 +16,7 @@ use crate::backend::{
     },
 };
 
+#[derive(Clone)]
 pub enum Message {
     User(UserMessage),
     Assistant(AssistantMessage),
@@ -52,14 +57,136 @@ pub fn join_assistant_message_parts(
     model
 }
 
+/// Sends messages changed outside the harness's events to the session's viewers.
+/// User messages go with their parts since viewers replace them whole
+pub async fn publish_changes(
+    ctx: &BackendContext,
+    session_id: Uuid,
+    changed: Vec<Message>,
+    removed: Vec<Uuid>,
+) -> Result<(), DatabaseError> {
+    if changed.is_empty() && removed.is_empty() {
+        return Ok(());
+    }
+
+    let mut user_parts: HashMap<Uuid, Vec<UserMessagePart>> = HashMap::new();
+    if changed
+        .iter()
+        .any(|message| matches!(message, Message::User(_)))
+    {
+        for part in ctx
+            .db
+            .list_user_message_parts_by_session(session_id)
+            .await?
+        {
+            user_parts
+                .entry(part.user_message_id)
+                .or_default()
+                .push(part);
+        }
+    }
+    let upserted = changed.into_iter().map(|message| {
+        let message = match message {
+            Message::User(user) => {
+                let parts = user_parts.remove(&user.id).unwrap_or_default();
+                proto_message::message_history::Message::UserMessage(join_user_message_parts(
+                    user, parts,
+                ))
+            }
+            Message::Assistant(assistant) => {
+                proto_message::message_history::Message::AssistantMessage(assistant.into())
+            }
+        };
+        Event::MessageUpserted(proto_message::MessageHistory {
+            message: Some(message),
+        })
+    });
+    let removed = removed.into_iter().map(|id| {
+        Event::MessageRemoved(proto_message::MessageRemoved {
+            message_id: id.to_string(),
+        })
+    });
+    publish_events(ctx, session_id, upserted.chain(removed)).await
+}
+
+/// Sends assistant parts changed outside the harness's events to the session's viewers
+pub async fn publish_part_changes(
+    ctx: &BackendContext,
+    session_id: Uuid,
+    changed: Vec<AssistantMessagePart>,
+) -> Result<(), DatabaseError> {
+    if changed.is_empty() {
+        return Ok(());
+    }
+    let upserted = changed
+        .into_iter()
+        .map(|part| Event::PartUpserted(part.into()));
+    publish_events(ctx, session_id, upserted).await
+}
+
+async fn publish_events(
+    ctx: &BackendContext,
+    session_id: Uuid,
+    events: impl Iterator<Item = Event>,
+) -> Result<(), DatabaseError> {
+    let events = events
+        .map(|event| proto_message::MessageEvent { event: Some(event) })
+        .collect();
+    let revision = ctx.db.session_revision(session_id).await?;
+    ctx.ingestor
+        .publish(session_id, IngestedChange { revision, events });
+    Ok(())
+}
+
+/// What a subscriber that last saw an earlier revision is missing
+#[derive(Debug)]
+pub struct MessageReplay {
+    /// Session revision the replay is current to
+    pub revision: i64,
+    pub messages: Vec<proto_message::MessageHistory>,
+    pub removed_message_ids: Vec<Uuid>,
+}
+
 #[derive(Debug, thiserror::Error)]
 pub enum MessageRepoError {
     #[error("database error: {0}")]
     Database(#[from] DatabaseError),
     #[error("session not found for {0}")]
     SessionNotFound(Uuid),
+    #[error("project not found for {0}")]
+    ProjectNotFound(Uuid),
     #[error("harness error: {0}")]
     Harness(#[from] crate::backend::harness::HarnessError),
+    #[error("assistant message part not found for {0}")]
+    PartNotFound(Uuid),
+    #[error("assistant message part {0} is not a file write")]
+    NotAFileWrite(Uuid),
+    #[error("file write {0} was already reverted")]
+    AlreadyReverted(Uuid),
+    #[error("file error: {0}")]
+    Files(#[from] ProjectFsError),
+    #[error("attachment rejected: {0}")]
+    Attachment(#[from] AttachmentError),
+}
+
+/// Rejects file parts the harness could not read or the model would not take,
+/// before anything is stored. Inlined files keep the mime type of their data URL
+fn check_attachment(part: &mut UserMessagePart) -> Result<(), AttachmentError> {
+    let Some(url) = part
+        .file_url
+        .as_deref()
+        .filter(|_| part.part_type == "file")
+    else {
+        return Ok(());
+    };
+    attachment::validate_url(part.file_name.as_deref().unwrap_or(url), url)?;
+    if part.file_mime.is_none() {
+        part.file_mime = url
+            .strip_prefix("data:")
+            .and_then(|data| data.split_once(";base64,"))
+            .map(|(mime, _)| mime.to_string());
+    }
+    Ok(())
 }
 
 pub struct MessageRepo {
@@ -71,23 +198,165 @@ impl MessageRepo {
         Self { ctx }
     }
 
+    /// Lists the latest messages of a session, oldest first, with their parts attached
     pub async fn list_by_session(
         &self,
         session_id: &Uuid,
         limit: u32,
-    ) -> Result<Vec<Message>, MessageRepoError> {
-        Ok(self
+    ) -> Result<Vec<proto_message::MessageHistory>, MessageRepoError> {
+        let messages = self
             .ctx
             .db
             .list_messages_by_session(*session_id, limit)
-            .await?)
+            .await?;
+        if messages.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        let (mut user_parts, mut assistant_parts) = self.parts_by_message(session_id).await?;
+
+        Ok(messages
+            .into_iter()
+            .map(|message| {
+                let message = match message {
+                    Message::User(user) => {
+                        let parts = user_parts.remove(&user.id).unwrap_or_default();
+                        proto_message::message_history::Message::UserMessage(
+                            join_user_message_parts(user, parts),
+                        )
+                    }
+                    Message::Assistant(assistant) => {
+                        let parts = assistant_parts.remove(&assistant.id).unwrap_or_default();
+                        proto_message::message_history::Message::AssistantMessage(
+                            join_assistant_message_parts(assistant, parts),
+                        )
+                    }
+                };
+                proto_message::MessageHistory {
+                    message: Some(message),
+                }
+            })
+            .collect())
+    }
+
+    /// Messages written or removed after `since_revision`.
+    /// Assistant messages only carry the parts that changed, user messages come whole
+    pub async fn list_changes_since(
+        &self,
+        session_id: &Uuid,
+        since_revision: i64,
+    ) -> Result<MessageReplay, MessageRepoError> {
+        let changes = self
+            .ctx
+            .db
+            .list_message_changes_since(*session_id, since_revision)
+            .await?;
+        if changes.user_messages.is_empty()
+            && changes.user_message_parts.is_empty()
+            && changes.assistant_messages.is_empty()
+            && changes.assistant_message_parts.is_empty()
+        {
+            return Ok(MessageReplay {
+                revision: changes.revision,
+                messages: Vec::new(),
+                removed_message_ids: changes.removed_messages.into_iter().collect(),
+            });
+        }
+
+        let messages = self
+            .ctx
+            .db
+            .list_messages_by_session(*session_id, u32::MAX)
+            .await?;
+        let (mut user_parts, mut assistant_parts) = self.parts_by_message(session_id).await?;
+
+        let changed = messages
+            .into_iter()
+            .filter_map(|message| {
+                let message = match message {
+                    Message::User(user) => {
+                        let parts = user_parts.remove(&user.id).unwrap_or_default();
+                        if !changes.user_messages.contains(&user.id)
+                            && !parts
+                                .iter()
+                                .any(|part| changes.user_message_parts.contains(&part.id))
+                        {
+                            return None;
+                        }
+                        proto_message::message_history::Message::UserMessage(
+                            join_user_message_parts(user, parts),
+                        )
+                    }
+                    Message::Assistant(assistant) => {
+                        let parts = assistant_parts
+                            .remove(&assistant.id)
+                            .unwrap_or_default()
+                            .into_iter()
+                            .filter(|part| changes.assistant_message_parts.contains(&part.id))
+                            .collect::<Vec<_>>();
+                        if !changes.assistant_messages.contains(&assistant.id) && parts.is_empty() {
+                            return None;
+                        }
+                        proto_message::message_history::Message::AssistantMessage(
+                            join_assistant_message_parts(assistant, parts),
+                        )
+                    }
+                };
+                Some(proto_message::MessageHistory {
+                    message: Some(message),
+                })
+            })
+            .collect();
+
+        Ok(MessageReplay {
+            revision: changes.revision,
+            messages: changed,
+            removed_message_ids: changes.removed_messages.into_iter().collect(),
+        })
+    }
+
+    async fn parts_by_message(
+        &self,
+        session_id: &Uuid,
+    ) -> Result<
+        (
+            HashMap<Uuid, Vec<UserMessagePart>>,
+            HashMap<Uuid, Vec<AssistantMessagePart>>,
+        ),
+        MessageRepoError,
+    > {
+        let mut user_parts: HashMap<Uuid, Vec<UserMessagePart>> = HashMap::new();
+        for part in self
+            .ctx
+            .db
+            .list_user_message_parts_by_session(*session_id)
+            .await?
+        {
+            user_parts
+                .entry(part.user_message_id)
+                .or_default()
+                .push(part);
+        }
+        let mut assistant_parts: HashMap<Uuid, Vec<AssistantMessagePart>> = HashMap::new();
+        for part in self
+            .ctx
+            .db
+            .list_assistant_message_parts_by_session(*session_id)
+            .await?
+        {
+            assistant_parts
+                .entry(part.assistant_message_id)
+                .or_default()
+                .push(part);
+        }
+        Ok((user_parts, assistant_parts))
     }
 
     pub async fn create_user_message(
         &self,
         message: UserMessage,
         mut message_parts: Vec<UserMessagePart>,
-    ) -> Result<UserMessage, MessageRepoError> {
+    ) -> Result<(UserMessage, Vec<UserMessagePart>), MessageRepoError> {
         let session = self
             .ctx
             .db
@@ -95,34 +364,175 @@ impl MessageRepo {
             .await?
             .ok_or(MessageRepoError::SessionNotFound(message.session_id))?;
 
-        log::debug!("sending message to harness");
-        let _ = self
-            .ctx
-            .harness
-            .send_message_async(
-                session.harness_session_id,
-                message.clone(),
-                message_parts.clone(),
-                session.dir,
-            )
-            .await?;
-        log::debug!("sent message to harness");
+        let harness = self.ctx.harnesses.get(&session.harness_type)?;
+
+        for message_part in &mut message_parts {
+            check_attachment(message_part)?;
+        }
 
-        let created_message = self.ctx.db.create_user_message(message.clone()).await?;
+        // Persist before dispatching so assistant events streamed back by the harness
+        // always find the user message they reply to.
+        let created_message = self.ctx.db.create_user_message(message).await?;
 
+        let mut created_parts = Vec::with_capacity(message_parts.len());
         for message_part in &mut message_parts {
             message_part.user_message_id = created_message.id;
             message_part.session_id = created_message.session_id;
-            let _ = self
+            created_parts.push(
+                self.ctx
+                    .db
+                    .create_user_message_part(message_part.clone())
+                    .await?,
+            );
+        }
+
+        log::debug!("sending message {} to harness", created_message.id);
+        // Listen before prompting so the start of the reply is stored too
+        let sent = match self.ctx.ingestor.ingest(&session).await {
+            Ok(_) => {
+                harness
+                    .send_message_async(
+                        session.harness_session_id,
+                        created_message.clone(),
+                        created_parts.clone(),
+                        session.dir,
+                    )
+                    .await
+            }
+            Err(err) => Err(err),
+        };
+        if let Err(err) = sent {
+            // Parts cascade with the message, the tombstone reaches viewers resuming later
+            let rolled_back = match self
                 .ctx
                 .db
-                .create_user_message_part(message_part.clone())
-                .await?;
+                .remove_user_message(session.id, created_message.id)
+                .await
+            {
+                Ok(_) => {
+                    publish_changes(&self.ctx, session.id, Vec::new(), vec![created_message.id])
+                        .await
+                }
+                Err(err) => Err(err),
+            };
+            if let Err(delete_err) = rolled_back {
+                log::error!(
+                    "failed to roll back user message {} after harness error: {delete_err}",
+                    created_message.id
+                );
+            }
+            return Err(err.into());
+        }
+        log::debug!("sent message {} to harness", created_message.id);
+
+        // The delivered message settles a revert, what it undid is dropped like the harness
+        // drops it. A stash taken for it stays in git's stash list
+        if let Err(err) = self.settle_revert(session.id).await {
+            log::error!(
+                "failed to settle the revert of session {}: {err}",
+                session.id
+            );
+        }
+
+        // The harness only streams the reply, viewers get the prompt from here
+        if let Err(err) = publish_changes(
+            &self.ctx,
+            session.id,
+            vec![Message::User(created_message.clone())],
+            Vec::new(),
+        )
+        .await
+        {
+            log::error!(
+                "failed to publish user message {}: {err}",
+                created_message.id
+            );
         }
 
-        Ok(created_message)
+        // The next message and the project's next session start from this choice
+        if let Err(err) = self
+            .ctx
+            .db
+            .update_session_defaults(session.id, MessageDefaultsModel::from(&created_message))
+            .await
+        {
+            log::error!(
+                "failed to remember the defaults of message {}: {err}",
+                created_message.id
+            );
+        }
+
+        Ok((created_message, created_parts))
+    }
+
+    async fn settle_revert(&self, session_id: Uuid) -> Result<(), DatabaseError> {
+        let removed = self.ctx.db.remove_reverted_messages(session_id).await?;
+        if removed.is_empty() {
+            return Ok(());
+        }
+        self.ctx
+            .db
+            .set_session_revert_stash(session_id, None)
+            .await?;
+        publish_changes(&self.ctx, session_id, Vec::new(), removed).await
+    }
+
+    /// Puts back what was on disk before an agent write and marks the write as reverted.
+    /// Returns the updated part with its message so subscribers can merge it
+    pub async fn revert_file_write(
+        &self,
+        part_id: &Uuid,
+    ) -> Result<proto_message::MessageHistory, MessageRepoError> {
+        let mut part = self
+            .ctx
+            .db
+            .get_assistant_message_part(*part_id)
+            .await?
+            .ok_or(MessageRepoError::PartNotFound(*part_id))?;
+        let write = part
+            .file_write()
+            .ok_or(MessageRepoError::NotAFileWrite(*part_id))?;
+        if part.is_reverted() {
+            return Err(MessageRepoError::AlreadyReverted(*part_id));
+        }
+
+        let session = self
+            .ctx
+            .db
+            .get_session(part.session_id)
+            .await?
+            .ok_or(MessageRepoError::SessionNotFound(part.session_id))?;
+        let root = match session.dir {
+            Some(dir) => dir,
+            None => {
+                self.ctx
+                    .db
+                    .get_project(session.project_id)
+                    .await?
+                    .ok_or(MessageRepoError::ProjectNotFound(session.project_id))?
+                    .dir
+            }
+        };
+        ProjectFs::new(root).revert(&write).await?;
+
+        part.mark_reverted();
+        let part = self.ctx.db.update_assistant_message_part(part).await?;
+        publish_part_changes(&self.ctx, session.id, vec![part.clone()]).await?;
+        let assistant = self
+            .ctx
+            .db
+            .get_assistant_message(part.assistant_message_id)
+            .await?
+            .ok_or(MessageRepoError::PartNotFound(*part_id))?;
+
+        Ok(proto_message::MessageHistory {
+            message: Some(proto_message::message_history::Message::AssistantMessage(
+                join_assistant_message_parts(assistant, vec![part]),
+            )),
+        })
     }
 
+    #[cfg(test)]
     pub async fn list_user_messages(
         &self,
         session_id: &Uuid,
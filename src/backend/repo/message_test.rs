@@ -7,14 +7,15 @@ use uuid::Uuid;
 
 use crate::backend::{
     BackendContext,
+    agent::fs::FILE_WRITE_PART_TYPE,
     db::Database,
-    harness::Harness,
-    harness::opencode::OpencodeHarness,
+    harness::{Harness, HarnessRegistry, opencode::OpencodeHarness},
     models::project_model::ProjectModel,
     models::session_model::SessionModel,
+    proto_message,
     repo::{
-        assistant_message::AssistantMessage,
-        message::{Message, MessageRepo, MessageRepoError},
+        assistant_message::{AssistantMessage, AssistantMessagePart},
+        message::{MessageRepo, MessageRepoError},
         user_message::UserMessage,
         user_message_part::UserMessagePart,
     },
@@ -27,6 +28,10 @@ fn test_project(id: Uuid, at: NaiveDateTime) -> ProjectModel {
         id,
         name: "proj".to_string(),
         dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: at,
         updated_at: at,
     }
@@ -45,6 +50,10 @@ fn test_session(id: Uuid, project_id: Uuid, at: NaiveDateTime) -> SessionModel {
         summary_additions: None,
         summary_deletions: None,
         summary_files: None,
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: at,
         updated_at: at,
     }
@@ -61,6 +70,8 @@ fn user_message(id: Uuid, session_id: Uuid, at: NaiveDateTime) -> UserMessage {
         structured_output_type: "text".to_string(),
         tools_list: "{}".to_string(),
         thinking_variant: None,
+        harness_message_id: None,
+        reverted: false,
         created_at: at,
         updated_at: at,
     }
@@ -90,6 +101,7 @@ fn assistant_message(
         token_cache_read: 0,
         token_cache_write: 0,
         error_message: None,
+        reverted: false,
         created_at: at,
         updated_at: at,
         completed_at: Some(at),
@@ -113,7 +125,15 @@ fn user_message_part(
         text: Some(text.to_string()),
         file_name: None,
         file_url: None,
+        file_mime: None,
         agent_name: None,
+        agent_source_value: None,
+        agent_source_start: None,
+        agent_source_end: None,
+        file_source_path: None,
+        file_source_text_value: None,
+        file_source_text_start: None,
+        file_source_text_end: None,
         subtask_prompt: None,
         subtask_description: None,
         created_at: at,
@@ -144,6 +164,10 @@ async fn create_user_message_sends_message_to_harness() {
         id: project_id,
         name: "proj".to_string(),
         dir: project_dir_string.clone(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: now,
         updated_at: now,
     })
@@ -166,10 +190,10 @@ async fn create_user_message_sends_message_to_harness() {
         .expect("create session should succeed");
 
     let harness_for_asserts = harness.clone();
-    let ctx = BackendContext::new(db, harness);
+    let ctx = BackendContext::new(db, HarnessRegistry::new().with(harness));
     let repo = MessageRepo::new(ctx);
 
-    let created = repo
+    let (created, created_parts) = repo
         .create_user_message(
             user_message(message_id, session_id, now + Duration::seconds(1)),
             vec![user_message_part(
@@ -186,6 +210,8 @@ async fn create_user_message_sends_message_to_harness() {
 
     assert_eq!(created.id, message_id);
     assert_eq!(created.session_id, session_id);
+    assert_eq!(created_parts.len(), 1);
+    assert_eq!(created_parts[0].user_message_id, message_id);
 
     let messages = harness_for_asserts
         .get_session_messages(&harness_session_id, Some(50), Some(&project_dir_string))
@@ -198,3 +224,394 @@ async fn create_user_message_sends_message_to_harness() {
         "all returned messages should belong to test harness session"
     );
 }
+
+#[tokio::test]
+async fn create_user_message_rolls_back_when_harness_fails() {
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let now = fixed_datetime();
+    let project_id = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+    let message_id = Uuid::new_v4();
+
+    db.create_project(test_project(project_id, now))
+        .await
+        .expect("create project should succeed");
+    db.create_session(test_session(session_id, project_id, now))
+        .await
+        .expect("create session should succeed");
+
+    let ctx = BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(closed_port())),
+    );
+    let repo = MessageRepo::new(ctx);
+
+    let err = repo
+        .create_user_message(
+            user_message(message_id, session_id, now),
+            vec![user_message_part(
+                Uuid::new_v4(),
+                message_id,
+                session_id,
+                0,
+                "never delivered",
+                now,
+            )],
+        )
+        .await
+        .expect_err("closed harness port should fail");
+    assert!(matches!(err, MessageRepoError::Harness(_)));
+
+    let remaining = repo
+        .list_user_messages(&session_id, 10)
+        .await
+        .expect("list_user_messages should succeed");
+    assert!(remaining.is_empty());
+
+    // A viewer that saw the message before the rollback still learns it is gone
+    let replay = repo
+        .list_changes_since(&session_id, 0)
+        .await
+        .expect("list_changes_since should succeed");
+    assert_eq!(replay.removed_message_ids, vec![message_id]);
+}
+
+#[tokio::test]
+async fn create_user_message_keeps_a_revert_when_harness_fails() {
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let now = fixed_datetime();
+    let project_id = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+    let reverted_id = Uuid::new_v4();
+    let message_id = Uuid::new_v4();
+
+    db.create_project(test_project(project_id, now))
+        .await
+        .expect("create project should succeed");
+    db.create_session(test_session(session_id, project_id, now))
+        .await
+        .expect("create session should succeed");
+    db.create_user_message(UserMessage {
+        reverted: true,
+        ..user_message(reverted_id, session_id, now)
+    })
+    .await
+    .expect("create reverted message should succeed");
+    db.set_session_revert_stash(session_id, Some("stash-commit".to_string()))
+        .await
+        .expect("set revert stash should succeed");
+
+    let ctx = BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(closed_port())),
+    );
+    let repo = MessageRepo::new(ctx.clone());
+
+    repo.create_user_message(
+        user_message(message_id, session_id, now + Duration::seconds(1)),
+        Vec::new(),
+    )
+    .await
+    .expect_err("closed harness port should fail");
+
+    // The undelivered message leaves the revert to be unreverted
+    let remaining = repo
+        .list_user_messages(&session_id, 10)
+        .await
+        .expect("list_user_messages should succeed");
+    assert_eq!(
+        remaining
+            .iter()
+            .map(|message| message.id)
+            .collect::<Vec<_>>(),
+        vec![reverted_id]
+    );
+    assert!(remaining[0].reverted);
+    let stash = ctx
+        .db
+        .get_session_revert_stash(session_id)
+        .await
+        .expect("revert stash should load");
+    assert_eq!(stash.as_deref(), Some("stash-commit"));
+}
+
+#[tokio::test]
+async fn create_user_message_rejects_unreadable_attachments() {
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let now = fixed_datetime();
+    let project_id = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+    let message_id = Uuid::new_v4();
+
+    db.create_project(test_project(project_id, now))
+        .await
+        .expect("create project should succeed");
+    db.create_session(test_session(session_id, project_id, now))
+        .await
+        .expect("create session should succeed");
+
+    let ctx = BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(closed_port())),
+    );
+    let repo = MessageRepo::new(ctx);
+
+    let mut part = user_message_part(Uuid::new_v4(), message_id, session_id, 0, "", now);
+    part.part_type = "file".to_string();
+    part.text = None;
+    part.file_name = Some("gone.txt".to_string());
+    part.file_url = Some("file:///does/not/exist/gone.txt".to_string());
+    let err = repo
+        .create_user_message(user_message(message_id, session_id, now), vec![part])
+        .await
+        .expect_err("a missing file should be rejected");
+    assert!(matches!(err, MessageRepoError::Attachment(_)));
+
+    let stored = repo
+        .list_user_messages(&session_id, 10)
+        .await
+        .expect("list_user_messages should succeed");
+    assert!(stored.is_empty());
+}
+
+#[tokio::test]
+async fn list_by_session_attaches_parts_in_order() {
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let now = fixed_datetime();
+    let project_id = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+    let user_message_id = Uuid::new_v4();
+    let assistant_message_id = Uuid::new_v4();
+
+    db.create_project(test_project(project_id, now))
+        .await
+        .expect("create project should succeed");
+    db.create_session(test_session(session_id, project_id, now))
+        .await
+        .expect("create session should succeed");
+    db.create_user_message(user_message(user_message_id, session_id, now))
+        .await
+        .expect("create user message should succeed");
+    db.create_user_message_part(user_message_part(
+        Uuid::new_v4(),
+        user_message_id,
+        session_id,
+        0,
+        "hello",
+        now,
+    ))
+    .await
+    .expect("create user message part should succeed");
+    db.create_assistant_message(assistant_message(
+        assistant_message_id,
+        session_id,
+        user_message_id,
+        now + Duration::seconds(1),
+    ))
+    .await
+    .expect("create assistant message should succeed");
+
+    for (harness_part_id, part_type) in [("prt-1", "reasoning"), ("prt-2", "text")] {
+        let mut part = AssistantMessagePart::new_from_harness(
+            session_id,
+            assistant_message_id,
+            harness_part_id,
+            part_type,
+        );
+        part.position = db
+            .next_assistant_message_part_position(assistant_message_id)
+            .await
+            .expect("next position should resolve");
+        db.create_assistant_message_part(part)
+            .await
+            .expect("create assistant message part should succeed");
+    }
+
+    let repo = MessageRepo::new(BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(closed_port())),
+    ));
+    let history = repo
+        .list_by_session(&session_id, 10)
+        .await
+        .expect("list_by_session should succeed");
+
+    assert_eq!(history.len(), 2);
+    match &history[0].message {
+        Some(proto_message::message_history::Message::UserMessage(user)) => {
+            assert_eq!(user.parts.len(), 1);
+            assert_eq!(user.parts[0].text.as_deref(), Some("hello"));
+        }
+        other => panic!("expected user message first, got {other:?}"),
+    }
+    match &history[1].message {
+        Some(proto_message::message_history::Message::AssistantMessage(assistant)) => {
+            let part_types = assistant
+                .parts
+                .iter()
+                .map(|part| (part.position, part.part_type.as_str()))
+                .collect::<Vec<_>>();
+            assert_eq!(part_types, vec![(0, "reasoning"), (1, "text")]);
+        }
+        other => panic!("expected assistant message second, got {other:?}"),
+    }
+}
+
+#[tokio::test]
+async fn list_changes_since_replays_only_later_writes() {
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let now = fixed_datetime();
+    let project_id = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+    let user_message_id = Uuid::new_v4();
+    let assistant_message_id = Uuid::new_v4();
+
+    db.create_project(test_project(project_id, now))
+        .await
+        .expect("create project should succeed");
+    db.create_session(test_session(session_id, project_id, now))
+        .await
+        .expect("create session should succeed");
+    db.create_user_message(user_message(user_message_id, session_id, now))
+        .await
+        .expect("create user message should succeed");
+    db.create_assistant_message(assistant_message(
+        assistant_message_id,
+        session_id,
+        user_message_id,
+        now + Duration::seconds(1),
+    ))
+    .await
+    .expect("create assistant message should succeed");
+    let cursor = db
+        .session_revision(session_id)
+        .await
+        .expect("revision should load");
+    assert_eq!(cursor, 2);
+
+    let mut part =
+        AssistantMessagePart::new_from_harness(session_id, assistant_message_id, "prt-1", "text");
+    part.apply_delta("text".to_string(), "hi".to_string());
+    let part = db
+        .create_assistant_message_part(part)
+        .await
+        .expect("create assistant message part should succeed");
+
+    let repo = MessageRepo::new(BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(closed_port())),
+    ));
+    let replay = repo
+        .list_changes_since(&session_id, cursor)
+        .await
+        .expect("list_changes_since should succeed");
+    let (revision, changes) = (replay.revision, replay.messages);
+
+    assert_eq!(revision, 3);
+    assert_eq!(changes.len(), 1);
+    match &changes[0].message {
+        Some(proto_message::message_history::Message::AssistantMessage(assistant)) => {
+            assert_eq!(assistant.id, assistant_message_id.to_string());
+            assert_eq!(assistant.parts.len(), 1);
+            assert_eq!(assistant.parts[0].id, part.id.to_string());
+        }
+        other => panic!("expected the assistant message, got {other:?}"),
+    }
+
+    let unchanged = repo
+        .list_changes_since(&session_id, revision)
+        .await
+        .expect("list_changes_since should succeed");
+    assert!(unchanged.messages.is_empty());
+    assert!(unchanged.removed_message_ids.is_empty());
+}
+
+#[tokio::test]
+async fn revert_file_write_restores_file_and_marks_part() {
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let now = fixed_datetime();
+    let project_id = Uuid::new_v4();
+    let session_id = Uuid::new_v4();
+    let user_message_id = Uuid::new_v4();
+    let assistant_message_id = Uuid::new_v4();
+    let dir = tempfile::tempdir().expect("temp dir should be created");
+    let file = dir.path().join("notes.txt");
+    std::fs::write(&file, "agent").expect("write file should succeed");
+
+    db.create_project(test_project(project_id, now))
+        .await
+        .expect("create project should succeed");
+    let mut session = test_session(session_id, project_id, now);
+    session.dir = Some(dir.path().to_string_lossy().to_string());
+    db.create_session(session)
+        .await
+        .expect("create session should succeed");
+    db.create_user_message(user_message(user_message_id, session_id, now))
+        .await
+        .expect("create user message should succeed");
+    db.create_assistant_message(assistant_message(
+        assistant_message_id,
+        session_id,
+        user_message_id,
+        now,
+    ))
+    .await
+    .expect("create assistant message should succeed");
+
+    let mut part =
+        AssistantMessagePart::new_from_harness(session_id, assistant_message_id, "w-1", "text");
+    part.apply_payload_json(
+        serde_json::json!({
+            "type": FILE_WRITE_PART_TYPE,
+            "path": file.to_string_lossy(),
+            "metadata": {"before": "user", "after": "agent"},
+        }),
+        "text",
+    );
+    let part = db
+        .create_assistant_message_part(part)
+        .await
+        .expect("create assistant message part should succeed");
+
+    let repo = MessageRepo::new(BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(closed_port())),
+    ));
+    let history = repo
+        .revert_file_write(&part.id)
+        .await
+        .expect("revert should succeed");
+
+    assert_eq!(std::fs::read_to_string(&file).unwrap(), "user");
+    match &history.message {
+        Some(proto_message::message_history::Message::AssistantMessage(assistant)) => {
+            let metadata: serde_json::Value = serde_json::from_str(
+                assistant.parts[0]
+                    .part_metadata_json
+                    .as_deref()
+                    .expect("metadata should be kept"),
+            )
+            .unwrap();
+            assert_eq!(metadata["reverted"], true);
+        }
+        other => panic!("expected assistant message, got {other:?}"),
+    }
+
+    let err = repo
+        .revert_file_write(&part.id)
+        .await
+        .expect_err("second revert should fail");
+    assert!(matches!(err, MessageRepoError::AlreadyReverted(_)));
+}
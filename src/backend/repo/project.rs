@@ -1,18 +1,43 @@
+use std::path::Path;
+
 use thiserror::Error;
 use uuid::Uuid;
 
-use crate::backend::{BackendContext, db::DatabaseError, models::project_model::ProjectModel};
+use crate::backend::{
+    BackendContext,
+    agent::fs::{ProjectFs, ProjectFsError},
+    db::DatabaseError,
+    file_search::{self, FileMatch, FileSearchError},
+    models::project_model::ProjectModel,
+};
+
+/// How many files a search returns when the caller does not ask for a limit
+pub const DEFAULT_SEARCH_LIMIT: usize = 50;
 
 #[derive(Debug, Error)]
 pub enum ProjectRepoError {
     #[error("database error: {0}")]
     Database(#[from] DatabaseError),
+    #[error("project not found: {0}")]
+    NotFound(Uuid),
+    #[error("file search failed: {0}")]
+    Search(#[from] FileSearchError),
+    #[error("file error: {0}")]
+    Files(#[from] ProjectFsError),
 }
 
 impl From<ProjectRepoError> for tonic::Status {
     fn from(err: ProjectRepoError) -> Self {
         match err {
             ProjectRepoError::Database(e) => tonic::Status::internal(e.to_string()),
+            ProjectRepoError::NotFound(id) => {
+                tonic::Status::not_found(format!("project not found: {id}"))
+            }
+            ProjectRepoError::Search(e) => tonic::Status::internal(e.to_string()),
+            ProjectRepoError::Files(e @ ProjectFsError::OutsideProject(_)) => {
+                tonic::Status::invalid_argument(e.to_string())
+            }
+            ProjectRepoError::Files(e) => tonic::Status::internal(e.to_string()),
         }
     }
 }
@@ -46,4 +71,40 @@ impl ProjectRepo {
         self.ctx.db.delete_project(*project_id).await?;
         Ok(())
     }
+
+    /// Fuzzy searches the files in the project's dir, returning paths relative to it
+    pub async fn search_files(
+        &self,
+        project_id: &Uuid,
+        query: &str,
+        limit: Option<usize>,
+    ) -> Result<Vec<FileMatch>, ProjectRepoError> {
+        let project = self
+            .ctx
+            .db
+            .get_project(*project_id)
+            .await?
+            .ok_or(ProjectRepoError::NotFound(*project_id))?;
+        let limit = limit.unwrap_or(DEFAULT_SEARCH_LIMIT);
+        Ok(file_search::search(project.dir, query, limit).await?)
+    }
+
+    /// Records what an editor shows for a project file, `None` once it matches the disk
+    /// again. Agents read this content instead of the file
+    pub async fn set_unsaved_file(
+        &self,
+        project_id: &Uuid,
+        path: &Path,
+        content: Option<String>,
+    ) -> Result<(), ProjectRepoError> {
+        let project = self
+            .ctx
+            .db
+            .get_project(*project_id)
+            .await?
+            .ok_or(ProjectRepoError::NotFound(*project_id))?;
+        let path = ProjectFs::new(project.dir).resolve(path).await?;
+        self.ctx.unsaved.set(path, content);
+        Ok(())
+    }
 }
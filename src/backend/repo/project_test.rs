@@ -6,7 +6,7 @@ use uuid::Uuid;
 use crate::backend::{
     BackendContext,
     db::Database,
-    harness::opencode::OpencodeHarness,
+    harness::{HarnessRegistry, opencode::OpencodeHarness},
     models::project_model::ProjectModel,
     proto_project::ProjectModel as ProtoProjectModel,
     repo::project::{ProjectRepo, ProjectRepoError},
@@ -27,6 +27,10 @@ fn test_project(name: &str, dir: &str) -> ProjectModel {
         id: Uuid::new_v4(),
         name: name.to_string(),
         dir: dir.to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: now,
         updated_at: now,
     }
@@ -37,7 +41,7 @@ async fn test_repo() -> ProjectRepo {
         .await
         .expect("in-memory db should initialize");
     let harness = OpencodeHarness::new_for_test(1);
-    let ctx = BackendContext::new(db, harness);
+    let ctx = BackendContext::new(db, HarnessRegistry::new().with(harness));
     ProjectRepo::new(ctx)
 }
 
@@ -49,6 +53,10 @@ fn project_proto_serialize_to_model() {
         id,
         name: "proj".to_string(),
         dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: ts,
         updated_at: ts,
     };
@@ -68,6 +76,10 @@ fn project_proto_deserialize_from_model() {
         id: "11111111-2222-3333-4444-555555555555".to_string(),
         name: "proj".to_string(),
         dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: Some(fixed_timestamp()),
         updated_at: Some(fixed_timestamp()),
     };
@@ -90,6 +102,10 @@ fn project_proto_deserialize_rejects_invalid_uuid() {
         id: "not-a-uuid".to_string(),
         name: "proj".to_string(),
         dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: Some(fixed_timestamp()),
         updated_at: Some(fixed_timestamp()),
     };
@@ -105,6 +121,10 @@ fn project_proto_deserialize_rejects_invalid_datetime() {
         id: "11111111-2222-3333-4444-555555555555".to_string(),
         name: "proj".to_string(),
         dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: Some(Timestamp {
             seconds: 1_735_787_045,
             nanos: 1_000_000_000,
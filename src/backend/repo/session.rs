@@ -1,8 +1,23 @@
+use std::{collections::HashSet, path::PathBuf};
+
 use thiserror::Error;
 use uuid::Uuid;
 
 use crate::backend::{
-    BackendContext, db::DatabaseError, harness::Harness, models::session_model::SessionModel,
+    BackendContext,
+    agent::stash::{StashError, WorkspaceStash},
+    db::DatabaseError,
+    harness::RevertPoint,
+    ingest::IngestedChange,
+    models::{
+        file_diff_model::{self, SessionFileDiff},
+        session_model::SessionModel,
+    },
+    proto_message::{self, message_event::Event},
+    repo::{
+        assistant_message::AssistantMessagePart,
+        message::{self, Message},
+    },
 };
 
 #[derive(Debug, Error)]
@@ -11,8 +26,20 @@ pub enum SessionRepoError {
     Database(#[from] DatabaseError),
     #[error("project not found for session.project_id {0}")]
     ProjectNotFound(Uuid),
+    #[error("session not found: {0}")]
+    NotFound(Uuid),
     #[error("harness error: {0}")]
     Harness(String),
+    #[error("no harness registered for type {0}")]
+    UnknownHarness(String),
+    #[error("message {message_id} not found in session {session_id}")]
+    MessageNotFound { session_id: Uuid, message_id: Uuid },
+    #[error("part {part_id} not found in assistant message {message_id}")]
+    PartNotFound { message_id: Uuid, part_id: Uuid },
+    #[error("session {0} has nothing reverted")]
+    NotReverted(Uuid),
+    #[error("workspace stash failed: {0}")]
+    Stash(#[from] StashError),
 }
 
 impl From<SessionRepoError> for tonic::Status {
@@ -22,7 +49,18 @@ impl From<SessionRepoError> for tonic::Status {
             SessionRepoError::ProjectNotFound(id) => {
                 tonic::Status::not_found(format!("project not found: {id}"))
             }
+            SessionRepoError::NotFound(id) => {
+                tonic::Status::not_found(format!("session not found: {id}"))
+            }
             SessionRepoError::Harness(message) => tonic::Status::unavailable(message),
+            SessionRepoError::UnknownHarness(harness_type) => {
+                tonic::Status::invalid_argument(format!("unknown harness type: {harness_type}"))
+            }
+            err @ (SessionRepoError::MessageNotFound { .. }
+            | SessionRepoError::PartNotFound { .. }) => tonic::Status::not_found(err.to_string()),
+            err @ (SessionRepoError::NotReverted(_) | SessionRepoError::Stash(_)) => {
+                tonic::Status::failed_precondition(err.to_string())
+            }
         }
     }
 }
@@ -55,16 +93,22 @@ impl SessionRepo {
             .await?
             .ok_or(SessionRepoError::ProjectNotFound(session.project_id))?;
 
-        let project_dir = Some(project.dir.as_str());
-        let harness_session_id = self
+        let harness = self
             .ctx
-            .harness
+            .harnesses
+            .get(&session.harness_type)
+            .map_err(|_| SessionRepoError::UnknownHarness(session.harness_type.clone()))?;
+
+        let project_dir = Some(project.dir.as_str());
+        let harness_session_id = harness
             .create_session(session.clone(), project_dir)
             .await
             .map_err(|e| SessionRepoError::Harness(e.to_string()))?;
 
         let mut created = session.clone();
+        created.harness_type = harness.harness_type().to_string();
         created.harness_session_id = harness_session_id;
+        created.inherit_defaults(&project);
         if created.dir.is_none() {
             created.dir = Some(project.dir);
         }
@@ -74,8 +118,14 @@ impl SessionRepo {
 
     pub async fn update(&self, session: &SessionModel) -> Result<SessionModel, SessionRepoError> {
         let mut updated = session.clone();
-        if updated.harness_session_id.is_empty() {
-            if let Some(existing) = self.ctx.db.get_session(updated.id).await? {
+        if let Some(existing) = self.ctx.db.get_session(updated.id).await? {
+            // A session stays on the harness it was created with
+            updated.harness_type = existing.harness_type;
+            // The totals follow the diffs the harness reports, not the caller's copy
+            updated.summary_additions = existing.summary_additions;
+            updated.summary_deletions = existing.summary_deletions;
+            updated.summary_files = existing.summary_files;
+            if updated.harness_session_id.is_empty() {
                 updated.harness_session_id = existing.harness_session_id;
             }
         }
@@ -87,4 +137,388 @@ impl SessionRepo {
         self.ctx.db.delete_session(*session_id).await?;
         Ok(())
     }
+
+    /// Stops the assistant turn the session is running on its harness
+    pub async fn cancel(&self, session_id: &Uuid) -> Result<(), SessionRepoError> {
+        let session = self
+            .ctx
+            .db
+            .get_session(*session_id)
+            .await?
+            .ok_or(SessionRepoError::NotFound(*session_id))?;
+        let harness = self
+            .ctx
+            .harnesses
+            .get(&session.harness_type)
+            .map_err(|_| SessionRepoError::UnknownHarness(session.harness_type.clone()))?;
+
+        harness
+            .abort_session(session.harness_session_id, session.dir)
+            .await
+            .map_err(|e| SessionRepoError::Harness(e.to_string()))
+    }
+
+    /// Starts a child session on the parent's history up to `message_id`, on the harness
+    /// and in the database. A user message is left out so the fork can take the conversation
+    /// another way from there, an assistant message is kept
+    pub async fn fork(
+        &self,
+        session_id: &Uuid,
+        message_id: &Uuid,
+    ) -> Result<SessionModel, SessionRepoError> {
+        let parent = self
+            .ctx
+            .db
+            .get_session(*session_id)
+            .await?
+            .ok_or(SessionRepoError::NotFound(*session_id))?;
+        let mut messages = self
+            .ctx
+            .db
+            .list_messages_by_session(parent.id, u32::MAX)
+            .await?;
+        let index = messages
+            .iter()
+            .position(|message| message_id_of(message) == *message_id)
+            .ok_or(SessionRepoError::MessageNotFound {
+                session_id: parent.id,
+                message_id: *message_id,
+            })?;
+        let keep = match messages[index] {
+            Message::User(_) => index,
+            Message::Assistant(_) => index + 1,
+        };
+        messages.truncate(keep);
+
+        let harness = self
+            .ctx
+            .harnesses
+            .get(&parent.harness_type)
+            .map_err(|_| SessionRepoError::UnknownHarness(parent.harness_type.clone()))?;
+        // Messages still streaming when the fork happens have no harness id yet
+        let through_message_id = messages.iter().rev().find_map(|message| match message {
+            Message::Assistant(assistant) => assistant.harness_message_id.clone(),
+            Message::User(_) => None,
+        });
+        let harness_session_id = harness
+            .fork_session(
+                parent.harness_session_id.clone(),
+                through_message_id,
+                parent.dir.clone(),
+            )
+            .await
+            .map_err(|e| SessionRepoError::Harness(e.to_string()))?;
+
+        let now = chrono::Utc::now().naive_utc();
+        let child = SessionModel {
+            id: Uuid::new_v4(),
+            parent_session_id: Some(parent.id),
+            show_in_gui: true,
+            name: format!("{} (fork)", parent.name),
+            harness_session_id,
+            summary_additions: None,
+            summary_deletions: None,
+            summary_files: None,
+            created_at: now,
+            updated_at: now,
+            ..parent.clone()
+        };
+        Ok(self.ctx.db.fork_session(child, parent.id, messages).await?)
+    }
+
+    /// Every file the session's turns changed, in the order they were first changed
+    pub async fn diff(&self, session_id: &Uuid) -> Result<Vec<SessionFileDiff>, SessionRepoError> {
+        let session = self
+            .ctx
+            .db
+            .get_session(*session_id)
+            .await?
+            .ok_or(SessionRepoError::NotFound(*session_id))?;
+        let diffs = self.ctx.db.list_file_diffs_by_session(session.id).await?;
+        Ok(file_diff_model::session_diff(diffs))
+    }
+
+    /// Undoes the session from `message_id` on, or from `part_id` within that assistant
+    /// message, and restores the workspace to how it was before. Harnesses without snapshots
+    /// get the files their agent wrote from there on stashed instead, which takes those back
+    /// to the last commit and leaves every other file alone
+    pub async fn revert(
+        &self,
+        session_id: &Uuid,
+        message_id: &Uuid,
+        part_id: Option<&Uuid>,
+    ) -> Result<(), SessionRepoError> {
+        let session = self
+            .ctx
+            .db
+            .get_session(*session_id)
+            .await?
+            .ok_or(SessionRepoError::NotFound(*session_id))?;
+        let messages = self
+            .ctx
+            .db
+            .list_messages_by_session(session.id, u32::MAX)
+            .await?;
+        let index = messages
+            .iter()
+            .position(|message| message_id_of(message) == *message_id)
+            .ok_or(SessionRepoError::MessageNotFound {
+                session_id: session.id,
+                message_id: *message_id,
+            })?;
+
+        let part_not_found = |part_id: &Uuid| SessionRepoError::PartNotFound {
+            message_id: *message_id,
+            part_id: *part_id,
+        };
+        // Position of the first reverted part in a part revert
+        let mut first_reverted_part = None;
+        let point = match (&messages[index], part_id) {
+            (Message::Assistant(assistant), Some(part_id)) => {
+                let part = self
+                    .ctx
+                    .db
+                    .get_assistant_message_part(*part_id)
+                    .await?
+                    .filter(|part| part.assistant_message_id == assistant.id)
+                    .ok_or_else(|| part_not_found(part_id))?;
+                first_reverted_part = Some(part.position);
+                assistant
+                    .harness_message_id
+                    .clone()
+                    .zip(part.harness_part_id)
+                    .map(|(message_id, part_id)| RevertPoint::Part {
+                        message_id,
+                        part_id,
+                    })
+            }
+            (Message::User(_), Some(part_id)) => return Err(part_not_found(part_id)),
+            (Message::Assistant(assistant), None) => assistant
+                .harness_message_id
+                .clone()
+                .map(|message_id| RevertPoint::Message { message_id }),
+            // The harness numbers prompts itself, its first reply leads to the prompt
+            (Message::User(_), None) => {
+                messages[index..].iter().find_map(|message| match message {
+                    Message::Assistant(assistant) => assistant
+                        .harness_message_id
+                        .clone()
+                        .map(|reply_id| RevertPoint::PromptOf { reply_id }),
+                    Message::User(_) => None,
+                })
+            }
+        };
+
+        // A part revert keeps its own message, everything after the point is undone
+        let first_reverted = if part_id.is_some() { index + 1 } else { index };
+
+        // Nothing the harness saw yet means nothing it could have changed on disk
+        if let Some(point) = point {
+            let harness = self
+                .ctx
+                .harnesses
+                .get(&session.harness_type)
+                .map_err(|_| SessionRepoError::UnknownHarness(session.harness_type.clone()))?;
+            let restored = harness
+                .revert_session(
+                    session.harness_session_id.clone(),
+                    point,
+                    session.dir.clone(),
+                )
+                .await
+                .map_err(|e| SessionRepoError::Harness(e.to_string()))?;
+            if !restored {
+                // Reverting again while reverted puts the files back before stashing anew
+                pop_revert_stash(&self.ctx, &session).await?;
+                let reverted_messages = messages[first_reverted..]
+                    .iter()
+                    .map(message_id_of)
+                    .collect::<HashSet<_>>();
+                let dir = workspace_dir(&self.ctx, &session).await?;
+                let written = written_paths(&self.ctx, session.id, &dir, |part| {
+                    reverted_messages.contains(&part.assistant_message_id)
+                        || first_reverted_part.is_some_and(|position| {
+                            part.assistant_message_id == *message_id && part.position >= position
+                        })
+                })
+                .await?;
+                let stash = WorkspaceStash::new(dir)
+                    .push(&format!("revert of session {}", session.id), &written)
+                    .await?;
+                self.ctx
+                    .db
+                    .set_session_revert_stash(session.id, stash)
+                    .await?;
+            }
+        }
+
+        store_reverted(&self.ctx, session.id, messages, |position| {
+            position >= first_reverted
+        })
+        .await
+    }
+
+    /// Takes back the session's revert, restoring its messages and the workspace
+    pub async fn unrevert(&self, session_id: &Uuid) -> Result<(), SessionRepoError> {
+        let session = self
+            .ctx
+            .db
+            .get_session(*session_id)
+            .await?
+            .ok_or(SessionRepoError::NotFound(*session_id))?;
+        let messages = self
+            .ctx
+            .db
+            .list_messages_by_session(session.id, u32::MAX)
+            .await?;
+        if !messages.iter().any(is_reverted) {
+            return Err(SessionRepoError::NotReverted(session.id));
+        }
+
+        let harness = self
+            .ctx
+            .harnesses
+            .get(&session.harness_type)
+            .map_err(|_| SessionRepoError::UnknownHarness(session.harness_type.clone()))?;
+        let restored = harness
+            .unrevert_session(session.harness_session_id.clone(), session.dir.clone())
+            .await
+            .map_err(|e| SessionRepoError::Harness(e.to_string()))?;
+        if !restored {
+            pop_revert_stash(&self.ctx, &session).await?;
+        }
+
+        store_reverted(&self.ctx, session.id, messages, |_| false).await
+    }
+}
+
+/// Restores a reverted workspace when the harness could not, see `SessionRepo::revert`
+async fn pop_revert_stash(
+    ctx: &BackendContext,
+    session: &SessionModel,
+) -> Result<(), SessionRepoError> {
+    let Some(stash) = ctx.db.get_session_revert_stash(session.id).await? else {
+        return Ok(());
+    };
+    WorkspaceStash::new(workspace_dir(ctx, session).await?)
+        .pop(&stash)
+        .await?;
+    ctx.db.set_session_revert_stash(session.id, None).await?;
+    Ok(())
+}
+
+/// Files the session's agent wrote in the parts `reverted` picks, relative to `dir`.
+/// Files it wrote and that are gone again have nothing left to stash
+async fn written_paths(
+    ctx: &BackendContext,
+    session_id: Uuid,
+    dir: &str,
+    reverted: impl Fn(&AssistantMessagePart) -> bool,
+) -> Result<Vec<PathBuf>, SessionRepoError> {
+    // Writes are recorded with their resolved path
+    let root = tokio::fs::canonicalize(dir)
+        .await
+        .unwrap_or_else(|_| PathBuf::from(dir));
+    let mut paths = Vec::new();
+    for part in ctx
+        .db
+        .list_assistant_message_parts_by_session(session_id)
+        .await?
+    {
+        let Some(write) = part.file_write().filter(|_| reverted(&part)) else {
+            continue;
+        };
+        let Ok(path) = write.path.strip_prefix(&root) else {
+            continue;
+        };
+        if !paths.iter().any(|known| known == path)
+            && tokio::fs::try_exists(&write.path).await.unwrap_or(false)
+        {
+            paths.push(path.to_path_buf());
+        }
+    }
+    Ok(paths)
+}
+
+/// Directory the session's agent works in
+async fn workspace_dir(
+    ctx: &BackendContext,
+    session: &SessionModel,
+) -> Result<String, SessionRepoError> {
+    match &session.dir {
+        Some(dir) => Ok(dir.clone()),
+        None => Ok(ctx
+            .db
+            .get_project(session.project_id)
+            .await?
+            .ok_or(SessionRepoError::ProjectNotFound(session.project_id))?
+            .dir),
+    }
+}
+
+/// Flags each message by its position in the session and tells subscribers about the ones
+/// that changed
+async fn store_reverted(
+    ctx: &BackendContext,
+    session_id: Uuid,
+    messages: Vec<Message>,
+    reverted_at: impl Fn(usize) -> bool,
+) -> Result<(), SessionRepoError> {
+    let (reverted, restored): (Vec<_>, Vec<_>) = messages
+        .into_iter()
+        .enumerate()
+        .filter(|(position, message)| is_reverted(message) != reverted_at(*position))
+        .map(|(_, mut message)| {
+            let reverted = !is_reverted(&message);
+            set_reverted(&mut message, reverted);
+            message
+        })
+        .partition(is_reverted);
+    for (messages, flag) in [(&reverted, true), (&restored, false)] {
+        if !messages.is_empty() {
+            ctx.db
+                .set_messages_reverted(session_id, messages.clone(), flag)
+                .await?;
+        }
+    }
+    let changed = [reverted, restored].concat();
+    if changed.is_empty() {
+        return Ok(());
+    }
+    message::publish_changes(ctx, session_id, changed, Vec::new()).await?;
+
+    // Reverted turns no longer count towards what the session changed
+    let summary = ctx.db.update_session_diff_summary(session_id).await?;
+    let revision = ctx.db.session_revision(session_id).await?;
+    ctx.ingestor.publish(
+        session_id,
+        IngestedChange {
+            revision,
+            events: vec![proto_message::MessageEvent {
+                event: Some(Event::SessionDiff(summary.into())),
+            }],
+        },
+    );
+    Ok(())
+}
+
+fn is_reverted(message: &Message) -> bool {
+    match message {
+        Message::User(user) => user.reverted,
+        Message::Assistant(assistant) => assistant.reverted,
+    }
+}
+
+fn set_reverted(message: &mut Message, reverted: bool) {
+    match message {
+        Message::User(user) => user.reverted = reverted,
+        Message::Assistant(assistant) => assistant.reverted = reverted,
+    }
+}
+
+fn message_id_of(message: &Message) -> Uuid {
+    match message {
+        Message::User(user) => user.id,
+        Message::Assistant(assistant) => assistant.id,
+    }
 }
@@ -9,13 +9,24 @@ use uuid::Uuid;
 
 use crate::backend::{
     BackendContext, ProjectModel,
+    agent::fs::FILE_WRITE_PART_TYPE,
     db::Database,
-    harness::opencode::OpencodeHarness,
-    models::session_model::SessionModel,
+    harness::{
+        Harness, HarnessAssistantEventStream, HarnessError, HarnessFileDiff, HarnessMessage,
+        HarnessRegistry, opencode::OpencodeHarness,
+    },
+    models::{
+        file_diff_model::FileDiffModel, message_defaults_model::MessageDefaultsModel,
+        session_model::SessionModel,
+    },
     proto_session::SessionModel as ProtoSessionModel,
     repo::{
+        assistant_message::{AssistantMessage, AssistantMessagePart},
+        message::Message,
         project::ProjectRepo,
         session::{SessionRepo, SessionRepoError},
+        user_message::UserMessage,
+        user_message_part::UserMessagePart,
     },
 };
 
@@ -27,6 +38,10 @@ fn test_project(name: &str, dir: &str) -> ProjectModel {
         id: Uuid::new_v4(),
         name: name.to_string(),
         dir: dir.to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: now,
         updated_at: now,
     }
@@ -46,6 +61,10 @@ fn test_session(project_id: Uuid, name: &str, show_in_gui: bool) -> SessionModel
         summary_additions: None,
         summary_deletions: None,
         summary_files: None,
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: now,
         updated_at: now,
     }
@@ -63,7 +82,7 @@ async fn test_repos(port: u32) -> (ProjectRepo, SessionRepo) {
         .await
         .expect("in-memory db should initialize");
     let harness = OpencodeHarness::new_for_test(port);
-    let ctx = BackendContext::new(db, harness);
+    let ctx = BackendContext::new(db, HarnessRegistry::new().with(harness));
     (ProjectRepo::new(ctx.clone()), SessionRepo::new(ctx))
 }
 
@@ -85,6 +104,10 @@ fn session_proto_serialize_to_model() {
         summary_additions: None,
         summary_deletions: None,
         summary_files: None,
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: ts,
         updated_at: ts,
     };
@@ -95,6 +118,7 @@ fn session_proto_serialize_to_model() {
     assert_eq!(model.project_id, project_id.to_string());
     assert!(model.show_in_gui);
     assert_eq!(model.name, "sess");
+    assert_eq!(model.harness_type, "opencode");
     assert_eq!(model.created_at, Some(fixed_timestamp()));
     assert_eq!(model.updated_at, Some(fixed_timestamp()));
 }
@@ -106,6 +130,12 @@ fn session_proto_deserialize_from_model() {
         project_id: "11111111-2222-3333-4444-555555555555".to_string(),
         show_in_gui: false,
         name: "sess".to_string(),
+        harness_type: String::new(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        parent_session_id: None,
         created_at: Some(fixed_timestamp()),
         updated_at: Some(fixed_timestamp()),
     };
@@ -122,6 +152,7 @@ fn session_proto_deserialize_from_model() {
     );
     assert!(!session.show_in_gui);
     assert_eq!(session.name, "sess");
+    assert_eq!(session.harness_type, "opencode");
     assert_eq!(session.created_at, fixed_datetime());
     assert_eq!(session.updated_at, fixed_datetime());
 }
@@ -133,6 +164,12 @@ fn session_proto_deserialize_rejects_invalid_id() {
         project_id: "11111111-2222-3333-4444-555555555555".to_string(),
         show_in_gui: true,
         name: "sess".to_string(),
+        harness_type: String::new(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        parent_session_id: None,
         created_at: Some(fixed_timestamp()),
         updated_at: Some(fixed_timestamp()),
     };
@@ -149,6 +186,12 @@ fn session_proto_deserialize_rejects_invalid_project_id() {
         project_id: "not-a-uuid".to_string(),
         show_in_gui: true,
         name: "sess".to_string(),
+        harness_type: String::new(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        parent_session_id: None,
         created_at: Some(fixed_timestamp()),
         updated_at: Some(fixed_timestamp()),
     };
@@ -165,6 +208,12 @@ fn session_proto_deserialize_rejects_invalid_datetime() {
         project_id: "11111111-2222-3333-4444-555555555555".to_string(),
         show_in_gui: true,
         name: "sess".to_string(),
+        harness_type: String::new(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
+        parent_session_id: None,
         created_at: Some(Timestamp {
             seconds: 1_735_787_045,
             nanos: 1_000_000_000,
@@ -194,12 +243,18 @@ async fn spawn_fake_opencode_server() -> (u32, tokio::task::JoinHandle<()>) {
 
             tokio::spawn(async move {
                 let mut buf = [0_u8; 2048];
-                let _ = socket.read(&mut buf).await;
-
-                let body = format!(
-                    "{{\"id\":\"ses-{}\",\"title\":\"fake\"}}",
-                    Uuid::new_v4().simple()
-                );
+                let read = socket.read(&mut buf).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&buf[..read]);
+
+                // Sessions have no messages, everything else is answered with a new session
+                let body = if request.starts_with("GET") && request.contains("/message") {
+                    "[]".to_string()
+                } else {
+                    format!(
+                        "{{\"id\":\"ses-{}\",\"title\":\"fake\"}}",
+                        Uuid::new_v4().simple()
+                    )
+                };
                 let response = format!(
                     "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
                     body.len(),
@@ -303,6 +358,29 @@ async fn create_does_not_persist_when_harness_fails() {
     assert!(fetched.is_none());
 }
 
+#[tokio::test]
+async fn create_rejects_unknown_harness_type() {
+    let (project_repo, session_repo) = test_repos(closed_port()).await;
+    let project = project_repo
+        .create(&test_project("p", "/tmp/p"))
+        .await
+        .expect("project create should succeed");
+    let mut session = test_session(project.id, "unknown-harness", true);
+    session.harness_type = "not-a-harness".to_string();
+
+    let err = session_repo
+        .create(&session)
+        .await
+        .expect_err("unknown harness type should fail");
+    assert!(matches!(err, SessionRepoError::UnknownHarness(ref t) if t == "not-a-harness"));
+
+    let fetched = session_repo
+        .get(&session.id)
+        .await
+        .expect("get should succeed");
+    assert!(fetched.is_none());
+}
+
 #[tokio::test]
 async fn update_and_delete_session() {
     let (port, server) = spawn_fake_opencode_server().await;
@@ -342,3 +420,551 @@ async fn update_and_delete_session() {
 
     server.abort();
 }
+
+#[tokio::test]
+async fn cancel_aborts_the_session_on_its_harness() {
+    let (port, server) = spawn_fake_opencode_server().await;
+    let (project_repo, session_repo) = test_repos(port).await;
+    let project = project_repo
+        .create(&test_project("p", "/tmp/p"))
+        .await
+        .expect("project create should succeed");
+    let session = session_repo
+        .create(&test_session(project.id, "running", true))
+        .await
+        .expect("create session should succeed");
+
+    session_repo
+        .cancel(&session.id)
+        .await
+        .expect("cancel should succeed");
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn cancel_returns_not_found_for_missing_session() {
+    let (_project_repo, session_repo) = test_repos(closed_port()).await;
+    let missing = Uuid::new_v4();
+
+    let err = session_repo
+        .cancel(&missing)
+        .await
+        .expect_err("cancel should fail");
+    assert!(matches!(err, SessionRepoError::NotFound(id) if id == missing));
+}
+
+#[tokio::test]
+async fn create_inherits_the_project_defaults() {
+    let (port, server) = spawn_fake_opencode_server().await;
+    let (project_repo, session_repo) = test_repos(port).await;
+    let mut project = test_project("p", "/tmp/p");
+    project.default_agent = Some("plan".to_string());
+    project.default_model_provider_id = Some("openai".to_string());
+    project.default_model_id = Some("gpt-5".to_string());
+    project.default_thinking_variant = Some("high".to_string());
+    let project = project_repo
+        .create(&project)
+        .await
+        .expect("project create should succeed");
+
+    let mut session = test_session(project.id, "own agent", true);
+    session.default_agent = Some("build".to_string());
+    let created = session_repo
+        .create(&session)
+        .await
+        .expect("create session should succeed");
+
+    assert_eq!(created.default_agent.as_deref(), Some("build"));
+    assert_eq!(created.default_model_provider_id.as_deref(), Some("openai"));
+    assert_eq!(created.default_model_id.as_deref(), Some("gpt-5"));
+    assert_eq!(created.default_thinking_variant.as_deref(), Some("high"));
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn remembered_defaults_carry_over_to_the_next_session() {
+    let (port, server) = spawn_fake_opencode_server().await;
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let ctx = BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(port)),
+    );
+    let project_repo = ProjectRepo::new(ctx.clone());
+    let session_repo = SessionRepo::new(ctx.clone());
+    let project = project_repo
+        .create(&test_project("p", "/tmp/p"))
+        .await
+        .expect("project create should succeed");
+    let first = session_repo
+        .create(&test_session(project.id, "first", true))
+        .await
+        .expect("create session should succeed");
+
+    ctx.db
+        .update_session_defaults(
+            first.id,
+            MessageDefaultsModel {
+                default_agent: Some("plan".to_string()),
+                default_model_provider_id: Some("anthropic".to_string()),
+                default_model_id: Some("claude-sonnet".to_string()),
+                default_thinking_variant: Some("high".to_string()),
+            },
+        )
+        .await
+        .expect("update defaults should succeed");
+    // A message the harness picked the model for keeps the remembered one
+    let first = ctx
+        .db
+        .update_session_defaults(
+            first.id,
+            MessageDefaultsModel {
+                default_agent: Some("build".to_string()),
+                ..Default::default()
+            },
+        )
+        .await
+        .expect("update defaults should succeed");
+    assert_eq!(first.default_agent.as_deref(), Some("build"));
+    assert_eq!(first.default_model_id.as_deref(), Some("claude-sonnet"));
+    assert_eq!(first.default_thinking_variant, None);
+
+    let second = session_repo
+        .create(&test_session(project.id, "second", true))
+        .await
+        .expect("create session should succeed");
+    assert_eq!(second.default_agent.as_deref(), Some("build"));
+    assert_eq!(
+        second.default_model_provider_id.as_deref(),
+        Some("anthropic")
+    );
+    assert_eq!(second.default_model_id.as_deref(), Some("claude-sonnet"));
+    assert_eq!(second.default_thinking_variant, None);
+
+    server.abort();
+}
+
+/// Turns of a prompt and a reply with one part, none of them known to the harness
+async fn create_turns(
+    ctx: &BackendContext,
+    session_id: Uuid,
+    count: i64,
+) -> Vec<(UserMessage, AssistantMessage)> {
+    let mut turns = Vec::new();
+    for turn in 0..count {
+        let at = Utc::now().naive_utc() + chrono::Duration::seconds(turn * 2);
+        let user = ctx
+            .db
+            .create_user_message(UserMessage {
+                id: Uuid::new_v4(),
+                session_id,
+                agent: "build".to_string(),
+                model_provider_id: "openai".to_string(),
+                model_id: "gpt-5".to_string(),
+                system_prompt: None,
+                structured_output_type: "text".to_string(),
+                tools_list: "{}".to_string(),
+                thinking_variant: None,
+                harness_message_id: None,
+                reverted: false,
+                created_at: at,
+                updated_at: at,
+            })
+            .await
+            .expect("create user message should succeed");
+        let mut reply = AssistantMessage::new_from_harness(session_id, user.id, "msg");
+        reply.harness_message_id = None;
+        reply.created_at = at + chrono::Duration::seconds(1);
+        let reply = ctx
+            .db
+            .create_assistant_message(reply)
+            .await
+            .expect("create assistant message should succeed");
+        let mut part = AssistantMessagePart::new_from_harness(session_id, reply.id, "part", "text");
+        part.text = Some(format!("answer {turn}"));
+        ctx.db
+            .create_assistant_message_part(part)
+            .await
+            .expect("create part should succeed");
+        turns.push((user, reply));
+    }
+    turns
+}
+
+#[tokio::test]
+async fn fork_copies_the_history_up_to_the_message() {
+    let (port, server) = spawn_fake_opencode_server().await;
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let ctx = BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(port)),
+    );
+    let project_repo = ProjectRepo::new(ctx.clone());
+    let session_repo = SessionRepo::new(ctx.clone());
+    let project = project_repo
+        .create(&test_project("p", "/tmp/p"))
+        .await
+        .expect("project create should succeed");
+    let parent = session_repo
+        .create(&test_session(project.id, "parent", true))
+        .await
+        .expect("create session should succeed");
+
+    let turns = create_turns(&ctx, parent.id, 2).await;
+
+    let forked = session_repo
+        .fork(&parent.id, &turns[0].1.id)
+        .await
+        .expect("fork should succeed");
+    assert_eq!(forked.parent_session_id, Some(parent.id));
+    assert_eq!(forked.project_id, project.id);
+    assert_ne!(forked.harness_session_id, parent.harness_session_id);
+
+    let messages = ctx
+        .db
+        .list_messages_by_session(forked.id, 100)
+        .await
+        .expect("list messages should succeed");
+    let [Message::User(user), Message::Assistant(reply)] = messages.as_slice() else {
+        panic!("the fork should hold the first turn only");
+    };
+    assert_ne!(user.id, turns[0].0.id);
+    assert_eq!(reply.user_message_id, user.id);
+    let parts = ctx
+        .db
+        .list_assistant_message_parts_by_session(forked.id)
+        .await
+        .expect("list parts should succeed");
+    assert_eq!(parts.len(), 1);
+    assert_eq!(parts[0].assistant_message_id, reply.id);
+    assert_eq!(parts[0].text.as_deref(), Some("answer 0"));
+
+    // Forking at a prompt leaves the prompt out
+    let forked = session_repo
+        .fork(&parent.id, &turns[1].0.id)
+        .await
+        .expect("fork should succeed");
+    let messages = ctx
+        .db
+        .list_messages_by_session(forked.id, 100)
+        .await
+        .expect("list messages should succeed");
+    assert_eq!(messages.len(), 2);
+
+    let err = session_repo
+        .fork(&parent.id, &Uuid::new_v4())
+        .await
+        .expect_err("unknown message should fail");
+    assert!(matches!(err, SessionRepoError::MessageNotFound { .. }));
+
+    server.abort();
+}
+
+async fn reverted_flags(ctx: &BackendContext, session_id: Uuid) -> Vec<bool> {
+    ctx.db
+        .list_messages_by_session(session_id, 100)
+        .await
+        .expect("list messages should succeed")
+        .iter()
+        .map(|message| match message {
+            Message::User(user) => user.reverted,
+            Message::Assistant(assistant) => assistant.reverted,
+        })
+        .collect()
+}
+
+#[tokio::test]
+async fn revert_marks_later_messages_until_unreverted() {
+    let (port, server) = spawn_fake_opencode_server().await;
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let ctx = BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(port)),
+    );
+    let project_repo = ProjectRepo::new(ctx.clone());
+    let session_repo = SessionRepo::new(ctx.clone());
+    let project = project_repo
+        .create(&test_project("p", "/tmp/p"))
+        .await
+        .expect("project create should succeed");
+    let session = session_repo
+        .create(&test_session(project.id, "session", true))
+        .await
+        .expect("create session should succeed");
+    let turns = create_turns(&ctx, session.id, 2).await;
+
+    session_repo
+        .revert(&session.id, &turns[1].0.id, None)
+        .await
+        .expect("revert should succeed");
+    assert_eq!(
+        reverted_flags(&ctx, session.id).await,
+        [false, false, true, true]
+    );
+
+    // Reverting further back while reverted moves the point
+    session_repo
+        .revert(&session.id, &turns[0].1.id, None)
+        .await
+        .expect("revert should succeed");
+    assert_eq!(
+        reverted_flags(&ctx, session.id).await,
+        [false, true, true, true]
+    );
+
+    session_repo
+        .unrevert(&session.id)
+        .await
+        .expect("unrevert should succeed");
+    assert_eq!(
+        reverted_flags(&ctx, session.id).await,
+        [false, false, false, false]
+    );
+
+    let err = session_repo
+        .unrevert(&session.id)
+        .await
+        .expect_err("nothing reverted should fail");
+    assert!(matches!(err, SessionRepoError::NotReverted(_)));
+
+    let err = session_repo
+        .revert(&session.id, &turns[0].0.id, Some(&Uuid::new_v4()))
+        .await
+        .expect_err("a part of a user message should fail");
+    assert!(matches!(err, SessionRepoError::PartNotFound { .. }));
+
+    server.abort();
+}
+
+#[tokio::test]
+async fn revert_leaves_the_reverted_turns_out_of_the_diff() {
+    let (port, server) = spawn_fake_opencode_server().await;
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let ctx = BackendContext::new(
+        db,
+        HarnessRegistry::new().with(OpencodeHarness::new_for_test(port)),
+    );
+    let project_repo = ProjectRepo::new(ctx.clone());
+    let session_repo = SessionRepo::new(ctx.clone());
+    let project = project_repo
+        .create(&test_project("p", "/tmp/p"))
+        .await
+        .expect("project create should succeed");
+    let session = session_repo
+        .create(&test_session(project.id, "session", true))
+        .await
+        .expect("create session should succeed");
+    let turns = create_turns(&ctx, session.id, 2).await;
+    for (turn, (user, _)) in turns.iter().enumerate() {
+        let harness_message_id = format!("msg-user-{turn}");
+        let diff = FileDiffModel::new_from_harness(
+            session.id,
+            Some(user.id),
+            &harness_message_id,
+            HarnessFileDiff {
+                file: format!("file-{turn}.rs"),
+                before: String::new(),
+                after: "fn main() {}\n".to_string(),
+                additions: 1,
+                deletions: 0,
+            },
+        );
+        ctx.db
+            .replace_message_file_diffs(session.id, harness_message_id, vec![diff])
+            .await
+            .expect("store diffs should succeed");
+    }
+    let diffed_files = async || {
+        session_repo
+            .diff(&session.id)
+            .await
+            .expect("diff should load")
+            .into_iter()
+            .map(|diff| diff.file)
+            .collect::<Vec<_>>()
+    };
+    let summary_files = async || {
+        ctx.db
+            .get_session(session.id)
+            .await
+            .expect("session should load")
+            .expect("session should exist")
+            .summary_files
+    };
+
+    session_repo
+        .revert(&session.id, &turns[1].0.id, None)
+        .await
+        .expect("revert should succeed");
+    assert_eq!(diffed_files().await, ["file-0.rs"]);
+    assert_eq!(summary_files().await, Some(1));
+
+    session_repo
+        .unrevert(&session.id)
+        .await
+        .expect("unrevert should succeed");
+    assert_eq!(diffed_files().await, ["file-0.rs", "file-1.rs"]);
+    assert_eq!(summary_files().await, Some(2));
+
+    server.abort();
+}
+
+/// Keeps no snapshots, so reverts fall back to stashing
+struct SnapshotlessHarness;
+
+#[async_trait::async_trait]
+impl Harness for SnapshotlessHarness {
+    fn harness_type(&self) -> &'static str {
+        "opencode"
+    }
+
+    fn cleanup(&self) {}
+
+    async fn create_session(
+        &self,
+        _session: SessionModel,
+        _directory: Option<&str>,
+    ) -> anyhow::Result<String> {
+        Ok("hs-snapshotless".to_string())
+    }
+
+    async fn send_message_async(
+        &self,
+        _harness_session_id: String,
+        _message: UserMessage,
+        _message_parts: Vec<UserMessagePart>,
+        _directory: Option<String>,
+    ) -> Result<(), HarnessError> {
+        Ok(())
+    }
+
+    async fn get_session_messages(
+        &self,
+        _session_id: &str,
+        _limit: Option<i32>,
+        _directory: Option<&str>,
+    ) -> Result<Vec<HarnessMessage>, HarnessError> {
+        Ok(Vec::new())
+    }
+
+    async fn abort_session(
+        &self,
+        _harness_session_id: String,
+        _directory: Option<String>,
+    ) -> Result<(), HarnessError> {
+        Ok(())
+    }
+
+    async fn listen_assistant_events(
+        &self,
+        _harness_session_id: String,
+        _directory: Option<String>,
+    ) -> Result<HarnessAssistantEventStream, HarnessError> {
+        Ok(Box::pin(futures::stream::empty()))
+    }
+}
+
+fn git(dir: &std::path::Path, args: &[&str]) {
+    let output = std::process::Command::new("git")
+        .arg("-C")
+        .arg(dir)
+        .args(args)
+        .output()
+        .expect("git should run");
+    assert!(output.status.success(), "git {args:?} failed");
+}
+
+#[tokio::test]
+async fn revert_without_snapshots_stashes_only_the_files_the_agent_wrote() {
+    let dir = tempfile::tempdir().expect("temp dir should be created");
+    git(dir.path(), &["init", "--quiet"]);
+    git(dir.path(), &["config", "user.name", "test"]);
+    git(dir.path(), &["config", "user.email", "test@example.com"]);
+    std::fs::write(dir.path().join("agent.txt"), "committed\n").expect("write should succeed");
+    std::fs::write(dir.path().join("user.txt"), "committed\n").expect("write should succeed");
+    git(dir.path(), &["add", "."]);
+    git(dir.path(), &["commit", "--quiet", "-m", "initial"]);
+
+    let db = Database::new_in_memory()
+        .await
+        .expect("in-memory db should initialize");
+    let ctx = BackendContext::new(db, HarnessRegistry::new().with(SnapshotlessHarness));
+    let project_repo = ProjectRepo::new(ctx.clone());
+    let session_repo = SessionRepo::new(ctx.clone());
+    let project = project_repo
+        .create(&test_project("p", &dir.path().to_string_lossy()))
+        .await
+        .expect("project create should succeed");
+    let session = session_repo
+        .create(&test_session(project.id, "session", true))
+        .await
+        .expect("create session should succeed");
+    let turns = create_turns(&ctx, session.id, 2).await;
+
+    // The second turn's agent wrote one file, the user edited another meanwhile
+    let mut reply = turns[1].1.clone();
+    reply.harness_message_id = Some("msg-reply-1".to_string());
+    ctx.db
+        .update_assistant_message(reply.clone())
+        .await
+        .expect("update reply should succeed");
+    let written = dir
+        .path()
+        .canonicalize()
+        .expect("dir should resolve")
+        .join("agent.txt");
+    let mut part = AssistantMessagePart::new_from_harness(session.id, reply.id, "w-1", "text");
+    part.apply_payload_json(
+        serde_json::json!({
+            "type": FILE_WRITE_PART_TYPE,
+            "path": written.to_string_lossy(),
+            "metadata": {"before": "committed\n", "after": "agent\n"},
+        }),
+        "text",
+    );
+    part.position = 1;
+    ctx.db
+        .create_assistant_message_part(part)
+        .await
+        .expect("create part should succeed");
+    std::fs::write(dir.path().join("agent.txt"), "agent\n").expect("write should succeed");
+    std::fs::write(dir.path().join("user.txt"), "user\n").expect("write should succeed");
+    let read = |name: &str| std::fs::read_to_string(dir.path().join(name)).expect("read");
+
+    session_repo
+        .revert(&session.id, &turns[1].0.id, None)
+        .await
+        .expect("revert should succeed");
+    assert_eq!(read("agent.txt"), "committed\n");
+    assert_eq!(read("user.txt"), "user\n");
+
+    // Reverting again keeps a single stash
+    session_repo
+        .revert(&session.id, &turns[1].0.id, None)
+        .await
+        .expect("revert should succeed");
+    assert_eq!(read("agent.txt"), "committed\n");
+
+    session_repo
+        .unrevert(&session.id)
+        .await
+        .expect("unrevert should succeed");
+    assert_eq!(read("agent.txt"), "agent\n");
+    assert_eq!(read("user.txt"), "user\n");
+    assert_eq!(
+        ctx.db
+            .get_session_revert_stash(session.id)
+            .await
+            .expect("stash should load"),
+        None
+    );
+}
@@ -19,6 +19,10 @@ pub struct UserMessage {
     pub structured_output_type: String,
     pub tools_list: String,
     pub thinking_variant: Option<String>,
+    /// Set once the harness reported storing the prompt, its turn's diffs name this id
+    pub harness_message_id: Option<String>,
+    /// Undone by a revert, gone once the next message is sent
+    pub reverted: bool,
     pub created_at: NaiveDateTime,
     pub updated_at: NaiveDateTime,
 }
@@ -35,6 +39,7 @@ impl From<UserMessage> for proto_message::UserMessageModel {
             structured_output_type: value.structured_output_type,
             tools_list: value.tools_list,
             thinking_variant: value.thinking_variant,
+            reverted: value.reverted,
             created_at: Some(naive_datetime_to_timestamp(value.created_at)),
             updated_at: Some(naive_datetime_to_timestamp(value.updated_at)),
             parts: Vec::new(),
@@ -56,6 +61,8 @@ impl TryFrom<proto_message::UserMessageModel> for UserMessage {
             structured_output_type: value.structured_output_type,
             tools_list: value.tools_list,
             thinking_variant: value.thinking_variant,
+            harness_message_id: None,
+            reverted: value.reverted,
             created_at: timestamp_to_naive_datetime("user_message.created_at", value.created_at)?,
             updated_at: timestamp_to_naive_datetime("user_message.updated_at", value.updated_at)?,
         })
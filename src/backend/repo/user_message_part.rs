@@ -18,7 +18,17 @@ pub struct UserMessagePart {
     pub text: Option<String>,
     pub file_name: Option<String>,
     pub file_url: Option<String>,
+    pub file_mime: Option<String>,
     pub agent_name: Option<String>,
+    /// Where an agent part was mentioned in the prompt, char offsets into the text part
+    pub agent_source_value: Option<String>,
+    pub agent_source_start: Option<i64>,
+    pub agent_source_end: Option<i64>,
+    /// Project file a file part was mentioned as, with where the mention sits in the text part
+    pub file_source_path: Option<String>,
+    pub file_source_text_value: Option<String>,
+    pub file_source_text_start: Option<i64>,
+    pub file_source_text_end: Option<i64>,
     pub subtask_prompt: Option<String>,
     pub subtask_description: Option<String>,
     pub created_at: NaiveDateTime,
@@ -36,7 +46,15 @@ impl From<UserMessagePart> for proto_message::UserMessagePartModel {
             text: value.text,
             file_name: value.file_name,
             file_url: value.file_url,
+            file_mime: value.file_mime,
             agent_name: value.agent_name,
+            agent_source_value: value.agent_source_value,
+            agent_source_start: value.agent_source_start,
+            agent_source_end: value.agent_source_end,
+            file_source_path: value.file_source_path,
+            file_source_text_value: value.file_source_text_value,
+            file_source_text_start: value.file_source_text_start,
+            file_source_text_end: value.file_source_text_end,
             subtask_prompt: value.subtask_prompt,
             subtask_description: value.subtask_description,
             created_at: Some(naive_datetime_to_timestamp(value.created_at)),
@@ -61,7 +79,15 @@ impl TryFrom<proto_message::UserMessagePartModel> for UserMessagePart {
             text: value.text,
             file_name: value.file_name,
             file_url: value.file_url,
+            file_mime: value.file_mime,
             agent_name: value.agent_name,
+            agent_source_value: value.agent_source_value,
+            agent_source_start: value.agent_source_start,
+            agent_source_end: value.agent_source_end,
+            file_source_path: value.file_source_path,
+            file_source_text_value: value.file_source_text_value,
+            file_source_text_start: value.file_source_text_start,
+            file_source_text_end: value.file_source_text_end,
             subtask_prompt: value.subtask_prompt,
             subtask_description: value.subtask_description,
             created_at: timestamp_to_naive_datetime(
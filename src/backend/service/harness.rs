@@ -0,0 +1,154 @@
+use std::{pin::Pin, sync::Arc};
+
+use futures::{Stream, StreamExt, stream};
+use tokio::sync::watch;
+use tonic::{Request, Response, Status};
+
+use crate::backend::{
+    BackendService,
+    harness::{Harness, HarnessAgent, HarnessHealth, HarnessModel, HarnessRegistry, Model},
+    proto_harness::{
+        AgentInfo, HarnessHealthModel, HarnessHealthStatus, ListAgentsReply, ListAgentsRequest,
+        ListModelsReply, ListModelsRequest, ModelInfo, ModelRef, SubscribeHarnessHealthReply,
+        SubscribeHarnessHealthRequest, harnesses_server::Harnesses as HarnessService,
+    },
+};
+
+#[tonic::async_trait]
+impl HarnessService for Arc<BackendService> {
+    type SubscribeHarnessHealthStream =
+        Pin<Box<dyn Stream<Item = Result<SubscribeHarnessHealthReply, Status>> + Send + 'static>>;
+
+    async fn subscribe_harness_health(
+        &self,
+        _request: Request<SubscribeHarnessHealthRequest>,
+    ) -> Result<Response<Self::SubscribeHarnessHealthStream>, Status> {
+        let receivers = self
+            .ctx
+            .harnesses
+            .all()
+            .into_iter()
+            .map(|harness| (harness.harness_type(), harness.subscribe_health()))
+            .collect::<Vec<_>>();
+
+        // Any harness changing its health sends a fresh snapshot of all of them
+        let changes = stream::select_all(receivers.iter().map(|(_, receiver)| {
+            stream::unfold(receiver.clone(), |mut receiver| async move {
+                receiver.changed().await.ok()?;
+                Some(((), receiver))
+            })
+            .boxed()
+        }));
+        let initial = stream::once(std::future::ready(()));
+        // Harnesses that never change health end their streams, the subscription stays open
+        let output = initial
+            .chain(changes)
+            .chain(stream::pending())
+            .map(move |()| Ok(health_reply(&receivers)));
+
+        Ok(Response::new(Box::pin(output)))
+    }
+
+    async fn list_models(
+        &self,
+        request: Request<ListModelsRequest>,
+    ) -> Result<Response<ListModelsReply>, Status> {
+        let harness = harness_by_type(&self.ctx.harnesses, &request.into_inner().harness_type)?;
+
+        let catalog = harness
+            .list_models(None)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+
+        Ok(Response::new(ListModelsReply {
+            models: catalog.models.into_iter().map(Into::into).collect(),
+            default_model: catalog.default_model.map(Into::into),
+        }))
+    }
+
+    async fn list_agents(
+        &self,
+        request: Request<ListAgentsRequest>,
+    ) -> Result<Response<ListAgentsReply>, Status> {
+        let harness = harness_by_type(&self.ctx.harnesses, &request.into_inner().harness_type)?;
+
+        let agents = harness
+            .list_agents(None)
+            .await
+            .map_err(|e| Status::unavailable(e.to_string()))?;
+
+        Ok(Response::new(ListAgentsReply {
+            agents: agents.into_iter().map(Into::into).collect(),
+        }))
+    }
+}
+
+impl From<HarnessAgent> for AgentInfo {
+    fn from(agent: HarnessAgent) -> Self {
+        Self {
+            name: agent.name,
+            description: agent.description,
+            primary: agent.primary,
+            subagent: agent.subagent,
+        }
+    }
+}
+
+impl From<HarnessModel> for ModelInfo {
+    fn from(model: HarnessModel) -> Self {
+        Self {
+            provider_id: model.provider_id,
+            provider_name: model.provider_name,
+            model_id: model.model_id,
+            model_name: model.model_name,
+        }
+    }
+}
+
+impl From<Model> for ModelRef {
+    fn from(model: Model) -> Self {
+        Self {
+            provider_id: model.provider_id,
+            model_id: model.model_id,
+        }
+    }
+}
+
+/// Resolves the harness a request names, an empty type selects the default one
+fn harness_by_type(
+    harnesses: &HarnessRegistry,
+    harness_type: &str,
+) -> Result<Arc<dyn Harness>, Status> {
+    harnesses
+        .get(harness_type)
+        .map_err(|_| Status::invalid_argument(format!("unknown harness type: {harness_type}")))
+}
+
+fn health_reply(
+    receivers: &[(&'static str, watch::Receiver<HarnessHealth>)],
+) -> SubscribeHarnessHealthReply {
+    SubscribeHarnessHealthReply {
+        harnesses: receivers
+            .iter()
+            .map(|(harness_type, receiver)| {
+                health_model(harness_type.to_string(), receiver.borrow().clone())
+            })
+            .collect(),
+    }
+}
+
+fn health_model(harness_type: String, health: HarnessHealth) -> HarnessHealthModel {
+    let (status, attempt, error) = match health {
+        HarnessHealth::Starting => (HarnessHealthStatus::Starting, 0, None),
+        HarnessHealth::Healthy => (HarnessHealthStatus::Healthy, 0, None),
+        HarnessHealth::Restarting { attempt, error } => {
+            (HarnessHealthStatus::Restarting, attempt, Some(error))
+        }
+    };
+    HarnessHealthModel {
+        harness_type,
+        status: status.into(),
+        attempt,
+        error,
+    }
+}
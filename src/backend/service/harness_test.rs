@@ -0,0 +1,73 @@
+use futures::StreamExt;
+use tonic::{Code, Request};
+
+use crate::backend::{
+    proto_harness::{
+        HarnessHealthStatus, ListAgentsRequest, ListModelsRequest, SubscribeHarnessHealthRequest,
+        harnesses_server::Harnesses as HarnessService,
+    },
+    service::test_helpers::{closed_port, test_backend},
+};
+
+#[tokio::test]
+async fn subscribe_harness_health_sends_a_snapshot_of_every_harness() {
+    let backend = test_backend(closed_port()).await;
+
+    let mut stream = backend
+        .subscribe_harness_health(Request::new(SubscribeHarnessHealthRequest {}))
+        .await
+        .expect("subscribe should succeed")
+        .into_inner();
+    let reply = stream
+        .next()
+        .await
+        .expect("stream should send the current health")
+        .expect("health reply should be ok");
+
+    assert_eq!(reply.harnesses.len(), 1);
+    assert_eq!(reply.harnesses[0].harness_type, "opencode");
+    assert_eq!(reply.harnesses[0].status(), HarnessHealthStatus::Healthy);
+    assert_eq!(reply.harnesses[0].error, None);
+}
+
+#[tokio::test]
+async fn list_models_rejects_unknown_harness_types() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .list_models(Request::new(ListModelsRequest {
+            harness_type: "not-a-harness".to_string(),
+        }))
+        .await
+        .expect_err("unknown harness should fail");
+
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn list_agents_rejects_unknown_harness_types() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .list_agents(Request::new(ListAgentsRequest {
+            harness_type: "not-a-harness".to_string(),
+        }))
+        .await
+        .expect_err("unknown harness should fail");
+
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
+
+#[tokio::test]
+async fn list_models_is_unavailable_while_the_harness_is_unreachable() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .list_models(Request::new(ListModelsRequest {
+            harness_type: String::new(),
+        }))
+        .await
+        .expect_err("closed harness port should fail");
+
+    assert_eq!(err.code(), Code::Unavailable);
+}
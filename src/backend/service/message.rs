@@ -1,22 +1,22 @@
-use chrono::{DateTime, Utc};
 use futures::{Stream, StreamExt, stream};
 use std::sync::Arc;
+use tokio::sync::broadcast;
 use tonic::{Request, Response, Status};
 use uuid::Uuid;
 
 use super::required_field;
 use crate::backend::{
     BackendService,
-    harness::{Harness, HarnessAssistantEvent},
+    agent::fs::ProjectFsError,
     proto_message::{
-        self, CreateUserMessageReply, CreateUserMessageRequest, ListMessagesBySessionReply,
-        ListMessagesBySessionRequest, SubscribeMessagesBySessionReply,
-        SubscribeMessagesBySessionRequest, messages_server::Messages as MessageService,
+        CreateUserMessageReply, CreateUserMessageRequest, ListMessagesBySessionReply,
+        ListMessagesBySessionRequest, MessageEvent, MessageRemoved, RevertFileWriteReply,
+        RevertFileWriteRequest, SubscribeMessagesBySessionReply, SubscribeMessagesBySessionRequest,
+        message_event::Event, messages_server::Messages as MessageService,
     },
     proto_utils::parse_uuid,
     repo::{
-        assistant_message::{AssistantMessage, AssistantMessagePart},
-        message::MessageRepoError,
+        message::{MessageRepoError, join_user_message_parts},
         user_message::UserMessage,
         user_message_part::UserMessagePart,
     },
@@ -47,9 +47,7 @@ impl MessageService for Arc<BackendService> {
             .await
             .map_err(message_repo_error_to_status)?;
 
-        Ok(Response::new(ListMessagesBySessionReply {
-            messages: messages.into_iter().map(Into::into).collect(),
-        }))
+        Ok(Response::new(ListMessagesBySessionReply { messages }))
     }
 
     async fn create_user_message(
@@ -60,23 +58,38 @@ impl MessageService for Arc<BackendService> {
         let message_model = required_field(req.message, "message")?;
         let parts_model = req.parts;
 
-        let message = UserMessage::try_from(message_model.clone())?;
+        let message = UserMessage::try_from(message_model)?;
         let parts = parts_model
             .into_iter()
             .map(UserMessagePart::try_from)
             .collect::<Result<Vec<_>, _>>()?;
 
-        let created = self
+        let (created, created_parts) = self
             .message_repo
             .create_user_message(message, parts)
             .await
             .map_err(message_repo_error_to_status)?;
 
-        let mut model: proto_message::UserMessageModel = created.into();
-        model.parts = message_model.parts;
-
         Ok(Response::new(CreateUserMessageReply {
-            message: Some(model),
+            message: Some(join_user_message_parts(created, created_parts)),
+        }))
+    }
+
+    async fn revert_file_write(
+        &self,
+        request: Request<RevertFileWriteRequest>,
+    ) -> Result<Response<RevertFileWriteReply>, Status> {
+        let req = request.into_inner();
+        let part_id = parse_uuid("part_id", &req.part_id)?;
+
+        let message = self
+            .message_repo
+            .revert_file_write(&part_id)
+            .await
+            .map_err(message_repo_error_to_status)?;
+
+        Ok(Response::new(RevertFileWriteReply {
+            message: Some(message),
         }))
     }
 
@@ -94,280 +107,122 @@ impl MessageService for Arc<BackendService> {
             .map_err(|e| Status::internal(e.to_string()))?
             .ok_or_else(|| Status::not_found("session not found"))?;
 
-        let initial_messages = self
-            .message_repo
-            .list_by_session(&session_id, 100)
-            .await
-            .map_err(message_repo_error_to_status)?;
-
-        let events = self
+        // Subscribe before reading the snapshot so nothing stored in between is missed
+        let changes = self
             .ctx
-            .harness
-            .listen_assistant_events(session.harness_session_id.clone(), session.dir.clone())
+            .ingestor
+            .ingest(&session)
             .await
             .map_err(|e| Status::unavailable(e.to_string()))?;
 
-        let (tx, rx) =
-            tokio::sync::mpsc::channel::<Result<SubscribeMessagesBySessionReply, Status>>(32);
-
-        tx.send(Ok(SubscribeMessagesBySessionReply {
-            messages: initial_messages.into_iter().map(Into::into).collect(),
-        }))
-        .await
-        .map_err(|_| Status::internal("subscriber closed"))?;
+        // Streamed text viewers were already sent must be in what they read next
+        self.ctx
+            .ingestor
+            .flush(session_id)
+            .await
+            .map_err(|e| Status::internal(e.to_string()))?;
+        let initial_reply = match req.since_revision {
+            Some(since_revision) => replay_since(self, &session_id, since_revision).await?,
+            None => {
+                let revision = self
+                    .ctx
+                    .db
+                    .session_revision(session_id)
+                    .await
+                    .map_err(|e| Status::internal(e.to_string()))?;
+                let messages = self
+                    .message_repo
+                    .list_by_session(&session_id, 100)
+                    .await
+                    .map_err(message_repo_error_to_status)?;
+                SubscribeMessagesBySessionReply {
+                    messages,
+                    revision,
+                    events: session_status_event(self, &session_id)
+                        .await?
+                        .into_iter()
+                        .collect(),
+                }
+            }
+        };
+        let revision = initial_reply.revision;
 
+        let initial = stream::once(async move { Ok(initial_reply) });
         let backend = Arc::clone(self);
-        tokio::spawn(async move {
-            tokio::pin!(events);
-            while let Some(item) = events.next().await {
-                let event = match item {
-                    Ok(event) => event,
-                    Err(err) => {
-                        let _ = tx.send(Err(Status::unavailable(err.to_string()))).await;
-                        break;
-                    }
-                };
-
-                let changed = match apply_harness_event(&backend, session_id, event).await {
-                    Ok(changed) => changed,
-                    Err(err) => {
-                        let _ = tx.send(Err(err)).await;
-                        break;
+        let updates = stream::unfold((changes, revision), move |(mut changes, revision)| {
+            let backend = Arc::clone(&backend);
+            async move {
+                let reply = match changes.recv().await {
+                    Ok(change) => Ok(SubscribeMessagesBySessionReply {
+                        messages: Vec::new(),
+                        revision: change.revision,
+                        events: change.events,
+                    }),
+                    // Everything is stored already, replaying from the last revision catches up
+                    Err(broadcast::error::RecvError::Lagged(skipped)) => {
+                        log::warn!(
+                            "message subscriber for {session_id} lagged, skipped {skipped} updates"
+                        );
+                        match backend.ctx.ingestor.flush(session_id).await {
+                            Ok(()) => replay_since(&backend, &session_id, revision).await,
+                            Err(e) => Err(Status::internal(e.to_string())),
+                        }
                     }
+                    Err(broadcast::error::RecvError::Closed) => return None,
                 };
-
-                if changed.is_empty() {
-                    continue;
-                }
-
-                if tx
-                    .send(Ok(SubscribeMessagesBySessionReply { messages: changed }))
-                    .await
-                    .is_err()
-                {
-                    break;
-                }
+                let next_revision = reply
+                    .as_ref()
+                    .map_or(revision, |reply| reply.revision.max(revision));
+                Some((reply, (changes, next_revision)))
             }
         });
-
-        let output = stream::unfold(rx, |mut rx| async {
-            rx.recv().await.map(|item| (item, rx))
-        });
+        let output = initial.chain(updates);
 
         Ok(Response::new(Box::pin(output)))
     }
 }
 
-async fn apply_harness_event(
-    backend: &Arc<BackendService>,
-    session_id: Uuid,
-    event: HarnessAssistantEvent,
-) -> Result<Vec<proto_message::MessageHistory>, Status> {
-    match event {
-        HarnessAssistantEvent::MessageUpdated {
-            message_id,
-            completed_at,
-            error,
-            ..
-        } => {
-            let changed =
-                upsert_assistant_message(backend, session_id, &message_id, completed_at, error)
-                    .await?;
-            Ok(vec![proto_message::MessageHistory {
-                message: Some(proto_message::message_history::Message::AssistantMessage(
-                    changed.into(),
-                )),
-            }])
-        }
-        HarnessAssistantEvent::MessagePartUpdated {
-            message_id,
-            part_id,
-            part_type,
-            payload,
-            ..
-        } => {
-            let assistant =
-                upsert_assistant_message(backend, session_id, &message_id, None, None).await?;
-            upsert_assistant_part(
-                backend,
-                session_id,
-                assistant.id,
-                &part_id,
-                &part_type,
-                Some(payload),
-                None,
-            )
-            .await?;
-
-            Ok(vec![proto_message::MessageHistory {
-                message: Some(proto_message::message_history::Message::AssistantMessage(
-                    assistant.into(),
-                )),
-            }])
-        }
-        HarnessAssistantEvent::MessagePartDelta {
-            message_id,
-            part_id,
-            field,
-            delta,
-            ..
-        } => {
-            let assistant =
-                upsert_assistant_message(backend, session_id, &message_id, None, None).await?;
-            upsert_assistant_part(
-                backend,
-                session_id,
-                assistant.id,
-                &part_id,
-                "text",
-                None,
-                Some((field, delta)),
-            )
-            .await?;
-
-            Ok(vec![proto_message::MessageHistory {
-                message: Some(proto_message::message_history::Message::AssistantMessage(
-                    assistant.into(),
-                )),
-            }])
-        }
-        _ => Ok(Vec::new()),
-    }
-}
-
-async fn upsert_assistant_message(
-    backend: &Arc<BackendService>,
-    session_id: Uuid,
-    harness_message_id: &str,
-    completed_at: Option<i64>,
-    error: Option<String>,
-) -> Result<AssistantMessage, Status> {
-    let existing = backend
-        .ctx
-        .db
-        .get_assistant_message_by_harness_id(session_id, harness_message_id.to_string())
-        .await
-        .map_err(|e| Status::internal(e.to_string()))?;
-
-    let mut message = if let Some(message) = existing {
-        message
-    } else {
-        AssistantMessage::new_from_harness(
-            session_id,
-            latest_user_message_id(backend, session_id).await?,
-            harness_message_id,
-        )
-    };
-
-    message.ensure_harness_message_id(harness_message_id);
-
-    message.apply_harness_update(
-        completed_at
-            .and_then(|ms| DateTime::<Utc>::from_timestamp_millis(ms).map(|dt| dt.naive_utc())),
-        error,
-    );
-
-    let saved = if backend
-        .ctx
-        .db
-        .get_assistant_message(message.id)
+/// Everything written or removed after `since_revision`, with the session's current status
+async fn replay_since(
+    backend: &BackendService,
+    session_id: &Uuid,
+    since_revision: i64,
+) -> Result<SubscribeMessagesBySessionReply, Status> {
+    let replay = backend
+        .message_repo
+        .list_changes_since(session_id, since_revision)
         .await
-        .map_err(|e| Status::internal(e.to_string()))?
-        .is_some()
-    {
-        backend
-            .ctx
-            .db
-            .update_assistant_message(message)
-            .await
-            .map_err(|e| Status::internal(e.to_string()))?
-    } else {
-        backend
-            .ctx
-            .db
-            .create_assistant_message(message)
-            .await
-            .map_err(|e| Status::internal(e.to_string()))?
-    };
-
-    Ok(saved)
+        .map_err(message_repo_error_to_status)?;
+    let mut events = replay
+        .removed_message_ids
+        .into_iter()
+        .map(|id| MessageEvent {
+            event: Some(Event::MessageRemoved(MessageRemoved {
+                message_id: id.to_string(),
+            })),
+        })
+        .collect::<Vec<_>>();
+    events.extend(session_status_event(backend, session_id).await?);
+    Ok(SubscribeMessagesBySessionReply {
+        messages: replay.messages,
+        revision: replay.revision,
+        events,
+    })
 }
 
-async fn upsert_assistant_part(
-    backend: &Arc<BackendService>,
-    session_id: Uuid,
-    assistant_message_id: Uuid,
-    harness_part_id: &str,
-    default_part_type: &str,
-    payload: Option<serde_json::Value>,
-    delta: Option<(String, String)>,
-) -> Result<AssistantMessagePart, Status> {
-    let existing = backend
+async fn session_status_event(
+    backend: &BackendService,
+    session_id: &Uuid,
+) -> Result<Option<MessageEvent>, Status> {
+    let status = backend
         .ctx
         .db
-        .get_assistant_message_part_by_harness_id(assistant_message_id, harness_part_id.to_string())
+        .get_session_status(*session_id)
         .await
         .map_err(|e| Status::internal(e.to_string()))?;
-
-    let mut part = existing.unwrap_or_else(|| {
-        AssistantMessagePart::new_from_harness(
-            session_id,
-            assistant_message_id,
-            harness_part_id,
-            default_part_type,
-        )
-    });
-
-    if let Some(payload) = payload {
-        part.apply_payload_json(payload, default_part_type);
-    }
-
-    if let Some((field, value)) = delta {
-        part.apply_delta(field, value);
-    }
-
-    part.ensure_harness_part_id(harness_part_id);
-
-    let saved = if backend
-        .ctx
-        .db
-        .get_assistant_message_part(part.id)
-        .await
-        .map_err(|e| Status::internal(e.to_string()))?
-        .is_some()
-    {
-        backend
-            .ctx
-            .db
-            .update_assistant_message_part(part)
-            .await
-            .map_err(|e| Status::internal(e.to_string()))?
-    } else {
-        backend
-            .ctx
-            .db
-            .create_assistant_message_part(part)
-            .await
-            .map_err(|e| Status::internal(e.to_string()))?
-    };
-
-    Ok(saved)
-}
-
-async fn latest_user_message_id(
-    backend: &Arc<BackendService>,
-    session_id: Uuid,
-) -> Result<Uuid, Status> {
-    let message_id = backend
-        .message_repo
-        .list_user_messages(&session_id, 1)
-        .await
-        .map_err(message_repo_error_to_status)?
-        .into_iter()
-        .next()
-        .map(|m| m.id)
-        .ok_or_else(|| Status::failed_precondition("no user message found"))?;
-    Ok(message_id)
+    Ok(status.map(|status| MessageEvent {
+        event: Some(Event::SessionStatus(status.into())),
+    }))
 }
 
 fn message_repo_error_to_status(err: MessageRepoError) -> Status {
@@ -376,6 +231,22 @@ fn message_repo_error_to_status(err: MessageRepoError) -> Status {
         MessageRepoError::SessionNotFound(id) => {
             Status::not_found(format!("session not found: {id}"))
         }
+        MessageRepoError::ProjectNotFound(id) => {
+            Status::not_found(format!("project not found: {id}"))
+        }
         MessageRepoError::Harness(e) => Status::unavailable(e.to_string()),
+        MessageRepoError::PartNotFound(id) => {
+            Status::not_found(format!("message part not found: {id}"))
+        }
+        MessageRepoError::NotAFileWrite(_) => Status::invalid_argument(err.to_string()),
+        MessageRepoError::AlreadyReverted(_) => Status::failed_precondition(err.to_string()),
+        MessageRepoError::Files(ProjectFsError::Conflict(_) | ProjectFsError::Unsaved(_)) => {
+            Status::aborted(err.to_string())
+        }
+        MessageRepoError::Files(ProjectFsError::OutsideProject(_)) => {
+            Status::permission_denied(err.to_string())
+        }
+        MessageRepoError::Files(ProjectFsError::Io { .. }) => Status::internal(err.to_string()),
+        MessageRepoError::Attachment(_) => Status::invalid_argument(err.to_string()),
     }
 }
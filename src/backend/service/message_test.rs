@@ -27,6 +27,7 @@ async fn subscribe_messages_by_session_rejects_unknown_session() {
     let result = backend
         .subscribe_messages_by_session(Request::new(SubscribeMessagesBySessionRequest {
             session_id: uuid::Uuid::new_v4().to_string(),
+            since_revision: None,
         }))
         .await;
 
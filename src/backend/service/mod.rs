@@ -1,9 +1,13 @@
 use tonic::Status;
 
+pub mod harness;
 pub mod message;
+pub mod permission;
 pub mod project;
 pub mod session;
 
+#[cfg(test)]
+mod harness_test;
 #[cfg(test)]
 mod message_test;
 #[cfg(test)]
@@ -0,0 +1,77 @@
+use std::{pin::Pin, sync::Arc};
+
+use futures::{Stream, StreamExt, stream};
+use tonic::{Request, Response, Status};
+use uuid::Uuid;
+
+use crate::backend::{
+    BackendService,
+    permission::{PendingPermission, PermissionDecision},
+    proto_permission::{
+        self, RespondPermissionReply, RespondPermissionRequest, SubscribePermissionRequestsReply,
+        SubscribePermissionRequestsRequest, permissions_server::Permissions as PermissionService,
+    },
+    proto_utils::parse_uuid,
+};
+
+#[tonic::async_trait]
+impl PermissionService for Arc<BackendService> {
+    type SubscribePermissionRequestsStream = Pin<
+        Box<dyn Stream<Item = Result<SubscribePermissionRequestsReply, Status>> + Send + 'static>,
+    >;
+
+    async fn subscribe_permission_requests(
+        &self,
+        request: Request<SubscribePermissionRequestsRequest>,
+    ) -> Result<Response<Self::SubscribePermissionRequestsStream>, Status> {
+        let session_id = parse_uuid("session_id", &request.into_inner().session_id)?;
+
+        let mut receiver = self.ctx.permissions.subscribe();
+        let initial_reply = session_requests(&receiver.borrow_and_update(), session_id);
+        let initial = stream::once(async move { Ok(initial_reply) });
+        let updates = stream::unfold(receiver, move |mut receiver| async move {
+            if receiver.changed().await.is_err() {
+                return None;
+            }
+
+            let reply = session_requests(&receiver.borrow_and_update(), session_id);
+            Some((Ok(reply), receiver))
+        });
+
+        Ok(Response::new(Box::pin(initial.chain(updates))))
+    }
+
+    async fn respond_permission(
+        &self,
+        request: Request<RespondPermissionRequest>,
+    ) -> Result<Response<RespondPermissionReply>, Status> {
+        let req = request.into_inner();
+        let request_id = parse_uuid("request_id", &req.request_id)?;
+        let decision = match req.decision() {
+            proto_permission::PermissionDecision::Approve => PermissionDecision::Approve,
+            proto_permission::PermissionDecision::Deny => PermissionDecision::Deny,
+            proto_permission::PermissionDecision::AlwaysAllow => PermissionDecision::AlwaysAllow,
+            proto_permission::PermissionDecision::Unspecified => {
+                return Err(Status::invalid_argument("missing decision"));
+            }
+        };
+
+        self.ctx.permissions.respond(request_id, decision).await?;
+
+        Ok(Response::new(RespondPermissionReply {}))
+    }
+}
+
+fn session_requests(
+    pending: &[PendingPermission],
+    session_id: Uuid,
+) -> SubscribePermissionRequestsReply {
+    SubscribePermissionRequestsReply {
+        requests: pending
+            .iter()
+            .filter(|pending| pending.session_id == session_id)
+            .cloned()
+            .map(Into::into)
+            .collect(),
+    }
+}
@@ -1,4 +1,4 @@
-use std::{collections::hash_map::Entry, pin::Pin, sync::Arc};
+use std::{collections::hash_map::Entry, path::Path, pin::Pin, sync::Arc};
 
 use futures::{Stream, StreamExt, stream};
 use tonic::{Request, Response, Status};
@@ -8,7 +8,8 @@ use crate::backend::{
     BackendService, ProjectModel,
     proto_project::{
         CreateProjectReply, CreateProjectRequest, DeleteProjectReply, DeleteProjectRequest,
-        GetProjectReply, GetProjectRequest, ListProjectsReply, ListProjectsRequest,
+        FileMatch, GetProjectReply, GetProjectRequest, ListProjectsReply, ListProjectsRequest,
+        SearchFilesReply, SearchFilesRequest, SetUnsavedFileReply, SetUnsavedFileRequest,
         SubscribeProjectReply, SubscribeProjectRequest, SubscribeProjectsReply,
         SubscribeProjectsRequest, UpdateProjectReply, UpdateProjectRequest,
         project_server::Project as ProjectService,
@@ -172,6 +173,42 @@ impl ProjectService for Arc<BackendService> {
         });
         Ok(Response::new(Box::pin(initial.chain(updates))))
     }
+
+    async fn search_files(
+        &self,
+        request: Request<SearchFilesRequest>,
+    ) -> Result<Response<SearchFilesReply>, Status> {
+        let req = request.into_inner();
+        let project_id = parse_uuid("project_id", &req.project_id)?;
+        let limit = (req.limit > 0).then_some(req.limit as usize);
+        let matches = self
+            .project_repo
+            .search_files(&project_id, &req.query, limit)
+            .await?;
+
+        Ok(Response::new(SearchFilesReply {
+            files: matches
+                .into_iter()
+                .map(|file| FileMatch {
+                    path: file.path,
+                    absolute_path: file.absolute_path.to_string_lossy().into_owned(),
+                })
+                .collect(),
+        }))
+    }
+
+    async fn set_unsaved_file(
+        &self,
+        request: Request<SetUnsavedFileRequest>,
+    ) -> Result<Response<SetUnsavedFileReply>, Status> {
+        let req = request.into_inner();
+        let project_id = parse_uuid("project_id", &req.project_id)?;
+        self.project_repo
+            .set_unsaved_file(&project_id, Path::new(&req.path), req.content)
+            .await?;
+
+        Ok(Response::new(SetUnsavedFileReply {}))
+    }
 }
 
 fn notify_project_subscribers(
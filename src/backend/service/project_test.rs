@@ -6,8 +6,8 @@ use uuid::Uuid;
 use crate::backend::{
     proto_project::{
         CreateProjectRequest, DeleteProjectRequest, GetProjectRequest, ListProjectsRequest,
-        SubscribeProjectRequest, SubscribeProjectsRequest, UpdateProjectRequest,
-        project_server::Project as ProjectService,
+        SearchFilesRequest, SetUnsavedFileRequest, SubscribeProjectRequest,
+        SubscribeProjectsRequest, UpdateProjectRequest, project_server::Project as ProjectService,
     },
     proto_utils::naive_datetime_to_timestamp,
     service::test_helpers::{closed_port, test_backend, test_project, valid_project_model},
@@ -274,3 +274,107 @@ async fn subscribe_project_emits_update_and_delete_events() {
         "deleted project event should clear project details"
     );
 }
+
+#[tokio::test]
+async fn search_files_returns_fuzzy_matches_in_the_project_dir() {
+    let backend = test_backend(closed_port()).await;
+    let dir = tempfile::tempdir().expect("tempdir should be created");
+    std::fs::create_dir_all(dir.path().join("src")).expect("src should be created");
+    std::fs::write(dir.path().join("src/main.rs"), "").expect("file should be written");
+    std::fs::write(dir.path().join("README.md"), "").expect("file should be written");
+    let project = test_project("proj", &dir.path().to_string_lossy());
+    backend
+        .project_repo
+        .create(&project)
+        .await
+        .expect("seed create should succeed");
+
+    let files = backend
+        .search_files(Request::new(SearchFilesRequest {
+            project_id: project.id.to_string(),
+            query: "main".to_string(),
+            limit: 0,
+        }))
+        .await
+        .expect("search_files should succeed")
+        .into_inner()
+        .files;
+
+    assert_eq!(files.len(), 1);
+    assert_eq!(files[0].path, "src/main.rs");
+    assert_eq!(
+        files[0].absolute_path,
+        dir.path().join("src/main.rs").to_string_lossy()
+    );
+}
+
+#[tokio::test]
+async fn search_files_rejects_unknown_projects() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .search_files(Request::new(SearchFilesRequest {
+            project_id: Uuid::new_v4().to_string(),
+            query: "main".to_string(),
+            limit: 0,
+        }))
+        .await
+        .expect_err("unknown project should fail");
+
+    assert_eq!(err.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn set_unsaved_file_tracks_editor_content_apart_from_disk() {
+    let backend = test_backend(closed_port()).await;
+    let dir = tempfile::tempdir().expect("tempdir should be created");
+    std::fs::write(dir.path().join("main.rs"), "saved").expect("file should be written");
+    let project = test_project("proj", &dir.path().to_string_lossy());
+    backend
+        .project_repo
+        .create(&project)
+        .await
+        .expect("seed create should succeed");
+    let resolved = dir
+        .path()
+        .canonicalize()
+        .expect("dir should resolve")
+        .join("main.rs");
+
+    backend
+        .set_unsaved_file(Request::new(SetUnsavedFileRequest {
+            project_id: project.id.to_string(),
+            path: "main.rs".to_string(),
+            content: Some("edited".to_string()),
+        }))
+        .await
+        .expect("set_unsaved_file should succeed");
+    assert_eq!(
+        backend.ctx.unsaved.get(&resolved).as_deref(),
+        Some("edited")
+    );
+    assert_eq!(
+        std::fs::read_to_string(dir.path().join("main.rs")).expect("file should be read"),
+        "saved"
+    );
+
+    backend
+        .set_unsaved_file(Request::new(SetUnsavedFileRequest {
+            project_id: project.id.to_string(),
+            path: "main.rs".to_string(),
+            content: None,
+        }))
+        .await
+        .expect("clearing should succeed");
+    assert_eq!(backend.ctx.unsaved.get(&resolved), None);
+
+    let err = backend
+        .set_unsaved_file(Request::new(SetUnsavedFileRequest {
+            project_id: project.id.to_string(),
+            path: "../outside.rs".to_string(),
+            content: Some("nope".to_string()),
+        }))
+        .await
+        .expect_err("paths outside the project should fail");
+    assert_eq!(err.code(), Code::InvalidArgument);
+}
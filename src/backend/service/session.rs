@@ -5,10 +5,12 @@ use super::required_field;
 use crate::backend::{
     BackendService, SessionModel,
     proto_session::{
-        CreateSessionReply, CreateSessionRequest, DeleteSessionReply, DeleteSessionRequest,
-        GetSessionReply, GetSessionRequest, ListSessionsByProjectReply,
-        ListSessionsByProjectRequest, UpdateSessionReply, UpdateSessionRequest,
-        session_server::Session as SessionService,
+        CancelSessionReply, CancelSessionRequest, CreateSessionReply, CreateSessionRequest,
+        DeleteSessionReply, DeleteSessionRequest, ForkSessionReply, ForkSessionRequest,
+        GetSessionDiffReply, GetSessionDiffRequest, GetSessionReply, GetSessionRequest,
+        ListSessionsByProjectReply, ListSessionsByProjectRequest, RevertToMessageReply,
+        RevertToMessageRequest, UnrevertReply, UnrevertRequest, UpdateSessionReply,
+        UpdateSessionRequest, session_server::Session as SessionService,
     },
     proto_utils::parse_uuid,
 };
@@ -78,4 +80,70 @@ impl SessionService for Arc<BackendService> {
 
         Ok(Response::new(DeleteSessionReply {}))
     }
+
+    async fn cancel_session(
+        &self,
+        request: Request<CancelSessionRequest>,
+    ) -> Result<Response<CancelSessionReply>, Status> {
+        let session_id = parse_uuid("session_id", &request.into_inner().session_id)?;
+        self.session_repo.cancel(&session_id).await?;
+
+        Ok(Response::new(CancelSessionReply {}))
+    }
+
+    async fn fork_session(
+        &self,
+        request: Request<ForkSessionRequest>,
+    ) -> Result<Response<ForkSessionReply>, Status> {
+        let req = request.into_inner();
+        let session_id = parse_uuid("session_id", &req.session_id)?;
+        let message_id = parse_uuid("message_id", &req.message_id)?;
+        let forked = self.session_repo.fork(&session_id, &message_id).await?;
+
+        Ok(Response::new(ForkSessionReply {
+            session: Some(forked.into()),
+        }))
+    }
+
+    async fn revert_to_message(
+        &self,
+        request: Request<RevertToMessageRequest>,
+    ) -> Result<Response<RevertToMessageReply>, Status> {
+        let req = request.into_inner();
+        let session_id = parse_uuid("session_id", &req.session_id)?;
+        let message_id = parse_uuid("message_id", &req.message_id)?;
+        let part_id = req
+            .part_id
+            .as_deref()
+            .map(|part_id| parse_uuid("part_id", part_id))
+            .transpose()?;
+        self.session_repo
+            .revert(&session_id, &message_id, part_id.as_ref())
+            .await?;
+
+        Ok(Response::new(RevertToMessageReply {}))
+    }
+
+    async fn unrevert(
+        &self,
+        request: Request<UnrevertRequest>,
+    ) -> Result<Response<UnrevertReply>, Status> {
+        let req = request.into_inner();
+        let session_id = parse_uuid("session_id", &req.session_id)?;
+        self.session_repo.unrevert(&session_id).await?;
+
+        Ok(Response::new(UnrevertReply {}))
+    }
+
+    async fn get_session_diff(
+        &self,
+        request: Request<GetSessionDiffRequest>,
+    ) -> Result<Response<GetSessionDiffReply>, Status> {
+        let session_id = parse_uuid("session_id", &request.into_inner().session_id)?;
+        let files = self.session_repo.diff(&session_id).await?;
+
+        Ok(Response::new(GetSessionDiffReply {
+            files: files.into_iter().map(Into::into).collect(),
+        }))
+    }
 }
@@ -3,9 +3,9 @@ use uuid::Uuid;
 
 use crate::backend::{
     proto_session::{
-        CreateSessionRequest, DeleteSessionRequest, GetSessionRequest,
-        ListSessionsByProjectRequest, UpdateSessionRequest,
-        session_server::Session as SessionService,
+        CancelSessionRequest, CreateSessionRequest, DeleteSessionRequest, ForkSessionRequest,
+        GetSessionRequest, ListSessionsByProjectRequest, RevertToMessageRequest, UnrevertRequest,
+        UpdateSessionRequest, session_server::Session as SessionService,
     },
     service::test_helpers::{
         closed_port, spawn_fake_opencode_server, test_backend, test_project, test_session,
@@ -221,3 +221,79 @@ async fn update_and_delete_session_happy_path() {
 
     server.abort();
 }
+
+#[tokio::test]
+async fn cancel_session_returns_not_found_for_missing_session() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .cancel_session(Request::new(CancelSessionRequest {
+            session_id: Uuid::new_v4().to_string(),
+        }))
+        .await
+        .expect_err("missing session should fail");
+
+    assert_eq!(err.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn fork_session_rejects_invalid_message_id() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .fork_session(Request::new(ForkSessionRequest {
+            session_id: Uuid::new_v4().to_string(),
+            message_id: "not-a-uuid".to_string(),
+        }))
+        .await
+        .expect_err("invalid message id should fail");
+
+    assert_eq!(err.code(), Code::InvalidArgument);
+    assert!(err.message().contains("message_id"));
+}
+
+#[tokio::test]
+async fn fork_session_returns_not_found_for_missing_session() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .fork_session(Request::new(ForkSessionRequest {
+            session_id: Uuid::new_v4().to_string(),
+            message_id: Uuid::new_v4().to_string(),
+        }))
+        .await
+        .expect_err("missing session should fail");
+
+    assert_eq!(err.code(), Code::NotFound);
+}
+
+#[tokio::test]
+async fn revert_to_message_rejects_invalid_part_id() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .revert_to_message(Request::new(RevertToMessageRequest {
+            session_id: Uuid::new_v4().to_string(),
+            message_id: Uuid::new_v4().to_string(),
+            part_id: Some("not-a-uuid".to_string()),
+        }))
+        .await
+        .expect_err("invalid part id should fail");
+
+    assert_eq!(err.code(), Code::InvalidArgument);
+    assert!(err.message().contains("part_id"));
+}
+
+#[tokio::test]
+async fn unrevert_returns_not_found_for_missing_session() {
+    let backend = test_backend(closed_port()).await;
+
+    let err = backend
+        .unrevert(Request::new(UnrevertRequest {
+            session_id: Uuid::new_v4().to_string(),
+        }))
+        .await
+        .expect_err("missing session should fail");
+
+    assert_eq!(err.code(), Code::NotFound);
+}
@@ -14,7 +14,7 @@ use uuid::Uuid;
 use crate::backend::{
     BackendContext, BackendService, ProjectModel, SessionModel,
     db::Database,
-    harness::opencode::OpencodeHarness,
+    harness::{HarnessRegistry, opencode::OpencodeHarness},
     proto_project::ProjectModel as ProtoProjectModel,
     repo::{message::MessageRepo, project::ProjectRepo, session::SessionRepo},
 };
@@ -105,7 +105,7 @@ pub async fn test_backend(port: u32) -> Arc<BackendService> {
         .await
         .expect("in-memory db should initialize");
     let harness = OpencodeHarness::new_for_test(port);
-    let ctx = BackendContext::new(db, harness);
+    let ctx = BackendContext::new(db, HarnessRegistry::new().with(harness));
     let (projects_sender, _) = watch::channel(Vec::new());
 
     Arc::new(BackendService {
@@ -124,6 +124,10 @@ pub fn test_project(name: &str, dir: &str) -> ProjectModel {
         id: Uuid::new_v4(),
         name: name.to_string(),
         dir: dir.to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: now,
         updated_at: now,
     }
@@ -143,6 +147,10 @@ pub fn test_session(project_id: Uuid, name: &str, show_in_gui: bool) -> SessionM
         summary_additions: None,
         summary_deletions: None,
         summary_files: None,
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: now,
         updated_at: now,
     }
@@ -153,6 +161,10 @@ pub fn valid_project_model() -> ProtoProjectModel {
         id: Uuid::new_v4().to_string(),
         name: "proj".to_string(),
         dir: "/tmp/proj".to_string(),
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: Some(fixed_timestamp()),
         updated_at: Some(fixed_timestamp()),
     }
@@ -172,6 +184,10 @@ pub fn valid_session_model(project_id: Uuid) -> SessionModel {
         summary_additions: None,
         summary_deletions: None,
         summary_files: None,
+        default_agent: None,
+        default_model_provider_id: None,
+        default_model_id: None,
+        default_thinking_variant: None,
         created_at: now,
         updated_at: now,
     }
@@ -0,0 +1,71 @@
+use crate::backend::proto_harness::AgentInfo;
+use crate::theme::{BG_50, BG_500, BG_700, BG_800, BG_900, FUCHSIA_500, RADIUS_MD};
+use egui::{
+    Align, Button, FontSelection, Frame, Popup, PopupCloseBehavior, RectAlign, Response, RichText,
+    Style, Ui, text::LayoutJob, vec2,
+};
+
+/// Lists the agents that can answer the user's messages, `selected` takes the pick
+pub struct AgentSelector<'a> {
+    agents: &'a [AgentInfo],
+    selected: &'a mut Option<String>,
+}
+
+impl<'a> AgentSelector<'a> {
+    pub fn new(agents: &'a [AgentInfo], selected: &'a mut Option<String>) -> Self {
+        Self { agents, selected }
+    }
+
+    pub fn show(self, trigger: &Response) {
+        Popup::from_toggle_button_response(trigger)
+            .align(RectAlign::TOP_START)
+            .close_behavior(PopupCloseBehavior::CloseOnClickOutside)
+            .frame(Frame::popup(&Style::default()).fill(BG_900))
+            .show(|ui| {
+                ui.set_min_width(200.0);
+
+                let mut primary = self.agents.iter().filter(|agent| agent.primary).peekable();
+                if primary.peek().is_none() {
+                    ui.label("No agents available");
+                    return;
+                }
+
+                for agent in primary {
+                    let is_selected = self.selected.as_deref() == Some(agent.name.as_str());
+                    if render_agent_item(ui, agent, is_selected).clicked() {
+                        *self.selected = Some(agent.name.clone());
+                        ui.close();
+                    }
+                }
+            });
+    }
+}
+
+fn render_agent_item(ui: &mut Ui, agent: &AgentInfo, is_selected: bool) -> Response {
+    let mut layout_job = LayoutJob::default();
+    let style = Style::default();
+    RichText::new(&agent.name).color(BG_50).append_to(
+        &mut layout_job,
+        &style,
+        FontSelection::Default,
+        Align::LEFT,
+    );
+    if let Some(description) = &agent.description {
+        RichText::new(format!("\n{description}"))
+            .color(BG_500)
+            .append_to(&mut layout_job, &style, FontSelection::Default, Align::LEFT);
+    }
+
+    let styles = ui.style_mut();
+    styles.visuals.widgets.inactive.weak_bg_fill = if is_selected { FUCHSIA_500 } else { BG_900 };
+    styles.visuals.widgets.hovered.weak_bg_fill = if is_selected { FUCHSIA_500 } else { BG_700 };
+    styles.visuals.widgets.active.weak_bg_fill = if is_selected { FUCHSIA_500 } else { BG_800 };
+    styles.spacing.button_padding = vec2(8.0, 4.0);
+
+    ui.add_sized(
+        [ui.available_width(), 0.0],
+        Button::new(layout_job)
+            .corner_radius(RADIUS_MD)
+            .selected(is_selected),
+    )
+}
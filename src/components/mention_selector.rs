@@ -0,0 +1,157 @@
+use crate::theme::{BG_50, BG_500, BG_700, BG_800, BG_900, RADIUS_MD};
+use egui::{
+    Align, Button, FontSelection, Frame, Key, Modifiers, Popup, PopupCloseBehavior, RectAlign,
+    Response, RichText, ScrollArea, Style, Ui, text::LayoutJob, vec2,
+};
+
+/// Something the mention being typed can be completed to
+#[derive(Debug, Clone, PartialEq)]
+pub enum MentionOption {
+    Agent {
+        name: String,
+        description: Option<String>,
+    },
+    /// A project file, `path` is relative to the project dir
+    File { path: String, absolute_path: String },
+}
+
+impl MentionOption {
+    /// What the mention reads once completed, without the `@`
+    pub fn label(&self) -> &str {
+        match self {
+            MentionOption::Agent { name, .. } => name,
+            MentionOption::File { path, .. } => path,
+        }
+    }
+}
+
+#[derive(Default)]
+pub struct MentionSelectorState {
+    query: String,
+    focused_index: usize,
+    scroll_to_focused: bool,
+    /// Escape hides the popup until the mention changes
+    dismissed: bool,
+}
+
+/// Popup under the prompt offering agents and files for the `@` mention being typed
+pub struct MentionSelector<'a> {
+    state: &'a mut MentionSelectorState,
+    options: &'a [MentionOption],
+}
+
+impl<'a> MentionSelector<'a> {
+    pub fn new(
+        state: &'a mut MentionSelectorState,
+        query: &str,
+        options: &'a [MentionOption],
+    ) -> Self {
+        if state.query != query {
+            state.query = query.to_string();
+            state.focused_index = 0;
+            state.scroll_to_focused = true;
+            state.dismissed = false;
+        }
+        state.focused_index = state.focused_index.min(options.len().saturating_sub(1));
+        Self { state, options }
+    }
+
+    pub fn is_open(&self) -> bool {
+        !self.options.is_empty() && !self.state.dismissed
+    }
+
+    /// Takes the arrow, Enter, Tab and Escape presses before the prompt's text edit sees them.
+    /// Returns the option picked with Enter or Tab
+    pub fn handle_keys(&mut self, ui: &Ui) -> Option<&'a MentionOption> {
+        if !self.is_open() {
+            return None;
+        }
+
+        if ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::ArrowDown)) {
+            self.state.focused_index = (self.state.focused_index + 1).min(self.options.len() - 1);
+            self.state.scroll_to_focused = true;
+        }
+        if ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::ArrowUp)) {
+            self.state.focused_index = self.state.focused_index.saturating_sub(1);
+            self.state.scroll_to_focused = true;
+        }
+        if ui.input_mut(|i| i.consume_key(Modifiers::NONE, Key::Escape)) {
+            self.state.dismissed = true;
+            return None;
+        }
+        let picked = ui.input_mut(|i| {
+            i.consume_key(Modifiers::NONE, Key::Enter) || i.consume_key(Modifiers::NONE, Key::Tab)
+        });
+        picked.then(|| &self.options[self.state.focused_index])
+    }
+
+    /// Lists the options under `anchor` and returns the one clicked
+    pub fn show(self, anchor: &Response) -> Option<&'a MentionOption> {
+        let is_open = self.is_open();
+        let mut picked = None;
+        Popup::from_response(anchor)
+            .open(is_open)
+            .align(RectAlign::TOP_START)
+            .close_behavior(PopupCloseBehavior::IgnoreClicks)
+            .frame(Frame::popup(&Style::default()).fill(BG_900))
+            .show(|ui| {
+                ui.set_min_width(250.0);
+                ScrollArea::vertical().max_height(240.0).show(ui, |ui| {
+                    for (index, option) in self.options.iter().enumerate() {
+                        let response =
+                            render_mention_item(ui, option, index == self.state.focused_index);
+                        if index == self.state.focused_index && self.state.scroll_to_focused {
+                            response.scroll_to_me(Some(Align::Center));
+                        }
+                        if response.clicked() {
+                            picked = Some(option);
+                        }
+                    }
+                });
+                self.state.scroll_to_focused = false;
+            });
+        picked
+    }
+}
+
+fn render_mention_item(ui: &mut Ui, option: &MentionOption, is_focused: bool) -> Response {
+    let (title, detail) = match option {
+        MentionOption::Agent { name, description } => (
+            format!("@{name}"),
+            description.clone().unwrap_or_else(|| "Agent".to_string()),
+        ),
+        MentionOption::File { path, .. } => {
+            let (dir, file_name) = path.rsplit_once('/').unwrap_or(("", path));
+            (
+                file_name.to_string(),
+                if dir.is_empty() { "." } else { dir }.to_string(),
+            )
+        }
+    };
+
+    let mut layout_job = LayoutJob::default();
+    let style = Style::default();
+    RichText::new(format!("{title}\n")).color(BG_50).append_to(
+        &mut layout_job,
+        &style,
+        FontSelection::Default,
+        Align::LEFT,
+    );
+    RichText::new(detail).color(BG_500).append_to(
+        &mut layout_job,
+        &style,
+        FontSelection::Default,
+        Align::LEFT,
+    );
+
+    let styles = ui.style_mut();
+    styles.visuals.widgets.inactive.weak_bg_fill = if is_focused { BG_700 } else { BG_900 };
+    styles.visuals.widgets.hovered.weak_bg_fill = BG_700;
+    styles.visuals.widgets.active.weak_bg_fill = BG_800;
+    styles.spacing.button_padding = vec2(8.0, 4.0);
+
+    ui.add_sized(
+        [ui.available_width(), 0.0],
+        Button::new(layout_job).corner_radius(RADIUS_MD),
+    )
+}
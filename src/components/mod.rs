@@ -1,5 +1,7 @@
+pub mod agent_selector;
 pub mod button;
 pub mod dir_button;
+pub mod mention_selector;
 pub mod model_selector;
 pub mod project_card;
 pub mod text_input;
@@ -30,6 +30,12 @@ pub struct ModelSelectorState {
     scroll_to_focused: bool,
 }
 
+impl Default for ModelSelectorState {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ModelSelectorState {
     pub fn new() -> Self {
         Self {
@@ -66,6 +72,10 @@ impl ModelSelectorState {
             .collect();
     }
 
+    pub fn has_models(&self) -> bool {
+        !self.available_models.is_empty()
+    }
+
     pub fn selected_model(&self) -> Option<&ModelOption> {
         self.selected_model_index
             .and_then(|i| self.available_models.get(i))
@@ -0,0 +1,193 @@
+use chrono::Utc;
+use poll_promise::Promise;
+use tonic::{Request, transport::Channel};
+use uuid::Uuid;
+
+use crate::backend::{
+    CreateUserMessageRequest, MAX_MESSAGES_BYTES, MessagesClient, RevertFileWriteRequest,
+    proto_message::{MessageHistory, UserMessageModel, UserMessagePartModel},
+    proto_utils::naive_datetime_to_timestamp,
+};
+use crate::components::model_selector::ModelOption;
+
+/// Agent used when the composer has none picked
+pub const DEFAULT_AGENT: &str = "build";
+
+/// What the composer sends along with the prompt, `None` leaves the choice to the backend
+#[derive(Debug, Clone, Default)]
+pub struct MessageOptions {
+    pub model: Option<ModelOption>,
+    pub agent: Option<String>,
+    pub thinking_variant: Option<String>,
+}
+
+/// A mention in the prompt text and the range it covers, in UTF-16 code units as opencode
+/// indexes strings
+#[derive(Debug, Clone, PartialEq)]
+pub struct PromptSource {
+    pub value: String,
+    pub start: i64,
+    pub end: i64,
+}
+
+/// A piece of the composed prompt, sent as one user message part
+#[derive(Debug, Clone, PartialEq)]
+pub enum PromptPart {
+    Text(String),
+    /// An `@agent` mention
+    Agent {
+        name: String,
+        source: Option<PromptSource>,
+    },
+    Subtask {
+        agent: String,
+        description: String,
+        prompt: String,
+    },
+    /// An attachment, `url` is a `file://` or data URL. `source` is set for an `@path`
+    /// mention and holds the mentioned project file's path
+    File {
+        name: String,
+        mime: String,
+        url: String,
+        source: Option<(String, PromptSource)>,
+    },
+}
+
+pub fn send_user_message(
+    backend_channel: Channel,
+    session_id: Uuid,
+    prompt: Vec<PromptPart>,
+    options: MessageOptions,
+) -> Promise<Result<UserMessageModel, String>> {
+    Promise::spawn_async(async move {
+        let now = Some(naive_datetime_to_timestamp(Utc::now().naive_utc()));
+        let message_id = Uuid::new_v4().to_string();
+        let session_id = session_id.to_string();
+        let (model_provider_id, model_id) = options
+            .model
+            .map(|model| (model.provider_id, model.model_id))
+            .unwrap_or_default();
+
+        let message = UserMessageModel {
+            id: message_id.clone(),
+            session_id: session_id.clone(),
+            agent: options.agent.unwrap_or_else(|| DEFAULT_AGENT.to_string()),
+            model_provider_id,
+            model_id,
+            system_prompt: None,
+            structured_output_type: "text".to_string(),
+            tools_list: "{}".to_string(),
+            thinking_variant: options.thinking_variant,
+            reverted: false,
+            created_at: now,
+            updated_at: now,
+            parts: Vec::new(),
+        };
+        let parts = prompt
+            .into_iter()
+            .enumerate()
+            .map(|(position, part)| {
+                let mut model = UserMessagePartModel {
+                    id: Uuid::new_v4().to_string(),
+                    user_message_id: message_id.clone(),
+                    session_id: session_id.clone(),
+                    position: position as i64,
+                    part_type: String::new(),
+                    text: None,
+                    file_name: None,
+                    file_url: None,
+                    file_mime: None,
+                    agent_name: None,
+                    agent_source_value: None,
+                    agent_source_start: None,
+                    agent_source_end: None,
+                    file_source_path: None,
+                    file_source_text_value: None,
+                    file_source_text_start: None,
+                    file_source_text_end: None,
+                    subtask_prompt: None,
+                    subtask_description: None,
+                    created_at: now,
+                    updated_at: now,
+                };
+                match part {
+                    PromptPart::Text(text) => {
+                        model.part_type = "text".to_string();
+                        model.text = Some(text);
+                    }
+                    PromptPart::Agent { name, source } => {
+                        model.part_type = "agent".to_string();
+                        model.agent_name = Some(name);
+                        if let Some(source) = source {
+                            model.agent_source_value = Some(source.value);
+                            model.agent_source_start = Some(source.start);
+                            model.agent_source_end = Some(source.end);
+                        }
+                    }
+                    PromptPart::Subtask {
+                        agent,
+                        description,
+                        prompt,
+                    } => {
+                        model.part_type = "subtask".to_string();
+                        model.agent_name = Some(agent);
+                        model.subtask_description = Some(description);
+                        model.subtask_prompt = Some(prompt);
+                    }
+                    PromptPart::File {
+                        name,
+                        mime,
+                        url,
+                        source,
+                    } => {
+                        model.part_type = "file".to_string();
+                        model.file_name = Some(name);
+                        model.file_mime = Some(mime);
+                        model.file_url = Some(url);
+                        if let Some((path, text)) = source {
+                            model.file_source_path = Some(path);
+                            model.file_source_text_value = Some(text.value);
+                            model.file_source_text_start = Some(text.start);
+                            model.file_source_text_end = Some(text.end);
+                        }
+                    }
+                }
+                model
+            })
+            .collect::<Vec<_>>();
+
+        log::debug!("sending user message");
+        let reply = MessagesClient::new(backend_channel)
+            .max_decoding_message_size(MAX_MESSAGES_BYTES)
+            .create_user_message(Request::new(CreateUserMessageRequest {
+                message: Some(message),
+                parts,
+            }))
+            .await
+            .map_err(|error| format!("failed to send message: {}", error.message()))?
+            .into_inner();
+
+        reply
+            .message
+            .ok_or_else(|| "send reply is missing the message".to_string())
+    })
+}
+
+pub fn revert_file_write(
+    backend_channel: Channel,
+    part_id: String,
+) -> Promise<Result<MessageHistory, String>> {
+    Promise::spawn_async(async move {
+        let reply = MessagesClient::new(backend_channel)
+            .max_decoding_message_size(MAX_MESSAGES_BYTES)
+            .revert_file_write(Request::new(RevertFileWriteRequest { part_id }))
+            .await
+            .map_err(|error| format!("failed to revert file: {}", error.message()))?
+            .into_inner();
+
+        reply
+            .message
+            .ok_or_else(|| "revert reply is missing the message".to_string())
+    })
+}
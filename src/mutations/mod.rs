@@ -2,11 +2,18 @@ use poll_promise::Promise;
 use tonic::transport::Channel;
 use uuid::Uuid;
 
-use crate::backend::{ProjectModel, SessionModel};
+use crate::backend::{
+    PermissionDecision, ProjectModel, SessionModel,
+    proto_message::{MessageHistory, UserMessageModel},
+};
 
+mod message;
+mod permission;
 mod project;
 mod session;
 
+pub use message::{DEFAULT_AGENT, MessageOptions, PromptPart, PromptSource};
+
 pub struct MutationsClient {
     backend_channel: Channel,
 }
@@ -28,4 +35,55 @@ impl MutationsClient {
     ) -> Promise<Result<Uuid, String>> {
         project::create_project_with_initial_session(self.backend_channel.clone(), project, session)
     }
+
+    pub fn send_user_message(
+        &self,
+        session_id: Uuid,
+        prompt: Vec<PromptPart>,
+        options: MessageOptions,
+    ) -> Promise<Result<UserMessageModel, String>> {
+        message::send_user_message(self.backend_channel.clone(), session_id, prompt, options)
+    }
+
+    pub fn cancel_session(&self, session_id: Uuid) -> Promise<Result<(), String>> {
+        session::cancel_session(self.backend_channel.clone(), session_id)
+    }
+
+    pub fn fork_session(
+        &self,
+        session_id: Uuid,
+        message_id: String,
+    ) -> Promise<Result<SessionModel, String>> {
+        session::fork_session(self.backend_channel.clone(), session_id, message_id)
+    }
+
+    pub fn revert_to_message(
+        &self,
+        session_id: Uuid,
+        message_id: String,
+        part_id: Option<String>,
+    ) -> Promise<Result<(), String>> {
+        session::revert_to_message(
+            self.backend_channel.clone(),
+            session_id,
+            message_id,
+            part_id,
+        )
+    }
+
+    pub fn unrevert(&self, session_id: Uuid) -> Promise<Result<(), String>> {
+        session::unrevert(self.backend_channel.clone(), session_id)
+    }
+
+    pub fn revert_file_write(&self, part_id: String) -> Promise<Result<MessageHistory, String>> {
+        message::revert_file_write(self.backend_channel.clone(), part_id)
+    }
+
+    pub fn respond_permission(
+        &self,
+        request_id: String,
+        decision: PermissionDecision,
+    ) -> Promise<Result<(), String>> {
+        permission::respond_permission(self.backend_channel.clone(), request_id, decision)
+    }
 }
@@ -0,0 +1,22 @@
+use poll_promise::Promise;
+use tonic::{Request, transport::Channel};
+
+use crate::backend::{PermissionDecision, PermissionsClient, RespondPermissionRequest};
+
+pub fn respond_permission(
+    backend_channel: Channel,
+    request_id: String,
+    decision: PermissionDecision,
+) -> Promise<Result<(), String>> {
+    Promise::spawn_async(async move {
+        PermissionsClient::new(backend_channel)
+            .respond_permission(Request::new(RespondPermissionRequest {
+                request_id,
+                decision: decision.into(),
+            }))
+            .await
+            .map_err(|error| format!("failed to answer permission: {}", error.message()))?;
+
+        Ok(())
+    })
+}
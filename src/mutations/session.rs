@@ -1,6 +1,14 @@
+use poll_promise::Promise;
 use tonic::{Request, transport::Channel};
+use uuid::Uuid;
 
-use crate::backend::{SessionClient, SessionModel, proto_session::CreateSessionRequest};
+use crate::backend::{
+    SessionClient, SessionModel,
+    proto_session::{
+        CancelSessionRequest, CreateSessionRequest, ForkSessionRequest, RevertToMessageRequest,
+        UnrevertRequest,
+    },
+};
 
 #[allow(dead_code)]
 pub fn create_session(backend_channel: Channel, session: SessionModel) {
@@ -17,3 +25,72 @@ pub fn create_session(backend_channel: Channel, session: SessionModel) {
         }
     });
 }
+
+pub fn cancel_session(backend_channel: Channel, session_id: Uuid) -> Promise<Result<(), String>> {
+    Promise::spawn_async(async move {
+        SessionClient::new(backend_channel)
+            .cancel_session(Request::new(CancelSessionRequest {
+                session_id: session_id.to_string(),
+            }))
+            .await
+            .map_err(|error| format!("failed to stop session: {}", error.message()))?;
+
+        Ok(())
+    })
+}
+
+pub fn fork_session(
+    backend_channel: Channel,
+    session_id: Uuid,
+    message_id: String,
+) -> Promise<Result<SessionModel, String>> {
+    Promise::spawn_async(async move {
+        let reply = SessionClient::new(backend_channel)
+            .fork_session(Request::new(ForkSessionRequest {
+                session_id: session_id.to_string(),
+                message_id,
+            }))
+            .await
+            .map_err(|error| format!("failed to fork session: {}", error.message()))?
+            .into_inner();
+
+        reply
+            .session
+            .ok_or_else(|| "fork reply is missing the session".to_string())?
+            .try_into()
+            .map_err(|error: tonic::Status| error.message().to_string())
+    })
+}
+
+pub fn revert_to_message(
+    backend_channel: Channel,
+    session_id: Uuid,
+    message_id: String,
+    part_id: Option<String>,
+) -> Promise<Result<(), String>> {
+    Promise::spawn_async(async move {
+        SessionClient::new(backend_channel)
+            .revert_to_message(Request::new(RevertToMessageRequest {
+                session_id: session_id.to_string(),
+                message_id,
+                part_id,
+            }))
+            .await
+            .map_err(|error| format!("failed to revert session: {}", error.message()))?;
+
+        Ok(())
+    })
+}
+
+pub fn unrevert(backend_channel: Channel, session_id: Uuid) -> Promise<Result<(), String>> {
+    Promise::spawn_async(async move {
+        SessionClient::new(backend_channel)
+            .unrevert(Request::new(UnrevertRequest {
+                session_id: session_id.to_string(),
+            }))
+            .await
+            .map_err(|error| format!("failed to unrevert session: {}", error.message()))?;
+
+        Ok(())
+    })
+}
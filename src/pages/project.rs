@@ -1,15 +1,22 @@
-use crate::backend::{ProjectModel, SessionModel};
+use crate::backend::{
+    HarnessHealthStatus, ProjectModel, SessionModel, proto_harness::HarnessHealthModel,
+};
 use crate::components::button::{ButtonSize, ButtonVariant, StyledButton};
 use crate::pages::{PageAction, PageContext, Route};
 use crate::query::QueryState;
-use crate::theme::{BG_50, BG_500, BG_800, BG_900, BG_950, FUCHSIA_500, RADIUS_MD};
+use crate::theme::{
+    AMBER_500, BG_50, BG_500, BG_800, BG_900, BG_950, FUCHSIA_500, GREEN_500, RADIUS_MD, RED_500,
+};
+mod diff_tab;
+mod prompt;
 mod session_tab;
+mod transcript;
 use egui::epaint::CornerRadiusF32;
 use egui::{CentralPanel, Color32, Frame, Label, RichText, Ui, vec2};
 use egui_dock::{DockArea, DockState, Style, TabAddAlign};
 use egui_flex::{Flex, item};
 use egui_phosphor::regular;
-use session_tab::{SessionTabStateMap, TabViewer};
+use session_tab::{ProjectTab, SessionTabStateMap, TabViewer};
 use std::collections::{HashMap, HashSet};
 use uuid::Uuid;
 
@@ -18,7 +25,7 @@ pub struct ProjectPage {
     redirected_missing_project: bool,
     session_tab_ids: Vec<Uuid>,
 
-    session_tabs_tree: DockState<Uuid>,
+    session_tabs_tree: DockState<ProjectTab>,
     sessions_states: SessionTabStateMap,
 }
 
@@ -135,6 +142,25 @@ impl ProjectPage {
                         item(),
                         Label::new(RichText::new(&project.name).size(14.0).color(BG_50)),
                     );
+
+                    flex.add(item().grow(1.0), Label::new(""));
+                    let health = match page_ctx.query.use_harness_health(flex.ui()) {
+                        QueryState::Loading => None,
+                        QueryState::Error(error) => Some((RED_500, error)),
+                        QueryState::Data(harnesses) => harness_health_summary(&harnesses),
+                    };
+                    let (color, summary) =
+                        health.unwrap_or((GREEN_500, "All harnesses healthy".to_string()));
+                    let text = if color == GREEN_500 {
+                        regular::CIRCLE.to_string()
+                    } else {
+                        format!("{} {summary}", regular::CIRCLE)
+                    };
+                    flex.add(
+                        item(),
+                        Label::new(RichText::new(text).size(12.0).color(color)),
+                    )
+                    .on_hover_text(summary);
                 });
         });
     }
@@ -149,7 +175,10 @@ impl ProjectPage {
         let current_tab_ids: Vec<Uuid> = self
             .session_tabs_tree
             .iter_all_tabs()
-            .map(|(_, tab_id)| *tab_id)
+            .filter_map(|(_, tab)| match tab {
+                ProjectTab::Session(session_id) => Some(*session_id),
+                ProjectTab::Diff(_) => None,
+            })
             .collect();
         let current_set: HashSet<Uuid> = current_tab_ids.iter().copied().collect();
 
@@ -157,25 +186,55 @@ impl ProjectPage {
         let order_differs = current_tab_ids != next_tab_ids;
 
         if sets_differ {
-            self.session_tabs_tree
-                .retain_tabs(|tab_id| next_set.contains(tab_id));
+            self.session_tabs_tree.retain_tabs(|tab| match tab {
+                ProjectTab::Session(session_id) | ProjectTab::Diff(session_id) => {
+                    next_set.contains(session_id)
+                }
+            });
 
             for session_id in &next_tab_ids {
-                if self.session_tabs_tree.find_tab(session_id).is_none() {
-                    self.session_tabs_tree.push_to_focused_leaf(*session_id);
+                let tab = ProjectTab::Session(*session_id);
+                if self.session_tabs_tree.find_tab(&tab).is_none() {
+                    self.session_tabs_tree.push_to_focused_leaf(tab);
                 }
             }
         } else if order_differs {
-            self.session_tabs_tree = DockState::new(next_tab_ids.clone());
+            let diff_tabs: Vec<ProjectTab> = self
+                .session_tabs_tree
+                .iter_all_tabs()
+                .map(|(_, tab)| *tab)
+                .filter(|tab| matches!(tab, ProjectTab::Diff(_)))
+                .collect();
+            self.session_tabs_tree = DockState::new(
+                next_tab_ids
+                    .iter()
+                    .map(|session_id| ProjectTab::Session(*session_id))
+                    .chain(diff_tabs)
+                    .collect(),
+            );
         }
 
         self.session_tab_ids = next_tab_ids;
     }
 
+    /// Brings up the diff tabs sessions asked for, next to the tab that asked
+    fn open_diff_tabs(&mut self) {
+        for (session_id, state) in &mut self.sessions_states {
+            if !state.take_open_diff() {
+                continue;
+            }
+            let tab = ProjectTab::Diff(*session_id);
+            match self.session_tabs_tree.find_tab(&tab) {
+                Some(location) => self.session_tabs_tree.set_active_tab(location),
+                None => self.session_tabs_tree.push_to_focused_leaf(tab),
+            }
+        }
+    }
+
     fn render_sessions_dock(
         &mut self,
         ui: &mut Ui,
-        _page_ctx: &mut super::PageContext,
+        page_ctx: &mut super::PageContext,
         sessions: &[SessionModel],
     ) {
         let sessions_by_id: HashMap<Uuid, &SessionModel> = sessions
@@ -244,7 +303,35 @@ impl ProjectPage {
             .show_add_buttons(true)
             .show_inside(
                 ui,
-                &mut TabViewer::new(&sessions_by_id, &mut self.sessions_states),
+                &mut TabViewer::new(
+                    &sessions_by_id,
+                    &mut self.sessions_states,
+                    page_ctx.query,
+                    page_ctx.mutations,
+                ),
             );
+        self.open_diff_tabs();
     }
 }
+
+/// Color and text for the least healthy harness, `None` when all of them are healthy
+fn harness_health_summary(harnesses: &[HarnessHealthModel]) -> Option<(Color32, String)> {
+    let restarting = harnesses
+        .iter()
+        .find(|harness| harness.status() == HarnessHealthStatus::Restarting);
+    if let Some(harness) = restarting {
+        let error = harness.error.as_deref().unwrap_or("unknown error");
+        return Some((
+            RED_500,
+            format!(
+                "{} restarting (attempt {}): {error}",
+                harness.harness_type, harness.attempt
+            ),
+        ));
+    }
+
+    harnesses
+        .iter()
+        .find(|harness| harness.status() != HarnessHealthStatus::Healthy)
+        .map(|harness| (AMBER_500, format!("{} starting", harness.harness_type)))
+}
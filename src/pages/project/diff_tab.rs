@@ -0,0 +1,237 @@
+use crate::backend::diff::{DiffLine, diff_lines};
+use crate::backend::proto_session::FileDiffModel;
+use crate::components::button::{ButtonSize, ButtonVariant, StyledButton};
+use crate::query::{QueryClient, QueryState};
+use crate::theme::{BG_50, BG_500, BG_700, BG_900};
+use egui::{
+    CentralPanel, Color32, Frame, Id, Label, RichText, ScrollArea, SidePanel, TextStyle,
+    TopBottomPanel, Ui,
+};
+use egui_phosphor::regular;
+use uuid::Uuid;
+
+#[derive(Default)]
+pub struct DiffTabState {
+    /// Falls back to the first file while unset or gone from the diff
+    selected_file: Option<String>,
+    side_by_side: bool,
+}
+
+/// One row of the side by side view, a removed line next to the line that replaced it
+type SplitRow<'a> = (Option<DiffLine<'a>>, Option<DiffLine<'a>>);
+
+/// Reviews every file the session changed, one file at a time
+pub fn show_diff_tab(
+    ui: &mut Ui,
+    query: &mut QueryClient,
+    session_id: Uuid,
+    state: &mut DiffTabState,
+) {
+    let files = match query.use_session_diff(ui, session_id) {
+        QueryState::Loading => {
+            ui.label(RichText::new("Loading changes...").color(BG_500));
+            return;
+        }
+        QueryState::Error(error) => {
+            ui.label(RichText::new(error).color(Color32::RED));
+            return;
+        }
+        QueryState::Data(files) => files,
+    };
+    if files.is_empty() {
+        ui.label(RichText::new("No changes yet").color(BG_500));
+        return;
+    }
+
+    let selected = state
+        .selected_file
+        .as_ref()
+        .and_then(|selected| files.iter().find(|file| &file.file == selected))
+        .unwrap_or(&files[0]);
+
+    TopBottomPanel::top(Id::new(("diff_toolbar", session_id)))
+        .show_separator_line(false)
+        .frame(Frame::new().inner_margin(8.0))
+        .show_inside(ui, |ui| {
+            ui.horizontal(|ui| {
+                let additions: i64 = files.iter().map(|file| file.additions).sum();
+                let deletions: i64 = files.iter().map(|file| file.deletions).sum();
+                ui.label(
+                    RichText::new(format!(
+                        "{} {}",
+                        files.len(),
+                        if files.len() == 1 { "file" } else { "files" }
+                    ))
+                    .color(BG_50),
+                );
+                show_counts(ui, additions, deletions);
+                ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                    for (side_by_side, label, icon) in [
+                        (true, "Split", regular::COLUMNS),
+                        (false, "Unified", regular::ROWS),
+                    ] {
+                        let variant = if state.side_by_side == side_by_side {
+                            ButtonVariant::Secondary
+                        } else {
+                            ButtonVariant::Ghost
+                        };
+                        if StyledButton::new(label)
+                            .size(ButtonSize::Sm)
+                            .variant(variant)
+                            .icon(icon)
+                            .show(ui)
+                            .clicked()
+                        {
+                            state.side_by_side = side_by_side;
+                        }
+                    }
+                });
+            });
+        });
+
+    SidePanel::left(Id::new(("diff_files", session_id)))
+        .resizable(true)
+        .default_width(220.0)
+        .frame(Frame::new().inner_margin(8.0).fill(BG_900))
+        .show_inside(ui, |ui| {
+            ScrollArea::vertical().show(ui, |ui| {
+                for file in files.iter() {
+                    let is_selected = file.file == selected.file;
+                    let response = ui
+                        .horizontal(|ui| {
+                            let (dir, name) =
+                                file.file.rsplit_once('/').unwrap_or(("", &file.file));
+                            let label = ui
+                                .selectable_label(is_selected, RichText::new(name).color(BG_50))
+                                .on_hover_text(&file.file);
+                            if !dir.is_empty() {
+                                ui.add(
+                                    Label::new(RichText::new(dir).small().color(BG_500)).truncate(),
+                                );
+                            }
+                            show_counts(ui, file.additions, file.deletions);
+                            label
+                        })
+                        .inner;
+                    if response.clicked() {
+                        state.selected_file = Some(file.file.clone());
+                    }
+                }
+            });
+        });
+
+    CentralPanel::default()
+        .frame(Frame::new().inner_margin(8.0))
+        .show_inside(ui, |ui| {
+            ui.label(RichText::new(&selected.file).monospace().color(BG_50));
+            ui.separator();
+            if state.side_by_side {
+                show_split(ui, selected);
+            } else {
+                show_unified(ui, selected);
+            }
+        });
+}
+
+fn show_counts(ui: &mut Ui, additions: i64, deletions: i64) {
+    ui.label(
+        RichText::new(format!("+{additions}"))
+            .small()
+            .color(Color32::LIGHT_GREEN),
+    );
+    ui.label(
+        RichText::new(format!("-{deletions}"))
+            .small()
+            .color(Color32::LIGHT_RED),
+    );
+}
+
+fn show_unified(ui: &mut Ui, file: &FileDiffModel) {
+    let lines = diff_lines(&file.before, &file.after);
+    let row_height = ui.text_style_height(&TextStyle::Monospace);
+    ScrollArea::both()
+        .id_salt(("diff_unified", &file.file))
+        .auto_shrink([false, false])
+        .show_rows(ui, row_height, lines.len(), |ui, rows| {
+            for line in &lines[rows] {
+                ui.add(diff_line_label(Some(*line)).extend());
+            }
+        });
+}
+
+fn show_split(ui: &mut Ui, file: &FileDiffModel) {
+    let rows = split_rows(diff_lines(&file.before, &file.after));
+    let row_height = ui.text_style_height(&TextStyle::Monospace);
+    ScrollArea::vertical()
+        .id_salt(("diff_split", &file.file))
+        .auto_shrink([false, false])
+        .show_rows(ui, row_height, rows.len(), |ui, range| {
+            for (before, after) in &rows[range] {
+                ui.columns(2, |columns| {
+                    columns[0].add(diff_line_label(*before).truncate());
+                    columns[1].add(diff_line_label(*after).truncate());
+                });
+            }
+        });
+}
+
+/// Pairs each run of removed lines with the run of added lines after it
+fn split_rows(lines: Vec<DiffLine>) -> Vec<SplitRow> {
+    let mut rows = Vec::new();
+    let mut removed = Vec::new();
+    let mut added = Vec::new();
+    for line in lines {
+        match line {
+            DiffLine::Same(_) => {
+                flush_run(&mut rows, &mut removed, &mut added);
+                rows.push((Some(line), Some(line)));
+            }
+            DiffLine::Removed(_) => {
+                // A removal after additions starts the next run
+                if !added.is_empty() {
+                    flush_run(&mut rows, &mut removed, &mut added);
+                }
+                removed.push(line);
+            }
+            DiffLine::Added(_) => added.push(line),
+        }
+    }
+    flush_run(&mut rows, &mut removed, &mut added);
+    rows
+}
+
+fn flush_run<'a>(
+    rows: &mut Vec<SplitRow<'a>>,
+    removed: &mut Vec<DiffLine<'a>>,
+    added: &mut Vec<DiffLine<'a>>,
+) {
+    let len = removed.len().max(added.len());
+    let mut removed = std::mem::take(removed).into_iter();
+    let mut added = std::mem::take(added).into_iter();
+    rows.extend((0..len).map(|_| (removed.next(), added.next())));
+}
+
+fn diff_line_label(line: Option<DiffLine>) -> Label {
+    let (sign, text, color, background) = match line {
+        Some(DiffLine::Same(text)) => (' ', text, BG_500, Color32::TRANSPARENT),
+        Some(DiffLine::Removed(text)) => (
+            '-',
+            text,
+            Color32::LIGHT_RED,
+            Color32::from_rgba_unmultiplied(220, 38, 38, 24),
+        ),
+        Some(DiffLine::Added(text)) => (
+            '+',
+            text,
+            Color32::LIGHT_GREEN,
+            Color32::from_rgba_unmultiplied(22, 163, 74, 24),
+        ),
+        None => (' ', "", BG_700, Color32::TRANSPARENT),
+    };
+    Label::new(
+        RichText::new(format!("{sign} {text}"))
+            .monospace()
+            .color(color)
+            .background_color(background),
+    )
+}
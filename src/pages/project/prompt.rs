@@ -0,0 +1,291 @@
+use std::path::Path;
+
+use crate::backend::attachment;
+use crate::mutations::{PromptPart, PromptSource};
+
+/// Opens a prompt that is handed to a subagent as a task of its own
+const SUBTASK_COMMAND: &str = "/subtask";
+/// Subagent a `/subtask` goes to when it names none
+const DEFAULT_SUBTASK_AGENT: &str = "general";
+/// Longest subtask description, taken from the first line of its prompt
+const SUBTASK_DESCRIPTION_CHARS: usize = 60;
+
+/// Chars that end a sentence rather than an `@path` mention
+const MENTION_TRAILING_PUNCTUATION: &[char] = &['.', ',', ';', ':', '!', '?', ')', '"', '\''];
+
+/// A project file picked from the mention popup
+#[derive(Debug, Clone, PartialEq)]
+pub struct MentionedFile {
+    /// Relative to the project dir, as written after the `@`
+    pub path: String,
+    pub absolute_path: String,
+}
+
+/// Splits the composer text into message parts.
+/// `/subtask [@agent] prompt` hands the prompt to a subagent, anything else is sent as text
+/// with an agent part for every `@name` that names one of `subagents` and a file part for
+/// every `@path` that names one of the picked `files`
+pub fn parse_prompt(
+    prompt: &str,
+    subagents: &[&str],
+    files: &[MentionedFile],
+) -> Result<Vec<PromptPart>, String> {
+    let prompt = prompt.trim();
+    if prompt.is_empty() {
+        return Err("Message cannot be empty".to_string());
+    }
+
+    if let Some(rest) = prompt.strip_prefix(SUBTASK_COMMAND)
+        && (rest.is_empty() || rest.starts_with(char::is_whitespace))
+    {
+        return parse_subtask(rest.trim_start(), subagents).map(|part| vec![part]);
+    }
+
+    let mut parts = vec![PromptPart::Text(prompt.to_string())];
+    let mut attached = Vec::new();
+    for (name, start, end) in mentions(prompt) {
+        let source = PromptSource {
+            value: format!("@{name}"),
+            start: start as i64,
+            end: end as i64,
+        };
+        if subagents.contains(&name) {
+            parts.push(PromptPart::Agent {
+                name: name.to_string(),
+                source: Some(source),
+            });
+        } else if let Some(file) = files.iter().find(|file| file.path == name)
+            // The agent gets each file once, however often it is mentioned
+            && !attached.contains(&name)
+        {
+            attached.push(name);
+            parts.push(PromptPart::File {
+                name: file.path.clone(),
+                mime: attachment::sniff_mime(&[], &file.path).to_string(),
+                url: attachment::file_url(Path::new(&file.absolute_path)),
+                source: Some((file.absolute_path.clone(), source)),
+            });
+        }
+    }
+    Ok(parts)
+}
+
+fn parse_subtask(rest: &str, subagents: &[&str]) -> Result<PromptPart, String> {
+    let named = rest.strip_prefix('@').and_then(|named| {
+        let (name, task) = named.split_once(char::is_whitespace).unwrap_or((named, ""));
+        subagents.contains(&name).then_some((name, task.trim()))
+    });
+    let (agent, task) = named.unwrap_or((DEFAULT_SUBTASK_AGENT, rest));
+    if task.is_empty() {
+        return Err(format!("{SUBTASK_COMMAND} needs a prompt"));
+    }
+
+    Ok(PromptPart::Subtask {
+        agent: agent.to_string(),
+        description: task
+            .lines()
+            .next()
+            .unwrap_or_default()
+            .chars()
+            .take(SUBTASK_DESCRIPTION_CHARS)
+            .collect(),
+        prompt: task.to_string(),
+    })
+}
+
+/// `@name` and `@path` mentions that start a word, with the UTF-16 range they cover.
+/// Punctuation closing a sentence is not part of the mention
+fn mentions(prompt: &str) -> Vec<(&str, usize, usize)> {
+    let mut utf16 = 0;
+    let chars = prompt
+        .char_indices()
+        .map(|(byte, c)| {
+            let offset = utf16;
+            utf16 += c.len_utf16();
+            (byte, c, offset)
+        })
+        .collect::<Vec<_>>();
+    let mut found = Vec::new();
+    for (index, &(byte, c, start)) in chars.iter().enumerate() {
+        if c != '@' || (index > 0 && !chars[index - 1].1.is_whitespace()) {
+            continue;
+        }
+        let word = chars[index + 1..]
+            .iter()
+            .take_while(|(_, c, _)| !c.is_whitespace())
+            .map(|(_, c, _)| *c)
+            .collect::<Vec<_>>();
+        let len = word.len()
+            - word
+                .iter()
+                .rev()
+                .take_while(|c| MENTION_TRAILING_PUNCTUATION.contains(c))
+                .count();
+        if len == 0 {
+            continue;
+        }
+        let (name_end, end) = chars
+            .get(index + 1 + len)
+            .map_or((prompt.len(), utf16), |(byte, _, offset)| (*byte, *offset));
+        found.push((&prompt[byte + 1..name_end], start, end));
+    }
+    found
+}
+
+/// The name or path typed after a trailing `@`, while a mention is being written
+pub fn mention_query(prompt: &str) -> Option<&str> {
+    let word = prompt.rsplit(char::is_whitespace).next()?;
+    word.strip_prefix('@')
+}
+
+/// Replaces the mention being written with the full agent name or file path
+pub fn complete_mention(prompt: &mut String, name: &str) {
+    if let Some(query) = mention_query(prompt) {
+        let start = prompt.len() - query.len();
+        prompt.truncate(start);
+        prompt.push_str(name);
+        prompt.push(' ');
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn file(path: &str) -> MentionedFile {
+        MentionedFile {
+            path: path.to_string(),
+            absolute_path: format!("/project/{path}"),
+        }
+    }
+
+    fn span(value: &str, start: i64, end: i64) -> PromptSource {
+        PromptSource {
+            value: value.to_string(),
+            start,
+            end,
+        }
+    }
+
+    #[test]
+    fn file_mentions_become_file_parts_once() {
+        let files = [file("src/main.rs")];
+        let parts = parse_prompt("see @src/main.rs, then @src/main.rs", &[], &files).unwrap();
+
+        assert_eq!(parts.len(), 2);
+        assert_eq!(
+            parts[0],
+            PromptPart::Text("see @src/main.rs, then @src/main.rs".to_string())
+        );
+        let PromptPart::File {
+            name, url, source, ..
+        } = &parts[1]
+        else {
+            panic!("expected a file part, got {:?}", parts[1]);
+        };
+        assert_eq!(name, "src/main.rs");
+        assert_eq!(
+            url,
+            &attachment::file_url(Path::new("/project/src/main.rs"))
+        );
+        assert_eq!(
+            source,
+            &Some((
+                "/project/src/main.rs".to_string(),
+                span("@src/main.rs", 4, 16)
+            ))
+        );
+    }
+
+    #[test]
+    fn unknown_mentions_stay_text() {
+        let parts = parse_prompt("ask @nobody about @missing.rs", &["explore"], &[]).unwrap();
+
+        assert_eq!(
+            parts,
+            [PromptPart::Text(
+                "ask @nobody about @missing.rs".to_string()
+            )]
+        );
+    }
+
+    #[test]
+    fn mentions_at_the_start_and_end_of_the_prompt() {
+        let parts = parse_prompt("@explore look at @a.rs", &["explore"], &[file("a.rs")]).unwrap();
+
+        assert_eq!(
+            parts[1],
+            PromptPart::Agent {
+                name: "explore".to_string(),
+                source: Some(span("@explore", 0, 8)),
+            }
+        );
+        let PromptPart::File { source, .. } = &parts[2] else {
+            panic!("expected a file part, got {:?}", parts[2]);
+        };
+        assert_eq!(source.as_ref().unwrap().1, span("@a.rs", 17, 22));
+    }
+
+    #[test]
+    fn mention_offsets_count_utf16_code_units() {
+        // `é` is one UTF-16 unit over two bytes, the emoji two units over four bytes
+        assert_eq!(mentions("é 🦀 @a.rs!"), [("a.rs", 5, 10)]);
+        assert_eq!(mentions("🦀@a.rs"), []);
+        assert_eq!(mentions("@агент"), [("агент", 0, 6)]);
+    }
+
+    #[test]
+    fn subtask_goes_to_the_named_subagent() {
+        let parts = parse_prompt(
+            "/subtask @explore find the bug\nand fix it",
+            &["explore"],
+            &[],
+        )
+        .unwrap();
+
+        assert_eq!(
+            parts,
+            [PromptPart::Subtask {
+                agent: "explore".to_string(),
+                description: "find the bug".to_string(),
+                prompt: "find the bug\nand fix it".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn subtask_with_an_unknown_agent_goes_to_the_default_one() {
+        let parts = parse_prompt("/subtask @nobody find the bug", &["explore"], &[]).unwrap();
+
+        assert_eq!(
+            parts,
+            [PromptPart::Subtask {
+                agent: DEFAULT_SUBTASK_AGENT.to_string(),
+                description: "@nobody find the bug".to_string(),
+                prompt: "@nobody find the bug".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn subtask_description_is_cut_at_a_char_boundary() {
+        let task = "ü".repeat(SUBTASK_DESCRIPTION_CHARS + 10);
+        let parts = parse_prompt(&format!("/subtask {task}"), &[], &[]).unwrap();
+
+        let PromptPart::Subtask { description, .. } = &parts[0] else {
+            panic!("expected a subtask, got {:?}", parts[0]);
+        };
+        assert_eq!(description.chars().count(), SUBTASK_DESCRIPTION_CHARS);
+    }
+
+    #[test]
+    fn rejects_empty_prompts_and_subtasks() {
+        assert!(parse_prompt("  ", &[], &[]).is_err());
+        assert!(parse_prompt("/subtask", &[], &[]).is_err());
+        assert!(parse_prompt("/subtask @explore", &["explore"], &[]).is_err());
+        assert!(matches!(
+            parse_prompt("/subtasks", &[], &[]).unwrap()[..],
+            [PromptPart::Text(_)]
+        ));
+    }
+}
@@ -1,64 +1,669 @@
-use crate::backend::SessionModel;
-use crate::components::button::StyledButton;
-use crate::theme::{BG_700, BG_800, RADIUS_MD, STROKE_WIDTH};
-use egui::{Align2, Color32, Frame, Id, Stroke, TextEdit, TopBottomPanel, vec2};
+use crate::backend::attachment::{self, Attachment};
+use crate::backend::{
+    PermissionDecision, SessionModel,
+    proto_harness::AgentInfo,
+    proto_message::{
+        MessageHistory, SessionStatusKind, SessionStatusModel, UserMessageModel, message_history,
+    },
+    proto_permission::PermissionRequestModel,
+};
+use crate::components::agent_selector::AgentSelector;
+use crate::components::button::{ButtonSize, ButtonVariant, StyledButton};
+use crate::components::mention_selector::{MentionOption, MentionSelector, MentionSelectorState};
+use crate::components::model_selector::{ModelSelector, ModelSelectorState};
+use crate::mutations::{DEFAULT_AGENT, MessageOptions, MutationsClient, PromptPart};
+use crate::pages::project::diff_tab::{DiffTabState, show_diff_tab};
+use crate::pages::project::prompt::{MentionedFile, complete_mention, mention_query, parse_prompt};
+use crate::pages::project::transcript::{TranscriptAction, show_transcript};
+use crate::query::{QueryClient, QueryState};
+use crate::theme::{AMBER_500, BG_500, BG_700, BG_800, RADIUS_MD, STROKE_WIDTH};
+use egui::{
+    Align2, CentralPanel, Color32, Frame, Id, Modal, RichText, Stroke, TextEdit, TopBottomPanel,
+    text::{CCursor, CCursorRange},
+    text_edit::TextEditState,
+    vec2,
+};
 use egui_dock::tab_viewer::OnCloseResponse;
-use egui_flex::{Flex, item};
+use egui_flex::{Flex, FlexInstance, item};
+use egui_inbox::UiInbox;
+use egui_phosphor::regular;
+use poll_promise::Promise;
 use std::collections::HashMap;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
 use uuid::Uuid;
 
 pub type SessionTabStateMap = HashMap<Uuid, SessionTabState>;
 
+/// Tabs of the project's dock, all of them belong to a session
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProjectTab {
+    Session(Uuid),
+    /// Reviews what the session's agent changed
+    Diff(Uuid),
+}
+
+/// A fork in flight with the prompt its tab starts with
+struct PendingFork {
+    promise: Promise<Result<SessionModel, String>>,
+    prompt: Option<String>,
+}
+
 #[derive(Default)]
 pub struct SessionTabState {
     prompt_input: String,
+    model_selector: ModelSelectorState,
+    agent: Option<String>,
+    thinking_variant: Option<String>,
+    /// Set once the session's agent and thinking variant were taken over
+    has_defaults: bool,
+    send_promise: Option<Promise<Result<UserMessageModel, String>>>,
     send_msg_error: Option<String>,
+    revert_promise: Option<Promise<Result<MessageHistory, String>>>,
+    revert_error: Option<String>,
+    permission_promise: Option<Promise<Result<(), String>>>,
+    permission_error: Option<String>,
+    cancel_promise: Option<Promise<Result<(), String>>>,
+    cancel_error: Option<String>,
+    fork: Option<PendingFork>,
+    fork_error: Option<String>,
+    /// A revert or unrevert of the session, whose messages come back through the subscription
+    session_revert_promise: Option<Promise<Result<(), String>>>,
+    session_revert_error: Option<String>,
+    attachments: Vec<Attachment>,
+    attachment_inbox: UiInbox<Result<Attachment, String>>,
+    attachment_error: Option<String>,
+    mention_selector: MentionSelectorState,
+    /// Files picked from the mention popup, sent along when the prompt still mentions them
+    mentioned_files: Vec<MentionedFile>,
+    diff: DiffTabState,
+    /// Asks the project page to show the diff tab
+    open_diff: bool,
+}
+
+impl SessionTabState {
+    /// Whether the session's diff tab was asked for since the last call
+    pub fn take_open_diff(&mut self) -> bool {
+        std::mem::take(&mut self.open_diff)
+    }
+
+    fn is_sending(&self) -> bool {
+        self.send_promise.is_some()
+    }
+
+    /// Resolves the in-flight send, clearing the prompt only once the backend accepted it.
+    /// The sent message is merged right away rather than waiting on the subscription
+    fn poll_send_result(&mut self, query: &mut QueryClient, session_id: Uuid) {
+        let Some(result) = self
+            .send_promise
+            .as_ref()
+            .and_then(|promise| promise.ready())
+        else {
+            return;
+        };
+
+        match result {
+            Ok(message) => {
+                query.merge_messages(
+                    session_id,
+                    vec![MessageHistory {
+                        message: Some(message_history::Message::UserMessage(message.clone())),
+                    }],
+                );
+                self.prompt_input.clear();
+                self.attachments.clear();
+                self.mentioned_files.clear();
+                self.send_msg_error = None;
+            }
+            Err(error) => {
+                self.send_msg_error = Some(error.clone());
+            }
+        }
+
+        self.send_promise = None;
+    }
+
+    /// Reverts are not streamed back by the subscription, so the reply is merged by hand
+    fn poll_revert_result(&mut self, query: &mut QueryClient, session_id: Uuid) {
+        let Some(result) = self
+            .revert_promise
+            .as_ref()
+            .and_then(|promise| promise.ready())
+        else {
+            return;
+        };
+
+        match result {
+            Ok(message) => {
+                query.merge_messages(session_id, vec![message.clone()]);
+                self.revert_error = None;
+            }
+            Err(error) => {
+                self.revert_error = Some(error.clone());
+            }
+        }
+
+        self.revert_promise = None;
+    }
+
+    /// The answered request disappears through the subscription, only failures need handling
+    fn poll_permission_result(&mut self) {
+        let Some(result) = self
+            .permission_promise
+            .as_ref()
+            .and_then(|promise| promise.ready())
+        else {
+            return;
+        };
+
+        self.permission_error = result.as_ref().err().cloned();
+        self.permission_promise = None;
+    }
+
+    /// Adds the files `attach` finished reading to the prompt
+    fn poll_attachments(&mut self, ui: &egui::Ui) {
+        for loaded in self.attachment_inbox.read(ui) {
+            match loaded {
+                Ok(attachment) => {
+                    self.attachments.push(attachment);
+                    self.attachment_error = None;
+                }
+                Err(error) => self.attachment_error = Some(error),
+            }
+        }
+    }
+
+    /// Checks the files off the UI thread, a slow disk never stalls the frame
+    fn attach(&self, paths: impl FnOnce() -> Vec<PathBuf> + Send + 'static) {
+        let sender = self.attachment_inbox.sender();
+        std::thread::spawn(move || {
+            for path in paths() {
+                let loaded = attachment::load(&path).map_err(|error| error.to_string());
+                if sender.send(loaded).is_err() {
+                    break;
+                }
+            }
+        });
+    }
+
+    /// Returns the forked session and the prompt to start it with, once the backend created it
+    fn poll_fork_result(&mut self) -> Option<(SessionModel, Option<String>)> {
+        let result = self.fork.as_ref()?.promise.ready()?.clone();
+        let prompt = self.fork.take()?.prompt;
+
+        match result {
+            Ok(session) => {
+                self.fork_error = None;
+                Some((session, prompt))
+            }
+            Err(error) => {
+                self.fork_error = Some(error);
+                None
+            }
+        }
+    }
+
+    fn poll_session_revert_result(&mut self) {
+        let Some(result) = self
+            .session_revert_promise
+            .as_ref()
+            .and_then(|promise| promise.ready())
+        else {
+            return;
+        };
+
+        self.session_revert_error = result.as_ref().err().cloned();
+        self.session_revert_promise = None;
+    }
+
+    /// The turn ending shows up as an idle status, only failures need handling
+    fn poll_cancel_result(&mut self) {
+        let Some(result) = self
+            .cancel_promise
+            .as_ref()
+            .and_then(|promise| promise.ready())
+        else {
+            return;
+        };
+
+        self.cancel_error = result.as_ref().err().cloned();
+        self.cancel_promise = None;
+    }
+
+    fn cancel(&mut self, mutations: &MutationsClient, session_id: Uuid) {
+        if self.cancel_promise.is_none() {
+            self.cancel_promise = Some(mutations.cancel_session(session_id));
+            self.cancel_error = None;
+        }
+    }
+
+    fn respond_permission(
+        &mut self,
+        mutations: &MutationsClient,
+        request_id: String,
+        decision: PermissionDecision,
+    ) {
+        if self.permission_promise.is_none() {
+            self.permission_promise = Some(mutations.respond_permission(request_id, decision));
+            self.permission_error = None;
+        }
+    }
+
+    fn handle_transcript_action(
+        &mut self,
+        mutations: &MutationsClient,
+        session_id: Uuid,
+        action: TranscriptAction,
+    ) {
+        match action {
+            TranscriptAction::RevertFileWrite { part_id } => {
+                if self.revert_promise.is_none() {
+                    self.revert_promise = Some(mutations.revert_file_write(part_id));
+                }
+            }
+            TranscriptAction::Fork { message_id, prompt } => {
+                if self.fork.is_none() {
+                    self.fork = Some(PendingFork {
+                        promise: mutations.fork_session(session_id, message_id),
+                        prompt,
+                    });
+                    self.fork_error = None;
+                }
+            }
+            TranscriptAction::Revert {
+                message_id,
+                part_id,
+            } => {
+                if self.session_revert_promise.is_none() {
+                    self.session_revert_promise =
+                        Some(mutations.revert_to_message(session_id, message_id, part_id));
+                    self.session_revert_error = None;
+                }
+            }
+            TranscriptAction::Unrevert => {
+                if self.session_revert_promise.is_none() {
+                    self.session_revert_promise = Some(mutations.unrevert(session_id));
+                    self.session_revert_error = None;
+                }
+            }
+        }
+    }
+
+    fn send(&mut self, mutations: &MutationsClient, session_id: Uuid, agents: &[AgentInfo]) {
+        if self.is_sending() {
+            return;
+        }
+
+        let subagents = agents
+            .iter()
+            .filter(|agent| agent.subagent)
+            .map(|agent| agent.name.as_str())
+            .collect::<Vec<_>>();
+        // Attachments can go out on their own
+        let mut prompt = if self.prompt_input.trim().is_empty() && !self.attachments.is_empty() {
+            Vec::new()
+        } else {
+            match parse_prompt(&self.prompt_input, &subagents, &self.mentioned_files) {
+                Ok(prompt) => prompt,
+                Err(error) => {
+                    self.send_msg_error = Some(error);
+                    return;
+                }
+            }
+        };
+        prompt.extend(self.attachments.iter().map(|attachment| PromptPart::File {
+            name: attachment.file_name.clone(),
+            mime: attachment.mime.clone(),
+            url: attachment.url.clone(),
+            source: None,
+        }));
+
+        log::info!(
+            "send clicked for session {session_id} ({} parts)",
+            prompt.len()
+        );
+        let options = MessageOptions {
+            model: self.model_selector.selected_model().cloned(),
+            agent: self.agent.clone(),
+            thinking_variant: self.thinking_variant.clone(),
+        };
+        self.send_promise = Some(mutations.send_user_message(session_id, prompt, options));
+        self.send_msg_error = None;
+    }
+}
+
+/// Lists what goes with the next message, a click takes a file off again
+fn show_attachments(flex: &mut FlexInstance, attachments: &mut Vec<Attachment>) {
+    if attachments.is_empty() {
+        return;
+    }
+
+    let mut removed = None;
+    flex.add_flex(
+        item(),
+        Flex::horizontal().gap(vec2(4.0, 4.0)).wrap(true),
+        |flex| {
+            for (index, attachment) in attachments.iter().enumerate() {
+                let chip = flex.add(
+                    item(),
+                    StyledButton::new(&attachment.file_name)
+                        .size(ButtonSize::Sm)
+                        .variant(ButtonVariant::Secondary)
+                        .icon(if attachment.is_image() {
+                            regular::IMAGE
+                        } else {
+                            regular::FILE
+                        }),
+                );
+                if chip.on_hover_text("Remove").clicked() {
+                    removed = Some(index);
+                }
+            }
+        },
+    );
+    if let Some(index) = removed {
+        attachments.remove(index);
+    }
+}
+
+/// Completes the mention being typed with the picked option and puts the cursor after it
+fn apply_mention(
+    ui: &egui::Ui,
+    prompt_input: &egui::Response,
+    session_state: &mut SessionTabState,
+    option: MentionOption,
+) {
+    complete_mention(&mut session_state.prompt_input, option.label());
+    if let MentionOption::File {
+        path,
+        absolute_path,
+    } = option
+        && !session_state
+            .mentioned_files
+            .iter()
+            .any(|file| file.path == path)
+    {
+        session_state.mentioned_files.push(MentionedFile {
+            path,
+            absolute_path,
+        });
+    }
+
+    let mut text_state = TextEditState::load(ui.ctx(), prompt_input.id).unwrap_or_default();
+    let end = CCursor::new(session_state.prompt_input.chars().count());
+    text_state
+        .cursor
+        .set_char_range(Some(CCursorRange::one(end)));
+    text_state.store(ui.ctx(), prompt_input.id);
+    prompt_input.request_focus();
+}
+
+fn show_permission_modal(
+    ui: &egui::Ui,
+    session_id: Uuid,
+    session_state: &mut SessionTabState,
+    mutations: &MutationsClient,
+    request: &PermissionRequestModel,
+) {
+    let mut decision = None;
+    Modal::new(Id::new(("permission_modal", session_id))).show(ui.ctx(), |ui| {
+        ui.set_max_width(420.0);
+        ui.label(RichText::new("Permission required").strong());
+        ui.label(RichText::new(&request.kind).color(BG_500));
+        ui.add_space(8.0);
+        ui.label(&request.title);
+        if let Some(error) = &session_state.permission_error {
+            ui.label(RichText::new(error).color(Color32::RED));
+        }
+        ui.add_space(12.0);
+
+        ui.add_enabled_ui(session_state.permission_promise.is_none(), |ui| {
+            ui.horizontal(|ui| {
+                if ui
+                    .add(StyledButton::new("Approve").id("permission_approve"))
+                    .clicked()
+                {
+                    decision = Some(PermissionDecision::Approve);
+                }
+                if ui
+                    .add(
+                        StyledButton::new("Always allow")
+                            .id("permission_always_allow")
+                            .variant(ButtonVariant::Secondary),
+                    )
+                    .clicked()
+                {
+                    decision = Some(PermissionDecision::AlwaysAllow);
+                }
+                if ui
+                    .add(
+                        StyledButton::new("Deny")
+                            .id("permission_deny")
+                            .variant(ButtonVariant::Ghost),
+                    )
+                    .clicked()
+                {
+                    decision = Some(PermissionDecision::Deny);
+                }
+            });
+        });
+    });
+
+    if let Some(decision) = decision {
+        session_state.respond_permission(mutations, request.id.clone(), decision);
+    }
+}
+
+fn show_session_status(ui: &mut egui::Ui, status: &SessionStatusModel) {
+    match SessionStatusKind::try_from(status.kind) {
+        Ok(SessionStatusKind::Busy) => {
+            ui.label(RichText::new("Working...").color(BG_500));
+        }
+        Ok(SessionStatusKind::Retry) => {
+            let wait_ms = status.next - chrono::Utc::now().timestamp_millis();
+            let text = if wait_ms > 0 {
+                format!(
+                    "Retrying in {}s (attempt {}): {}",
+                    (wait_ms + 999) / 1000,
+                    status.attempt,
+                    status.message
+                )
+            } else {
+                format!("Retrying (attempt {}): {}", status.attempt, status.message)
+            };
+            ui.label(RichText::new(text).color(AMBER_500));
+            // Keep the countdown ticking without other input
+            ui.ctx().request_repaint_after(Duration::from_secs(1));
+        }
+        _ => {}
+    }
+    if let Some(error) = &status.error {
+        ui.label(RichText::new(error).color(Color32::RED));
+    }
 }
 
 /// A tab viewer is responsible for all session tabs within a project
 pub struct TabViewer<'sessions> {
     sessions_by_id: &'sessions HashMap<Uuid, &'sessions SessionModel>,
     sessions_states: &'sessions mut SessionTabStateMap,
+    query: &'sessions mut QueryClient,
+    mutations: &'sessions MutationsClient,
 }
 
 impl<'sessions> TabViewer<'sessions> {
     pub fn new(
         sessions_by_id: &'sessions HashMap<Uuid, &'sessions SessionModel>,
         sessions_states: &'sessions mut SessionTabStateMap,
+        query: &'sessions mut QueryClient,
+        mutations: &'sessions MutationsClient,
     ) -> Self {
         Self {
             sessions_by_id,
             sessions_states,
+            query,
+            mutations,
         }
     }
 }
 
 impl<'sessions> egui_dock::TabViewer for TabViewer<'sessions> {
-    type Tab = Uuid;
+    type Tab = ProjectTab;
 
     fn id(&mut self, tab: &mut Self::Tab) -> egui::Id {
         Id::new(*tab)
     }
 
     fn title(&mut self, tab: &mut Self::Tab) -> egui::WidgetText {
-        self.sessions_by_id
-            .get(tab)
+        let (ProjectTab::Session(session_id) | ProjectTab::Diff(session_id)) = *tab;
+        let title = self
+            .sessions_by_id
+            .get(&session_id)
             .map(|session| {
-                if session.name.trim().is_empty() {
-                    "New Session".to_string()
+                let name = if session.name.trim().is_empty() {
+                    "New Session"
+                } else {
+                    session.name.as_str()
+                };
+                // Forks are named after their parent, the icon sets them apart from it
+                if session.parent_session_id.is_some() {
+                    format!("{} {name}", regular::GIT_FORK)
                 } else {
-                    session.name.clone()
+                    name.to_string()
                 }
             })
-            .unwrap_or_else(|| tab.to_string())
-            .into()
+            .unwrap_or_else(|| session_id.to_string());
+        match tab {
+            ProjectTab::Session(_) => title.into(),
+            ProjectTab::Diff(_) => format!("{} {title}", regular::GIT_DIFF).into(),
+        }
     }
 
     fn ui(&mut self, ui: &mut egui::Ui, tab: &mut Self::Tab) {
-        let session_id = *tab;
+        match *tab {
+            ProjectTab::Session(session_id) => self.session_ui(ui, session_id),
+            ProjectTab::Diff(session_id) => {
+                let state = self.sessions_states.entry(session_id).or_default();
+                show_diff_tab(ui, self.query, session_id, &mut state.diff);
+            }
+        }
+    }
+
+    fn on_close(&mut self, tab: &mut Self::Tab) -> OnCloseResponse {
+        println!("Closed tab: {tab:?}");
+        OnCloseResponse::Close
+    }
+}
+
+impl TabViewer<'_> {
+    fn session_ui(&mut self, ui: &mut egui::Ui, session_id: Uuid) {
+        let mutations = self.mutations;
+        // The fork opens as a new tab once the sessions list has it
+        let forked = self
+            .sessions_states
+            .entry(session_id)
+            .or_default()
+            .poll_fork_result();
+        if let Some((session, prompt)) = forked {
+            let fork_state = self.sessions_states.entry(session.id).or_default();
+            fork_state.prompt_input = prompt.unwrap_or_default();
+            self.query.insert_session(session);
+        }
         let session_state = self.sessions_states.entry(session_id).or_default();
+        session_state.poll_send_result(self.query, session_id);
+        session_state.poll_revert_result(self.query, session_id);
+        session_state.poll_permission_result();
+        session_state.poll_cancel_result();
+        session_state.poll_session_revert_result();
+        session_state.poll_attachments(ui);
+        // Files dropped anywhere on the tab go with the next message
+        if ui.rect_contains_pointer(ui.max_rect()) {
+            let dropped = ui.ctx().input(|input| {
+                input
+                    .raw
+                    .dropped_files
+                    .iter()
+                    .filter_map(|file| file.path.clone())
+                    .collect::<Vec<_>>()
+            });
+            if !dropped.is_empty() {
+                session_state.attach(move || dropped);
+            }
+        }
+        let is_running = self
+            .query
+            .session_status(session_id)
+            .and_then(|status| SessionStatusKind::try_from(status.kind).ok())
+            .is_some_and(|kind| matches!(kind, SessionStatusKind::Busy | SessionStatusKind::Retry));
+
+        let session = self.sessions_by_id.get(&session_id).copied();
+        if !session_state.has_defaults
+            && let Some(session) = session
+        {
+            session_state.agent = session.default_agent.clone();
+            session_state.thinking_variant = session.default_thinking_variant.clone();
+            session_state.has_defaults = true;
+        }
 
-        TopBottomPanel::bottom(Id::new(("bottom_panel", *tab)))
+        let harness_type = session
+            .map(|session| session.harness_type.clone())
+            .unwrap_or_default();
+        let models = self.query.use_models(ui, &harness_type);
+        // The session's last model is preselected once, the harness default when it has none
+        // or the harness stopped offering it. Later picks stay with the tab
+        if let QueryState::Data(catalog) = &models
+            && !session_state.model_selector.has_models()
+        {
+            let session_model = session.and_then(|session| {
+                catalog.index_of(
+                    session.default_model_provider_id.as_deref()?,
+                    session.default_model_id.as_deref()?,
+                )
+            });
+            session_state.model_selector.set_models(
+                catalog.models.clone(),
+                session_model.or(catalog.default_index),
+            );
+        }
+        let is_loading_models = matches!(models, QueryState::Loading);
+        let agents = match self.query.use_agents(ui, &harness_type) {
+            QueryState::Data(agents) => agents,
+            _ => Arc::default(),
+        };
+        // Subagents first, then the project files matching the mention being typed
+        let mention = mention_query(&session_state.prompt_input).map(str::to_string);
+        let mut mention_options = Vec::new();
+        if let Some(query) = &mention {
+            mention_options.extend(
+                agents
+                    .iter()
+                    .filter(|agent| agent.subagent && agent.name.starts_with(query.as_str()))
+                    .map(|agent| MentionOption::Agent {
+                        name: agent.name.clone(),
+                        description: agent.description.clone(),
+                    }),
+            );
+            if let Some(session) = session
+                && let QueryState::Data(files) =
+                    self.query.use_file_search(ui, session.project_id, query)
+            {
+                mention_options.extend(files.iter().map(|file| MentionOption::File {
+                    path: file.path.clone(),
+                    absolute_path: file.absolute_path.clone(),
+                }));
+            }
+        }
+
+        // Oldest request first, the next one shows up once it is answered
+        if let QueryState::Data(requests) = self
+            .query
+            .use_permission_requests_by_session(ui, session_id)
+            && let Some(request) = requests.first()
+        {
+            show_permission_modal(ui, session_id, session_state, mutations, request);
+        }
+
+        TopBottomPanel::bottom(Id::new(("bottom_panel", session_id)))
             .show_separator_line(false)
             .default_height(120.0)
             .show_inside(ui, |ui| {
@@ -73,13 +678,33 @@ impl<'sessions> egui_dock::TabViewer for TabViewer<'sessions> {
                             .w_full()
                             .gap(vec2(0.0, 16.0))
                             .show(ui, |flex| {
-                                flex.add(
+                                show_attachments(flex, &mut session_state.attachments);
+                                let mut mention_selector = MentionSelector::new(
+                                    &mut session_state.mention_selector,
+                                    mention.as_deref().unwrap_or_default(),
+                                    &mention_options,
+                                );
+                                // Keys go to the popup first so Enter picks instead of breaking the line
+                                let picked_by_key = mention_selector.handle_keys(flex.ui());
+                                let prompt_input = flex.add(
                                     item().align_self_content(Align2::LEFT_TOP),
                                     TextEdit::multiline(&mut session_state.prompt_input)
-                                        .hint_text("Type anything")
+                                        .id(Id::new(("prompt_input", session_id)))
+                                        .hint_text(
+                                            "Type anything, @ mentions an agent or file, /subtask hands off a task",
+                                        )
                                         .frame(false)
                                         .desired_rows(2),
                                 );
+                                let picked_by_click = mention_selector.show(&prompt_input);
+                                if let Some(option) = picked_by_key.or(picked_by_click).cloned() {
+                                    apply_mention(
+                                        flex.ui(),
+                                        &prompt_input,
+                                        session_state,
+                                        option,
+                                    );
+                                }
                                 flex.add_flex(
                                     item(),
                                     Flex::horizontal()
@@ -87,45 +712,163 @@ impl<'sessions> egui_dock::TabViewer for TabViewer<'sessions> {
                                         .justify(egui_flex::FlexJustify::SpaceBetween)
                                         .align_items(egui_flex::FlexAlign::Center),
                                     |flex| {
-                                        let btn = flex.add(
+                                        let model_label = session_state
+                                            .model_selector
+                                            .selected_model()
+                                            .map(|model| model.label.clone())
+                                            .unwrap_or_else(|| {
+                                                if is_loading_models {
+                                                    "Loading models...".to_string()
+                                                } else {
+                                                    "Default model".to_string()
+                                                }
+                                            });
+                                        let model_btn = flex.add(
                                             item(),
-                                            StyledButton::new("Send").id("send_button"),
+                                            StyledButton::new(&model_label)
+                                                .id("model_selector_button")
+                                                .size(ButtonSize::Sm)
+                                                .variant(ButtonVariant::Ghost)
+                                                .icon(regular::CUBE),
                                         );
-                                        if btn.clicked() {
-                                            let prompt = session_state.prompt_input.trim().to_string();
-                                            if prompt.is_empty() {
-                                                session_state.send_msg_error =
-                                                    Some("Message cannot be empty".to_string());
-                                            } else {
-                                                log::info!(
-                                                    "send clicked for session {session_id} ({} chars)",
-                                                    prompt.len()
-                                                );
-                                                session_state.send_msg_error = Some(
-                                                    "Message sending is temporarily disabled"
-                                                        .to_string(),
-                                                );
-                                                session_state.prompt_input.clear();
-                                            }
+                                        ModelSelector::new(&mut session_state.model_selector)
+                                            .show(&model_btn);
+
+                                        let agent_label = session_state
+                                            .agent
+                                            .clone()
+                                            .unwrap_or_else(|| DEFAULT_AGENT.to_string());
+                                        let agent_btn = flex.add(
+                                            item(),
+                                            StyledButton::new(&agent_label)
+                                                .id("agent_selector_button")
+                                                .size(ButtonSize::Sm)
+                                                .variant(ButtonVariant::Ghost)
+                                                .icon(regular::ROBOT),
+                                        );
+                                        AgentSelector::new(&agents, &mut session_state.agent)
+                                            .show(&agent_btn);
+                                        let attach_btn = flex.add(
+                                            item(),
+                                            StyledButton::new("Attach")
+                                                .id("attach_button")
+                                                .size(ButtonSize::Sm)
+                                                .variant(ButtonVariant::Ghost)
+                                                .icon(regular::PAPERCLIP),
+                                        );
+                                        if attach_btn.clicked() {
+                                            session_state.attach(|| {
+                                                rfd::FileDialog::new()
+                                                    .pick_files()
+                                                    .unwrap_or_default()
+                                            });
                                         }
 
-                                        if let Some(err) = &session_state.send_msg_error {
+                                        if let Some(err) = session_state
+                                            .send_msg_error
+                                            .as_ref()
+                                            .or(session_state.cancel_error.as_ref())
+                                            .or(session_state.attachment_error.as_ref())
+                                        {
                                             flex.add(
                                                 item(),
                                                 egui::Label::new(
-                                                    egui::RichText::new(err).color(Color32::RED),
+                                                    RichText::new(err).color(Color32::RED),
                                                 ),
                                             );
                                         }
+
+                                        let is_sending = session_state.is_sending();
+                                        let btn = flex.add(
+                                            item(),
+                                            StyledButton::new(if is_sending {
+                                                "Sending..."
+                                            } else {
+                                                "Send"
+                                            })
+                                            .id("send_button"),
+                                        );
+                                        if is_running {
+                                            let is_cancelling =
+                                                session_state.cancel_promise.is_some();
+                                            let stop_btn = flex.add(
+                                                item(),
+                                                StyledButton::new(if is_cancelling {
+                                                    "Stopping..."
+                                                } else {
+                                                    "Stop"
+                                                })
+                                                .id("stop_button")
+                                                .variant(ButtonVariant::Secondary)
+                                                .icon(regular::STOP),
+                                            );
+                                            if stop_btn.clicked() {
+                                                session_state.cancel(mutations, session_id);
+                                            }
+                                        }
+                                        // Waits for the catalog so the default model is not skipped
+                                        if btn.clicked() && !is_loading_models {
+                                            session_state.send(mutations, session_id, &agents);
+                                        }
                                     },
                                 );
                             })
                     });
             });
-    }
 
-    fn on_close(&mut self, tab: &mut Self::Tab) -> OnCloseResponse {
-        println!("Closed tab: {tab}");
-        OnCloseResponse::Close
+        CentralPanel::default()
+            .frame(Frame::new())
+            .show_inside(ui, |ui| {
+                if let Some(error) = session_state
+                    .revert_error
+                    .as_ref()
+                    .or(session_state.fork_error.as_ref())
+                    .or(session_state.session_revert_error.as_ref())
+                {
+                    ui.label(RichText::new(error).color(Color32::RED));
+                }
+                let messages = self.query.use_messages_by_session(ui, session_id);
+                if let Some(status) = self.query.session_status(session_id) {
+                    show_session_status(ui, status);
+                }
+                // The summary event is newer than the sessions list once the session changed
+                let counts = match self.query.session_diff_summary(session_id) {
+                    Some(summary) => Some((summary.additions, summary.deletions, summary.files)),
+                    None => session.and_then(|session| {
+                        Some((
+                            session.summary_additions?,
+                            session.summary_deletions?,
+                            session.summary_files?,
+                        ))
+                    }),
+                };
+                if let Some((additions, deletions, files)) = counts.filter(|counts| counts.2 > 0)
+                    && StyledButton::new(&format!(
+                        "{files} {} changed (+{additions} -{deletions})",
+                        if files == 1 { "file" } else { "files" }
+                    ))
+                    .size(ButtonSize::Sm)
+                    .variant(ButtonVariant::Ghost)
+                    .icon(regular::GIT_DIFF)
+                    .show(ui)
+                    .on_hover_text("Review the changes")
+                    .clicked()
+                {
+                    session_state.open_diff = true;
+                }
+                match messages {
+                    QueryState::Loading => {
+                        ui.label(RichText::new("Loading messages...").color(BG_500));
+                    }
+                    QueryState::Error(error) => {
+                        ui.label(RichText::new(error).color(Color32::RED));
+                    }
+                    QueryState::Data(messages) => {
+                        if let Some(action) = show_transcript(ui, &messages) {
+                            session_state.handle_transcript_action(mutations, session_id, action);
+                        }
+                    }
+                }
+            });
     }
 }
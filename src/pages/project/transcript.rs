@@ -0,0 +1,420 @@
+use crate::backend::attachment;
+use crate::backend::proto_message::{
+    AssistantMessageModel, AssistantMessagePartModel, MessageHistory, UserMessageModel,
+    UserMessagePartModel, message_history,
+};
+use crate::components::button::{ButtonSize, ButtonVariant, StyledButton};
+use crate::theme::{BG_500, BG_700, BG_800, RADIUS_MD, STROKE_WIDTH};
+use egui::{
+    CollapsingHeader, Color32, Frame, Id, Image, Label, RichText, ScrollArea, Stroke, Ui, vec2,
+};
+use egui_phosphor::regular;
+
+/// Longest side of an attached image in the transcript
+const THUMBNAIL_SIZE: f32 = 160.0;
+
+/// Something the user asked for from inside the transcript
+pub enum TranscriptAction {
+    RevertFileWrite {
+        part_id: String,
+    },
+    /// `prompt` is the text of a forked user message, which the fork leaves out
+    Fork {
+        message_id: String,
+        prompt: Option<String>,
+    },
+    /// Undoes the session from the message on, or from the part on when there is one
+    Revert {
+        message_id: String,
+        part_id: Option<String>,
+    },
+    Unrevert,
+}
+
+/// Renders the conversation of a session, following new output as it streams in
+pub fn show_transcript(ui: &mut Ui, messages: &[MessageHistory]) -> Option<TranscriptAction> {
+    let mut action = None;
+    ScrollArea::vertical()
+        .auto_shrink([false, false])
+        .stick_to_bottom(true)
+        .show(ui, |ui| {
+            ui.add_space(8.0);
+            ui.spacing_mut().item_spacing = vec2(8.0, 8.0);
+            let mut shown_revert_banner = false;
+            for history in messages {
+                let reverted = match &history.message {
+                    Some(message_history::Message::UserMessage(message)) => message.reverted,
+                    Some(message_history::Message::AssistantMessage(message)) => message.reverted,
+                    None => false,
+                };
+                if reverted && !shown_revert_banner {
+                    shown_revert_banner = true;
+                    action = show_revert_banner(ui).or(action.take());
+                }
+                // Reverted messages stay readable until the next send drops them, but offer nothing
+                ui.scope(|ui| {
+                    if reverted {
+                        ui.multiply_opacity(0.4);
+                    }
+                    let message_action = match &history.message {
+                        Some(message_history::Message::UserMessage(message)) => {
+                            show_user_message(ui, message)
+                        }
+                        Some(message_history::Message::AssistantMessage(message)) => {
+                            show_assistant_message(ui, message)
+                        }
+                        None => None,
+                    };
+                    if !reverted {
+                        action = message_action.or(action.take());
+                    }
+                });
+            }
+        });
+    action
+}
+
+fn show_user_message(ui: &mut Ui, message: &UserMessageModel) -> Option<TranscriptAction> {
+    // Agent parts point into the text, a subtask is shown the way it was typed
+    let text = message
+        .parts
+        .iter()
+        .filter_map(|part| match part.part_type.as_str() {
+            "subtask" => Some(format!(
+                "/subtask @{} {}",
+                part.agent_name.as_deref().unwrap_or_default(),
+                part.subtask_prompt.as_deref().unwrap_or_default()
+            )),
+            _ => part.text.clone(),
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Frame::new()
+        .inner_margin(8.0)
+        .outer_margin(vec2(8.0, 0.0))
+        .corner_radius(RADIUS_MD)
+        .fill(BG_800)
+        .stroke(Stroke::new(STROKE_WIDTH, BG_700))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            if !text.is_empty() {
+                ui.add(Label::new(&text).wrap());
+            }
+            let files = message
+                .parts
+                .iter()
+                .filter(|part| part.part_type == "file")
+                .collect::<Vec<_>>();
+            if !files.is_empty() {
+                ui.horizontal_wrapped(|ui| {
+                    for part in files {
+                        show_attachment(ui, part);
+                    }
+                });
+            }
+            ui.horizontal(|ui| {
+                let forked = fork_button(ui, &message.id, "Fork before this message").then(|| {
+                    TranscriptAction::Fork {
+                        message_id: message.id.clone(),
+                        prompt: Some(text.clone()),
+                    }
+                });
+                let reverted = revert_button(
+                    ui,
+                    &message.id,
+                    "Revert",
+                    "Undo this message and everything after it, files included",
+                )
+                .then(|| TranscriptAction::Revert {
+                    message_id: message.id.clone(),
+                    part_id: None,
+                });
+                forked.or(reverted)
+            })
+            .inner
+        })
+        .inner
+}
+
+/// Marks where the reverted part of the session starts
+fn show_revert_banner(ui: &mut Ui) -> Option<TranscriptAction> {
+    Frame::new()
+        .inner_margin(8.0)
+        .outer_margin(vec2(8.0, 0.0))
+        .corner_radius(RADIUS_MD)
+        .stroke(Stroke::new(STROKE_WIDTH, BG_700))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            ui.horizontal(|ui| {
+                ui.label(
+                    RichText::new(format!(
+                        "{} Reverted from here, sending a message discards the rest",
+                        regular::ARROW_COUNTER_CLOCKWISE
+                    ))
+                    .color(BG_500),
+                );
+                StyledButton::new("Unrevert")
+                    .size(ButtonSize::Sm)
+                    .variant(ButtonVariant::Secondary)
+                    .icon(regular::ARROW_CLOCKWISE)
+                    .show(ui)
+                    .on_hover_text("Bring back the reverted messages and their file changes")
+                    .clicked()
+                    .then_some(TranscriptAction::Unrevert)
+            })
+            .inner
+        })
+        .inner
+}
+
+/// Small button under a message that starts a new session from that point
+fn fork_button(ui: &mut Ui, message_id: &str, hover_text: &str) -> bool {
+    ui.push_id(("fork_message", message_id), |ui| {
+        StyledButton::new("Fork from here")
+            .size(ButtonSize::Sm)
+            .variant(ButtonVariant::Ghost)
+            .icon(regular::GIT_FORK)
+            .show(ui)
+            .on_hover_text(hover_text)
+            .clicked()
+    })
+    .inner
+}
+
+/// Small button that rewinds the session, files included, to a point in the transcript
+fn revert_button(ui: &mut Ui, id: &str, label: &str, hover_text: &str) -> bool {
+    ui.push_id(("revert_to", id), |ui| {
+        StyledButton::new(label)
+            .size(ButtonSize::Sm)
+            .variant(ButtonVariant::Ghost)
+            .icon(regular::ARROW_COUNTER_CLOCKWISE)
+            .show(ui)
+            .on_hover_text(hover_text)
+            .clicked()
+    })
+    .inner
+}
+
+/// Images show as thumbnails, other files by name
+fn show_attachment(ui: &mut Ui, part: &UserMessagePartModel) {
+    let name = part.file_name.as_deref().unwrap_or("attachment");
+    let is_image = part
+        .file_mime
+        .as_deref()
+        .is_some_and(|mime| mime.starts_with("image/"));
+    if is_image && let Some(url) = part.file_url.as_deref() {
+        // Read once, egui keeps the bytes under the part's uri from then on
+        let uri = format!("bytes://attachment/{}", part.id);
+        let loaded = Id::new(&uri);
+        if !ui.data(|data| data.get_temp::<bool>(loaded).unwrap_or(false)) {
+            if let Some(bytes) = attachment::read_url(url) {
+                ui.ctx().include_bytes(uri.clone(), bytes);
+            }
+            ui.data_mut(|data| data.insert_temp(loaded, true));
+        }
+        ui.add(
+            Image::new(uri)
+                .max_size(vec2(THUMBNAIL_SIZE, THUMBNAIL_SIZE))
+                .corner_radius(RADIUS_MD),
+        )
+        .on_hover_text(name);
+        return;
+    }
+
+    ui.label(RichText::new(format!("{} {name}", regular::FILE)).color(BG_500));
+}
+
+fn show_assistant_message(
+    ui: &mut Ui,
+    message: &AssistantMessageModel,
+) -> Option<TranscriptAction> {
+    Frame::new()
+        .inner_margin(vec2(16.0, 0.0))
+        .show(ui, |ui| {
+            ui.set_width(ui.available_width());
+            let mut action = None;
+            for (index, part) in message.parts.iter().enumerate() {
+                action = show_assistant_part(ui, part).or(action.take());
+                // A finished step can be returned to by reverting what came after it
+                if part.part_type == "step-finish"
+                    && let Some(next) = message.parts.get(index + 1)
+                    && revert_button(
+                        ui,
+                        &part.id,
+                        "Restore this step",
+                        "Undo what the agent did after this step, files included",
+                    )
+                {
+                    action = Some(TranscriptAction::Revert {
+                        message_id: message.id.clone(),
+                        part_id: Some(next.id.clone()),
+                    });
+                }
+            }
+            if let Some(error) = &message.error_message {
+                ui.add(Label::new(RichText::new(error).color(Color32::RED)).wrap());
+            }
+            // A message still streaming has nothing settled to fork from yet
+            if message.completed_at.is_some()
+                && fork_button(ui, &message.id, "Fork after this message")
+            {
+                action = Some(TranscriptAction::Fork {
+                    message_id: message.id.clone(),
+                    prompt: None,
+                });
+            }
+            action
+        })
+        .inner
+}
+
+fn show_assistant_part(ui: &mut Ui, part: &AssistantMessagePartModel) -> Option<TranscriptAction> {
+    match part.part_type.as_str() {
+        "file-write" => return show_file_write_part(ui, part),
+        "text" => {
+            if part.text_ignored == Some(true) {
+                return None;
+            }
+            if let Some(text) = &part.text {
+                ui.add(Label::new(text).wrap());
+            }
+        }
+        "reasoning" => {
+            if let Some(text) = part.text.as_deref().filter(|text| !text.trim().is_empty()) {
+                ui.add(Label::new(RichText::new(text).italics().color(BG_500)).wrap());
+            }
+        }
+        "tool" => show_tool_part(ui, part),
+        "plan" => {
+            if let Some(text) = &part.text {
+                ui.add(Label::new(RichText::new(text).monospace().color(BG_500)).wrap());
+            }
+        }
+        "step-finish" => {
+            let mut summary = part
+                .finish_reason
+                .clone()
+                .unwrap_or_else(|| "step finished".to_string());
+            if let Some(tokens) = part.token_output {
+                summary.push_str(&format!(" · {tokens} output tokens"));
+            }
+            if let Some(cost) = part.cost.filter(|cost| *cost > 0.0) {
+                summary.push_str(&format!(" · ${cost:.4}"));
+            }
+            ui.label(RichText::new(summary).small().color(BG_500));
+        }
+        _ => {}
+    }
+    None
+}
+
+fn show_file_write_part(ui: &mut Ui, part: &AssistantMessagePartModel) -> Option<TranscriptAction> {
+    let path = part.file_source_path.as_deref().unwrap_or("file");
+    let metadata = part
+        .part_metadata_json
+        .as_deref()
+        .and_then(|metadata| serde_json::from_str::<serde_json::Value>(metadata).ok())
+        .unwrap_or_default();
+    let before = metadata["before"].as_str();
+    let after = metadata["after"].as_str().unwrap_or_default();
+    let reverted = metadata["reverted"].as_bool().unwrap_or(false);
+
+    let (removed, added) = changed_lines(before.unwrap_or_default(), after);
+    let mut header = format!(
+        "{} {} {path} (+{} -{})",
+        regular::PENCIL_SIMPLE,
+        if before.is_some() {
+            "edited"
+        } else {
+            "created"
+        },
+        added.len(),
+        removed.len()
+    );
+    if reverted {
+        header.push_str(" · reverted");
+    }
+
+    let mut action = None;
+    CollapsingHeader::new(RichText::new(header).color(BG_500))
+        .id_salt(("file_write_part", &part.id))
+        .show(ui, |ui| {
+            for line in &removed {
+                ui.add(
+                    Label::new(
+                        RichText::new(format!("- {line}"))
+                            .monospace()
+                            .color(Color32::LIGHT_RED),
+                    )
+                    .wrap(),
+                );
+            }
+            for line in &added {
+                ui.add(
+                    Label::new(
+                        RichText::new(format!("+ {line}"))
+                            .monospace()
+                            .color(Color32::LIGHT_GREEN),
+                    )
+                    .wrap(),
+                );
+            }
+            if !reverted
+                && StyledButton::new("Revert")
+                    .size(ButtonSize::Sm)
+                    .variant(ButtonVariant::Ghost)
+                    .icon(regular::ARROW_COUNTER_CLOCKWISE)
+                    .show(ui)
+                    .clicked()
+            {
+                action = Some(TranscriptAction::RevertFileWrite {
+                    part_id: part.id.clone(),
+                });
+            }
+        });
+    action
+}
+
+/// Lines between the common prefix and suffix of both versions, which is enough to show
+/// what a single write changed without a full diff
+fn changed_lines<'a>(before: &'a str, after: &'a str) -> (Vec<&'a str>, Vec<&'a str>) {
+    let before: Vec<&str> = before.lines().collect();
+    let after: Vec<&str> = after.lines().collect();
+    let prefix = before
+        .iter()
+        .zip(&after)
+        .take_while(|(old, new)| old == new)
+        .count();
+    let suffix = before[prefix..]
+        .iter()
+        .rev()
+        .zip(after[prefix..].iter().rev())
+        .take_while(|(old, new)| old == new)
+        .count();
+    (
+        before[prefix..before.len() - suffix].to_vec(),
+        after[prefix..after.len() - suffix].to_vec(),
+    )
+}
+
+fn show_tool_part(ui: &mut Ui, part: &AssistantMessagePartModel) {
+    let name = part.tool_name.as_deref().unwrap_or("tool");
+    let status = part.tool_status.as_deref().unwrap_or("pending");
+    let mut header = format!("{} {name}", regular::WRENCH);
+    if let Some(title) = part.tool_title.as_deref().filter(|title| !title.is_empty()) {
+        header.push_str(&format!(" · {title}"));
+    }
+    header.push_str(&format!(" ({status})"));
+
+    CollapsingHeader::new(RichText::new(header).color(BG_500))
+        .id_salt(("tool_part", &part.id))
+        .show(ui, |ui| {
+            if let Some(output) = &part.tool_output_text {
+                ui.add(Label::new(RichText::new(output).monospace()).wrap());
+            }
+            if let Some(error) = &part.tool_error_text {
+                ui.add(Label::new(RichText::new(error).color(Color32::RED)).wrap());
+            }
+        });
+}
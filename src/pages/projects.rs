@@ -280,6 +280,10 @@ impl ProjectsPage {
             id: project_id,
             name: self.form_fields.name.clone(),
             dir: self.form_fields.dir.clone(),
+            default_agent: None,
+            default_model_provider_id: None,
+            default_model_id: None,
+            default_thinking_variant: None,
             created_at: now,
             updated_at: now,
         };
@@ -296,6 +300,10 @@ impl ProjectsPage {
             summary_additions: None,
             summary_deletions: None,
             summary_files: None,
+            default_agent: None,
+            default_model_provider_id: None,
+            default_model_id: None,
+            default_thinking_variant: None,
             created_at: now,
             updated_at: now,
         };
@@ -0,0 +1,88 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use egui::Ui;
+use egui_inbox::UiInbox;
+use tonic::{Request, transport::Channel};
+
+use crate::backend::{HarnessesClient, ListAgentsRequest, proto_harness::AgentInfo};
+
+use super::QueryState;
+
+/// Pause before asking again after a failure, e.g. while the harness is still starting
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+pub type AgentsState = QueryState<Arc<Vec<AgentInfo>>>;
+
+/// Agents by harness type, fetched once and kept for the whole run
+pub struct Agents {
+    backend_channel: Channel,
+    state_by_harness: HashMap<String, AgentsState>,
+    is_fetching: HashSet<String>,
+    inbox: UiInbox<(String, AgentsState)>,
+}
+
+impl Agents {
+    pub fn new(backend_channel: Channel) -> Self {
+        Self {
+            backend_channel,
+            state_by_harness: HashMap::new(),
+            is_fetching: HashSet::new(),
+            inbox: UiInbox::new(),
+        }
+    }
+
+    pub fn subscribe_state(&mut self, ui: &Ui, harness_type: &str) -> AgentsState {
+        for (updated_harness_type, updated_state) in self.inbox.read(ui) {
+            self.is_fetching.remove(&updated_harness_type);
+            self.state_by_harness
+                .insert(updated_harness_type, updated_state);
+        }
+
+        self.fetch_if_needed(harness_type);
+
+        self.state_by_harness
+            .get(harness_type)
+            .cloned()
+            .unwrap_or(QueryState::Loading)
+    }
+
+    fn fetch_if_needed(&mut self, harness_type: &str) {
+        if self.is_fetching.contains(harness_type) {
+            return;
+        }
+
+        if matches!(
+            self.state_by_harness.get(harness_type),
+            Some(QueryState::Data(_))
+        ) {
+            return;
+        }
+
+        self.is_fetching.insert(harness_type.to_string());
+
+        let sender = self.inbox.sender().clone();
+        let channel = self.backend_channel.clone();
+        let harness_type = harness_type.to_string();
+
+        tokio::spawn(async move {
+            let response = HarnessesClient::new(channel)
+                .list_agents(Request::new(ListAgentsRequest {
+                    harness_type: harness_type.clone(),
+                }))
+                .await;
+
+            let state = match response {
+                Ok(resp) => QueryState::Data(Arc::new(resp.into_inner().agents)),
+                Err(e) => {
+                    // The last state stays up meanwhile, the next read asks again
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    QueryState::Error(e.message().to_string())
+                }
+            };
+
+            let _ = sender.send((harness_type, state));
+        });
+    }
+}
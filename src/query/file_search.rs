@@ -0,0 +1,101 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use egui::Ui;
+use egui_inbox::UiInbox;
+use tonic::{Request, transport::Channel};
+use uuid::Uuid;
+
+use crate::backend::{FileMatch, ProjectClient, SearchFilesRequest};
+
+use super::QueryState;
+
+/// Pause before asking again after a failure
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+/// How many matches the mention popup lists
+const SEARCH_LIMIT: u32 = 20;
+
+pub type FileSearchState = QueryState<Arc<Vec<FileMatch>>>;
+
+/// Project files matching the mention being typed. Only the latest query of a project is
+/// kept, replies to queries typed over meanwhile are dropped
+pub struct FileSearch {
+    backend_channel: Channel,
+    /// The state and the query it answers
+    state_by_project: HashMap<Uuid, (String, FileSearchState)>,
+    query_by_project: HashMap<Uuid, String>,
+    is_fetching: HashSet<Uuid>,
+    inbox: UiInbox<(Uuid, String, FileSearchState)>,
+}
+
+impl FileSearch {
+    pub fn new(backend_channel: Channel) -> Self {
+        Self {
+            backend_channel,
+            state_by_project: HashMap::new(),
+            query_by_project: HashMap::new(),
+            is_fetching: HashSet::new(),
+            inbox: UiInbox::new(),
+        }
+    }
+
+    /// Matches for `query`, the previous query's matches stay up while it is searched
+    pub fn subscribe_state(&mut self, ui: &Ui, project_id: Uuid, query: &str) -> FileSearchState {
+        for (updated_project_id, updated_query, updated_state) in self.inbox.read(ui) {
+            self.is_fetching.remove(&updated_project_id);
+            if self.query_by_project.get(&updated_project_id) == Some(&updated_query) {
+                self.state_by_project
+                    .insert(updated_project_id, (updated_query, updated_state));
+            }
+        }
+
+        self.query_by_project.insert(project_id, query.to_string());
+        self.fetch_if_needed(project_id, query);
+
+        match self.state_by_project.get(&project_id) {
+            Some((_, state @ QueryState::Data(_))) => state.clone(),
+            Some((answered, state)) if answered == query => state.clone(),
+            _ => QueryState::Loading,
+        }
+    }
+
+    fn fetch_if_needed(&mut self, project_id: Uuid, query: &str) {
+        if self.is_fetching.contains(&project_id) {
+            return;
+        }
+
+        if matches!(
+            self.state_by_project.get(&project_id),
+            Some((answered, QueryState::Data(_))) if answered == query
+        ) {
+            return;
+        }
+
+        self.is_fetching.insert(project_id);
+
+        let sender = self.inbox.sender().clone();
+        let channel = self.backend_channel.clone();
+        let query = query.to_string();
+
+        tokio::spawn(async move {
+            let response = ProjectClient::new(channel)
+                .search_files(Request::new(SearchFilesRequest {
+                    project_id: project_id.to_string(),
+                    query: query.clone(),
+                    limit: SEARCH_LIMIT,
+                }))
+                .await;
+
+            let state = match response {
+                Ok(resp) => QueryState::Data(Arc::new(resp.into_inner().files)),
+                Err(e) => {
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    QueryState::Error(e.message().to_string())
+                }
+            };
+
+            let _ = sender.send((project_id, query, state));
+        });
+    }
+}
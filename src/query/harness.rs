@@ -0,0 +1,70 @@
+use std::sync::Arc;
+
+use egui::Ui;
+use egui_inbox::UiInbox;
+use futures::StreamExt;
+use tonic::{Request, transport::Channel};
+
+use crate::backend::{
+    HarnessesClient, SubscribeHarnessHealthRequest, proto_harness::HarnessHealthModel,
+};
+
+use super::QueryState;
+
+pub type HarnessHealthState = QueryState<Arc<Vec<HarnessHealthModel>>>;
+
+pub struct HarnessHealth {
+    backend_channel: Channel,
+    state: HarnessHealthState,
+    inbox: UiInbox<HarnessHealthState>,
+}
+
+impl HarnessHealth {
+    pub fn new(backend_channel: Channel) -> Self {
+        Self {
+            backend_channel,
+            state: QueryState::Loading,
+            inbox: UiInbox::new(),
+        }
+    }
+
+    pub fn listen_updates(&self) {
+        let sender = self.inbox.sender().clone();
+        let channel = self.backend_channel.clone();
+
+        tokio::spawn(async move {
+            let mut stream = match HarnessesClient::new(channel)
+                .subscribe_harness_health(Request::new(SubscribeHarnessHealthRequest {}))
+                .await
+            {
+                Ok(resp) => resp.into_inner(),
+                Err(e) => {
+                    let _ = sender.send(QueryState::Error(e.to_string()));
+                    return;
+                }
+            };
+
+            while let Some(next) = stream.next().await {
+                match next {
+                    Ok(reply) => {
+                        let _ = sender.send(QueryState::Data(Arc::new(reply.harnesses)));
+                    }
+                    Err(e) => {
+                        let _ = sender.send(QueryState::Error(e.to_string()));
+                    }
+                };
+            }
+
+            let _ = sender.send(QueryState::Error(
+                "harness health stream closed unexpectedly".to_string(),
+            ));
+        });
+    }
+
+    pub fn subscribe_state(&mut self, ui: &Ui) -> HarnessHealthState {
+        if let Some(update) = self.inbox.read(ui).last() {
+            self.state = update;
+        }
+        self.state.clone()
+    }
+}
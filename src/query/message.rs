@@ -0,0 +1,286 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use egui::Ui;
+use egui_inbox::UiInbox;
+use futures::StreamExt;
+use tonic::{Code, Request, Status, transport::Channel};
+use uuid::Uuid;
+
+use crate::backend::{
+    MAX_MESSAGES_BYTES, MessagesClient, SubscribeMessagesBySessionRequest,
+    proto_message::{
+        AssistantMessageModel, AssistantMessagePartModel, MessageEvent, MessageHistory, PartDelta,
+        SessionDiffSummary, SessionStatusModel, message_event::Event, message_history,
+    },
+};
+
+use super::QueryState;
+
+/// Pause before resubscribing after the backend or the connection went away
+const RESUBSCRIBE_DELAY: Duration = Duration::from_secs(1);
+
+pub type MessagesState = QueryState<Arc<Vec<MessageHistory>>>;
+
+pub struct Messages {
+    backend_channel: Channel,
+    state_by_session: HashMap<Uuid, MessagesState>,
+    status_by_session: HashMap<Uuid, SessionStatusModel>,
+    diff_summary_by_session: HashMap<Uuid, SessionDiffSummary>,
+    session_subscriptions: HashSet<Uuid>,
+    inbox: UiInbox<(Uuid, Result<Vec<MessageEvent>, String>)>,
+}
+
+impl Messages {
+    pub fn new(backend_channel: Channel) -> Self {
+        Self {
+            backend_channel,
+            state_by_session: HashMap::new(),
+            status_by_session: HashMap::new(),
+            diff_summary_by_session: HashMap::new(),
+            session_subscriptions: HashSet::new(),
+            inbox: UiInbox::new(),
+        }
+    }
+
+    pub fn subscribe_state(&mut self, ui: &Ui, session_id: Uuid) -> MessagesState {
+        for (updated_session_id, update) in self.inbox.read(ui) {
+            match update {
+                Ok(events) => self.apply(updated_session_id, events),
+                Err(e) => {
+                    self.state_by_session
+                        .insert(updated_session_id, QueryState::Error(e));
+                }
+            }
+        }
+
+        self.subscribe_session_if_needed(session_id);
+
+        self.state_by_session
+            .get(&session_id)
+            .cloned()
+            .unwrap_or(QueryState::Loading)
+    }
+
+    /// Last status the harness reported for the session, as of the latest `subscribe_state`
+    pub fn session_status(&self, session_id: Uuid) -> Option<&SessionStatusModel> {
+        self.status_by_session.get(&session_id)
+    }
+
+    /// Totals of the files the session changed, once they changed since subscribing
+    pub fn session_diff_summary(&self, session_id: Uuid) -> Option<&SessionDiffSummary> {
+        self.diff_summary_by_session.get(&session_id)
+    }
+
+    /// Merges messages changed outside of the subscription, e.g. returned by a mutation
+    pub fn merge(&mut self, session_id: Uuid, changed: Vec<MessageHistory>) {
+        self.apply(
+            session_id,
+            changed.into_iter().map(message_upserted).collect(),
+        );
+    }
+
+    fn apply(&mut self, session_id: Uuid, events: Vec<MessageEvent>) {
+        let state = self
+            .state_by_session
+            .entry(session_id)
+            .or_insert(QueryState::Loading);
+        if !matches!(state, QueryState::Data(_)) {
+            *state = QueryState::Data(Arc::new(Vec::new()));
+        }
+        let QueryState::Data(messages) = state else {
+            return;
+        };
+        let messages = Arc::make_mut(messages);
+
+        for event in events.into_iter().filter_map(|event| event.event) {
+            match event {
+                Event::MessageUpserted(message) => merge_messages(messages, vec![message]),
+                Event::PartUpserted(part) => {
+                    if let Some(assistant) =
+                        find_assistant_message(messages, &part.assistant_message_id)
+                    {
+                        merge_assistant_part(&mut assistant.parts, part);
+                    }
+                }
+                Event::PartDelta(delta) => append_part_delta(messages, delta),
+                Event::MessageRemoved(removed) => messages
+                    .retain(|message| message_id(message) != Some(removed.message_id.as_str())),
+                Event::SessionStatus(status) => {
+                    self.status_by_session.insert(session_id, status);
+                }
+                Event::SessionDiff(summary) => {
+                    self.diff_summary_by_session.insert(session_id, summary);
+                }
+            }
+        }
+    }
+
+    fn subscribe_session_if_needed(&mut self, session_id: Uuid) {
+        if self.session_subscriptions.contains(&session_id) {
+            return;
+        }
+
+        self.session_subscriptions.insert(session_id);
+        self.state_by_session
+            .insert(session_id, QueryState::Loading);
+
+        let sender = self.inbox.sender().clone();
+        let channel = self.backend_channel.clone();
+
+        tokio::spawn(async move {
+            // Revision of the last reply, resubscribing from it only replays what we missed
+            let mut since_revision = None;
+            loop {
+                let result = MessagesClient::new(channel.clone())
+                    .max_decoding_message_size(MAX_MESSAGES_BYTES)
+                    .subscribe_messages_by_session(Request::new(
+                        SubscribeMessagesBySessionRequest {
+                            session_id: session_id.to_string(),
+                            since_revision,
+                        },
+                    ))
+                    .await;
+                let error = match result {
+                    Ok(resp) => {
+                        let mut stream = resp.into_inner();
+                        loop {
+                            match stream.next().await {
+                                Some(Ok(reply)) => {
+                                    since_revision = Some(reply.revision);
+                                    let events = reply
+                                        .messages
+                                        .into_iter()
+                                        .map(message_upserted)
+                                        .chain(reply.events)
+                                        .collect();
+                                    let _ = sender.send((session_id, Ok(events)));
+                                }
+                                Some(Err(e)) => break e,
+                                None => break Status::unavailable("messages stream closed"),
+                            }
+                        }
+                    }
+                    Err(e) => e,
+                };
+
+                if error.code() == Code::NotFound {
+                    let _ = sender.send((session_id, Err(error.message().to_string())));
+                    return;
+                }
+                // Keep showing what we have while reconnecting, only a first failure is an error
+                if since_revision.is_none() {
+                    let _ = sender.send((session_id, Err(error.message().to_string())));
+                }
+                log::warn!("messages subscription for {session_id} dropped: {error}");
+                tokio::time::sleep(RESUBSCRIBE_DELAY).await;
+            }
+        });
+    }
+}
+
+fn message_upserted(message: MessageHistory) -> MessageEvent {
+    MessageEvent {
+        event: Some(Event::MessageUpserted(message)),
+    }
+}
+
+fn message_id(history: &MessageHistory) -> Option<&str> {
+    match history.message.as_ref()? {
+        message_history::Message::UserMessage(user) => Some(&user.id),
+        message_history::Message::AssistantMessage(assistant) => Some(&assistant.id),
+    }
+}
+
+fn created_at(history: &MessageHistory) -> Option<(i64, i32)> {
+    let created_at = match history.message.as_ref()? {
+        message_history::Message::UserMessage(user) => user.created_at.as_ref(),
+        message_history::Message::AssistantMessage(assistant) => assistant.created_at.as_ref(),
+    }?;
+    Some((created_at.seconds, created_at.nanos))
+}
+
+/// Merges streamed messages into the transcript. Assistant updates only carry the parts
+/// that changed, so their parts are merged by id instead of replacing the whole list
+fn merge_messages(messages: &mut Vec<MessageHistory>, changed: Vec<MessageHistory>) {
+    for incoming in changed {
+        let Some(id) = message_id(&incoming) else {
+            continue;
+        };
+        let Some(existing) = messages
+            .iter_mut()
+            .find(|message| message_id(message) == Some(id))
+        else {
+            // A sent prompt can be merged after the start of its reply already streamed in
+            let position = messages
+                .iter()
+                .rposition(|message| created_at(message) <= created_at(&incoming))
+                .map_or(0, |position| position + 1);
+            messages.insert(position, incoming);
+            continue;
+        };
+
+        match (existing.message.as_mut(), incoming.message) {
+            (
+                Some(message_history::Message::AssistantMessage(current)),
+                Some(message_history::Message::AssistantMessage(update)),
+            ) => merge_assistant_message(current, update),
+            (_, message) => existing.message = message,
+        }
+    }
+}
+
+fn merge_assistant_message(current: &mut AssistantMessageModel, mut update: AssistantMessageModel) {
+    let mut parts = std::mem::take(&mut current.parts);
+    for part in std::mem::take(&mut update.parts) {
+        merge_assistant_part(&mut parts, part);
+    }
+    *current = update;
+    current.parts = parts;
+}
+
+fn merge_assistant_part(
+    parts: &mut Vec<AssistantMessagePartModel>,
+    part: AssistantMessagePartModel,
+) {
+    if let Some(existing) = parts.iter_mut().find(|existing| existing.id == part.id) {
+        *existing = part;
+        return;
+    }
+    let index = parts.partition_point(|existing| existing.position <= part.position);
+    parts.insert(index, part);
+}
+
+fn find_assistant_message<'a>(
+    messages: &'a mut [MessageHistory],
+    assistant_message_id: &str,
+) -> Option<&'a mut AssistantMessageModel> {
+    messages
+        .iter_mut()
+        .find_map(|message| match message.message.as_mut()? {
+            message_history::Message::AssistantMessage(assistant)
+                if assistant.id == assistant_message_id =>
+            {
+                Some(assistant)
+            }
+            _ => None,
+        })
+}
+
+/// Appends streamed text to the part in place, the same way the backend stores it
+fn append_part_delta(messages: &mut [MessageHistory], delta: PartDelta) {
+    let Some(part) = find_assistant_message(messages, &delta.message_id).and_then(|assistant| {
+        assistant
+            .parts
+            .iter_mut()
+            .find(|part| part.id == delta.part_id)
+    }) else {
+        return;
+    };
+    if delta.field == "text" {
+        part.text.get_or_insert_default().push_str(&delta.delta);
+    }
+    part.delta_field = Some(delta.field);
+    part.delta_text = Some(delta.delta);
+}
@@ -4,14 +4,32 @@ use uuid::Uuid;
 
 use crate::{
     BACKEND_ADDR,
+    backend::{
+        SessionModel,
+        proto_message::{MessageHistory, SessionDiffSummary, SessionStatusModel},
+    },
     query::{
+        agent::{Agents, AgentsState},
+        file_search::{FileSearch, FileSearchState},
+        harness::{HarnessHealth, HarnessHealthState},
+        message::{Messages, MessagesState},
+        model::{Models, ModelsState},
+        permission::{PermissionRequests, PermissionRequestsState},
         project::{ProjectState, Projects, ProjectsState},
         session::{Sessions, SessionsState},
+        session_diff::{SessionDiffState, SessionDiffs},
     },
 };
 
+mod agent;
+mod file_search;
+mod harness;
+mod message;
+mod model;
+mod permission;
 mod project;
 mod session;
+mod session_diff;
 
 #[derive(Debug, Clone)]
 pub enum QueryState<T> {
@@ -23,6 +41,13 @@ pub enum QueryState<T> {
 pub struct QueryClient {
     projects: Projects,
     sessions: Sessions,
+    messages: Messages,
+    permission_requests: PermissionRequests,
+    harness_health: HarnessHealth,
+    models: Models,
+    agents: Agents,
+    file_search: FileSearch,
+    session_diffs: SessionDiffs,
 }
 
 impl QueryClient {
@@ -33,8 +58,26 @@ impl QueryClient {
         let projects = Projects::new(backend_channel.clone());
         projects.listen_updates();
         let sessions = Sessions::new(backend_channel.clone());
+        let messages = Messages::new(backend_channel.clone());
+        let permission_requests = PermissionRequests::new(backend_channel.clone());
+        let harness_health = HarnessHealth::new(backend_channel.clone());
+        harness_health.listen_updates();
+        let models = Models::new(backend_channel.clone());
+        let agents = Agents::new(backend_channel.clone());
+        let file_search = FileSearch::new(backend_channel.clone());
+        let session_diffs = SessionDiffs::new(backend_channel.clone());
 
-        Self { projects, sessions }
+        Self {
+            projects,
+            sessions,
+            messages,
+            permission_requests,
+            harness_health,
+            models,
+            agents,
+            file_search,
+            session_diffs,
+        }
     }
 
     pub fn use_projects(&mut self, ui: &Ui) -> ProjectsState {
@@ -48,4 +91,58 @@ impl QueryClient {
     pub fn use_sessions_by_project(&mut self, ui: &Ui, project_id: Uuid) -> SessionsState {
         self.sessions.subscribe_state(ui, project_id)
     }
+
+    pub fn use_messages_by_session(&mut self, ui: &Ui, session_id: Uuid) -> MessagesState {
+        self.messages.subscribe_state(ui, session_id)
+    }
+
+    /// Status the harness last reported, read after `use_messages_by_session` in the same frame
+    pub fn session_status(&self, session_id: Uuid) -> Option<&SessionStatusModel> {
+        self.messages.session_status(session_id)
+    }
+
+    /// Totals of the files the session changed, read after `use_messages_by_session` in the
+    /// same frame. `None` until they change while subscribed
+    pub fn session_diff_summary(&self, session_id: Uuid) -> Option<&SessionDiffSummary> {
+        self.messages.session_diff_summary(session_id)
+    }
+
+    /// The files the session changed, kept current through the session's message subscription
+    pub fn use_session_diff(&mut self, ui: &Ui, session_id: Uuid) -> SessionDiffState {
+        self.messages.subscribe_state(ui, session_id);
+        let summary = self.messages.session_diff_summary(session_id);
+        self.session_diffs.subscribe_state(ui, session_id, summary)
+    }
+
+    pub fn use_permission_requests_by_session(
+        &mut self,
+        ui: &Ui,
+        session_id: Uuid,
+    ) -> PermissionRequestsState {
+        self.permission_requests.subscribe_state(ui, session_id)
+    }
+
+    pub fn use_harness_health(&mut self, ui: &Ui) -> HarnessHealthState {
+        self.harness_health.subscribe_state(ui)
+    }
+
+    pub fn use_models(&mut self, ui: &Ui, harness_type: &str) -> ModelsState {
+        self.models.subscribe_state(ui, harness_type)
+    }
+
+    pub fn use_agents(&mut self, ui: &Ui, harness_type: &str) -> AgentsState {
+        self.agents.subscribe_state(ui, harness_type)
+    }
+
+    pub fn use_file_search(&mut self, ui: &Ui, project_id: Uuid, query: &str) -> FileSearchState {
+        self.file_search.subscribe_state(ui, project_id, query)
+    }
+
+    pub fn insert_session(&mut self, session: SessionModel) {
+        self.sessions.insert(session);
+    }
+
+    pub fn merge_messages(&mut self, session_id: Uuid, changed: Vec<MessageHistory>) {
+        self.messages.merge(session_id, changed);
+    }
 }
@@ -0,0 +1,127 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use egui::Ui;
+use egui_inbox::UiInbox;
+use tonic::{Request, transport::Channel};
+
+use crate::backend::{HarnessesClient, ListModelsRequest, proto_harness::ListModelsReply};
+use crate::components::model_selector::ModelOption;
+
+use super::QueryState;
+
+/// Pause before asking again after a failure, e.g. while the harness is still starting
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Models a harness offers, with the index of the one to preselect
+#[derive(Debug, Clone, Default)]
+pub struct ModelCatalog {
+    pub models: Vec<ModelOption>,
+    pub default_index: Option<usize>,
+}
+
+impl ModelCatalog {
+    pub fn index_of(&self, provider_id: &str, model_id: &str) -> Option<usize> {
+        self.models
+            .iter()
+            .position(|model| model.provider_id == provider_id && model.model_id == model_id)
+    }
+}
+
+pub type ModelsState = QueryState<Arc<ModelCatalog>>;
+
+/// Model catalogs by harness type, fetched once and kept for the whole run
+pub struct Models {
+    backend_channel: Channel,
+    state_by_harness: HashMap<String, ModelsState>,
+    is_fetching: HashSet<String>,
+    inbox: UiInbox<(String, ModelsState)>,
+}
+
+impl Models {
+    pub fn new(backend_channel: Channel) -> Self {
+        Self {
+            backend_channel,
+            state_by_harness: HashMap::new(),
+            is_fetching: HashSet::new(),
+            inbox: UiInbox::new(),
+        }
+    }
+
+    pub fn subscribe_state(&mut self, ui: &Ui, harness_type: &str) -> ModelsState {
+        for (updated_harness_type, updated_state) in self.inbox.read(ui) {
+            self.is_fetching.remove(&updated_harness_type);
+            self.state_by_harness
+                .insert(updated_harness_type, updated_state);
+        }
+
+        self.fetch_if_needed(harness_type);
+
+        self.state_by_harness
+            .get(harness_type)
+            .cloned()
+            .unwrap_or(QueryState::Loading)
+    }
+
+    fn fetch_if_needed(&mut self, harness_type: &str) {
+        if self.is_fetching.contains(harness_type) {
+            return;
+        }
+
+        if matches!(
+            self.state_by_harness.get(harness_type),
+            Some(QueryState::Data(_))
+        ) {
+            return;
+        }
+
+        self.is_fetching.insert(harness_type.to_string());
+
+        let sender = self.inbox.sender().clone();
+        let channel = self.backend_channel.clone();
+        let harness_type = harness_type.to_string();
+
+        tokio::spawn(async move {
+            let response = HarnessesClient::new(channel)
+                .list_models(Request::new(ListModelsRequest {
+                    harness_type: harness_type.clone(),
+                }))
+                .await;
+
+            let state = match response {
+                Ok(resp) => QueryState::Data(Arc::new(Models::map(resp.into_inner()))),
+                Err(e) => {
+                    // The last state stays up meanwhile, the next read asks again
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    QueryState::Error(e.message().to_string())
+                }
+            };
+
+            let _ = sender.send((harness_type, state));
+        });
+    }
+
+    fn map(reply: ListModelsReply) -> ModelCatalog {
+        let models = reply
+            .models
+            .into_iter()
+            .map(|model| ModelOption {
+                label: format!("{} / {}", model.provider_name, model.model_name),
+                provider_id: model.provider_id,
+                provider_name: model.provider_name,
+                model_id: model.model_id,
+                model_name: model.model_name,
+            })
+            .collect::<Vec<_>>();
+        let mut catalog = ModelCatalog {
+            models,
+            default_index: None,
+        };
+        catalog.default_index = reply
+            .default_model
+            .and_then(|default| catalog.index_of(&default.provider_id, &default.model_id))
+            .or((!catalog.models.is_empty()).then_some(0));
+        catalog
+    }
+}
@@ -0,0 +1,97 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use egui::Ui;
+use egui_inbox::UiInbox;
+use futures::StreamExt;
+use tonic::{Request, transport::Channel};
+use uuid::Uuid;
+
+use crate::backend::{
+    PermissionsClient, SubscribePermissionRequestsRequest, proto_permission::PermissionRequestModel,
+};
+
+use super::QueryState;
+
+pub type PermissionRequestsState = QueryState<Arc<Vec<PermissionRequestModel>>>;
+
+pub struct PermissionRequests {
+    backend_channel: Channel,
+    state_by_session: HashMap<Uuid, PermissionRequestsState>,
+    session_subscriptions: HashSet<Uuid>,
+    inbox: UiInbox<(Uuid, Result<Vec<PermissionRequestModel>, String>)>,
+}
+
+impl PermissionRequests {
+    pub fn new(backend_channel: Channel) -> Self {
+        Self {
+            backend_channel,
+            state_by_session: HashMap::new(),
+            session_subscriptions: HashSet::new(),
+            inbox: UiInbox::new(),
+        }
+    }
+
+    pub fn subscribe_state(&mut self, ui: &Ui, session_id: Uuid) -> PermissionRequestsState {
+        for (updated_session_id, update) in self.inbox.read(ui) {
+            // Every reply carries the full pending list, so it replaces what we had
+            let state = match update {
+                Ok(requests) => QueryState::Data(Arc::new(requests)),
+                Err(e) => QueryState::Error(e),
+            };
+            self.state_by_session.insert(updated_session_id, state);
+        }
+
+        self.subscribe_session_if_needed(session_id);
+
+        self.state_by_session
+            .get(&session_id)
+            .cloned()
+            .unwrap_or(QueryState::Loading)
+    }
+
+    fn subscribe_session_if_needed(&mut self, session_id: Uuid) {
+        if self.session_subscriptions.contains(&session_id) {
+            return;
+        }
+
+        self.session_subscriptions.insert(session_id);
+        self.state_by_session
+            .insert(session_id, QueryState::Loading);
+
+        let sender = self.inbox.sender().clone();
+        let channel = self.backend_channel.clone();
+
+        tokio::spawn(async move {
+            let mut stream = match PermissionsClient::new(channel)
+                .subscribe_permission_requests(Request::new(SubscribePermissionRequestsRequest {
+                    session_id: session_id.to_string(),
+                }))
+                .await
+            {
+                Ok(resp) => resp.into_inner(),
+                Err(e) => {
+                    let _ = sender.send((session_id, Err(e.to_string())));
+                    return;
+                }
+            };
+
+            while let Some(next) = stream.next().await {
+                match next {
+                    Ok(reply) => {
+                        let _ = sender.send((session_id, Ok(reply.requests)));
+                    }
+                    Err(e) => {
+                        let _ = sender.send((session_id, Err(e.to_string())));
+                        return;
+                    }
+                }
+            }
+
+            let _ = sender.send((
+                session_id,
+                Err("permission requests stream closed unexpectedly".to_string()),
+            ));
+        });
+    }
+}
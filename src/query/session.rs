@@ -45,6 +45,15 @@ impl Sessions {
             .unwrap_or(QueryState::Loading)
     }
 
+    /// Adds a session created from the GUI, e.g. a fork, to its project's loaded list
+    pub fn insert(&mut self, session: SessionModel) {
+        if let Some(QueryState::Data(sessions)) = self.state_by_project.get_mut(&session.project_id)
+            && !sessions.iter().any(|known| known.id == session.id)
+        {
+            sessions.push(session);
+        }
+    }
+
     fn fetch_if_needed(&mut self, project_id: Uuid) {
         if self.is_fetching.contains(&project_id) {
             return;
@@ -0,0 +1,102 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+use std::time::Duration;
+
+use egui::Ui;
+use egui_inbox::UiInbox;
+use tonic::{Request, transport::Channel};
+use uuid::Uuid;
+
+use crate::backend::{
+    SessionClient,
+    proto_message::SessionDiffSummary,
+    proto_session::{FileDiffModel, GetSessionDiffRequest},
+};
+
+use super::QueryState;
+
+/// Pause before asking again after a failure
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+pub type SessionDiffState = QueryState<Arc<Vec<FileDiffModel>>>;
+
+/// The files each session changed, fetched again whenever the session's totals change
+pub struct SessionDiffs {
+    backend_channel: Channel,
+    state_by_session: HashMap<Uuid, SessionDiffState>,
+    /// Totals each fetch was made for, `None` for the first fetch
+    fetched_for: HashMap<Uuid, Option<SessionDiffSummary>>,
+    is_fetching: HashSet<Uuid>,
+    inbox: UiInbox<(Uuid, SessionDiffState)>,
+}
+
+impl SessionDiffs {
+    pub fn new(backend_channel: Channel) -> Self {
+        Self {
+            backend_channel,
+            state_by_session: HashMap::new(),
+            fetched_for: HashMap::new(),
+            is_fetching: HashSet::new(),
+            inbox: UiInbox::new(),
+        }
+    }
+
+    pub fn subscribe_state(
+        &mut self,
+        ui: &Ui,
+        session_id: Uuid,
+        summary: Option<&SessionDiffSummary>,
+    ) -> SessionDiffState {
+        for (updated_session_id, updated_state) in self.inbox.read(ui) {
+            self.is_fetching.remove(&updated_session_id);
+            if matches!(updated_state, QueryState::Error(_)) {
+                // Asks again on the next read
+                self.fetched_for.remove(&updated_session_id);
+            }
+            self.state_by_session
+                .insert(updated_session_id, updated_state);
+        }
+
+        self.fetch_if_needed(session_id, summary);
+
+        self.state_by_session
+            .get(&session_id)
+            .cloned()
+            .unwrap_or(QueryState::Loading)
+    }
+
+    fn fetch_if_needed(&mut self, session_id: Uuid, summary: Option<&SessionDiffSummary>) {
+        if self.is_fetching.contains(&session_id)
+            || self
+                .fetched_for
+                .get(&session_id)
+                .is_some_and(|fetched_for| fetched_for.as_ref() == summary)
+        {
+            return;
+        }
+
+        self.is_fetching.insert(session_id);
+        self.fetched_for.insert(session_id, summary.cloned());
+
+        let sender = self.inbox.sender().clone();
+        let channel = self.backend_channel.clone();
+
+        tokio::spawn(async move {
+            let response = SessionClient::new(channel)
+                .get_session_diff(Request::new(GetSessionDiffRequest {
+                    session_id: session_id.to_string(),
+                }))
+                .await;
+
+            let state = match response {
+                Ok(resp) => QueryState::Data(Arc::new(resp.into_inner().files)),
+                Err(e) => {
+                    tokio::time::sleep(RETRY_DELAY).await;
+                    QueryState::Error(e.message().to_string())
+                }
+            };
+
+            let _ = sender.send((session_id, state));
+        });
+    }
+}
@@ -15,5 +15,9 @@ pub const BG_800: Color32 = Color32::from_rgb(38, 38, 38);
 pub const BG_900: Color32 = Color32::from_rgb(23, 23, 23);
 pub const BG_950: Color32 = Color32::from_rgb(10, 10, 10);
 
+pub const GREEN_500: Color32 = Color32::from_rgb(34, 197, 94);
+pub const AMBER_500: Color32 = Color32::from_rgb(245, 158, 11);
+pub const RED_500: Color32 = Color32::from_rgb(239, 68, 68);
+
 pub const RADIUS_MD: f32 = 8.0;
 pub const STROKE_WIDTH: f32 = 1.0;